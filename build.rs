@@ -0,0 +1,231 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+// Turns the PNGs in assets/ into RGB565 for src/assets.rs to include, so art
+// can be drawn in an image editor rather than converted by hand. Each one
+// becomes a gfx::Image named after the file, FERRIS for ferris.png. The ones
+// in assets/tiles/ are strips of square tiles, one under the other, and
+// become gfx::TileSets with tiles as wide as the strip. The ones in
+// assets/screens/ are run-length packed for src/rle.rs: a square one is an
+// rle::Image, and a strip of square frames is an rle::Animation.
+//
+// Any PNG will do, at any bit depth, with or without a palette or alpha. One
+// that doesn't decode stops the build, rather than coming out wrong on the
+// panel.
+
+// What a mostly transparent pixel comes out as, for gfx::blit_keyed
+const KEY: u16 = 0xf81f;
+
+fn main() {
+    memory();
+    defmt();
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap()).join("assets.rs");
+    println!("cargo:rerun-if-changed=assets");
+
+    let mut code = String::new();
+    writeln!(code, "pub const KEY: u16 = {:#06x};", KEY).unwrap();
+    for path in pngs(Path::new("assets")) {
+        let (name, image) = load(&path);
+        writeln!(code, "\n// {}", path.display()).unwrap();
+        writeln!(code, "pub const {}: gfx::Image<'static> = gfx::Image {{", name).unwrap();
+        writeln!(code, "    w: {},\n    h: {},", image.w, image.h).unwrap();
+        pixels(&mut code, &image.pixels);
+        code.push_str("};\n");
+    }
+    for path in pngs(Path::new("assets/tiles")) {
+        let (name, image) = load(&path);
+        if image.h % image.w != 0 {
+            panic!("{}: not a strip of square tiles", path.display());
+        }
+        writeln!(code, "\n// {}, {} tiles", path.display(), image.h / image.w).unwrap();
+        writeln!(code, "pub const {}: gfx::TileSet<'static> = gfx::TileSet {{", name).unwrap();
+        writeln!(code, "    size: {},", image.w).unwrap();
+        pixels(&mut code, &image.pixels);
+        code.push_str("};\n");
+    }
+    for path in pngs(Path::new("assets/screens")) {
+        let (name, image) = load(&path);
+        if image.h % image.w != 0 {
+            panic!("{}: not square, or a strip of square frames", path.display());
+        }
+        let frames: Vec<Vec<u8>> = image.pixels.chunks(image.w * image.w).map(pack).collect();
+        let packed: usize = frames.iter().map(Vec::len).sum();
+        if frames.len() == 1 {
+            writeln!(code, "\n// {}, {} bytes packed", path.display(), packed).unwrap();
+            writeln!(code, "pub const {}: rle::Image<'static> = rle::Image {{", name).unwrap();
+            writeln!(code, "    w: {},\n    h: {},", image.w, image.w).unwrap();
+            bytes(&mut code, "data", &frames[0]);
+        } else {
+            let (count, path) = (frames.len(), path.display());
+            writeln!(code, "\n// {}, {} frames, {} bytes packed", path, count, packed).unwrap();
+            let kind = "rle::Animation";
+            writeln!(code, "pub const {}: {}<'static> = {} {{", name, kind, kind).unwrap();
+            writeln!(code, "    w: {},\n    h: {},", image.w, image.w).unwrap();
+            code.push_str("    frames: &[\n");
+            for frame in &frames {
+                bytes(&mut code, "", frame);
+            }
+            code.push_str("    ],\n");
+        }
+        code.push_str("};\n");
+    }
+    fs::write(out, code).unwrap();
+}
+
+// The memory map for cortex-m-rt's link.x, which includes whichever memory.x
+// the linker finds first. This build script's output comes ahead of the
+// one nrf52840-hal writes, so this is the one that counts.
+fn memory() {
+    let source = match env::var_os("CARGO_FEATURE_DFU") {
+        Some(_) => "memory-dfu.x",
+        None => "memory-swd.x",
+    };
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy(source, out.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory-swd.x");
+    println!("cargo:rerun-if-changed=memory-dfu.x");
+}
+
+// defmt keeps its format strings in a section of their own, which its
+// linker script lays out. Only the board's builds link it.
+fn defmt() {
+    let board = env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "none");
+    if env::var_os("CARGO_FEATURE_DEFMT").is_some() && board {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
+}
+
+// In name order, so the output doesn't change from one build to the next
+fn pngs(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+        Err(_) => return Vec::new(),
+    };
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "png"));
+    paths.sort();
+    paths
+}
+
+fn load(path: &Path) -> (String, Image) {
+    println!("cargo:rerun-if-changed={}", path.display());
+    let name = path
+        .file_stem()
+        .unwrap()
+        .to_string_lossy()
+        .to_uppercase()
+        .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+    let data = fs::read(path).unwrap();
+    match decode(&data) {
+        Ok(image) => (name, image),
+        Err(err) => panic!("{}: {}", path.display(), err),
+    }
+}
+
+fn pixels(code: &mut String, pixels: &[u16]) {
+    code.push_str("    pixels: &[");
+    for (i, pixel) in pixels.iter().enumerate() {
+        if i % 12 == 0 {
+            code.push_str("\n       ");
+        }
+        write!(code, " {:#06x},", pixel).unwrap();
+    }
+    code.push_str("\n    ],\n");
+}
+
+// `field: &[...],` with a field name, or just `&[...],` inside a list
+fn bytes(code: &mut String, field: &str, bytes: &[u8]) {
+    match field {
+        "" => code.push_str("    &["),
+        field => write!(code, "    {}: &[", field).unwrap(),
+    }
+    for (i, byte) in bytes.iter().enumerate() {
+        if i % 16 == 0 {
+            code.push_str("\n       ");
+        }
+        write!(code, " {:#04x},", byte).unwrap();
+    }
+    code.push_str("\n    ],\n");
+}
+
+// The other half of rle::Pixels: runs of two or more of the same pixel, and
+// whatever's between them copied as it is
+fn pack(pixels: &[u16]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = pixels[i..].iter().take(129).take_while(|&&p| p == pixels[i]).count();
+        if run >= 2 {
+            out.push(0x80 + (run - 2) as u8);
+            out.extend_from_slice(&pixels[i].to_le_bytes());
+            i += run;
+            continue;
+        }
+        // Up to where the next run starts
+        let mut len = 1;
+        while len < 128 && i + len < pixels.len() {
+            let next = i + len;
+            if next + 1 < pixels.len() && pixels[next] == pixels[next + 1] {
+                break;
+            }
+            len += 1;
+        }
+        out.push(len as u8 - 1);
+        for pixel in &pixels[i..i + len] {
+            out.extend_from_slice(&pixel.to_le_bytes());
+        }
+        i += len;
+    }
+    out
+}
+
+struct Image {
+    w: usize,
+    h: usize,
+    pixels: Vec<u16>,
+}
+
+// Packed the way `rgb565` in main.rs packs them, blue in the top bits, since
+// the panel is BGR
+fn rgb565([r, g, b, a]: [u8; 4]) -> u16 {
+    if a < 128 {
+        return KEY;
+    }
+    let (r, g, b) = (r as u16 >> 3, g as u16 >> 2, b as u16 >> 3);
+    let color = (b << 11) | (g << 5) | r;
+    // Nudged a shade of green off the key, so it still shows
+    if color == KEY {
+        color ^ (1 << 5)
+    } else {
+        color
+    }
+}
+
+fn decode(data: &[u8]) -> Result<Image, png::DecodingError> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    // Palettes, low bit depths and 16 bit channels all come out as 8 bit grey
+    // or RGB, with or without alpha
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+    let info = reader.next_frame(&mut buf)?;
+    let (w, h) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+
+    let mut pixels = Vec::with_capacity(w * h);
+    for row in buf.chunks(info.line_size).take(h) {
+        for px in row[..w * channels].chunks(channels) {
+            let rgba = match px {
+                [v] => [*v, *v, *v, 255],
+                [v, a] => [*v, *v, *v, *a],
+                [r, g, b] => [*r, *g, *b, 255],
+                _ => [px[0], px[1], px[2], px[3]],
+            };
+            pixels.push(rgb565(rgba));
+        }
+    }
+    Ok(Image { w, h, pixels })
+}
@@ -0,0 +1,18 @@
+/* For Nordic's open USB bootloader, the one the nRF52840 Dongle ships with,
+   with the `dfu` feature. The MBR and the bootloader keep their own flash,
+   and the pages src/storage.rs keeps things in move down below the
+   bootloader. The MBR keeps the first 8 bytes of RAM for itself.
+
+   0x00000000  the MBR, which forwards interrupts to the firmware
+   0x00001000  the firmware
+   0x000D9000  the panic page, then settings, scores and totals, and the
+               checkpoint, 0x000DF000
+   0x000E0000  the bootloader
+   0x000FE000  the MBR's parameters
+   0x000FF000  the bootloader's settings
+   0x00100000  the end of flash */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00001000, LENGTH = 0xD8000
+  RAM : ORIGIN = 0x20000008, LENGTH = 256K - 8
+}
@@ -0,0 +1,15 @@
+/* The nRF52840's whole flash and RAM, for flashing over SWD with nothing
+   else on the chip. build.rs copies this, or memory-dfu.x with the `dfu`
+   feature, to the memory.x the linker finds, in place of the one
+   nrf52840-hal ships. Neither can be called memory.x here: the linker would
+   find it first, whatever the feature.
+
+   0x00000000  the firmware
+   0x000F9000  the panic page, then settings, scores and totals, and the
+               checkpoint in the last page, 0x000FF000 (see src/storage.rs)
+   0x00100000  the end of flash */
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 0xF9000
+  RAM : ORIGIN = 0x20000000, LENGTH = 256K
+}
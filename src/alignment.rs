@@ -0,0 +1,46 @@
+use crate::draw;
+use crate::limits::Limits;
+
+// A test card for lining up the panel, shown instead of the game while `grid
+// on` is set. The border is the outermost pixel on every side, so any of it
+// missing or doubled up means the panel offset or layout is off. Each
+// corner has its own color, clockwise from red at the top left, to tell
+// which way round the orientation has put it.
+
+const WIDTH: i32 = Limits::SCREEN_WIDTH as i32;
+const HEIGHT: i32 = Limits::SCREEN_HEIGHT as i32;
+
+const WHITE: u16 = 0xFFFF;
+// Red, green, blue and yellow
+const CORNERS: [u16; 4] = [0xF800, 0x07E0, 0x001F, 0xFFE0];
+// How far the corner markers sit inside the border, and how long their arms are
+const INSET: i32 = 2;
+const ARM: i32 = 8;
+// Half the length of each arm of the crosshair
+const CROSS: i32 = 6;
+
+pub fn draw(frame: &mut [u8]) {
+    let (right, bottom) = (WIDTH - 1, HEIGHT - 1);
+    draw::draw_line(frame, 0, 0, right, 0, WHITE);
+    draw::draw_line(frame, right, 0, right, bottom, WHITE);
+    draw::draw_line(frame, right, bottom, 0, bottom, WHITE);
+    draw::draw_line(frame, 0, bottom, 0, 0, WHITE);
+
+    // Odd sizes have a middle pixel, even ones put it just after the middle
+    let (cx, cy) = (WIDTH / 2, HEIGHT / 2);
+    draw::draw_line(frame, cx - CROSS, cy, cx + CROSS, cy, WHITE);
+    draw::draw_line(frame, cx, cy - CROSS, cx, cy + CROSS, WHITE);
+
+    // Top left, top right, bottom right and bottom left, with which way the
+    // arms go from each
+    let corners = [
+        (INSET, INSET, 1, 1),
+        (right - INSET, INSET, -1, 1),
+        (right - INSET, bottom - INSET, -1, -1),
+        (INSET, bottom - INSET, 1, -1),
+    ];
+    for (&(x, y, dx, dy), &color) in corners.iter().zip(CORNERS.iter()) {
+        draw::draw_line(frame, x, y, x + dx * (ARM - 1), y, color);
+        draw::draw_line(frame, x, y, x, y + dy * (ARM - 1), color);
+    }
+}
@@ -0,0 +1,10 @@
+// Art from the assets/ directory, converted to RGB565 by build.rs: a
+// gfx::Image for each PNG there, a gfx::TileSet for each in
+// assets/tiles/, and an rle::Image or rle::Animation for each in
+// assets/screens/. Mostly transparent pixels come out as KEY, for
+// gfx::blit_keyed and rle::draw to leave alone.
+
+use crate::gfx;
+use crate::rle;
+
+include!(concat!(env!("OUT_DIR"), "/assets.rs"));
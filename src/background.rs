@@ -0,0 +1,203 @@
+use crate::effect::Effect;
+use crate::game::{Rect, State};
+use crate::limits::Limits;
+use crate::settings::Settings;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Background {
+    // These change every frame, so there's nothing to cache
+    Plasma,
+    // The plasma at this intensity, out of 255, behind the game
+    Backdrop(u8),
+    Starfield,
+    Tiles,
+    Solid(u16),
+}
+
+impl Background {
+    // What goes behind the game in `state`. The full plasma is the title's,
+    // and a game gets it dimmed, only with a backdrop.
+    pub fn of(state: State, settings: &Settings) -> Self {
+        match (state, settings.effect) {
+            (State::Title, Effect::Plasma) => Background::Plasma,
+            (_, Effect::Plasma) if settings.backdrop > 0 => Background::Backdrop(settings.backdrop),
+            (_, Effect::Starfield) => Background::Starfield,
+            (_, Effect::Tiles) => Background::Tiles,
+            _ => Background::Solid(settings.clear_color),
+        }
+    }
+
+    pub fn is_static(self) -> bool {
+        !matches!(
+            self,
+            Background::Plasma | Background::Backdrop(_) | Background::Starfield | Background::Tiles
+        )
+    }
+}
+
+// Foreground rects remembered between frames. A frame with more sprites than
+// this falls back to a full transfer.
+pub const MAX_RECTS: usize = Limits::SPRITE_RECTS;
+
+// Keeps a copy of the last background that was sent to the panel in full.
+// While the background stays the same, each frame starts from that copy and
+// only the regions the foreground touched this frame or the last one need to
+// go out over SPI, instead of the whole buffer.
+//
+// Costs one extra framebuffer of RAM (8 KiB at 64x64 RGB565) for `pixels`.
+pub struct BackgroundCache<const N: usize> {
+    pixels: [u8; N],
+    cached: Option<Background>,
+    prev: [Rect; MAX_RECTS],
+    prev_len: usize,
+    // The last frame had more foreground than `prev` could hold
+    prev_overflowed: bool,
+    // `pixels` has been lent out, see `lend`
+    lent: bool,
+}
+
+impl<const N: usize> BackgroundCache<N> {
+    pub const fn new() -> Self {
+        BackgroundCache {
+            pixels: [0; N],
+            cached: None,
+            prev: [Rect {
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+            }; MAX_RECTS],
+            prev_len: 0,
+            prev_overflowed: false,
+            lent: false,
+        }
+    }
+
+    // Fills `frame` with the cached copy of `background` and returns true if
+    // it's on the panel already. Otherwise the caller has to render the
+    // background itself, hand it to `store`, and send the whole frame.
+    pub fn restore(&mut self, background: Background, frame: &mut [u8; N]) -> bool {
+        if background.is_static() && self.cached == Some(background) && !self.prev_overflowed {
+            frame.copy_from_slice(&self.pixels);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Forces the next frame to be sent in full
+    pub fn invalidate(&mut self) {
+        self.cached = None;
+    }
+
+    pub fn store(&mut self, background: Background, frame: &[u8; N]) {
+        if background.is_static() && !self.lent {
+            self.pixels.copy_from_slice(frame);
+            self.cached = Some(background);
+        } else {
+            self.cached = None;
+        }
+        self.prev_len = 0;
+        self.prev_overflowed = false;
+    }
+
+    // Hands the cache's buffer over for something else to use for a while.
+    // Nothing is cached until it's given back with `reclaim`.
+    pub fn lend(&mut self) -> &mut [u8; N] {
+        self.lent = true;
+        self.cached = None;
+        &mut self.pixels
+    }
+
+    // The lent buffer, as whoever borrowed it left it
+    pub fn lent(&self) -> &[u8; N] {
+        &self.pixels
+    }
+
+    pub fn reclaim(&mut self) {
+        self.lent = false;
+    }
+
+    // Records this frame's foreground and calls `send` with every region that
+    // needs to be transmitted: where the foreground was last frame (to erase
+    // it) and where it is now. If there are too many rects to remember, the
+    // next frame will be sent in full.
+    //
+    // Rects that overlap enough go out as one, so a sprite that moved a
+    // pixel is sent once rather than twice over, and each rect saved is an
+    // address window less to set.
+    pub fn flush_dirty(
+        &mut self,
+        foreground: impl Iterator<Item = Rect>,
+        mut send: impl FnMut(Rect),
+    ) {
+        let mut dirty = Dirty::new();
+        for &rect in &self.prev[..self.prev_len] {
+            dirty.add(rect, &mut send);
+        }
+
+        self.prev_len = 0;
+        for rect in foreground {
+            dirty.add(rect, &mut send);
+            if self.prev_len == MAX_RECTS {
+                self.prev_overflowed = true;
+                continue;
+            }
+            self.prev[self.prev_len] = rect;
+            self.prev_len += 1;
+        }
+
+        for &rect in &dirty.rects[..dirty.len] {
+            send(rect);
+        }
+    }
+}
+
+// The regions a frame has to send, merged where that doesn't cost more
+// pixels than it saves
+struct Dirty {
+    rects: [Rect; 2 * MAX_RECTS],
+    len: usize,
+}
+
+impl Dirty {
+    fn new() -> Self {
+        Dirty {
+            rects: [Rect {
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+            }; 2 * MAX_RECTS],
+            len: 0,
+        }
+    }
+
+    // Merging can make a rect big enough to swallow others it didn't touch
+    // before, so it goes round again until nothing else merges. With no
+    // room left the rect is sent as it is.
+    fn add(&mut self, mut rect: Rect, send: &mut impl FnMut(Rect)) {
+        if rect.area() == 0 {
+            return;
+        }
+        let mut i = 0;
+        while i < self.len {
+            let other = self.rects[i];
+            let union = rect.union(other);
+            if union.area() <= rect.area() + other.area() {
+                rect = union;
+                self.len -= 1;
+                self.rects[i] = self.rects[self.len];
+                i = 0;
+            } else {
+                i += 1;
+            }
+        }
+        if self.len == self.rects.len() {
+            send(rect);
+            return;
+        }
+        self.rects[self.len] = rect;
+        self.len += 1;
+    }
+}
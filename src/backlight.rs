@@ -0,0 +1,63 @@
+use embedded_hal_1::pwm::SetDutyCycle;
+
+// How the backlight is dimmed.
+//
+// A faster PWM flickers less, on camera as well as to the eye, but switches
+// the LED driver more often, costing a little power and radiating more, and
+// leaves fewer steps per period: on the nRF52840's undivided 16 MHz PWM
+// clock, 1 kHz has 16000 of them but 100 kHz only 160. Whatever the
+// frequency, some drivers can't light the LEDs properly from a very short
+// pulse, and the lowest levels flicker or come out uneven. `min_on_us` sets the shortest pulse
+// sent, so any level above 0 is at least that bright.
+//
+// The PWM itself is set up by whoever owns it, to run at `frequency_hz`.
+#[derive(Clone, Copy)]
+pub struct BacklightConfig {
+    pub frequency_hz: u32,
+    pub min_on_us: u16,
+}
+
+impl BacklightConfig {
+    pub const DEFAULT: BacklightConfig = BacklightConfig {
+        frequency_hz: 2_000,
+        min_on_us: 8,
+    };
+}
+
+pub struct Backlight<P: SetDutyCycle> {
+    pwm: P,
+    // Shortest pulse for a level above 0, in PWM counts
+    min_duty: u16,
+    level: Option<u8>,
+}
+
+impl<P: SetDutyCycle> Backlight<P> {
+    // The backlight starts off, until the first `set`
+    pub fn new(mut pwm: P, config: BacklightConfig) -> Self {
+        pwm.set_duty_cycle_fully_off().ok();
+
+        let max = pwm.max_duty_cycle();
+        let min_duty =
+            config.min_on_us as u64 * config.frequency_hz as u64 * max as u64 / 1_000_000;
+        Backlight {
+            min_duty: min_duty.min(max as u64) as u16,
+            pwm,
+            level: None,
+        }
+    }
+
+    // 0 is off, 255 fully on. Cheap to call with the same level again.
+    pub fn set(&mut self, level: u8) {
+        if self.level == Some(level) {
+            return;
+        }
+        self.level = Some(level);
+
+        let max = self.pwm.max_duty_cycle() as u32;
+        let duty = match level {
+            0 => 0,
+            _ => ((level as u32 * max / 255) as u16).max(self.min_duty),
+        };
+        self.pwm.set_duty_cycle(duty).ok();
+    }
+}
@@ -0,0 +1,110 @@
+use core::fmt;
+use nrf52840_pac::POWER;
+
+// Every cargo feature, and whether this build has it. Keep in step with
+// Cargo.toml.
+const FEATURES: &[(&str, bool)] = &[
+    ("sound", cfg!(feature = "sound")),
+    ("console", cfg!(feature = "console")),
+    ("usb", cfg!(feature = "usb")),
+    ("flash", cfg!(feature = "flash")),
+    ("max-level-error", cfg!(feature = "max-level-error")),
+    ("max-level-warn", cfg!(feature = "max-level-warn")),
+    ("max-level-info", cfg!(feature = "max-level-info")),
+    ("max-level-debug", cfg!(feature = "max-level-debug")),
+    ("release", cfg!(feature = "release")),
+    ("diag", cfg!(feature = "diag")),
+    ("perf-overlay", cfg!(feature = "perf-overlay")),
+    ("profile", cfg!(feature = "profile")),
+    ("stick", cfg!(feature = "stick")),
+    ("light", cfg!(feature = "light")),
+    ("ble", cfg!(feature = "ble")),
+    ("link", cfg!(feature = "link")),
+    ("st7789", cfg!(feature = "st7789")),
+    ("shared-spi", cfg!(feature = "shared-spi")),
+    ("scanline", cfg!(feature = "scanline")),
+    ("dma-frames", cfg!(feature = "dma-frames")),
+    ("power-off", cfg!(feature = "power-off")),
+    ("rtc-ticks", cfg!(feature = "rtc-ticks")),
+    ("backlight-switch", cfg!(feature = "backlight-switch")),
+    ("backlight", cfg!(feature = "backlight")),
+    ("encoder", cfg!(feature = "encoder")),
+    ("buttons", cfg!(feature = "buttons")),
+    ("battery", cfg!(feature = "battery")),
+    ("tilt", cfg!(feature = "tilt")),
+    ("haptics", cfg!(feature = "haptics")),
+    ("aa-lines", cfg!(feature = "aa-lines")),
+    ("plasma-float", cfg!(feature = "plasma-float")),
+    ("small-pools", cfg!(feature = "small-pools")),
+    ("dfu", cfg!(feature = "dfu")),
+];
+
+// RESETREAS bits, lowest first
+const RESET_REASONS: &[(u32, &str)] = &[
+    (1 << 0, "reset pin"),
+    (1 << 1, "watchdog"),
+    (1 << 2, "soft reset"),
+    (1 << 3, "lockup"),
+    (1 << 16, "GPIO wake from off"),
+    (1 << 17, "LPCOMP wake from off"),
+    (1 << 18, "debug interface"),
+    (1 << 19, "NFC wake from off"),
+    (1 << 20, "VBUS wake from off"),
+];
+
+struct Features;
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut any = false;
+        for &(name, _) in FEATURES.iter().filter(|&&(_, enabled)| enabled) {
+            if any {
+                f.write_str(" ")?;
+            }
+            f.write_str(name)?;
+            any = true;
+        }
+        if !any {
+            f.write_str("none")?;
+        }
+        Ok(())
+    }
+}
+
+struct ResetReason(u32);
+
+impl fmt::Display for ResetReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The register only records resets other than power-on
+        if self.0 == 0 {
+            return f.write_str("power on");
+        }
+        let mut any = false;
+        for &(_, name) in RESET_REASONS.iter().filter(|&&(bit, _)| self.0 & bit != 0) {
+            if any {
+                f.write_str(", ")?;
+            }
+            f.write_str(name)?;
+            any = true;
+        }
+        if !any {
+            write!(f, "unknown ({:#x})", self.0)?;
+        }
+        Ok(())
+    }
+}
+
+// Logged once at boot so that a bug report says what it was built with.
+// Reading the reset reason also clears it, since the bits otherwise pile up
+// across resets.
+pub fn log(power: &POWER) {
+    let reasons = power.resetreas.read().bits();
+    power.resetreas.write(|w| unsafe { w.bits(reasons) });
+
+    log_info!(
+        "pewpew {}\n  features: {}\n  reset: {}",
+        env!("CARGO_PKG_VERSION"),
+        Features,
+        ResetReason(reasons)
+    );
+}
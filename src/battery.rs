@@ -0,0 +1,194 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use embedded_hal::adc::OneShot;
+use nrf52840_hal::saadc::{InternalVdd, Saadc, SaadcConfig};
+use nrf52840_hal::spim::Frequency;
+use nrf52840_pac::saadc::ch::config::{GAIN_A, REFSEL_A};
+use nrf52840_pac::{POWER, SAADC, SPIM1};
+
+// Supply voltage, read through the SAADC's internal VDD input, and the SPI
+// clock to match. The board runs straight off the battery, so VDD is the
+// battery voltage. As it drops, neither the nRF nor the panel is as happy at
+// full SPI speed, so below `low_mv` the display's SPI clock comes down to
+// `low_frequency`, and goes back up once VDD is back above `recover_mv`.
+// Keeping some distance between the two stops it flapping when the voltage
+// hovers around one threshold. Being low also puts a battery icon in the
+// corner of the screen, and below `flat_mv` it's time to save everything and
+// shut down while there's still the voltage to write flash.
+//
+// Readings go through a slow moving average first, so the dip from a burst
+// of SPI or the synth doesn't count.
+#[derive(Clone, Copy)]
+pub struct BatteryConfig {
+    pub low_mv: u16,
+    pub recover_mv: u16,
+    pub flat_mv: u16,
+    pub low_frequency: Frequency,
+}
+
+impl BatteryConfig {
+    pub const DEFAULT: BatteryConfig = BatteryConfig {
+        low_mv: 2_400,
+        recover_mv: 2_600,
+        flat_mv: 2_150,
+        low_frequency: Frequency::M2,
+    };
+}
+
+// Full scale with the internal 0.6 V reference at 1/6 gain
+const FULL_SCALE_MV: i32 = 3_600;
+// 14 bit conversions
+const FULL_SCALE_RAW: i32 = 1 << 14;
+
+// How much of each new reading goes into the average, as a shift: a quarter
+const FILTER_SHIFT: u32 = 2;
+
+// Charge left against millivolts, for a pair of alkaline cells or a lithium
+// coin cell, which both start at about 3 V and fall away faster towards the
+// end. In between points it's a straight line.
+const CURVE: [(u16, u8); 7] = [
+    (3_000, 100),
+    (2_900, 80),
+    (2_800, 60),
+    (2_700, 40),
+    (2_600, 20),
+    (2_400, 5),
+    (2_150, 0),
+];
+
+// For the frame task, which draws the icon and does the shutting down.
+// PERCENT is u8::MAX until the first reading.
+static LOW: AtomicBool = AtomicBool::new(false);
+static FLAT: AtomicBool = AtomicBool::new(false);
+static PERCENT: AtomicU8 = AtomicU8::new(u8::MAX);
+
+pub fn is_low() -> bool {
+    LOW.load(Ordering::Relaxed)
+}
+
+pub fn is_flat() -> bool {
+    FLAT.load(Ordering::Relaxed)
+}
+
+// An estimate of the charge left, once there's been a reading
+pub fn percent() -> Option<u8> {
+    match PERCENT.load(Ordering::Relaxed) {
+        u8::MAX => None,
+        percent => Some(percent),
+    }
+}
+
+fn percent_at(mv: u16) -> u8 {
+    if mv >= CURVE[0].0 {
+        return CURVE[0].1;
+    }
+    for pair in CURVE.windows(2) {
+        let ((hi_mv, hi), (lo_mv, lo)) = (pair[0], pair[1]);
+        if mv >= lo_mv {
+            let span = (hi - lo) as u32 * (mv - lo_mv) as u32 / (hi_mv - lo_mv) as u32;
+            return lo + span as u8;
+        }
+    }
+    0
+}
+
+// Powers everything down for good. Only a reset, or fresh batteries, starts
+// it again from the top.
+pub fn system_off() -> ! {
+    let power = unsafe { &*POWER::ptr() };
+    power.systemoff.write(|w| w.systemoff().enter());
+    // With a debugger attached System OFF is only emulated, and this carries
+    // on until the watchdog resets it
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+pub struct Battery {
+    saadc: Saadc,
+    config: BatteryConfig,
+    normal_frequency: Frequency,
+    low: bool,
+    // Moving average of the readings, in millivolts shifted up by
+    // FILTER_SHIFT, once there's been one
+    average: Option<u32>,
+}
+
+impl Battery {
+    // `normal_frequency` is what the display's SPI runs at with a healthy
+    // battery
+    pub fn new(saadc: SAADC, config: BatteryConfig, normal_frequency: Frequency) -> Self {
+        let saadc_config = SaadcConfig {
+            reference: REFSEL_A::INTERNAL,
+            gain: GAIN_A::GAIN1_6,
+            ..SaadcConfig::default()
+        };
+        Battery {
+            saadc: Saadc::new(saadc, saadc_config),
+            config,
+            normal_frequency,
+            low: false,
+            average: None,
+        }
+    }
+
+    // Blocks for one conversion
+    pub fn millivolts(&mut self) -> Option<u16> {
+        let raw = self.saadc.read(&mut InternalVdd).ok()? as i32;
+        Some((raw.max(0) * FULL_SCALE_MV / FULL_SCALE_RAW) as u16)
+    }
+
+    // Measures VDD and moves the SPI clock across if the average has crossed
+    // a threshold. Must be called from the same priority as whatever draws,
+    // so it can't land in the middle of a transfer. A frame going out with
+    // `dma-frames` is waited out.
+    pub fn update(&mut self) {
+        let reading = match self.millivolts() {
+            Some(mv) => mv as u32,
+            None => return,
+        };
+        let average = match self.average {
+            Some(average) => average - (average >> FILTER_SHIFT) + reading,
+            None => reading << FILTER_SHIFT,
+        };
+        self.average = Some(average);
+        let mv = (average >> FILTER_SHIFT) as u16;
+        PERCENT.store(percent_at(mv), Ordering::Relaxed);
+        if mv < self.config.flat_mv && !FLAT.swap(true, Ordering::Relaxed) {
+            log_error!("Battery flat at {} mV", mv);
+        }
+
+        let low = if self.low {
+            mv < self.config.recover_mv
+        } else {
+            mv < self.config.low_mv
+        };
+        if low == self.low {
+            return;
+        }
+        self.low = low;
+        LOW.store(low, Ordering::Relaxed);
+
+        let frequency = if low {
+            self.config.low_frequency
+        } else {
+            self.normal_frequency
+        };
+        // The display driver owns the Spim. Re-creating it would mean tearing
+        // down the driver too, while the frequency register can simply be
+        // rewritten between transfers. With `shared-spi` this slows down
+        // everything else on the bus as well.
+        #[cfg(feature = "dma-frames")]
+        crate::pipeline::wait();
+        let spim = unsafe { &*SPIM1::ptr() };
+        spim.frequency.write(|w| w.frequency().variant(frequency));
+        if low {
+            log_warn!("Battery at {} mV, display SPI down to {:?}", mv, frequency);
+        } else {
+            log_info!(
+                "Battery back to {} mV, display SPI up to {:?}",
+                mv,
+                frequency
+            );
+        }
+    }
+}
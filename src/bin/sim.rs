@@ -0,0 +1,258 @@
+// The game on the host, in an embedded-graphics-simulator window, for trying
+// things out without a board: `cargo sim`. It's the library's game, scenes
+// and drawing, stepped once a frame at the game's tick rate, with the
+// keyboard going through the same pad as the buttons:
+//
+// - the arrow keys for the D-pad
+// - Z or space for A
+// - X or Escape for B
+//
+// Nothing that needs the board is here: the panel's partial updates, flash,
+// sound, the radio and the console. Settings and scores last until the
+// window's closed. The window is the default panel, with the default layout's
+// regions on it.
+//
+// `cargo sim -- --demo <frames> <png>` plays that many frames of the demo
+// without a window instead, and saves the last one, for checking the drawing
+// somewhere with no display.
+
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics_simulator::sdl2::Keycode;
+use embedded_graphics_simulator::{
+    OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+};
+use pewpew::assets;
+use pewpew::background::Background;
+use pewpew::buttons::{ButtonState, Pad};
+use pewpew::color::rgb565;
+use pewpew::demo::Demo;
+use pewpew::display::DisplayConfig;
+use pewpew::game::{self, State, World};
+use pewpew::gameloop::TICK_HZ;
+use pewpew::hud::{self, Values};
+use pewpew::input::{Controls, FireButton};
+use pewpew::layout::Content;
+use pewpew::limits::Limits;
+use pewpew::minimap;
+use pewpew::plasma::{self, Variant};
+use pewpew::quality;
+use pewpew::render;
+use pewpew::rle;
+use pewpew::rng::Rng;
+use pewpew::scene::Scenes;
+use pewpew::settings::Settings;
+use pewpew::starfield::{Scroll, Starfield};
+use pewpew::tilemap::Tilemap;
+use std::env;
+use std::process;
+
+const WIDTH: usize = Limits::SCREEN_WIDTH;
+const CONFIG: DisplayConfig = DisplayConfig::DEFAULT;
+// Window pixels to a panel pixel
+const SCALE: u32 = 4;
+// The firmware's, so the demo plays out the same
+const SEED: u32 = 0x5EED;
+
+const KEYS: [(Keycode, ButtonState); 8] = [
+    (Keycode::Up, ButtonState::UP),
+    (Keycode::Down, ButtonState::DOWN),
+    (Keycode::Left, ButtonState::LEFT),
+    (Keycode::Right, ButtonState::RIGHT),
+    (Keycode::Z, ButtonState::A),
+    (Keycode::Space, ButtonState::A),
+    (Keycode::X, ButtonState::B),
+    (Keycode::Escape, ButtonState::B),
+];
+
+// What the frame task keeps between frames, less the board
+struct Sim {
+    settings: Settings,
+    world: World,
+    rng: Rng,
+    demo: Demo,
+    scenes: Scenes,
+    pad: Pad,
+    controls: Controls,
+    scroll: Scroll,
+    far_stars: Starfield<24>,
+    near_stars: Starfield<12>,
+    tilemap: Tilemap,
+    frame: Vec<u8>,
+    #[cfg(feature = "indexed")]
+    cycling: Box<plasma::Cycling>,
+    ticks: u32,
+}
+
+impl Sim {
+    fn new() -> Self {
+        let mut rng = Rng::new(SEED);
+        let far_stars = Starfield::new(&mut rng, 1, rgb565(12, 24, 12));
+        let near_stars = Starfield::new(&mut rng, 2, rgb565(31, 63, 31));
+        let tilemap = Tilemap::new(&mut rng);
+        let world = World::new();
+        Sim {
+            settings: Settings::default(),
+            scenes: Scenes::new(world.state),
+            world,
+            rng,
+            demo: Demo::new(),
+            pad: Pad::new(),
+            controls: Controls {
+                buttons: ButtonState::default(),
+                fire: FireButton::new(),
+            },
+            scroll: Scroll::new(),
+            far_stars,
+            near_stars,
+            tilemap,
+            frame: vec![0; Limits::FRAME_BYTES],
+            #[cfg(feature = "indexed")]
+            cycling: Box::new(plasma::Cycling::new()),
+            ticks: 0,
+        }
+    }
+
+    // A tick of the game and a frame of it, with `held` the buttons down
+    // right now
+    fn frame(&mut self, held: ButtonState) {
+        self.pad.update(held);
+        let (held, events) = self.pad.take();
+        self.controls.buttons = held;
+        self.scenes.handle_input(events.press(), &mut self.settings);
+        self.scenes.update(self.world.state);
+        let input = self.controls.read(self.settings.turbo());
+
+        let world = &mut self.world;
+        if !self.scenes.is_paused() {
+            if !self.demo.is_active() {
+                game::advance_frame(world, input, &mut self.rng);
+            } else if let Some(input) = self.demo.step(world) {
+                game::advance_frame(world, input, &mut self.rng);
+            }
+        }
+        self.ticks += 1;
+        self.scroll.advance(world.ship.x + game::SHIP_W / 2 - WIDTH as i32 / 2);
+        self.tilemap.advance();
+
+        let (settings, bytes) = (&self.settings, &mut self.frame[..]);
+        let variant = Variant::get(settings.plasma);
+        match Background::of(world.state, settings) {
+            #[cfg(feature = "indexed")]
+            Background::Plasma => {
+                self.cycling.update(self.ticks, settings.plasma);
+                self.cycling.render(bytes)
+            }
+            #[cfg(not(feature = "indexed"))]
+            Background::Plasma => {
+                plasma::render(bytes, self.ticks, quality::MAX_LEVEL, variant, u8::MAX)
+            }
+            Background::Backdrop(intensity) => {
+                plasma::render(bytes, self.ticks, quality::MAX_LEVEL, variant, intensity)
+            }
+            Background::Starfield => {
+                render::fill(bytes, 0);
+                self.far_stars.draw(bytes, &self.scroll);
+                self.near_stars.draw(bytes, &self.scroll);
+            }
+            Background::Tiles => self.tilemap.draw(bytes),
+            Background::Solid(color) => render::fill(bytes, color),
+        }
+        match world.state {
+            State::Title => rle::draw(bytes, &assets::TITLE, assets::KEY),
+            _ => render::draw_world(bytes, world, settings.dither_edges),
+        }
+        self.scenes.render(bytes, world, settings);
+    }
+
+    fn show(&self, display: &mut SimulatorDisplay<Rgb565>) {
+        let mut strip = [0; hud::STRIP_BYTES];
+        if self.world.state != State::Title {
+            let values = Values {
+                score: self.world.score,
+                lives: self.world.lives,
+                low_battery: false,
+            };
+            hud::draw_strip(&mut strip, values);
+        }
+        let mut map = [0; minimap::BYTES];
+        minimap::draw(&mut map, &self.world);
+        for region in CONFIG.layout {
+            // Black wherever the strip's left clear
+            let clear = match region.content {
+                Content::Hud => Some(hud::CLEAR),
+                _ => None,
+            };
+            let bytes: &[u8] = match region.content {
+                Content::Playfield => &self.frame,
+                Content::Hud => &strip,
+                Content::Minimap => &map,
+            };
+            let width = region.content.size().0 as usize;
+            let (x, y) = (region.offset.0 as usize, region.offset.1 as usize);
+            let pixels = bytes.chunks_exact(2).enumerate().map(|(i, pixel)| {
+                let color = u16::from_le_bytes([pixel[0], pixel[1]]);
+                let color = if Some(color) == clear { 0 } else { color };
+                let at = Point::new((x + i % width) as i32, (y + i / width) as i32);
+                Pixel(at, Rgb565::from(RawU16::new(color)))
+            });
+            display.draw_iter(pixels).unwrap();
+        }
+    }
+}
+
+fn main() {
+    let mut sim = Sim::new();
+    let (w, h) = CONFIG.panel_size;
+    let mut display = SimulatorDisplay::new(Size::new(w as u32, h as u32));
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => {}
+        [demo, frames, png] if demo == "--demo" => {
+            let frames: u32 = frames.parse().unwrap_or_else(|_| usage());
+            sim.demo.start(&mut sim.world);
+            for _ in 0..frames {
+                sim.frame(ButtonState::default());
+            }
+            sim.show(&mut display);
+            let output = OutputSettingsBuilder::new().scale(1).build();
+            if let Err(err) = display.to_rgb_output_image(&output).save_png(png) {
+                eprintln!("Couldn't save {}: {}", png, err);
+                process::exit(1);
+            }
+            return;
+        }
+        _ => usage(),
+    }
+
+    let output = OutputSettingsBuilder::new().scale(SCALE).max_fps(TICK_HZ).build();
+    let mut window = Window::new("pewpew", &output);
+    let mut down: Vec<Keycode> = Vec::new();
+    loop {
+        sim.show(&mut display);
+        window.update(&display);
+        for event in window.events() {
+            match event {
+                SimulatorEvent::Quit => return,
+                SimulatorEvent::KeyDown { keycode, .. } if !down.contains(&keycode) => {
+                    down.push(keycode)
+                }
+                SimulatorEvent::KeyUp { keycode, .. } => down.retain(|&key| key != keycode),
+                _ => {}
+            }
+        }
+        let mut held = ButtonState::default();
+        for &(key, button) in KEYS.iter() {
+            if down.contains(&key) {
+                held.insert(button);
+            }
+        }
+        sim.frame(held);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: sim [--demo <frames> <png>]");
+    process::exit(2);
+}
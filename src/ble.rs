@@ -0,0 +1,375 @@
+use crate::game::Input;
+use crate::scores::{self, Table};
+use crate::settings::{self, Settings};
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use rubble::att::{
+    AttUuid, Attribute, AttributeAccessPermissions, AttributeProvider, Handle, HandleRange,
+};
+use rubble::config::Config;
+use rubble::l2cap::{BleChannelMap, L2CAPState};
+use rubble::link::ad_structure::{AdStructure, ServiceUuids};
+use rubble::link::queue::{PacketQueue, SimpleQueue};
+use rubble::link::{LinkLayer, Responder, MIN_PDU_BUF};
+use rubble::security::NoSecurity;
+use rubble::time::{Duration, Timer};
+use rubble::uuid::{Uuid128, Uuid16};
+use rubble::Error;
+use crate::ble_radio::{self, BleRadio, BleTimer, PacketBuffer};
+use nrf52840_pac::{RADIO, TIMER0};
+
+// A phone (or anything else that speaks the Nordic UART Service) connects
+// and writes controller packets to the RX characteristic:
+//
+//   byte 0: buttons, bit 0 = left, bit 1 = right, bit 2 = fire
+//   byte 1: stick x as i8, -127..=127 (optional, 0 if left out)
+//
+// Each write replaces the previous state, so the client should resend on
+// every change. The TX characteristic is there so that stock NUS apps
+// recognize the service, nothing is sent on it yet.
+//
+// Service: 6e400001-b5a3-f393-e0a9-e50e24dcca9e
+// RX:      6e400002-b5a3-f393-e0a9-e50e24dcca9e (write, write without response)
+// TX:      6e400003-b5a3-f393-e0a9-e50e24dcca9e (notify)
+//
+// A second service of our own is for a phone app to look after the board:
+//
+//   Scores:   read, the high score table as it's saved to flash (see
+//             scores.rs), 44 bytes, so a long read
+//   Settings: read and write, the 25 bytes settings.rs saves. A write that's
+//             the wrong length is ignored, and anything out of range goes
+//             back to its default as it would coming from flash.
+//   Control:  write without response. 0x01 reboots into the bootloader for
+//             an update over the air.
+//
+// Service:  9a3f0001-5c2b-4f5e-8b7d-70657770b1e0
+// Scores:   9a3f0002-5c2b-4f5e-8b7d-70657770b1e0
+// Settings: 9a3f0003-5c2b-4f5e-8b7d-70657770b1e0
+// Control:  9a3f0004-5c2b-4f5e-8b7d-70657770b1e0
+
+const NUS_SERVICE: Uuid128 = Uuid128::parse_static("6e400001-b5a3-f393-e0a9-e50e24dcca9e");
+const NUS_RX: Uuid128 = Uuid128::parse_static("6e400002-b5a3-f393-e0a9-e50e24dcca9e");
+const NUS_TX: Uuid128 = Uuid128::parse_static("6e400003-b5a3-f393-e0a9-e50e24dcca9e");
+
+const PEW_SCORES: Uuid128 = Uuid128::parse_static("9a3f0002-5c2b-4f5e-8b7d-70657770b1e0");
+const PEW_SETTINGS: Uuid128 = Uuid128::parse_static("9a3f0003-5c2b-4f5e-8b7d-70657770b1e0");
+const PEW_CONTROL: Uuid128 = Uuid128::parse_static("9a3f0004-5c2b-4f5e-8b7d-70657770b1e0");
+
+const NAME: &str = "pewpew";
+const ADVERTISING_INTERVAL_MS: u16 = 200;
+
+const RX_HANDLE: u16 = 0x0003;
+const CCCD_HANDLE: u16 = 0x0006;
+const SCORES_HANDLE: u16 = 0x0009;
+const SETTINGS_HANDLE: u16 = 0x000B;
+const CONTROL_HANDLE: u16 = 0x000D;
+
+// What a write to Control does, see bootloader.rs
+const REBOOT_TO_BOOTLOADER: u8 = 0x01;
+
+// The UUIDs as they go over the air, little-endian
+const SERVICE_VALUE: [u8; 16] = [
+    0x9E, 0xCA, 0xDC, 0x24, 0x0E, 0xE5, 0xA9, 0xE0, 0x93, 0xF3, 0xA3, 0xB5, 0x01, 0x00, 0x40, 0x6E,
+];
+// Properties (write 0x08, write without response 0x04, notify 0x10), value
+// handle, then the characteristic UUID
+const RX_DECLARATION: [u8; 19] = [
+    0x0C, 0x03, 0x00, 0x9E, 0xCA, 0xDC, 0x24, 0x0E, 0xE5, 0xA9, 0xE0, 0x93, 0xF3, 0xA3, 0xB5, 0x02,
+    0x00, 0x40, 0x6E,
+];
+const TX_DECLARATION: [u8; 19] = [
+    0x10, 0x05, 0x00, 0x9E, 0xCA, 0xDC, 0x24, 0x0E, 0xE5, 0xA9, 0xE0, 0x93, 0xF3, 0xA3, 0xB5, 0x03,
+    0x00, 0x40, 0x6E,
+];
+
+// 9a3f0000-5c2b-4f5e-8b7d-70657770b1e0, little-endian, with the 16 bits
+// that tell them apart at bytes 12 and 13
+const fn pew_uuid(short: u8) -> [u8; 16] {
+    [
+        0xE0, 0xB1, 0x70, 0x77, 0x65, 0x70, 0x7D, 0x8B, 0x5E, 0x4F, 0x2B, 0x5C, short, 0x00, 0x3F,
+        0x9A,
+    ]
+}
+
+const fn declaration(properties: u8, handle: u16, uuid: [u8; 16]) -> [u8; 19] {
+    let mut declaration = [0; 19];
+    declaration[0] = properties;
+    declaration[1] = handle as u8;
+    declaration[2] = (handle >> 8) as u8;
+    let mut i = 0;
+    while i < 16 {
+        declaration[3 + i] = uuid[i];
+        i += 1;
+    }
+    declaration
+}
+
+const PEW_SERVICE_VALUE: [u8; 16] = pew_uuid(0x01);
+// Read 0x02, write 0x08, write without response 0x04
+const SCORES_DECLARATION: [u8; 19] = declaration(0x02, SCORES_HANDLE, pew_uuid(0x02));
+const SETTINGS_DECLARATION: [u8; 19] = declaration(0x0A, SETTINGS_HANDLE, pew_uuid(0x03));
+const CONTROL_DECLARATION: [u8; 19] = declaration(0x04, CONTROL_HANDLE, pew_uuid(0x04));
+
+// Last packet written to RX: buttons in the low byte, x in the high byte
+static REMOTE: AtomicU16 = AtomicU16::new(0);
+
+pub fn remote_input() -> Input {
+    let [buttons, x] = REMOTE.load(Ordering::Relaxed).to_le_bytes();
+    Input {
+        left: buttons & 1 != 0,
+        right: buttons & 2 != 0,
+        fire: buttons & 4 != 0,
+        x: x as i8,
+    }
+}
+
+// What Scores and Settings read back as, kept up to date by whoever owns the
+// real thing, and a Settings write waiting to be applied
+static SCORES: Mutex<RefCell<[u8; scores::LEN]>> = Mutex::new(RefCell::new([0; scores::LEN]));
+static SETTINGS: Mutex<RefCell<[u8; settings::LEN]>> = Mutex::new(RefCell::new([0; settings::LEN]));
+static WRITTEN: Mutex<RefCell<Option<[u8; settings::LEN]>>> = Mutex::new(RefCell::new(None));
+static REBOOT: AtomicBool = AtomicBool::new(false);
+
+pub fn publish_scores(table: &Table) {
+    let mut save = [0; scores::LEN];
+    table.save(&mut save);
+    interrupt::free(|cs| *SCORES.borrow(cs).borrow_mut() = save);
+}
+
+// Cheap enough to call every frame
+pub fn publish_settings(settings: &Settings) {
+    let mut save = [0; settings::LEN];
+    settings.save(&mut save);
+    interrupt::free(|cs| *SETTINGS.borrow(cs).borrow_mut() = save);
+}
+
+// Settings written over BLE since the last call, unchecked
+pub fn take_settings() -> Option<Settings> {
+    interrupt::free(|cs| WRITTEN.borrow(cs).borrow_mut().take())
+        .and_then(|buf| Settings::load(&buf))
+}
+
+// Resets into the bootloader if that's been asked for. Left until the
+// responder's done, rather than done in the middle of handling the write.
+pub fn reboot_if_asked() {
+    if REBOOT.load(Ordering::Relaxed) {
+        crate::bootloader::reboot();
+    }
+}
+
+pub struct NusAttrs {
+    attributes: [Attribute<&'static [u8]>; 13],
+}
+
+impl NusAttrs {
+    fn new() -> Self {
+        NusAttrs {
+            attributes: [
+                // Primary Service
+                Attribute::new(
+                    Uuid16(0x2800).into(),
+                    Handle::from_raw(0x0001),
+                    &SERVICE_VALUE,
+                ),
+                // Characteristic
+                Attribute::new(
+                    Uuid16(0x2803).into(),
+                    Handle::from_raw(0x0002),
+                    &RX_DECLARATION,
+                ),
+                Attribute::new(NUS_RX.into(), Handle::from_raw(RX_HANDLE), &[]),
+                Attribute::new(
+                    Uuid16(0x2803).into(),
+                    Handle::from_raw(0x0004),
+                    &TX_DECLARATION,
+                ),
+                Attribute::new(NUS_TX.into(), Handle::from_raw(0x0005), &[]),
+                // Client Characteristic Configuration
+                Attribute::new(
+                    Uuid16(0x2902).into(),
+                    Handle::from_raw(CCCD_HANDLE),
+                    &[0, 0],
+                ),
+                // Primary Service, ours
+                Attribute::new(
+                    Uuid16(0x2800).into(),
+                    Handle::from_raw(0x0007),
+                    &PEW_SERVICE_VALUE,
+                ),
+                Attribute::new(
+                    Uuid16(0x2803).into(),
+                    Handle::from_raw(0x0008),
+                    &SCORES_DECLARATION,
+                ),
+                // Read from SCORES, see `read_attr_dynamic`
+                Attribute::new(PEW_SCORES.into(), Handle::from_raw(SCORES_HANDLE), &[]),
+                Attribute::new(
+                    Uuid16(0x2803).into(),
+                    Handle::from_raw(0x000A),
+                    &SETTINGS_DECLARATION,
+                ),
+                Attribute::new(PEW_SETTINGS.into(), Handle::from_raw(SETTINGS_HANDLE), &[]),
+                Attribute::new(
+                    Uuid16(0x2803).into(),
+                    Handle::from_raw(0x000C),
+                    &CONTROL_DECLARATION,
+                ),
+                Attribute::new(PEW_CONTROL.into(), Handle::from_raw(CONTROL_HANDLE), &[]),
+            ],
+        }
+    }
+}
+
+impl AttributeProvider for NusAttrs {
+    fn for_attrs_in_range(
+        &mut self,
+        range: HandleRange,
+        mut f: impl FnMut(&Self, &Attribute<dyn AsRef<[u8]>>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        // Handles start at 1
+        let start = usize::from(range.start().as_u16() - 1);
+        let end = usize::from(range.end().as_u16() - 1).min(self.attributes.len() - 1);
+
+        if start < self.attributes.len() {
+            for attr in &self.attributes[start..=end] {
+                f(self, attr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_grouping_attr(&self, uuid: AttUuid) -> bool {
+        uuid == Uuid16(0x2800)
+    }
+
+    fn group_end(&self, handle: Handle) -> Option<&Attribute<dyn AsRef<[u8]>>> {
+        match handle.as_u16() {
+            0x0001 | 0x0004 => Some(&self.attributes[5]),
+            0x0002 => Some(&self.attributes[2]),
+            0x0007 => Some(&self.attributes[12]),
+            _ => None,
+        }
+    }
+
+    fn attr_access_permissions(&self, handle: Handle) -> AttributeAccessPermissions {
+        match handle.as_u16() {
+            RX_HANDLE => AttributeAccessPermissions::Writeable,
+            CCCD_HANDLE | SETTINGS_HANDLE => AttributeAccessPermissions::ReadableAndWriteable,
+            CONTROL_HANDLE => AttributeAccessPermissions::Writeable,
+            _ => AttributeAccessPermissions::Readable,
+        }
+    }
+
+    fn write_attr(&mut self, handle: Handle, data: &[u8]) -> Result<(), Error> {
+        match (handle.as_u16(), data) {
+            (RX_HANDLE, &[buttons]) => REMOTE.store(buttons as u16, Ordering::Relaxed),
+            (RX_HANDLE, &[buttons, x, ..]) => {
+                REMOTE.store(u16::from_le_bytes([buttons, x]), Ordering::Relaxed)
+            }
+            (SETTINGS_HANDLE, data) if data.len() == settings::LEN => {
+                let mut buf = [0; settings::LEN];
+                buf.copy_from_slice(data);
+                interrupt::free(|cs| *WRITTEN.borrow(cs).borrow_mut() = Some(buf));
+            }
+            (CONTROL_HANDLE, &[REBOOT_TO_BOOTLOADER]) => REBOOT.store(true, Ordering::Relaxed),
+            // Notifications are never sent, so there's nothing to remember
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn read_attr_dynamic(&mut self, handle: Handle, buffer: &mut [u8]) -> Option<usize> {
+        interrupt::free(|cs| match handle.as_u16() {
+            SCORES_HANDLE => {
+                buffer[..scores::LEN].copy_from_slice(&*SCORES.borrow(cs).borrow());
+                Some(scores::LEN)
+            }
+            SETTINGS_HANDLE => {
+                buffer[..settings::LEN].copy_from_slice(&*SETTINGS.borrow(cs).borrow());
+                Some(settings::LEN)
+            }
+            _ => None,
+        })
+    }
+}
+
+pub enum BleConfig {}
+
+impl Config for BleConfig {
+    type Timer = BleTimer;
+    type Transmitter = BleRadio;
+    type ChannelMapper = BleChannelMap<NusAttrs, NoSecurity>;
+    type PacketQueue = &'static mut SimpleQueue;
+}
+
+// The real-time half of the stack, driven from the RADIO and TIMER0
+// interrupts. Both entry points return true when they've queued up packets
+// for the `Responder` to deal with.
+pub struct Ble {
+    radio: BleRadio,
+    ll: LinkLayer<BleConfig>,
+}
+
+impl Ble {
+    pub fn on_radio(&mut self) -> bool {
+        let now = self.ll.timer().now();
+        match self.radio.recv_interrupt(now, &mut self.ll) {
+            Some(cmd) => {
+                self.radio.configure_receiver(cmd.radio);
+                self.ll.timer().configure_interrupt(cmd.next_update);
+                cmd.queued_work
+            }
+            None => false,
+        }
+    }
+
+    pub fn on_timer(&mut self) -> bool {
+        let timer = self.ll.timer();
+        if !timer.is_interrupt_pending() {
+            return false;
+        }
+        timer.clear_interrupt();
+
+        let cmd = self.ll.update_timer(&mut self.radio);
+        self.radio.configure_receiver(cmd.radio);
+        self.ll.timer().configure_interrupt(cmd.next_update);
+        cmd.queued_work
+    }
+}
+
+// Starts advertising. Needs HFXO running. Must only be called once, the
+// packet buffers and queues are static singletons.
+pub fn init(radio: RADIO, timer: TIMER0) -> (Ble, Responder<BleConfig>) {
+    let tx_buf = cortex_m::singleton!(: PacketBuffer = [0; MIN_PDU_BUF]).unwrap();
+    let rx_buf = cortex_m::singleton!(: PacketBuffer = [0; MIN_PDU_BUF]).unwrap();
+    let tx_queue = cortex_m::singleton!(: SimpleQueue = SimpleQueue::new()).unwrap();
+    let rx_queue = cortex_m::singleton!(: SimpleQueue = SimpleQueue::new()).unwrap();
+    let (tx, tx_consumer) = tx_queue.split();
+    let (rx_producer, rx) = rx_queue.split();
+
+    let mut radio = BleRadio::new(radio, tx_buf, rx_buf);
+    let mut ll = LinkLayer::<BleConfig>::new(ble_radio::device_address(), BleTimer::new(timer));
+    let responder = Responder::new(
+        tx,
+        rx,
+        L2CAPState::new(BleChannelMap::with_attributes(NusAttrs::new())),
+    );
+
+    let services = [NUS_SERVICE];
+    let next_update = ll
+        .start_advertise(
+            Duration::from_millis(ADVERTISING_INTERVAL_MS),
+            &[
+                AdStructure::CompleteLocalName(NAME),
+                AdStructure::ServiceUuids128(ServiceUuids::from_uuids(true, &services)),
+            ],
+            &mut radio,
+            tx_consumer,
+            rx_producer,
+        )
+        .unwrap();
+    ll.timer().configure_interrupt(next_update);
+
+    (Ble { radio, ll }, responder)
+}
@@ -0,0 +1,301 @@
+use crate::timer::{Compare, Timer as _};
+use core::cmp;
+use core::sync::atomic::{compiler_fence, Ordering};
+use nrf52840_pac::ficr::deviceaddrtype::DEVICEADDRTYPE_A;
+use nrf52840_pac::radio::state::STATE_R;
+use nrf52840_pac::{FICR, RADIO, TIMER0};
+use rubble::config::Config;
+use rubble::link::{
+    advertising, data, AddressKind, Cmd, DeviceAddress, LinkLayer, NextUpdate, RadioCmd,
+    Transmitter, CRC_POLY, MIN_PDU_BUF,
+};
+use rubble::phy::{AdvertisingChannel, DataChannel};
+use rubble::time::{Duration, Instant, Timer};
+
+// rubble's radio and timer for the nRF52840, ported from rubble-nrf5x 0.0.4
+// (0BSD) onto the firmware's own PAC. rubble-nrf5x is built on
+// nrf52840-pac 0.9, and two copies of one chip's PAC don't link, since both
+// define DEVICE_PERIPHERALS.
+//
+// The radio frames a packet as S0, length, S1 and payload, and the stack only
+// fills in the payload, so the two header bytes go in ahead of it: S0 is the
+// header's low byte, and the length byte carries the RFU bits after it as
+// zeroes. CRC24 and whitening are the radio's.
+
+pub type PacketBuffer = [u8; MIN_PDU_BUF];
+
+pub struct BleRadio {
+    // On an advertising channel, rather than a data channel
+    advertising: bool,
+    radio: RADIO,
+    tx_buf: &'static mut PacketBuffer,
+    // Taken out while the link layer has the radio lent to it
+    rx_buf: Option<&'static mut PacketBuffer>,
+}
+
+impl BleRadio {
+    // Takes the radio over in BLE mode. HFXO has to be running.
+    pub fn new(
+        radio: RADIO,
+        tx_buf: &'static mut PacketBuffer,
+        rx_buf: &'static mut PacketBuffer,
+    ) -> Self {
+        assert!(radio.state.read().state().is_disabled());
+        radio.mode.write(|w| w.mode().ble_1mbit());
+        radio.txpower.write(|w| w.txpower().pos4d_bm());
+
+        let max_payload = rx_buf.len() - 2;
+        assert!(max_payload <= usize::from(u8::MAX));
+        unsafe {
+            // A 3 byte base address and 1 byte prefix, and whitening over
+            // the PDU and CRC
+            radio
+                .pcnf1
+                .write(|w| w.maxlen().bits(max_payload as u8).balen().bits(3).whiteen().set_bit());
+            // Only the PDU goes into the CRC
+            radio.crccnf.write(|w| w.skipaddr().skip().len().three());
+            radio.crcpoly.write(|w| w.crcpoly().bits(CRC_POLY & 0x00FF_FFFF));
+            // Logical address 0 is the advertising access address. BASE0
+            // sends its top 24 bits, so it goes in shifted up.
+            radio.base0.write(|w| w.bits(advertising::ACCESS_ADDRESS << 8));
+            radio.prefix0.write(|w| w.ap0().bits((advertising::ACCESS_ADDRESS >> 24) as u8));
+        }
+        // Start as soon as it's ramped up, and turn off once it's done
+        radio.shorts.write(|w| w.ready_start().enabled().end_disable().enabled());
+
+        BleRadio {
+            advertising: false,
+            radio,
+            tx_buf,
+            rx_buf: Some(rx_buf),
+        }
+    }
+
+    fn state(&self) -> STATE_R {
+        self.radio.state.read().state()
+    }
+
+    // Listens, or stops listening, as the link layer says
+    pub fn configure_receiver(&mut self, cmd: RadioCmd) {
+        // Lets a transmission finish, unless the connection event was lost
+        // and nothing should be going out anyway
+        if let RadioCmd::ListenData { timeout: false, .. } = cmd {
+            while self.state().is_tx() || self.state().is_tx_ru() {}
+        }
+        compiler_fence(Ordering::Acquire);
+
+        self.radio.intenclr.write(|w| w.disabled().clear());
+        self.radio.events_disabled.reset();
+        self.radio.tasks_disable.write(|w| unsafe { w.bits(1) });
+        while self.radio.events_disabled.read().bits() == 0 {}
+        self.radio.events_disabled.reset();
+
+        match cmd {
+            RadioCmd::Off => {}
+            RadioCmd::ListenAdvertising { channel } => {
+                self.prepare_advertising(channel);
+                self.listen();
+                self.radio.rxaddresses.write(|w| w.addr0().enabled());
+                self.radio.shorts.write(|w| w.ready_start().enabled().end_disable().enabled());
+                compiler_fence(Ordering::Release);
+                self.radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+            }
+            RadioCmd::ListenData {
+                channel,
+                access_address,
+                crc_init,
+                ..
+            } => {
+                self.prepare_data(channel, access_address, crc_init);
+                // The radio keeps to T_IFS itself
+                self.radio.tifs.write(|w| unsafe { w.bits(Duration::T_IFS.as_micros()) });
+                self.listen();
+                self.radio.rxaddresses.write(|w| w.addr1().enabled());
+                compiler_fence(Ordering::Release);
+                self.radio.tasks_rxen.write(|w| unsafe { w.bits(1) });
+                // Straight round into TXIDLE after a packet, for the reply
+                self.radio.shorts.write(|w| {
+                    w.end_disable().enabled().disabled_txen().enabled().ready_start().enabled()
+                });
+            }
+        }
+    }
+
+    // Receives into the RX buffer, with an interrupt once a packet's in
+    fn listen(&mut self) {
+        let rx_buf = self.rx_buf.as_mut().unwrap().as_mut_ptr() as u32;
+        self.radio.packetptr.write(|w| unsafe { w.bits(rx_buf) });
+        self.radio.intenset.write(|w| w.disabled().set());
+    }
+
+    // From the RADIO interrupt. Hands whatever came in to the link layer and
+    // returns what it wants done next, or None if the interrupt wasn't a
+    // packet.
+    pub fn recv_interrupt<C: Config<Transmitter = Self>>(
+        &mut self,
+        timestamp: Instant,
+        ll: &mut LinkLayer<C>,
+    ) -> Option<Cmd> {
+        if self.radio.events_disabled.read().bits() == 0 {
+            return None;
+        }
+        compiler_fence(Ordering::Acquire);
+        self.radio.events_disabled.reset();
+
+        let crc_ok = self.radio.crcstatus.read().crcstatus().is_crcok();
+        let rx_buf = self.rx_buf.take().unwrap();
+        let cmd = if self.advertising {
+            assert!(self.state().is_disabled());
+            let header = advertising::Header::parse(&rx_buf[..]);
+            let end = cmp::min(2 + usize::from(header.payload_length()), rx_buf.len());
+            ll.process_adv_packet(timestamp, self, header, &rx_buf[2..end], crc_ok)
+        } else {
+            // Has to be off well before TXREADY, about 150 us away
+            self.radio.shorts.modify(|_, w| w.ready_start().disabled());
+            assert!(!self.state().is_tx());
+            let header = data::Header::parse(&rx_buf[..]);
+            let end = cmp::min(2 + usize::from(header.payload_length()), rx_buf.len());
+            ll.process_data_packet(timestamp, self, header, &rx_buf[2..end], crc_ok)
+        };
+        self.rx_buf = Some(rx_buf);
+        Some(cmd)
+    }
+
+    // Stops whatever the radio's doing and sets it up for `channel`
+    fn prepare_advertising(&mut self, channel: AdvertisingChannel) {
+        self.advertising = true;
+        self.radio.events_disabled.reset();
+        if !self.state().is_disabled() {
+            self.radio.tasks_disable.write(|w| unsafe { w.bits(1) });
+            while self.radio.events_disabled.read().bits() == 0 {}
+        }
+        assert!(self.state().is_disabled());
+        unsafe {
+            self.radio.pcnf0.write(|w| w.s0len().bit(true).lflen().bits(8).s1len().bits(0));
+            self.radio.datawhiteiv.write(|w| w.datawhiteiv().bits(channel.whitening_iv()));
+            self.radio.crcinit.write(|w| w.crcinit().bits(advertising::CRC_PRESET));
+            self.radio.frequency.write(|w| w.frequency().bits((channel.freq() - 2400) as u8));
+        }
+    }
+
+    fn prepare_data(&mut self, channel: DataChannel, access_address: u32, crc_init: u32) {
+        self.advertising = false;
+        unsafe {
+            self.radio.pcnf0.write(|w| w.s0len().bit(true).lflen().bits(8).s1len().bits(0));
+            self.radio.datawhiteiv.write(|w| w.datawhiteiv().bits(channel.whitening_iv()));
+            self.radio.crcinit.write(|w| w.crcinit().bits(crc_init & 0x00FF_FFFF));
+            self.radio.frequency.write(|w| w.frequency().bits((channel.freq() - 2400) as u8));
+            // Logical address 1 is the connection's
+            self.radio.base1.write(|w| w.bits(access_address << 8));
+            self.radio.prefix0.write(|w| w.ap1().bits((access_address >> 24) as u8));
+        }
+    }
+
+    // Sends the TX buffer and waits for it to go
+    fn transmit(&mut self) {
+        assert!(self.state().is_disabled());
+        // Has to be set again before every start
+        let tx_buf = self.tx_buf.as_ptr() as u32;
+        self.radio.packetptr.write(|w| unsafe { w.bits(tx_buf) });
+        self.radio.events_disabled.reset();
+        compiler_fence(Ordering::Release);
+        self.radio.tasks_txen.write(|w| unsafe { w.bits(1) });
+        while self.radio.events_disabled.read().bits() == 0 {}
+        compiler_fence(Ordering::Acquire);
+    }
+}
+
+impl Transmitter for BleRadio {
+    fn tx_payload_buf(&mut self) -> &mut [u8] {
+        while self.state().is_tx() {}
+        compiler_fence(Ordering::Acquire);
+        &mut self.tx_buf[2..]
+    }
+
+    fn transmit_advertising(&mut self, header: advertising::Header, channel: AdvertisingChannel) {
+        self.tx_buf[0] = header.to_u16() as u8;
+        self.tx_buf[1] = header.payload_length();
+        self.prepare_advertising(channel);
+        self.radio.txaddress.write(|w| unsafe { w.txaddress().bits(0) });
+        self.transmit();
+    }
+
+    // Only sets the reply up: the shortcuts from `configure_receiver` send
+    // it T_IFS after the packet it answers
+    fn transmit_data(&mut self, _: u32, _: u32, header: data::Header, _: DataChannel) {
+        self.tx_buf[0] = header.to_u16() as u8;
+        self.tx_buf[1] = header.payload_length();
+        self.radio.txaddress.write(|w| unsafe { w.txaddress().bits(1) });
+        let tx_buf = self.tx_buf.as_ptr() as u32;
+        self.radio.packetptr.write(|w| unsafe { w.bits(tx_buf) });
+        compiler_fence(Ordering::Release);
+        self.radio.shorts.write(|w| w.ready_start().enabled().end_disable().disabled());
+    }
+}
+
+// The link layer's clock and wakeups, on TIMER0 at a microsecond a tick.
+// CC[1] is the wakeup.
+pub struct BleTimer {
+    timer: TIMER0,
+    next: Instant,
+    armed: bool,
+}
+
+impl BleTimer {
+    pub fn new(mut timer: TIMER0) -> Self {
+        timer.init();
+        BleTimer {
+            timer,
+            next: Instant::from_raw_micros(0),
+            armed: false,
+        }
+    }
+
+    pub fn configure_interrupt(&mut self, next: NextUpdate) {
+        match next {
+            // Setting it again would clear an event that's already come
+            NextUpdate::Keep if self.armed => {}
+            NextUpdate::Keep => self.arm(self.next),
+            NextUpdate::Disable => {
+                self.timer.stop(Compare::One);
+                self.armed = false;
+            }
+            NextUpdate::At(at) => self.arm(at),
+        }
+    }
+
+    fn arm(&mut self, at: Instant) {
+        self.next = at;
+        self.timer.fire_on(Compare::One, at.raw_micros());
+        self.armed = true;
+    }
+
+    // The TIMER0 interrupt has to check this, and clear it, before anything
+    pub fn is_interrupt_pending(&self) -> bool {
+        self.timer.is_compare_event(Compare::One)
+    }
+
+    pub fn clear_interrupt(&mut self) {
+        self.timer.stop(Compare::One);
+    }
+}
+
+impl Timer for BleTimer {
+    fn now(&self) -> Instant {
+        Instant::from_raw_micros(crate::timer::Timer::now(&self.timer))
+    }
+}
+
+// The address the factory gave this chip
+pub fn device_address() -> DeviceAddress {
+    // Read only
+    let ficr = unsafe { &*FICR::ptr() };
+    let mut address = [0; 6];
+    address[..4].copy_from_slice(&ficr.deviceaddr[0].read().bits().to_le_bytes());
+    address[4..].copy_from_slice(&(ficr.deviceaddr[1].read().bits() as u16).to_le_bytes());
+    let kind = match ficr.deviceaddrtype.read().deviceaddrtype().variant() {
+        DEVICEADDRTYPE_A::PUBLIC => AddressKind::Public,
+        DEVICEADDRTYPE_A::RANDOM => AddressKind::Random,
+    };
+    DeviceAddress::new(address, kind)
+}
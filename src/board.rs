@@ -0,0 +1,114 @@
+#[cfg(any(feature = "stick", feature = "light", feature = "tilt"))]
+use nrf52840_hal::gpio::Floating;
+#[cfg(any(
+    feature = "stick",
+    feature = "light",
+    feature = "tilt",
+    feature = "power-off",
+    feature = "encoder",
+    feature = "buttons"
+))]
+use nrf52840_hal::gpio::Input;
+#[cfg(any(feature = "power-off", feature = "encoder", feature = "buttons"))]
+use nrf52840_hal::gpio::PullUp;
+use nrf52840_hal::gpio::{p0, p1, Level, Output, Pin, PushPull};
+use nrf52840_pac::{P0, P1};
+
+#[cfg(feature = "light")]
+pub type LightPin = p0::P0_30<Input<Floating>>;
+
+// Every pin the firmware uses and what it's for, in one place, so wiring a
+// board differently means changing `Board::new` and nothing else. Pins for
+// features that aren't built in are left alone.
+//
+// Outputs start in the state they're safe in. The analog inputs keep their
+// own types, since that's how the SAADC knows which channel to read.
+pub struct Board {
+    pub spi_sck: Pin<Output<PushPull>>,
+    pub spi_mosi: Pin<Output<PushPull>>,
+    pub display_dc: Pin<Output<PushPull>>,
+    pub display_rst: Pin<Output<PushPull>>,
+    #[cfg(feature = "shared-spi")]
+    pub display_cs: Pin<Output<PushPull>>,
+    #[cfg(feature = "sound")]
+    pub buzzer: Pin<Output<PushPull>>,
+    // X, then Y
+    #[cfg(feature = "stick")]
+    pub stick: (p0::P0_04<Input<Floating>>, p0::P0_05<Input<Floating>>),
+    #[cfg(feature = "light")]
+    pub light: LightPin,
+    #[cfg(feature = "tilt")]
+    pub tilt_sda: Pin<Input<Floating>>,
+    #[cfg(feature = "tilt")]
+    pub tilt_scl: Pin<Input<Floating>>,
+    // The accelerometer's INT1, high when it has a reading
+    #[cfg(feature = "tilt")]
+    pub tilt_int: Pin<Input<Floating>>,
+    #[cfg(feature = "backlight")]
+    pub backlight: Pin<Output<PushPull>>,
+    // Through a transistor, with a diode across the motor
+    #[cfg(feature = "haptics")]
+    pub motor: Pin<Output<PushPull>>,
+    #[cfg(feature = "backlight-switch")]
+    pub load_switch: Pin<Output<PushPull>>,
+    #[cfg(feature = "power-off")]
+    pub wake: Pin<Input<PullUp>>,
+    // A, then B
+    #[cfg(feature = "encoder")]
+    pub encoder: [Pin<Input<PullUp>>; 2],
+    // Up, down, left, right, A and B
+    #[cfg(feature = "buttons")]
+    pub buttons: [Pin<Input<PullUp>>; 6],
+}
+
+impl Board {
+    pub fn new(p0: P0, p1: P1) -> Self {
+        let p0 = p0::Parts::new(p0);
+        let p1 = p1::Parts::new(p1);
+        Board {
+            spi_sck: p0.p0_14.into_push_pull_output(Level::Low).degrade(),
+            spi_mosi: p0.p0_13.into_push_pull_output(Level::Low).degrade(),
+            display_dc: p1.p1_08.into_push_pull_output(Level::Low).degrade(),
+            display_rst: p0.p0_07.into_push_pull_output(Level::Low).degrade(),
+            #[cfg(feature = "shared-spi")]
+            display_cs: p0.p0_06.into_push_pull_output(Level::High).degrade(),
+            #[cfg(feature = "sound")]
+            buzzer: p0.p0_15.into_push_pull_output(Level::Low).degrade(),
+            #[cfg(feature = "stick")]
+            stick: (
+                p0.p0_04.into_floating_input(),
+                p0.p0_05.into_floating_input(),
+            ),
+            #[cfg(feature = "light")]
+            light: p0.p0_30.into_floating_input(),
+            #[cfg(feature = "tilt")]
+            tilt_sda: p0.p0_24.into_floating_input().degrade(),
+            #[cfg(feature = "tilt")]
+            tilt_scl: p0.p0_25.into_floating_input().degrade(),
+            #[cfg(feature = "tilt")]
+            tilt_int: p0.p0_27.into_floating_input().degrade(),
+            #[cfg(feature = "backlight")]
+            backlight: p0.p0_26.into_push_pull_output(Level::Low).degrade(),
+            #[cfg(feature = "haptics")]
+            motor: p0.p0_16.into_push_pull_output(Level::Low).degrade(),
+            #[cfg(feature = "backlight-switch")]
+            load_switch: p0.p0_12.into_push_pull_output(Level::High).degrade(),
+            #[cfg(feature = "power-off")]
+            wake: p0.p0_11.into_pullup_input().degrade(),
+            #[cfg(feature = "encoder")]
+            encoder: [
+                p0.p0_28.into_pullup_input().degrade(),
+                p0.p0_29.into_pullup_input().degrade(),
+            ],
+            #[cfg(feature = "buttons")]
+            buttons: [
+                p1.p1_01.into_pullup_input().degrade(),
+                p1.p1_02.into_pullup_input().degrade(),
+                p1.p1_03.into_pullup_input().degrade(),
+                p1.p1_04.into_pullup_input().degrade(),
+                p1.p1_05.into_pullup_input().degrade(),
+                p1.p1_06.into_pullup_input().degrade(),
+            ],
+        }
+    }
+}
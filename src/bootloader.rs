@@ -0,0 +1,23 @@
+use cortex_m::peripheral::SCB;
+use nrf52840_pac::POWER;
+
+// Getting to the bootloader for an update without a debugger, from `dfu` on
+// the console or USB serial, or from BLE's Control characteristic. The value
+// left in GPREGRET survives the reset, and Nordic's bootloaders and
+// Adafruit's all take it to mean staying in DFU rather than starting the
+// firmware again. Built without `dfu` there's most likely no bootloader, and
+// this is just a reset.
+
+const BOOTLOADER_DFU_START: u8 = 0xB1;
+
+pub fn reboot() -> ! {
+    match cfg!(feature = "dfu") {
+        true => log_info!("Rebooting into the bootloader"),
+        false => log_warn!("Rebooting into the bootloader, built without dfu"),
+    }
+    let power = unsafe { &*POWER::ptr() };
+    power
+        .gpregret
+        .write(|w| unsafe { w.gpregret().bits(BOOTLOADER_DFU_START) });
+    SCB::sys_reset()
+}
@@ -0,0 +1,300 @@
+use crate::game::{Rect, ENEMY_W, WIDTH};
+
+// A big enemy that turns up every so often and takes a lot of hits. It
+// changes how it moves as it wears down: drifting side to side at first,
+// then following the ship, then swinging about fast, and from the second
+// phase on it drops ordinary enemies on the way. Like the rest of the game
+// this is plain data, driven by the tick and where the ship is.
+
+pub const W: i32 = 12;
+pub const H: i32 = 6;
+pub const MAX_HP: u8 = 24;
+// For finishing it off, on top of nothing for the hits before
+pub const POINTS: u32 = 500;
+// Ticks into a game between one boss and the next. Counting only starts
+// again once the last one has gone.
+pub const INTERVAL: u32 = 3600;
+
+// Where it settles once it's come on screen
+const TOP: i32 = 4;
+// It goes on to the next phase at or below these
+const CHASING_HP: u8 = MAX_HP * 2 / 3;
+const FRENZY_HP: u8 = MAX_HP / 3;
+// Ticks between enemies dropped in each phase
+const CHASING_DROP: u32 = 90;
+const FRENZY_DROP: u32 = 45;
+// How far it bobs up and down in a frenzy
+const FRENZY_BOB: i32 = 6;
+const HIT_FLASH_FRAMES: u32 = 3;
+
+// The health bar across the top of the screen
+pub const BAR: Rect = Rect {
+    x: 2,
+    y: 1,
+    w: WIDTH - 4,
+    h: 2,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Phase {
+    // Coming down from above the screen, not moving otherwise
+    Entering,
+    Sweeping,
+    Chasing,
+    Frenzy,
+}
+
+impl Phase {
+    // The phase for a boss that's finished entering
+    pub fn for_hp(hp: u8) -> Phase {
+        if hp <= FRENZY_HP {
+            Phase::Frenzy
+        } else if hp <= CHASING_HP {
+            Phase::Chasing
+        } else {
+            Phase::Sweeping
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Boss {
+    pub x: i32,
+    pub y: i32,
+    pub hp: u8,
+    pub phase: Phase,
+    // Which way it's sweeping, -1 or 1
+    pub dx: i32,
+    pub hit_flash_until: u32,
+}
+
+impl Boss {
+    pub const fn new() -> Self {
+        Boss {
+            x: (WIDTH - W) / 2,
+            y: -H,
+            hp: MAX_HP,
+            phase: Phase::Entering,
+            dx: 1,
+            hit_flash_until: 0,
+        }
+    }
+
+    // Moves it on a tick, `target_x` being the column the ship is centered
+    // on. Returns where to drop an enemy, if one's due.
+    pub fn update(&mut self, now: u32, target_x: i32) -> Option<(i32, i32)> {
+        let center = self.x + W / 2;
+        match self.phase {
+            Phase::Entering => {
+                self.y += (now % 2) as i32;
+                if self.y >= TOP {
+                    self.y = TOP;
+                    self.phase = Phase::for_hp(self.hp);
+                }
+                return None;
+            }
+            Phase::Sweeping => self.sweep((now % 2) as i32),
+            Phase::Chasing => {
+                if now.is_multiple_of(2) {
+                    self.x += (target_x - center).signum();
+                }
+            }
+            Phase::Frenzy => {
+                self.sweep(2);
+                // Down and back up again every 4 * FRENZY_BOB ticks
+                let bob = (now / 2 % (2 * FRENZY_BOB as u32)) as i32;
+                self.y = TOP + FRENZY_BOB - (bob - FRENZY_BOB).abs();
+            }
+        }
+        self.x = self.x.clamp(0, WIDTH - W);
+
+        let every = match self.phase {
+            Phase::Chasing => CHASING_DROP,
+            Phase::Frenzy => FRENZY_DROP,
+            _ => return None,
+        };
+        if now.is_multiple_of(every) {
+            Some((center - ENEMY_W / 2, self.y + H))
+        } else {
+            None
+        }
+    }
+
+    fn sweep(&mut self, speed: i32) {
+        self.x += self.dx * speed;
+        if self.x <= 0 || self.x >= WIDTH - W {
+            self.dx = -self.dx;
+        }
+    }
+
+    // Takes one hit point and returns whether it survived. Below each
+    // threshold it goes straight on to the next phase, unless it's still
+    // coming on.
+    pub fn damage(&mut self, now: u32) -> bool {
+        self.hp = self.hp.saturating_sub(1);
+        if self.hp == 0 {
+            return false;
+        }
+        self.hit_flash_until = now + HIT_FLASH_FRAMES;
+        if self.phase != Phase::Entering {
+            self.phase = Phase::for_hp(self.hp);
+        }
+        true
+    }
+
+    pub fn is_flashing(&self, now: u32) -> bool {
+        now < self.hit_flash_until
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            w: W,
+            h: H,
+        }
+    }
+
+    // While it's chasing, the line it's lining up along, from under its
+    // middle to `target`
+    pub fn aim(&self, target: (i32, i32)) -> Option<((i32, i32), (i32, i32))> {
+        match self.phase {
+            Phase::Chasing => Some(((self.x + W / 2, self.y + H), target)),
+            _ => None,
+        }
+    }
+
+    // The part of BAR that's filled, rounded up so it only empties at 0
+    pub fn bar_width(&self) -> i32 {
+        (BAR.w * self.hp as i32 + MAX_HP as i32 - 1) / MAX_HP as i32
+    }
+
+    // Centers for the explosions it goes out in, middle first
+    pub fn burst(&self) -> [(i32, i32); 5] {
+        let (x, y) = (self.x + W / 2, self.y + H / 2);
+        [
+            (x, y),
+            (x - W / 3, y - H / 3),
+            (x + W / 3, y + H / 3),
+            (x + W / 3, y - H / 3),
+            (x - W / 3, y + H / 3),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A boss that's come on screen, worn down to `hp`
+    fn boss(hp: u8) -> Boss {
+        let mut boss = Boss::new();
+        boss.y = TOP;
+        boss.hp = hp;
+        boss.phase = Phase::for_hp(hp);
+        boss
+    }
+
+    #[test]
+    fn phases_change_at_the_thresholds() {
+        for hp in 1..=MAX_HP {
+            let expected = match hp {
+                17..=24 => Phase::Sweeping,
+                9..=16 => Phase::Chasing,
+                _ => Phase::Frenzy,
+            };
+            assert_eq!(Phase::for_hp(hp), expected, "{} hp", hp);
+        }
+    }
+
+    #[test]
+    fn hits_wear_it_down_through_every_phase() {
+        let mut boss = boss(MAX_HP);
+        let mut phases = vec![boss.phase];
+        for now in 0..MAX_HP as u32 - 1 {
+            assert!(boss.damage(now));
+            if phases.last() != Some(&boss.phase) {
+                phases.push(boss.phase);
+            }
+        }
+        assert_eq!(phases, [Phase::Sweeping, Phase::Chasing, Phase::Frenzy]);
+        assert_eq!(boss.hp, 1);
+        assert!(!boss.damage(100));
+        assert_eq!(boss.hp, 0);
+    }
+
+    #[test]
+    fn it_comes_on_in_the_phase_for_what_it_has_left() {
+        let mut boss = Boss::new();
+        for _ in 0..MAX_HP - FRENZY_HP {
+            boss.damage(0);
+        }
+        assert_eq!(boss.phase, Phase::Entering);
+        let mut now = 0;
+        while boss.phase == Phase::Entering {
+            assert_eq!(boss.update(now, 0), None);
+            now += 1;
+            assert!(now < 100, "never settled");
+        }
+        assert_eq!((boss.y, boss.phase), (TOP, Phase::Frenzy));
+    }
+
+    #[test]
+    fn it_stays_on_screen() {
+        for &hp in &[MAX_HP, CHASING_HP, FRENZY_HP] {
+            let mut boss = boss(hp);
+            for now in 0..1000 {
+                boss.update(now, if now % 400 < 200 { -50 } else { WIDTH + 50 });
+                assert!(
+                    boss.x >= 0 && boss.x + W <= WIDTH,
+                    "{:?} at {}",
+                    boss.phase,
+                    now
+                );
+                assert!(boss.y >= TOP && boss.y <= TOP + FRENZY_BOB);
+            }
+        }
+    }
+
+    #[test]
+    fn chasing_follows_the_ship() {
+        let mut boss = boss(CHASING_HP);
+        for now in 0..200 {
+            boss.update(now, 10);
+        }
+        assert_eq!(boss.x + W / 2, 10);
+        assert!(boss.aim((10, 60)).is_some());
+        assert_eq!(Boss::new().aim((10, 60)), None);
+    }
+
+    #[test]
+    fn only_the_later_phases_drop_enemies() {
+        let drops = |hp| {
+            let mut boss = boss(hp);
+            (1..=FRENZY_DROP * CHASING_DROP)
+                .filter(|&now| boss.update(now, boss.x).is_some())
+                .count() as u32
+        };
+        assert_eq!(drops(MAX_HP), 0);
+        assert_eq!(drops(CHASING_HP), FRENZY_DROP);
+        assert_eq!(drops(FRENZY_HP), CHASING_DROP);
+    }
+
+    #[test]
+    fn the_bar_empties_with_its_hp() {
+        assert_eq!(boss(MAX_HP).bar_width(), BAR.w);
+        assert!(boss(1).bar_width() > 0);
+        assert_eq!(boss(0).bar_width(), 0);
+        for hp in 1..MAX_HP {
+            assert!(boss(hp).bar_width() <= boss(hp + 1).bar_width());
+        }
+    }
+
+    #[test]
+    fn a_hit_flashes() {
+        let mut boss = boss(MAX_HP);
+        boss.damage(10);
+        assert!(boss.is_flashing(10 + HIT_FLASH_FRAMES - 1));
+        assert!(!boss.is_flashing(10 + HIT_FLASH_FRAMES));
+    }
+}
@@ -0,0 +1,130 @@
+#![cfg_attr(not(debug_assertions), allow(dead_code))]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::DWT;
+
+use crate::clock;
+use crate::gameloop::{MAX_STEPS, TICK_HZ};
+#[cfg(feature = "sound")]
+use crate::sound::SAMPLE_PERIOD_US;
+use crate::watchdog;
+
+// The tasks come in three tiers of priority, each preempting the ones
+// below it, and each with a budget: how long one run of any of its tasks
+// can take without something below it missing its deadline.
+//
+// - 3, radio and audio: BLE's RADIO and TIMER0 interrupts, and the synth's
+//   TIMER2. Deadlines of tens of microseconds, so these do as little as
+//   they can, and hand anything more to a task below.
+// - 2, input and I/O: GPIOTE, RTC1's debouncing, USBD, SPIM1 chaining a
+//   frame's transfers, the metronome's timer, the heartbeat's RTC and
+//   BLE's worker.
+//   Deadlines of around a millisecond, USB's frame and a debounce tick.
+// - 1, render: the frame task, and everything spawned between frames (the
+//   console, chores, sensors, saving, sound effects and rumble). A frame
+//   has RENDER_US.
+//
+// Below all of them, idle draws the HUD with whatever's left, and feeds the
+// watchdog.
+//
+// The asserts at the bottom check the budgets add up, tier by tier, with
+// the time the tiers above can take out of each. In debug builds
+// `within_budget!` at the top of a task times each run, and logs an error
+// whenever a task runs longer than its budget by more than it has before.
+// Release builds leave the checks out.
+
+// Without the synth there are no samples, near enough
+#[cfg(not(feature = "sound"))]
+const SAMPLE_PERIOD_US: u32 = u32::MAX;
+
+// One sample of the synth
+pub const AUDIO_US: u32 = 8;
+// One of BLE's interrupts
+pub const RADIO_US: u32 = 40;
+// One run of anything in the input tier
+pub const INPUT_US: u32 = 250;
+const INPUT_DEADLINE_US: u32 = 1_000;
+// A frame, from the frame task starting to it finishing sending. Frames
+// slower than this count against the quality level.
+pub const RENDER_US: u32 = 33_000;
+
+// The most the radio and audio tier can take out of `us`: a sample every
+// sample period, and one radio interrupt. BLE's come far apart, a
+// connection event at most every 7.5 ms.
+const fn above_input(us: u32) -> u32 {
+    us.div_ceil(SAMPLE_PERIOD_US) * AUDIO_US + RADIO_US
+}
+
+// The same for the render tier, with a run of the input tier every
+// millisecond on top
+const fn above_render(us: u32) -> u32 {
+    above_input(us) + us.div_ceil(INPUT_DEADLINE_US) * INPUT_US
+}
+
+// A sample can have to wait for a radio interrupt, and still goes out before
+// the next one's due
+#[cfg(feature = "sound")]
+const _: () = assert!(RADIO_US + AUDIO_US <= SAMPLE_PERIOD_US);
+const _: () = assert!(INPUT_US + above_input(INPUT_DEADLINE_US) <= INPUT_DEADLINE_US);
+// A frame within budget, however much it's preempted, never costs the game
+// loop steps
+const _: () = assert!(RENDER_US + above_render(RENDER_US) <= MAX_STEPS * 1_000_000 / TICK_HZ);
+// Nor gets anywhere near the watchdog
+const _: () = assert!(2 * (RENDER_US + above_render(RENDER_US)) <= watchdog::TIMEOUT_MS * 1000);
+
+pub struct Task {
+    name: &'static str,
+    budget_us: u32,
+    // The longest run so far, once it's been over budget
+    worst_us: AtomicU32,
+}
+
+impl Task {
+    pub const fn new(name: &'static str, budget_us: u32) -> Self {
+        Task {
+            name,
+            budget_us,
+            worst_us: AtomicU32::new(0),
+        }
+    }
+}
+
+// Times its task's run from when it's made until it's dropped
+pub struct Guard {
+    task: &'static Task,
+    start: u32,
+}
+
+impl Guard {
+    #[inline(always)]
+    pub fn new(task: &'static Task) -> Self {
+        Guard {
+            task,
+            start: DWT::cycle_count(),
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let us = clock::cycles_to_us(DWT::cycle_count().wrapping_sub(self.start));
+        let task = self.task;
+        if us > task.budget_us && us > task.worst_us.fetch_max(us, Ordering::Relaxed) {
+            log_error!("{} took {} us, over its {} us budget", task.name, us, task.budget_us);
+        }
+    }
+}
+
+// `within_budget!("frame", RENDER_US)`, with the name of one of the budgets
+// above
+#[macro_export]
+macro_rules! within_budget {
+    ($name:expr, $budget:ident) => {
+        #[cfg(debug_assertions)]
+        let _budget = {
+            static TASK: $crate::budget::Task =
+                $crate::budget::Task::new($name, $crate::budget::$budget);
+            $crate::budget::Guard::new(&TASK)
+        };
+    };
+}
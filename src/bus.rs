@@ -0,0 +1,107 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+// An SPI bus that several devices, each with its own chip select, can take
+// turns on. Every `write` or `transfer` grabs the bus, asserts that device's
+// CS, transfers, releases CS and gives the bus back, so a device only owns
+// the bus for the length of a single transfer.
+//
+// The bus is claimed with an atomic flag rather than a critical section, so
+// interrupts stay enabled during long transfers. The flip side is that a
+// higher priority task that tries to use the bus while a lower priority one
+// holds it gets `BusError::Busy` rather than waiting, since it can't wait for
+// a task it has preempted. Devices that share a bus should either live at the
+// same priority or handle `Busy` by retrying later.
+pub struct SharedSpi<SPI> {
+    bus: UnsafeCell<SPI>,
+    busy: AtomicBool,
+}
+
+// Access to `bus` is serialized by `busy`
+unsafe impl<SPI: Send> Sync for SharedSpi<SPI> {}
+
+impl<SPI> SharedSpi<SPI> {
+    pub const fn new(spi: SPI) -> Self {
+        SharedSpi {
+            bus: UnsafeCell::new(spi),
+            busy: AtomicBool::new(false),
+        }
+    }
+
+    // `cs` should already be driven high (deasserted)
+    pub fn device<CS: OutputPin>(&self, cs: CS) -> SpiDevice<'_, SPI, CS> {
+        SpiDevice { bus: self, cs }
+    }
+}
+
+#[derive(Debug)]
+pub enum BusError<E> {
+    Busy,
+    ChipSelect,
+    Spi(E),
+}
+
+pub struct SpiDevice<'a, SPI, CS> {
+    bus: &'a SharedSpi<SPI>,
+    cs: CS,
+}
+
+impl<'a, SPI, CS: OutputPin> SpiDevice<'a, SPI, CS> {
+    // Runs `transfer` on the bus with this device's CS asserted
+    fn with_bus<E>(
+        &mut self,
+        transfer: impl FnOnce(&mut SPI) -> Result<(), E>,
+    ) -> Result<(), BusError<E>> {
+        if self
+            .bus
+            .busy
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(BusError::Busy);
+        }
+
+        // Safe since holding `busy` means nobody else has this reference
+        let spi = unsafe { &mut *self.bus.bus.get() };
+        let result = match self.cs.set_low() {
+            Ok(()) => {
+                let result = transfer(spi).map_err(BusError::Spi);
+                self.cs
+                    .set_high()
+                    .map_err(|_| BusError::ChipSelect)
+                    .and(result)
+            }
+            Err(_) => Err(BusError::ChipSelect),
+        };
+
+        self.bus.busy.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl<'a, SPI, CS> Write<u8> for SpiDevice<'a, SPI, CS>
+where
+    SPI: Write<u8>,
+    CS: OutputPin,
+{
+    type Error = BusError<SPI::Error>;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.with_bus(|spi| spi.write(words))
+    }
+}
+
+impl<'a, SPI, CS> Transfer<u8> for SpiDevice<'a, SPI, CS>
+where
+    SPI: Transfer<u8>,
+    CS: OutputPin,
+{
+    type Error = BusError<SPI::Error>;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.with_bus(|spi| spi.transfer(&mut *words).map(|_| ()))?;
+        Ok(words)
+    }
+}
@@ -0,0 +1,186 @@
+use embedded_hal::digital::v2::InputPin;
+use nrf52840_hal::gpio::{Input, Pin, Port, PullUp};
+use nrf52840_hal::gpiote::Gpiote;
+use nrf52840_hal::rtc::{Rtc, RtcInterrupt};
+use nrf52840_pac::{P0, P1, RTC1};
+
+use crate::scene::Press;
+
+// A D-pad and two action buttons, each switched to ground with the internal
+// pull-up. Nothing is polled while they're left alone: any edge on any of
+// them sets off GPIOTE's PORT event, which starts RTC1 ticking, and every
+// tick samples all six. Once they've all read the same for DEBOUNCE_TICKS in
+// a row that's taken as their state, the ticks stop again, and each pin is
+// set to sense the level it isn't at, so the next change sets off the PORT
+// event in turn.
+//
+// PORT rather than a channel per button, since that would take six of the
+// eight GPIOTE channels.
+
+// 32768 / (PRESCALER + 1), about a millisecond a tick
+const PRESCALER: u32 = 31;
+// How long the buttons have to hold still to count, in ticks
+const DEBOUNCE_TICKS: u8 = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ButtonState(u8);
+
+impl ButtonState {
+    pub const UP: ButtonState = ButtonState(1 << 0);
+    pub const DOWN: ButtonState = ButtonState(1 << 1);
+    pub const LEFT: ButtonState = ButtonState(1 << 2);
+    pub const RIGHT: ButtonState = ButtonState(1 << 3);
+    pub const A: ButtonState = ButtonState(1 << 4);
+    pub const B: ButtonState = ButtonState(1 << 5);
+
+    pub fn contains(self, other: ButtonState) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: ButtonState) {
+        self.0 |= other.0;
+    }
+}
+
+// What changed since the last time they were looked at
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ButtonEvents {
+    pub pressed: ButtonState,
+    pub released: ButtonState,
+}
+
+impl ButtonEvents {
+    // What the scenes hear about, B being back
+    pub fn press(&self) -> Press {
+        const PRESSES: [(ButtonState, Press); 5] = [
+            (ButtonState::B, Press::BACK),
+            (ButtonState::UP, Press::UP),
+            (ButtonState::DOWN, Press::DOWN),
+            (ButtonState::LEFT, Press::LEFT),
+            (ButtonState::RIGHT, Press::RIGHT),
+        ];
+        let mut press = Press::default();
+        for &(button, pressed) in PRESSES.iter() {
+            if self.pressed.contains(button) {
+                press.insert(pressed);
+            }
+        }
+        press
+    }
+}
+
+// The debounced buttons, as a shared resource for the game loop to read
+pub struct Pad {
+    held: ButtonState,
+    events: ButtonEvents,
+}
+
+impl Pad {
+    pub const fn new() -> Self {
+        Pad {
+            held: ButtonState(0),
+            events: ButtonEvents {
+                pressed: ButtonState(0),
+                released: ButtonState(0),
+            },
+        }
+    }
+
+    // From the debouncing, or the simulator's keyboard
+    pub fn update(&mut self, held: ButtonState) {
+        self.events.pressed.0 |= held.0 & !self.held.0;
+        self.events.released.0 |= self.held.0 & !held.0;
+        self.held = held;
+    }
+
+    // What's held now, and everything pressed and released since the last
+    // call, so a tap that's over between two frames still counts
+    pub fn take(&mut self) -> (ButtonState, ButtonEvents) {
+        (self.held, core::mem::take(&mut self.events))
+    }
+}
+
+// The interrupt side, which owns the pins and the RTC
+pub struct Buttons {
+    // In the order of the ButtonState bits
+    pins: [Pin<Input<PullUp>>; 6],
+    rtc: Rtc<RTC1>,
+    // The last sample, and how many ticks in a row it's been the same
+    sample: ButtonState,
+    stable: u8,
+}
+
+impl Buttons {
+    pub fn new(gpiote: &Gpiote, pins: [Pin<Input<PullUp>>; 6], rtc: RTC1) -> Self {
+        let mut rtc = Rtc::new(rtc, PRESCALER).unwrap();
+        rtc.enable_event(RtcInterrupt::Tick);
+        rtc.enable_interrupt(RtcInterrupt::Tick, None);
+        let mut buttons = Buttons {
+            pins,
+            rtc,
+            sample: ButtonState(0),
+            stable: 0,
+        };
+        buttons.sample = buttons.read();
+        buttons.arm(buttons.sample);
+        gpiote.port().enable_interrupt();
+        buttons
+    }
+
+    fn read(&self) -> ButtonState {
+        let mut state = 0;
+        for (i, pin) in self.pins.iter().enumerate() {
+            state |= (pin.is_low().unwrap_or(false) as u8) << i;
+        }
+        ButtonState(state)
+    }
+
+    // Each pin senses the level it isn't at in `state`
+    fn arm(&self, state: ButtonState) {
+        for (i, pin) in self.pins.iter().enumerate() {
+            let block = match pin.port() {
+                Port::Port0 => P0::ptr(),
+                Port::Port1 => P1::ptr(),
+            };
+            let cnf = unsafe { &(*block).pin_cnf[pin.pin() as usize] };
+            let pressed = state.0 & (1 << i) != 0;
+            cnf.modify(|_, w| if pressed { w.sense().high() } else { w.sense().low() });
+        }
+    }
+
+    // Called from the RTC1 interrupt, which the GPIOTE one pends to get
+    // things going. Hands `pad` the buttons once they've settled.
+    pub fn on_interrupt(&mut self, pad: &mut Pad) {
+        self.rtc.reset_event(RtcInterrupt::Tick);
+        let sample = self.read();
+        if sample != self.sample {
+            self.sample = sample;
+            self.stable = 0;
+        } else {
+            self.stable = self.stable.saturating_add(1);
+        }
+        if self.stable < DEBOUNCE_TICKS {
+            self.rtc.enable_counter();
+            return;
+        }
+
+        self.arm(sample);
+        // Anything that moved in the meantime has started bouncing again
+        if self.read() != sample {
+            self.rtc.enable_counter();
+            return;
+        }
+        self.rtc.disable_counter();
+        pad.update(sample);
+    }
+}
+
+// Called from the GPIOTE interrupt. Returns true if a button has moved, and
+// RTC1 should be pended to start looking at them.
+pub fn on_gpiote(gpiote: &Gpiote) -> bool {
+    if !gpiote.port().is_event_triggered() {
+        return false;
+    }
+    gpiote.port().reset_events();
+    true
+}
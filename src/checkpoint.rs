@@ -0,0 +1,453 @@
+use crate::boss::{Boss, Phase};
+use crate::crc;
+use crate::game::{
+    ActiveEffects, Bullet, Enemy, Events, PowerUp, PowerUpKind, Ship, State, World, MAX_BULLETS,
+    MAX_ENEMIES, MAX_POWER_UPS,
+};
+use crate::wave::Spawner;
+
+// A snapshot of a `World` in a fixed little-endian layout, for saving to
+// flash. The header carries a version and the pool sizes the save was made
+// with, so a save from a different build is thrown away rather than
+// misread. Bump VERSION whenever the layout below changes. A CRC32 of
+// everything before it comes last, which catches a half-written page.
+
+const MAGIC: [u8; 4] = *b"PEWS";
+const VERSION: u8 = 5;
+
+const HEADER_LEN: usize = 4 + 1 + 3;
+const SHIP_LEN: usize = 4 + 4 + 1;
+// Each slot starts with a byte saying whether it's occupied
+const BULLET_LEN: usize = 1 + 4 + 4 + 4;
+const ENEMY_LEN: usize = 1 + 4 + 4 + 1 + 4;
+const POWER_UP_LEN: usize = 1 + 1 + 4 + 4;
+const BOSS_LEN: usize = 1 + 4 + 4 + 1 + 1 + 4 + 4 + 4;
+const TAIL_LEN: usize = 4 * 4 + 4 + 1 + 4;
+const WAVE_LEN: usize = 1 + 1 + 1 + 4;
+const CRC_LEN: usize = 4;
+
+pub const LEN: usize = HEADER_LEN
+    + 1
+    + SHIP_LEN
+    + BULLET_LEN * MAX_BULLETS
+    + ENEMY_LEN * MAX_ENEMIES
+    + POWER_UP_LEN * MAX_POWER_UPS
+    + BOSS_LEN
+    + TAIL_LEN
+    + WAVE_LEN
+    + CRC_LEN;
+
+// Pausing saves one, from B or the console, and so does powering off or the
+// battery going flat
+pub fn save(world: &World, buf: &mut [u8; LEN]) {
+    let mut w = Writer { buf, pos: 0 };
+
+    w.bytes(&MAGIC);
+    w.u8(VERSION);
+    w.u8(MAX_BULLETS as u8);
+    w.u8(MAX_ENEMIES as u8);
+    w.u8(MAX_POWER_UPS as u8);
+
+    w.u8(match world.state {
+        State::Title => 0,
+        State::Playing => 1,
+        State::GameOver => 2,
+    });
+    w.i32(world.ship.x);
+    w.i32(world.ship.y);
+    w.u8(world.ship.cooldown);
+
+    for slot in world.bullets.iter() {
+        w.u8(slot.is_some() as u8);
+        let b = slot.unwrap_or(Bullet {
+            x: 0,
+            y: 0,
+            dx: 0,
+            partner: false,
+        });
+        w.i32(b.x);
+        w.i32(b.y);
+        w.i32(b.dx);
+    }
+    for slot in world.enemies.iter() {
+        w.u8(slot.is_some() as u8);
+        let e = slot.unwrap_or(Enemy {
+            x: 0,
+            y: 0,
+            hp: 0,
+            hit_flash_until: 0,
+        });
+        w.i32(e.x);
+        w.i32(e.y);
+        w.u8(e.hp);
+        w.u32(e.hit_flash_until);
+    }
+    for slot in world.power_ups.iter() {
+        w.u8(slot.is_some() as u8);
+        let p = slot.unwrap_or(PowerUp {
+            kind: PowerUpKind::RapidFire,
+            x: 0,
+            y: 0,
+        });
+        w.u8(match p.kind {
+            PowerUpKind::RapidFire => 0,
+            PowerUpKind::SpreadShot => 1,
+            PowerUpKind::Shield => 2,
+            PowerUpKind::ExtraLife => 3,
+            PowerUpKind::SlowMotion => 4,
+        });
+        w.i32(p.x);
+        w.i32(p.y);
+    }
+
+    w.u8(world.boss.is_some() as u8);
+    let b = world.boss.unwrap_or(Boss::new());
+    w.i32(b.x);
+    w.i32(b.y);
+    w.u8(b.hp);
+    w.u8(match b.phase {
+        Phase::Entering => 0,
+        Phase::Sweeping => 1,
+        Phase::Chasing => 2,
+        Phase::Frenzy => 3,
+    });
+    w.i32(b.dx);
+    w.u32(b.hit_flash_until);
+    w.u32(world.next_boss);
+
+    w.u32(world.effects.rapid_fire_until);
+    w.u32(world.effects.spread_shot_until);
+    w.u32(world.effects.shield_until);
+    w.u32(world.effects.slow_motion_until);
+    w.u32(world.score);
+    w.u8(world.lives);
+    w.u32(world.ticks);
+    w.u8(world.waves.wave);
+    w.u8(world.waves.next);
+    w.u8(world.waves.round);
+    w.u32(world.waves.started);
+
+    let crc = crc::crc32(&w.buf[..w.pos]);
+    w.u32(crc);
+}
+
+// Returns None for anything that isn't a save from this exact layout,
+// including erased flash
+pub fn load(buf: &[u8]) -> Option<World> {
+    let mut r = Reader { buf, pos: 0 };
+
+    if buf.len() < LEN || r.bytes(4) != MAGIC || r.u8() != VERSION {
+        return None;
+    }
+    let body = &buf[..LEN - CRC_LEN];
+    let stored = Reader { buf, pos: body.len() }.u32();
+    if crc::crc32(body) != stored {
+        log_warn!("Checkpoint is corrupt, ignoring it");
+        return None;
+    }
+    if (r.u8(), r.u8(), r.u8()) != (MAX_BULLETS as u8, MAX_ENEMIES as u8, MAX_POWER_UPS as u8) {
+        return None;
+    }
+
+    let mut world = World::new();
+    world.state = match r.u8() {
+        0 => State::Title,
+        1 => State::Playing,
+        2 => State::GameOver,
+        _ => return None,
+    };
+    world.ship = Ship {
+        x: r.i32(),
+        y: r.i32(),
+        cooldown: r.u8(),
+    };
+
+    for slot in world.bullets.iter_mut() {
+        let used = r.u8() != 0;
+        let b = Bullet {
+            x: r.i32(),
+            y: r.i32(),
+            dx: r.i32(),
+            // Only solo games are saved
+            partner: false,
+        };
+        *slot = if used { Some(b) } else { None };
+    }
+    for slot in world.enemies.iter_mut() {
+        let used = r.u8() != 0;
+        let e = Enemy {
+            x: r.i32(),
+            y: r.i32(),
+            hp: r.u8(),
+            hit_flash_until: r.u32(),
+        };
+        *slot = if used { Some(e) } else { None };
+    }
+    for slot in world.power_ups.iter_mut() {
+        let used = r.u8() != 0;
+        let kind = match r.u8() {
+            0 => PowerUpKind::RapidFire,
+            1 => PowerUpKind::SpreadShot,
+            2 => PowerUpKind::Shield,
+            3 => PowerUpKind::ExtraLife,
+            4 => PowerUpKind::SlowMotion,
+            _ => return None,
+        };
+        let p = PowerUp {
+            kind,
+            x: r.i32(),
+            y: r.i32(),
+        };
+        *slot = if used { Some(p) } else { None };
+    }
+
+    let used = r.u8() != 0;
+    let (x, y, hp) = (r.i32(), r.i32(), r.u8());
+    let phase = match r.u8() {
+        0 => Phase::Entering,
+        1 => Phase::Sweeping,
+        2 => Phase::Chasing,
+        3 => Phase::Frenzy,
+        _ => return None,
+    };
+    let b = Boss {
+        x,
+        y,
+        hp,
+        phase,
+        dx: r.i32(),
+        hit_flash_until: r.u32(),
+    };
+    world.boss = if used { Some(b) } else { None };
+    world.next_boss = r.u32();
+
+    world.effects = ActiveEffects {
+        rapid_fire_until: r.u32(),
+        spread_shot_until: r.u32(),
+        shield_until: r.u32(),
+        slow_motion_until: r.u32(),
+    };
+    world.score = r.u32();
+    world.lives = r.u8();
+    world.ticks = r.u32();
+    world.waves = Spawner {
+        wave: r.u8(),
+        next: r.u8(),
+        round: r.u8(),
+        started: r.u32(),
+    };
+    world.events = Events::default();
+
+    Some(world)
+}
+
+#[cfg_attr(not(feature = "console"), allow(dead_code))]
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+#[cfg_attr(not(feature = "console"), allow(dead_code))]
+impl Writer<'_> {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.buf[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.bytes(&[v]);
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.bytes(&v.to_le_bytes());
+    }
+
+    fn i32(&mut self, v: i32) {
+        self.bytes(&v.to_le_bytes());
+    }
+}
+
+// Callers check the length up front, so reads can't run off the end
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1)[0]
+    }
+
+    fn u32(&mut self) -> u32 {
+        let b = self.bytes(4);
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    }
+
+    fn i32(&mut self) -> i32 {
+        self.u32() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{self, Input};
+    use crate::rng::Rng;
+
+    // A game some way in, with something in every part of the layout
+    fn mid_game() -> World {
+        let (mut world, mut rng) = (World::new(), Rng::new(42));
+        let fire = Input {
+            fire: true,
+            ..Input::default()
+        };
+        game::advance_frame(&mut world, fire, &mut rng);
+        for frame in 0..90 {
+            let input = Input {
+                left: frame % 40 < 15,
+                ..fire
+            };
+            game::advance_frame(&mut world, input, &mut rng);
+        }
+        world.power_ups.spawn(PowerUp {
+            kind: PowerUpKind::SlowMotion,
+            x: 20,
+            y: -3,
+        });
+        let mut boss = Boss::new();
+        boss.hp = 11;
+        boss.phase = Phase::Chasing;
+        boss.dx = -1;
+        world.boss = Some(boss);
+        world.effects.shield_until = world.ticks + 100;
+        world.score = 123_456;
+        world
+    }
+
+    fn saved(world: &World) -> [u8; LEN] {
+        let mut buf = [0; LEN];
+        save(world, &mut buf);
+        buf
+    }
+
+    // Puts the right CRC back on a save that's been changed
+    fn reseal(buf: &mut [u8; LEN]) {
+        let crc = crc::crc32(&buf[..LEN - CRC_LEN]);
+        buf[LEN - CRC_LEN..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    #[test]
+    fn a_save_loads_back_as_it_was() {
+        let world = mid_game();
+        assert_eq!(world.state, State::Playing);
+        assert!(world.bullets.iter().flatten().count() > 0);
+        assert!(world.enemies.iter().flatten().count() > 0);
+
+        let buf = saved(&world);
+        let loaded = load(&buf).expect("didn't load");
+        assert_eq!(saved(&loaded), buf);
+        assert_eq!(loaded.ship, world.ship);
+        assert_eq!(
+            loaded.bullets.iter().collect::<Vec<_>>(),
+            world.bullets.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.enemies.iter().collect::<Vec<_>>(),
+            world.enemies.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.power_ups.iter().collect::<Vec<_>>(),
+            world.power_ups.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.boss, world.boss);
+        assert_eq!(loaded.effects, world.effects);
+        assert_eq!(loaded.waves, world.waves);
+        assert_eq!(
+            (loaded.score, loaded.lives, loaded.ticks, loaded.next_boss),
+            (world.score, world.lives, world.ticks, world.next_boss)
+        );
+        assert_eq!(loaded.events, Events::default());
+    }
+
+    #[test]
+    fn a_resumed_game_carries_on_the_same() {
+        let mut world = mid_game();
+        let mut loaded = load(&saved(&world)).unwrap();
+        let (mut rng, mut loaded_rng) = (Rng::new(9), Rng::new(9));
+        for frame in 0..200 {
+            let input = Input {
+                right: frame % 30 < 10,
+                fire: frame % 3 == 0,
+                ..Input::default()
+            };
+            game::advance_frame(&mut world, input, &mut rng);
+            game::advance_frame(&mut loaded, input, &mut loaded_rng);
+            assert_eq!(saved(&loaded)[..], saved(&world)[..], "frame {}", frame);
+        }
+    }
+
+    #[test]
+    fn every_state_round_trips() {
+        for &state in &[State::Title, State::Playing, State::GameOver] {
+            let mut world = World::new();
+            world.state = state;
+            assert_eq!(load(&saved(&world)).map(|world| world.state), Some(state));
+        }
+    }
+
+    #[test]
+    fn erased_or_short_flash_is_no_save() {
+        assert!(load(&[0xFF; LEN]).is_none());
+        assert!(load(&[0; LEN]).is_none());
+        assert!(load(&saved(&mid_game())[..LEN - 1]).is_none());
+        assert!(load(&[]).is_none());
+    }
+
+    #[test]
+    fn another_version_or_layout_is_refused() {
+        let good = saved(&mid_game());
+        let mut bad = good;
+        bad[0] = b'X';
+        reseal(&mut bad);
+        assert!(load(&bad).is_none());
+
+        for version in [VERSION - 1, VERSION + 1] {
+            let mut bad = good;
+            bad[4] = version;
+            reseal(&mut bad);
+            assert!(load(&bad).is_none(), "version {}", version);
+        }
+
+        // Pools of another size
+        for i in 5..8 {
+            let mut bad = good;
+            bad[i] += 1;
+            reseal(&mut bad);
+            assert!(load(&bad).is_none(), "pool {}", i - 5);
+        }
+
+        // A state that doesn't exist
+        let mut bad = good;
+        bad[HEADER_LEN] = 3;
+        reseal(&mut bad);
+        assert!(load(&bad).is_none());
+    }
+
+    #[test]
+    fn any_corruption_fails_the_crc() {
+        let good = saved(&mid_game());
+        for i in 0..LEN {
+            for bit in 0..8 {
+                let mut bad = good;
+                bad[i] ^= 1 << bit;
+                assert!(load(&bad).is_none(), "byte {} bit {}", i, bit);
+            }
+        }
+    }
+}
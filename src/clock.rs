@@ -0,0 +1,98 @@
+use cortex_m::peripheral::DWT;
+use nrf52840_hal::clocks::{HFCLK_FREQ, LFCLK_FREQ};
+use nrf52840_pac::{CLOCK, RTC0};
+
+// The core always runs at 64 MHz from HFCLK, whichever oscillator is behind
+// it. What changes is accuracy: HFXO is a crystal, HFINT is an RC oscillator
+// that can be off by a few percent. Anything that turns DWT cycle counts into
+// time should go through this.
+pub const CPU_HZ: u32 = HFCLK_FREQ;
+
+// How long to count core cycles for when checking CPU_HZ, in LFCLK ticks
+const MEASURE_TICKS: u32 = 328;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HfSource {
+    Rc,
+    Crystal,
+}
+
+pub fn hf_source() -> HfSource {
+    // Read-only access to a status register, the HAL owns the CLOCK block
+    let clock = unsafe { &*CLOCK::ptr() };
+    if clock.hfclkstat.read().src().is_xtal() {
+        HfSource::Crystal
+    } else {
+        HfSource::Rc
+    }
+}
+
+// HFXO is only needed for accurate timing, apart from the radio and USB, which
+// need it whenever they're on
+pub const CAN_STOP_HFXO: bool = !cfg!(any(feature = "ble", feature = "link", feature = "usb"));
+
+// Without HFXO the HF clock falls back to HFINT, which only runs while the CPU
+// or a peripheral asks for it, so with the CPU asleep and nothing else on the
+// HF clock it stops altogether. Only if CAN_STOP_HFXO.
+pub fn stop_hfxo() {
+    // The HAL's Clocks has been dropped by now, these are just the tasks
+    let clock = unsafe { &*CLOCK::ptr() };
+    clock
+        .tasks_hfclkstop
+        .write(|w| w.tasks_hfclkstop().set_bit());
+}
+
+// Waits for the crystal to settle, a few hundred microseconds, unless it's
+// running already
+pub fn start_hfxo() {
+    if hf_source() == HfSource::Crystal {
+        return;
+    }
+    let clock = unsafe { &*CLOCK::ptr() };
+    clock.events_hfclkstarted.reset();
+    clock
+        .tasks_hfclkstart
+        .write(|w| w.tasks_hfclkstart().set_bit());
+    while clock.events_hfclkstarted.read().bits() == 0 {}
+}
+
+pub const fn cycles_to_us(cycles: u32) -> u32 {
+    (cycles as u64 * 1_000_000 / CPU_HZ as u64) as u32
+}
+
+// Counts DWT cycles over ~10 ms of LFCLK to estimate the actual core
+// frequency. Only meaningful when LFCLK runs from the 32 kHz crystal, and
+// needs the DWT cycle counter enabled.
+pub fn measure_cpu_hz(rtc: &RTC0) -> u32 {
+    rtc.prescaler.write(|w| unsafe { w.prescaler().bits(0) });
+    rtc.tasks_clear.write(|w| unsafe { w.bits(1) });
+    rtc.tasks_start.write(|w| unsafe { w.bits(1) });
+
+    // Line up with a tick edge first
+    let first = rtc.counter.read().bits();
+    while rtc.counter.read().bits() == first {}
+    let start_tick = rtc.counter.read().bits();
+    let start = DWT::cycle_count();
+
+    while rtc.counter.read().bits().wrapping_sub(start_tick) < MEASURE_TICKS {}
+    let cycles = DWT::cycle_count().wrapping_sub(start);
+
+    rtc.tasks_stop.write(|w| unsafe { w.bits(1) });
+    rtc.tasks_clear.write(|w| unsafe { w.bits(1) });
+
+    (cycles as u64 * LFCLK_FREQ as u64 / MEASURE_TICKS as u64) as u32
+}
+
+pub fn log(rtc: &RTC0) {
+    let source = hf_source();
+    let measured = measure_cpu_hz(rtc);
+    log_info!(
+        "HFCLK source: {:?}, CPU_HZ = {}, measured ~{} Hz",
+        source,
+        CPU_HZ,
+        measured
+    );
+    if source == HfSource::Rc {
+        log_warn!("HFXO is not running, timing will drift");
+    }
+}
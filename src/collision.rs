@@ -0,0 +1,222 @@
+use crate::game::{Bullet, Enemy, PowerUp, Rect, HEIGHT, WIDTH};
+use crate::game::{MAX_BULLETS, MAX_ENEMIES, MAX_POWER_UPS};
+
+// Bounding box tests between the things in a pool and a rect: a bullet,
+// a ship, the boss. Slots are always tried in order and the first hit is
+// the one that counts, the same on both linked boards.
+//
+// Checking every bullet against every enemy is fine at eight of each, but
+// a Grid keeps it to the few enemies near each bullet however big the
+// pools get. It splits the screen into CELL-sized squares and remembers
+// which slots touch each one, a bit a slot.
+
+pub trait Bounds {
+    fn rect(&self) -> Rect;
+}
+
+impl Bounds for Bullet {
+    fn rect(&self) -> Rect {
+        Bullet::rect(self)
+    }
+}
+
+impl Bounds for Enemy {
+    fn rect(&self) -> Rect {
+        Enemy::rect(self)
+    }
+}
+
+impl Bounds for PowerUp {
+    fn rect(&self) -> Rect {
+        PowerUp::rect(self)
+    }
+}
+
+// The first slot with something in it that overlaps `rect`
+pub fn first_hit<T: Bounds>(rect: Rect, slots: &[Option<T>]) -> Option<usize> {
+    slots
+        .iter()
+        .position(|slot| matches!(slot, Some(thing) if thing.rect().overlaps(rect)))
+}
+
+// A ship's worth of pixels or more, so most things are in one or two cells
+const CELL: i32 = 16;
+const COLS: i32 = (WIDTH + CELL - 1) / CELL;
+const ROWS: i32 = (HEIGHT + CELL - 1) / CELL;
+// A bit a slot
+type Slots = u32;
+
+const _: () = assert!(
+    MAX_BULLETS <= Slots::BITS as usize
+        && MAX_ENEMIES <= Slots::BITS as usize
+        && MAX_POWER_UPS <= Slots::BITS as usize,
+    "a pool has more slots than a grid cell has bits"
+);
+
+pub struct Grid {
+    cells: [Slots; (COLS * ROWS) as usize],
+}
+
+impl Grid {
+    // Where everything in `slots` is now. Anything that moves afterwards
+    // is still looked for where it was, but anything despawned is skipped.
+    pub fn new<T: Bounds>(slots: &[Option<T>]) -> Self {
+        let mut grid = Grid {
+            cells: [0; (COLS * ROWS) as usize],
+        };
+        for (i, slot) in slots.iter().enumerate() {
+            if let Some(thing) = slot {
+                for cell in cells(thing.rect()) {
+                    grid.cells[cell] |= 1 << i;
+                }
+            }
+        }
+        grid
+    }
+
+    // Like `first_hit`, only trying the slots near `rect`, still in order
+    pub fn first_hit<T: Bounds>(&self, rect: Rect, slots: &[Option<T>]) -> Option<usize> {
+        let mut near = cells(rect).fold(0, |near, cell| near | self.cells[cell]);
+        while near != 0 {
+            let i = near.trailing_zeros() as usize;
+            near &= near - 1;
+            if let Some(Some(thing)) = slots.get(i) {
+                if thing.rect().overlaps(rect) {
+                    return Some(i);
+                }
+            }
+        }
+        None
+    }
+}
+
+// The cells `rect` is in. Anything off the edge of the screen counts as in
+// the cells along it.
+fn cells(rect: Rect) -> impl Iterator<Item = usize> {
+    let col = |x: i32| x.div_euclid(CELL).clamp(0, COLS - 1);
+    let row = |y: i32| y.div_euclid(CELL).clamp(0, ROWS - 1);
+    let cols = col(rect.x)..=col(rect.x + rect.w - 1);
+    (row(rect.y)..=row(rect.y + rect.h - 1))
+        .flat_map(move |y| cols.clone().map(move |x| (y * COLS + x) as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Rng;
+
+    struct Boxed(Rect);
+
+    impl Bounds for Boxed {
+        fn rect(&self) -> Rect {
+            self.0
+        }
+    }
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    fn boxed(x: i32, y: i32, w: i32, h: i32) -> Option<Boxed> {
+        Some(Boxed(rect(x, y, w, h)))
+    }
+
+    #[test]
+    fn rects_overlap_and_touching_ones_dont() {
+        let a = rect(10, 10, 4, 4);
+        assert!(a.overlaps(a));
+        assert!(a.overlaps(rect(13, 13, 4, 4)));
+        assert!(a.overlaps(rect(11, 11, 1, 1)));
+        assert!(rect(11, 11, 1, 1).overlaps(a));
+        assert!(!a.overlaps(rect(14, 10, 4, 4)));
+        assert!(!a.overlaps(rect(10, 14, 4, 4)));
+        assert!(!a.overlaps(rect(6, 10, 4, 4)));
+        assert!(!a.overlaps(rect(10, 6, 4, 4)));
+        assert!(!a.overlaps(rect(14, 14, 4, 4)));
+    }
+
+    #[test]
+    fn the_first_slot_hit_counts() {
+        let slots = [
+            None,
+            boxed(0, 0, 2, 2),
+            boxed(20, 20, 4, 4),
+            boxed(21, 21, 2, 2),
+        ];
+        assert_eq!(first_hit(rect(22, 22, 1, 1), &slots), Some(2));
+        assert_eq!(first_hit(rect(1, 1, 1, 1), &slots), Some(1));
+        assert_eq!(first_hit(rect(10, 10, 1, 1), &slots), None);
+        assert_eq!(first_hit::<Boxed>(rect(0, 0, 64, 64), &[None, None]), None);
+    }
+
+    #[test]
+    fn the_grid_finds_what_checking_everything_does() {
+        let mut rng = Rng::new(7);
+        let coord = |rng: &mut Rng| rng.below(WIDTH as u32 + 16) as i32 - 8;
+        for _ in 0..200 {
+            let slots: Vec<Option<Boxed>> = (0..MAX_ENEMIES)
+                .map(|_| match rng.one_in(4) {
+                    true => None,
+                    false => boxed(
+                        coord(&mut rng),
+                        coord(&mut rng),
+                        1 + rng.below(12) as i32,
+                        1 + rng.below(12) as i32,
+                    ),
+                })
+                .collect();
+            let grid = Grid::new(&slots);
+            for _ in 0..20 {
+                let probe = rect(
+                    coord(&mut rng),
+                    coord(&mut rng),
+                    1 + rng.below(6) as i32,
+                    1 + rng.below(6) as i32,
+                );
+                assert_eq!(
+                    grid.first_hit(probe, &slots),
+                    first_hit(probe, &slots),
+                    "{:?}",
+                    probe
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_grid_covers_things_across_cells_and_off_screen() {
+        // Straddling the corner of four cells, and hanging off each edge
+        let slots = [
+            boxed(CELL - 2, CELL - 2, 4, 4),
+            boxed(-6, 30, 8, 2),
+            boxed(WIDTH - 2, 30, 8, 2),
+            boxed(30, -6, 2, 8),
+            boxed(30, HEIGHT - 2, 2, 8),
+        ];
+        let grid = Grid::new(&slots);
+        for &(x, y) in &[
+            (CELL - 1, CELL - 1),
+            (CELL, CELL - 1),
+            (CELL - 1, CELL),
+            (CELL, CELL),
+        ] {
+            assert_eq!(grid.first_hit(rect(x, y, 1, 1), &slots), Some(0));
+        }
+        assert_eq!(grid.first_hit(rect(-3, 30, 1, 1), &slots), Some(1));
+        assert_eq!(grid.first_hit(rect(0, 30, 1, 1), &slots), Some(1));
+        assert_eq!(grid.first_hit(rect(WIDTH + 3, 31, 1, 1), &slots), Some(2));
+        assert_eq!(grid.first_hit(rect(31, -1, 1, 1), &slots), Some(3));
+        assert_eq!(grid.first_hit(rect(30, HEIGHT + 4, 1, 1), &slots), Some(4));
+        assert_eq!(grid.first_hit(rect(40, 40, 1, 1), &slots), None);
+    }
+
+    #[test]
+    fn the_grid_skips_what_has_gone_since() {
+        let mut slots = [boxed(5, 5, 4, 4), boxed(6, 6, 4, 4)];
+        let grid = Grid::new(&slots);
+        slots[0] = None;
+        assert_eq!(grid.first_hit(rect(7, 7, 1, 1), &slots), Some(1));
+        slots[1] = None;
+        assert_eq!(grid.first_hit(rect(7, 7, 1, 1), &slots), None);
+    }
+}
@@ -0,0 +1,74 @@
+// RGB565 the way the panel takes it, which is BGR: red in the low five bits,
+// green in the middle six and blue in the high five. Everything here works on
+// each field on its own and clamps rather than carrying into the next one.
+
+// Linear light for each of 256 levels, gamma 2.2. A gradient that steps
+// evenly through these looks even on the panel, where one that steps evenly
+// through the raw levels rushes through the dark end.
+const GAMMA: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2,
+    3, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 8, 8, 8, 9, 9, 9, 10, 10, 11, 11,
+    11, 12, 12, 13, 13, 13, 14, 14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19, 20, 20, 21, 22, 22, 23,
+    23, 24, 25, 25, 26, 26, 27, 28, 28, 29, 30, 30, 31, 32, 33, 33, 34, 35, 35, 36, 37, 38, 39, 39,
+    40, 41, 42, 43, 43, 44, 45, 46, 47, 48, 49, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61,
+    62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 73, 74, 75, 76, 77, 78, 79, 81, 82, 83, 84, 85, 87, 88,
+    89, 90, 91, 93, 94, 95, 97, 98, 99, 100, 102, 103, 105, 106, 107, 109, 110, 111, 113, 114, 116,
+    117, 119, 120, 121, 123, 124, 126, 127, 129, 130, 132, 133, 135, 137, 138, 140, 141, 143, 145,
+    146, 148, 149, 151, 153, 154, 156, 158, 159, 161, 163, 165, 166, 168, 170, 172, 173, 175, 177,
+    179, 181, 182, 184, 186, 188, 190, 192, 194, 196, 197, 199, 201, 203, 205, 207, 209, 211, 213,
+    215, 217, 219, 221, 223, 225, 227, 229, 231, 234, 236, 238, 240, 242, 244, 246, 248, 251, 253,
+    255,
+];
+
+pub const fn rgb565(r5: u16, g6: u16, b5: u16) -> u16 {
+    (b5 << 11) + (g6 << 5) + r5
+}
+
+// Red, green and blue out of 255, as they are
+pub const fn rgb(r: u8, g: u8, b: u8) -> u16 {
+    rgb565(r as u16 >> 3, g as u16 >> 2, b as u16 >> 3)
+}
+
+pub const fn gamma(level: u8) -> u8 {
+    GAMMA[level as usize]
+}
+
+// `hue` goes once round red, yellow, green, cyan, blue and magenta over 0 to
+// 255. The result is gamma corrected.
+pub fn hsv(hue: u8, saturation: u8, value: u8) -> u16 {
+    let (s, v) = (saturation as u32, value as u32);
+    let sector = hue as u32 * 6;
+    let (region, rem) = (sector >> 8, sector & 0xFF);
+    let p = v * (255 - s) / 255;
+    let q = v * (255 - s * rem / 255) / 255;
+    let t = v * (255 - s * (255 - rem) / 255) / 255;
+    let (r, g, b) = match region {
+        0 => (v, t, p),
+        1 => (q, v, p),
+        2 => (p, v, t),
+        3 => (p, q, v),
+        4 => (t, p, v),
+        _ => (v, p, q),
+    };
+    rgb(gamma(r as u8), gamma(g as u8), gamma(b as u8))
+}
+
+// Each field scaled by `level` out of 256. Past 256 it brightens, each field
+// stopping at its maximum.
+pub fn scale(color: u16, level: u32) -> u16 {
+    let field = |shift: u32, max: u32| {
+        let x = (color as u32 >> shift) & max;
+        ((x * level) >> 8).min(max) << shift
+    };
+    (field(11, 0x1F) | field(5, 0x3F) | field(0, 0x1F)) as u16
+}
+
+// `amount` out of 256 of the way from `from` to `to`
+pub fn lerp(from: u16, to: u16, amount: u32) -> u16 {
+    let amount = amount.min(256);
+    let field = |shift: u32, max: u32| {
+        let (x, y) = ((from as u32 >> shift) & max, (to as u32 >> shift) & max);
+        ((x * (256 - amount) + y * amount) >> 8) << shift
+    };
+    (field(11, 0x1F) | field(5, 0x3F) | field(0, 0x1F)) as u16
+}
@@ -0,0 +1,154 @@
+use crate::dma::{self, MAX_TRANSFER};
+use core::convert::Infallible;
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::spi;
+use embedded_hal::digital::v2;
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::digital::{self, OutputPin};
+use embedded_hal_1::pwm::{self, SetDutyCycle};
+use embedded_hal_1::spi::{ErrorKind, ErrorType, SpiBus};
+use nrf52840_hal::pwm::{Instance, Pwm};
+
+// The drivers here are written against embedded-hal 1.0, so they could sit on
+// another HAL, or a mock. nrf52840-hal, and st7735-lcd on the other side of
+// the display driver, still only know 0.2, so this bridges the two:
+//
+//  - `Compat` wraps a 0.2 (or plain nRF) type for things that want 1.0 traits
+//  - `Legacy` wraps a 1.0 type for st7735-lcd, which wants 0.2 ones
+//
+// Errors from the 0.2 side have no 1.0 kind to map to, so they all come out
+// as `Other`.
+
+pub struct Compat<T>(pub T);
+
+impl<T: v2::OutputPin> digital::ErrorType for Compat<T> {
+    type Error = digital::ErrorKind;
+}
+
+impl<T: v2::OutputPin> OutputPin for Compat<T> {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set_low().map_err(|_| digital::ErrorKind::Other)
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set_high().map_err(|_| digital::ErrorKind::Other)
+    }
+}
+
+impl<T: spi::Write<u8> + spi::Transfer<u8>> ErrorType for Compat<T> {
+    type Error = ErrorKind;
+}
+
+// 0.2 transfers are finished by the time they return, so there's never
+// anything to flush. Writes go down in chunks that fit one EasyDMA transfer,
+// whatever size the HAL underneath would take, so a frame that outgrows one
+// is never cut short.
+impl<T: spi::Write<u8> + spi::Transfer<u8>> SpiBus for Compat<T> {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), ErrorKind> {
+        words.iter_mut().for_each(|w| *w = 0);
+        self.transfer_in_place(words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), ErrorKind> {
+        dma::write_chunked(words, MAX_TRANSFER, |chunk| {
+            self.0.write(chunk).map(|_| chunk.len())
+        })
+        .map_err(|e| {
+            if let dma::TransferError::Short { sent, expected } = e {
+                log_error!("SPI write sent {} of {} bytes", sent, expected);
+            }
+            ErrorKind::Other
+        })
+    }
+
+    // Whichever buffer is longer has its tail clocked on its own
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), ErrorKind> {
+        let common = read.len().min(write.len());
+        read[..common].copy_from_slice(&write[..common]);
+        self.transfer_in_place(&mut read[..common])?;
+        if write.len() > common {
+            self.write(&write[common..])?;
+        }
+        if read.len() > common {
+            self.read(&mut read[common..])?;
+        }
+        Ok(())
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), ErrorKind> {
+        self.0
+            .transfer(words)
+            .map(|_| ())
+            .map_err(|_| ErrorKind::Other)
+    }
+
+    fn flush(&mut self) -> Result<(), ErrorKind> {
+        Ok(())
+    }
+}
+
+// The nRF timers only count whole microseconds, so anything shorter waits
+// for one
+impl<T: embedded_hal::blocking::delay::DelayUs<u32>> DelayNs for Compat<T> {
+    fn delay_ns(&mut self, ns: u32) {
+        self.0.delay_us(ns.div_ceil(1000));
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        self.0.delay_us(us);
+    }
+}
+
+// All four channels get the same duty, and the period is whatever the PWM was
+// set up with
+impl<T: Instance> pwm::ErrorType for Compat<Pwm<T>> {
+    type Error = Infallible;
+}
+
+impl<T: Instance> SetDutyCycle for Compat<Pwm<T>> {
+    fn max_duty_cycle(&self) -> u16 {
+        self.0.max_duty()
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.0.set_duty_on_common(duty);
+        Ok(())
+    }
+}
+
+pub struct Legacy<T>(pub T);
+
+impl<T: OutputPin> v2::OutputPin for Legacy<T> {
+    type Error = T::Error;
+
+    fn set_low(&mut self) -> Result<(), T::Error> {
+        self.0.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), T::Error> {
+        self.0.set_high()
+    }
+}
+
+impl<T: SpiBus> spi::Write<u8> for Legacy<T> {
+    type Error = T::Error;
+
+    // st7735-lcd toggles DC between writes, so each has to be out on the
+    // wire before it returns
+    fn write(&mut self, words: &[u8]) -> Result<(), T::Error> {
+        self.0.write(words)?;
+        self.0.flush()
+    }
+}
+
+impl<T: DelayNs> DelayMs<u8> for Legacy<T> {
+    fn delay_ms(&mut self, ms: u8) {
+        self.0.delay_ms(ms as u32);
+    }
+}
+
+impl<T: DelayNs> DelayMs<u16> for Legacy<T> {
+    fn delay_ms(&mut self, ms: u16) {
+        self.0.delay_ms(ms as u32);
+    }
+}
@@ -0,0 +1,380 @@
+use crate::effect::Effect;
+use crate::fade::Transition;
+use crate::logging::{Level, Module};
+use crate::plasma::{self, Variant};
+use crate::screensaver::Style;
+#[cfg(feature = "haptics")]
+use crate::haptics::Rumble;
+#[cfg(feature = "sound")]
+use crate::sound::SfxId;
+
+// Longest line the console will accept, not counting the newline
+pub const LINE_LEN: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    SetBrightness(u8),
+    AutoBrightness,
+    Effect(Effect),
+    Transition(Transition),
+    // Index into the settings' orientations
+    Orientation(u8),
+    AutoOrientation,
+    // Steering by tipping the board
+    TiltSteering(bool),
+    // plasma::RANDOM for a new one each time
+    Plasma(u8),
+    Backdrop(u8),
+    // Shift for how fast trails fade, 0 for none
+    Trails(u8),
+    // Red, green and blue, out of 255
+    ClearColor(u8, u8, u8),
+    Spawn(u8),
+    #[cfg(feature = "sound")]
+    Sfx(SfxId),
+    #[cfg(feature = "haptics")]
+    Rumble(Rumble),
+    Vignette(bool),
+    // The alignment grid, see alignment.rs
+    Grid(bool),
+    // FPS and CPU load in the corner of the screen
+    Perf(bool),
+    Dither(bool),
+    Sound(bool),
+    // Percent of full volume
+    Volume(u8),
+    FpsCap(u8),
+    PowerOff(u8),
+    // Minutes without input before it comes on, 0 for never
+    ScreensaverMins(u8),
+    Screensaver(Style),
+    Bpm(u8),
+    // Percent of full speed
+    Speed(u8),
+    Turbo(bool),
+    TurboHold(u8),
+    TurboRepeat(u8),
+    // Radio channel and pairing code for the link
+    LinkChannel(u8),
+    PairingCode(u8),
+    Versus(bool),
+    Pause,
+    Log(Level),
+    // A threshold for one module's messages
+    LogModule(Module, Level),
+    Stats,
+    // Min, average and max cycles for each profiled scope
+    #[cfg(feature = "profile")]
+    Profile,
+    #[cfg(feature = "profile")]
+    ProfileReset,
+    // The next frame, out on its own RTT channel
+    Screenshot,
+    Demo,
+    // The last game played alone, again
+    Replay,
+    Reset,
+    // Reset into the bootloader, for an update
+    Dfu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    LineTooLong,
+    NotUtf8,
+    UnknownCommand,
+    MissingArgument,
+    InvalidArgument,
+    TrailingInput,
+}
+
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let mut tokens = line.split_ascii_whitespace();
+
+    let command = match tokens.next().ok_or(ParseError::Empty)? {
+        "set" => match tokens.next().ok_or(ParseError::MissingArgument)? {
+            "brightness" => match tokens.next() {
+                Some("auto") => Command::AutoBrightness,
+                token => Command::SetBrightness(number(token)?),
+            },
+            "volume" => Command::Volume(number(tokens.next())?),
+            "fps" => Command::FpsCap(number(tokens.next())?),
+            "poweroff" => Command::PowerOff(number(tokens.next())?),
+            "screensaver" => Command::ScreensaverMins(number(tokens.next())?),
+            "backdrop" => Command::Backdrop(number(tokens.next())?),
+            "trails" => Command::Trails(number(tokens.next())?),
+            "clear" => Command::ClearColor(
+                number(tokens.next())?,
+                number(tokens.next())?,
+                number(tokens.next())?,
+            ),
+            "bpm" => Command::Bpm(number(tokens.next())?),
+            "speed" => Command::Speed(number(tokens.next())?),
+            "turbo-hold" => Command::TurboHold(number(tokens.next())?),
+            "turbo-repeat" => Command::TurboRepeat(number(tokens.next())?),
+            "channel" => Command::LinkChannel(number(tokens.next())?),
+            "pair" => Command::PairingCode(number(tokens.next())?),
+            _ => return Err(ParseError::InvalidArgument),
+        },
+        "effect" => {
+            let name = tokens.next().ok_or(ParseError::MissingArgument)?;
+            Command::Effect(Effect::from_name(name).ok_or(ParseError::InvalidArgument)?)
+        }
+        "transition" => {
+            let name = tokens.next().ok_or(ParseError::MissingArgument)?;
+            Command::Transition(Transition::from_name(name).ok_or(ParseError::InvalidArgument)?)
+        }
+        "screensaver" => {
+            let name = tokens.next().ok_or(ParseError::MissingArgument)?;
+            Command::Screensaver(Style::from_name(name).ok_or(ParseError::InvalidArgument)?)
+        }
+        "orientation" => match tokens.next() {
+            Some("auto") => Command::AutoOrientation,
+            token => Command::Orientation(number(token)?),
+        },
+        "plasma" => match tokens.next().ok_or(ParseError::MissingArgument)? {
+            "random" => Command::Plasma(plasma::RANDOM),
+            name => match Variant::from_name(name) {
+                Some(index) => Command::Plasma(index),
+                None => Command::Plasma(number(Some(name))?),
+            },
+        },
+        "spawn" => Command::Spawn(number(tokens.next())?),
+        #[cfg(feature = "sound")]
+        "sfx" => {
+            let name = tokens.next().ok_or(ParseError::MissingArgument)?;
+            Command::Sfx(SfxId::from_name(name).ok_or(ParseError::InvalidArgument)?)
+        }
+        #[cfg(feature = "haptics")]
+        "rumble" => {
+            let name = tokens.next().ok_or(ParseError::MissingArgument)?;
+            Command::Rumble(Rumble::from_name(name).ok_or(ParseError::InvalidArgument)?)
+        }
+        "vignette" => Command::Vignette(on_off(tokens.next())?),
+        "grid" => Command::Grid(on_off(tokens.next())?),
+        "perf" => Command::Perf(on_off(tokens.next())?),
+        "dither" => Command::Dither(on_off(tokens.next())?),
+        "sound" => Command::Sound(on_off(tokens.next())?),
+        "turbo" => Command::Turbo(on_off(tokens.next())?),
+        "versus" => Command::Versus(on_off(tokens.next())?),
+        "tilt" => Command::TiltSteering(on_off(tokens.next())?),
+        "pause" => Command::Pause,
+        "log" => {
+            let name = tokens.next().ok_or(ParseError::MissingArgument)?;
+            match (Level::from_name(name), tokens.next()) {
+                (Some(level), None) => Command::Log(level),
+                (None, Some(level)) => Command::LogModule(
+                    Module::from_name(name).ok_or(ParseError::InvalidArgument)?,
+                    Level::from_name(level).ok_or(ParseError::InvalidArgument)?,
+                ),
+                (None, None) => return Err(ParseError::InvalidArgument),
+                (Some(_), Some(_)) => return Err(ParseError::TrailingInput),
+            }
+        }
+        "stats" => Command::Stats,
+        #[cfg(feature = "profile")]
+        "profile" => match tokens.next() {
+            None => Command::Profile,
+            Some("reset") => Command::ProfileReset,
+            Some(_) => return Err(ParseError::InvalidArgument),
+        },
+        "screenshot" => Command::Screenshot,
+        "demo" => Command::Demo,
+        "replay" => Command::Replay,
+        "reset" => Command::Reset,
+        "dfu" => Command::Dfu,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+
+    match tokens.next() {
+        Some(_) => Err(ParseError::TrailingInput),
+        None => Ok(command),
+    }
+}
+
+fn on_off(token: Option<&str>) -> Result<bool, ParseError> {
+    match token.ok_or(ParseError::MissingArgument)? {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(ParseError::InvalidArgument),
+    }
+}
+
+fn number(token: Option<&str>) -> Result<u8, ParseError> {
+    token
+        .ok_or(ParseError::MissingArgument)?
+        .parse()
+        .map_err(|_| ParseError::InvalidArgument)
+}
+
+// Accumulates bytes until a newline, without ever holding more than LINE_LEN
+// of them. Overlong lines are dropped in their entirety rather than parsed
+// truncated.
+pub struct LineBuffer {
+    buf: [u8; LINE_LEN],
+    len: usize,
+    overflowed: bool,
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        LineBuffer {
+            buf: [0; LINE_LEN],
+            len: 0,
+            overflowed: false,
+        }
+    }
+
+    // Returns the parsed command once a full line has been received
+    pub fn push(&mut self, byte: u8) -> Option<Result<Command, ParseError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let result = if self.overflowed {
+                    Err(ParseError::LineTooLong)
+                } else {
+                    core::str::from_utf8(&self.buf[..self.len])
+                        .map_err(|_| ParseError::NotUtf8)
+                        .and_then(parse)
+                };
+                let empty = self.len == 0 && !self.overflowed;
+                self.len = 0;
+                self.overflowed = false;
+
+                // Swallow blank lines so that "\r\n" doesn't produce an error
+                if empty {
+                    None
+                } else {
+                    Some(result)
+                }
+            }
+            _ if self.len == LINE_LEN => {
+                self.overflowed = true;
+                None
+            }
+            _ => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(buffer: &mut LineBuffer, bytes: &[u8]) -> Option<Result<Command, ParseError>> {
+        let mut last = None;
+        for &byte in bytes {
+            if let Some(result) = buffer.push(byte) {
+                assert!(last.is_none(), "more than one line in {:?}", bytes);
+                last = Some(result);
+            }
+        }
+        last
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("set brightness 200"), Ok(Command::SetBrightness(200)));
+        assert_eq!(parse("set brightness auto"), Ok(Command::AutoBrightness));
+        assert_eq!(
+            parse("effect stars"),
+            Ok(Command::Effect(Effect::Starfield))
+        );
+        assert_eq!(parse("spawn 3"), Ok(Command::Spawn(3)));
+        assert_eq!(parse("stats"), Ok(Command::Stats));
+        assert_eq!(parse("set clear 1 2 3"), Ok(Command::ClearColor(1, 2, 3)));
+        assert_eq!(parse("log warn"), Ok(Command::Log(Level::Warn)));
+        assert_eq!(parse("vignette off"), Ok(Command::Vignette(false)));
+    }
+
+    #[test]
+    fn ignores_extra_whitespace() {
+        assert_eq!(
+            parse("  set \t brightness   7 "),
+            Ok(Command::SetBrightness(7))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+        assert_eq!(parse("frobnicate"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("set"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("set brightness"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("set nothing 1"), Err(ParseError::InvalidArgument));
+        assert_eq!(
+            parse("set brightness 256"),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(parse("set brightness -1"), Err(ParseError::InvalidArgument));
+        assert_eq!(
+            parse("set brightness ten"),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(parse("set clear 1 2"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("effect"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("effect lasers"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("grid maybe"), Err(ParseError::InvalidArgument));
+        assert_eq!(parse("stats now"), Err(ParseError::TrailingInput));
+        assert_eq!(parse("spawn 1 2"), Err(ParseError::TrailingInput));
+        assert_eq!(parse("log warn info"), Err(ParseError::TrailingInput));
+        assert_eq!(parse("log loud"), Err(ParseError::InvalidArgument));
+        assert_eq!(
+            parse("log a-module-name-too-long warn"),
+            Err(ParseError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn line_buffer_waits_for_a_newline() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(feed(&mut buffer, b"stats"), None);
+        assert_eq!(feed(&mut buffer, b"\n"), Some(Ok(Command::Stats)));
+        assert_eq!(feed(&mut buffer, b"spawn 2\r"), Some(Ok(Command::Spawn(2))));
+    }
+
+    #[test]
+    fn line_buffer_swallows_blank_lines() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(feed(&mut buffer, b"\n"), None);
+        assert_eq!(feed(&mut buffer, b"\r\n\r\n"), None);
+        // The \n of a \r\n ending isn't a second, blank, command
+        assert_eq!(feed(&mut buffer, b"demo\r\n"), Some(Ok(Command::Demo)));
+        // Only whitespace is a line, just not a command
+        assert_eq!(feed(&mut buffer, b"  \n"), Some(Err(ParseError::Empty)));
+    }
+
+    #[test]
+    fn line_buffer_drops_overlong_lines_whole() {
+        let mut buffer = LineBuffer::new();
+        let mut line = [b'x'; LINE_LEN + 10];
+        line[..6].copy_from_slice(b"stats ");
+        assert_eq!(feed(&mut buffer, &line), None);
+        assert_eq!(feed(&mut buffer, b"\n"), Some(Err(ParseError::LineTooLong)));
+        // And starts over afterwards
+        assert_eq!(feed(&mut buffer, b"stats\n"), Some(Ok(Command::Stats)));
+    }
+
+    #[test]
+    fn line_buffer_takes_a_line_of_exactly_line_len() {
+        let mut buffer = LineBuffer::new();
+        let mut line = [b' '; LINE_LEN];
+        line[..5].copy_from_slice(b"stats");
+        assert_eq!(feed(&mut buffer, &line), None);
+        assert_eq!(feed(&mut buffer, b"\n"), Some(Ok(Command::Stats)));
+    }
+
+    #[test]
+    fn line_buffer_rejects_bad_utf8() {
+        let mut buffer = LineBuffer::new();
+        assert_eq!(
+            feed(&mut buffer, b"st\xffats\n"),
+            Some(Err(ParseError::NotUtf8))
+        );
+    }
+}
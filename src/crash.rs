@@ -0,0 +1,233 @@
+use crate::clock::CPU_HZ;
+#[cfg(feature = "flash")]
+use crate::crc;
+use crate::display;
+use crate::layout::MAX_PLAYFIELDS;
+use crate::limits::Limits;
+#[cfg(feature = "flash")]
+use crate::storage::{Page, Storage};
+use crate::text;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+use embedded_hal::digital::v2::OutputPin;
+use nrf52840_hal::gpio::{Output, Pin, PushPull};
+use nrf52840_pac::{spim0, SPIM1};
+
+// What a panic leaves behind for when there's no debugger to read RTT: where
+// it happened and what it said, on the panel, and with `flash` in a page of
+// its own that the next boot logs and erases.
+//
+// The panel is written through SPIM1's registers, the way the pipeline does
+// it, one transfer at a time with interrupts off. Whatever the driver or a
+// frame was in the middle of is abandoned, which leaves the panel half way
+// through something, but CASET, RASET and RAMWR always start afresh.
+
+const W: usize = Limits::SCREEN_WIDTH;
+const H: usize = Limits::SCREEN_HEIGHT;
+const COLUMNS: usize = W / text::ADVANCE as usize;
+// Less the heading
+pub const TEXT_LEN: usize = COLUMNS * (H / text::LINE_H as usize - 1);
+
+// White on dark red, in the panel's BGR
+const TEXT: u16 = 0xffff;
+const BACKGROUND: u16 = 12;
+
+const CASET: u8 = 0x2A;
+const RASET: u8 = 0x2B;
+const RAMWR: u8 = 0x2C;
+
+// Packed x << 16 | y for each tile, or NO_TILE
+const NO_TILE: u32 = u32::MAX;
+static TILES: [AtomicU32; MAX_PLAYFIELDS] = [const { AtomicU32::new(NO_TILE) }; MAX_PLAYFIELDS];
+// Nothing's drawn until there's a DC pin
+static DC: AtomicU32 = AtomicU32::new(NO_TILE);
+#[cfg(feature = "shared-spi")]
+static CS: AtomicU32 = AtomicU32::new(NO_TILE);
+
+// Where the panel is, for `report` to draw on: the driver's DC pin, and the
+// tiles of a `W` by `H` image on a `panel`, the layout's playfields. Tiles
+// that don't fit are left out.
+pub fn set_screen(dc: &Pin<Output<PushPull>>, panel: (u16, u16), tiles: &[(u16, u16)]) {
+    for (slot, &offset) in TILES.iter().zip(tiles) {
+        let packed = match display::clamp_offset(panel, offset, (W as u16, H as u16)) {
+            Ok((x, y)) => (x as u32) << 16 | y as u32,
+            Err(_) => NO_TILE,
+        };
+        slot.store(packed, Ordering::Relaxed);
+    }
+    DC.store(dc.psel_bits(), Ordering::Relaxed);
+}
+
+// The display's chip select, when it shares the bus
+#[cfg(feature = "shared-spi")]
+pub fn set_chip_select(cs: &Pin<Output<PushPull>>) {
+    CS.store(cs.psel_bits(), Ordering::Relaxed);
+}
+
+// The panic's location and message, as much as fits, ASCII only so it can be
+// read back as a str
+struct Message {
+    bytes: [u8; TEXT_LEN],
+    len: usize,
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if self.len == TEXT_LEN {
+                break;
+            }
+            self.bytes[self.len] = if byte.is_ascii() { byte } else { b'?' };
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+// For the panic handler, with interrupts already off
+pub fn report(info: &PanicInfo) {
+    let mut message = Message {
+        bytes: [0; TEXT_LEN],
+        len: 0,
+    };
+    if let Some(location) = info.location() {
+        write!(message, "{}:{} ", location.file(), location.line()).ok();
+    }
+    write!(message, "{}", info.message()).ok();
+    let text = &message.bytes[..message.len];
+
+    #[cfg(feature = "flash")]
+    save(text);
+    draw(text);
+}
+
+// Magic, length, the text, then a CRC32 of all that
+#[cfg(feature = "flash")]
+const MAGIC: [u8; 2] = *b"PN";
+#[cfg(feature = "flash")]
+const RECORD_LEN: usize = MAGIC.len() + 1 + TEXT_LEN + 4;
+
+#[cfg(feature = "flash")]
+fn save(text: &[u8]) {
+    let mut record = [0; RECORD_LEN];
+    record[..2].copy_from_slice(&MAGIC);
+    record[2] = text.len() as u8;
+    record[3..3 + text.len()].copy_from_slice(text);
+    let end = 3 + text.len();
+    let crc = crc::crc32(&record[..end]);
+    record[end..end + 4].copy_from_slice(&crc.to_le_bytes());
+
+    // Whoever owned NVMC isn't getting it back. If this doesn't take there's
+    // nobody left to tell.
+    let nvmc = unsafe { nrf52840_pac::Peripherals::steal() }.NVMC;
+    Storage::new(nvmc).write(Page::Panic, &record[..end + 4]).ok();
+}
+
+// Logs the panic the last boot saved, if there is one, and erases it so it's
+// only logged the once
+#[cfg(feature = "flash")]
+pub fn load(storage: &mut Storage) {
+    let page = storage.read(Page::Panic);
+    let len = page[2] as usize;
+    if page[..2] != MAGIC || len > TEXT_LEN {
+        return;
+    }
+    let end = 3 + len;
+    let stored = u32::from_le_bytes([page[end], page[end + 1], page[end + 2], page[end + 3]]);
+    if crc::crc32(&page[..end]) == stored {
+        let text = core::str::from_utf8(&page[3..end]).unwrap_or("?");
+        log_error!("Panicked before this boot: {}", text);
+    }
+    storage.erase(Page::Panic);
+}
+
+// The text a line at a time under a heading, then the rest of the screen
+// blank, each strip sent to every tile
+fn draw(text: &[u8]) {
+    let dc = DC.load(Ordering::Relaxed);
+    if dc == NO_TILE {
+        return;
+    }
+    let mut dc = unsafe { Pin::<Output<PushPull>>::from_psel_bits(dc) };
+    let spim = unsafe { &*SPIM1::ptr() };
+    stop(spim);
+    #[cfg(feature = "shared-spi")]
+    match CS.load(Ordering::Relaxed) {
+        NO_TILE => (),
+        cs => {
+            unsafe { Pin::<Output<PushPull>>::from_psel_bits(cs) }
+                .set_low()
+                .ok();
+        }
+    }
+
+    let mut lines = core::iter::once(&b"PANIC"[..]).chain(text.chunks(COLUMNS));
+    let mut strip = [0u8; W * text::LINE_H as usize * 2];
+    for top in (0..H).step_by(text::LINE_H as usize) {
+        let h = (text::LINE_H as usize).min(H - top);
+        for pixel in strip.chunks_exact_mut(2) {
+            pixel.copy_from_slice(&BACKGROUND.to_le_bytes());
+        }
+        if let Some(line) = lines.next() {
+            text::draw(&mut strip, 0, 0, line, TEXT);
+        }
+        // The panel takes them big-endian
+        for pixel in strip.chunks_exact_mut(2) {
+            pixel.swap(0, 1);
+        }
+
+        for tile in &TILES {
+            let tile = tile.load(Ordering::Relaxed);
+            if tile == NO_TILE {
+                continue;
+            }
+            let (x, y) = ((tile >> 16) as u16, tile as u16 + top as u16);
+            let [x0h, x0l] = x.to_be_bytes();
+            let [x1h, x1l] = (x + W as u16 - 1).to_be_bytes();
+            let [y0h, y0l] = y.to_be_bytes();
+            let [y1h, y1l] = (y + h as u16 - 1).to_be_bytes();
+            command(spim, &mut dc, CASET, &[x0h, x0l, x1h, x1l]);
+            command(spim, &mut dc, RASET, &[y0h, y0l, y1h, y1l]);
+            command(spim, &mut dc, RAMWR, &strip[..W * h * 2]);
+        }
+    }
+}
+
+// Ends whatever transfer was going, giving it up to 10 ms to say so, and
+// keeps SPIM1's interrupt out of it
+fn stop(spim: &spim0::RegisterBlock) {
+    spim.intenclr.write(|w| unsafe { w.bits(u32::MAX) });
+    spim.events_stopped.reset();
+    spim.tasks_stop.write(|w| unsafe { w.bits(1) });
+    for _ in 0..CPU_HZ / 100 {
+        if spim.events_stopped.read().bits() != 0 {
+            break;
+        }
+    }
+    spim.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(0) });
+}
+
+// `params` must be in RAM, which EasyDMA reads from, and fit one transfer
+fn command(spim: &spim0::RegisterBlock, dc: &mut Pin<Output<PushPull>>, cmd: u8, params: &[u8]) {
+    let cmd = [cmd];
+    dc.set_low().ok();
+    transfer(spim, &cmd);
+    dc.set_high().ok();
+    transfer(spim, params);
+}
+
+fn transfer(spim: &spim0::RegisterBlock, bytes: &[u8]) {
+    compiler_fence(Ordering::SeqCst);
+    spim.txd
+        .ptr
+        .write(|w| unsafe { w.ptr().bits(bytes.as_ptr() as u32) });
+    spim.txd
+        .maxcnt
+        .write(|w| unsafe { w.maxcnt().bits(bytes.len() as u16) });
+    spim.events_end.reset();
+    spim.tasks_start.write(|w| unsafe { w.bits(1) });
+    while spim.events_end.read().bits() == 0 {}
+    spim.events_end.reset();
+    compiler_fence(Ordering::SeqCst);
+}
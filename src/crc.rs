@@ -0,0 +1,119 @@
+// CRC-32 as used by zlib and Ethernet: reflected, polynomial 0xEDB88320,
+// starting from and finished with all ones. The nRF52840's only CRC hardware
+// is inside the radio, so this is a byte at a time from a 1 KiB table, which
+// is built at compile time.
+
+const POLY: u32 = 0xEDB8_8320;
+
+const TABLE: [u32; 256] = table();
+
+const fn table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+// For data that arrives in pieces. Feeding it all to `update`, however it's
+// split up, gives the same result as `crc32` over the whole.
+#[derive(Clone, Copy)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Crc32 { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            let index = (self.state ^ byte as u32) & 0xFF;
+            self.state = (self.state >> 8) ^ TABLE[index as usize];
+        }
+    }
+
+    pub fn finish(self) -> u32 {
+        !self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From zlib's crc32, which is the same CRC
+    const VECTORS: [(&[u8], u32); 6] = [
+        (b"", 0),
+        (b"a", 0xE8B7_BE43),
+        (b"abc", 0x3524_41C2),
+        (b"123456789", 0xCBF4_3926),
+        (b"message digest", 0x2015_9D7F),
+        (b"The quick brown fox jumps over the lazy dog", 0x414F_A339),
+    ];
+
+    #[test]
+    fn known_vectors() {
+        for &(data, crc) in &VECTORS {
+            assert_eq!(crc32(data), crc, "{:?}", core::str::from_utf8(data));
+        }
+        assert_eq!(crc32(&[0; 32]), 0x190A_55AD);
+        assert_eq!(crc32(&[0xFF; 32]), 0xFF6C_AB0B);
+    }
+
+    #[test]
+    fn the_table_matches_the_polynomial() {
+        assert_eq!(TABLE[0], 0);
+        assert_eq!(TABLE[1], 0x7707_3096);
+        assert_eq!(TABLE[128], POLY);
+        assert_eq!(TABLE[255], 0x2D02_EF8D);
+    }
+
+    #[test]
+    fn pieces_give_the_same_as_the_whole() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let whole = crc32(&data);
+        for split in [0, 1, 7, 500, 999, 1000].iter().copied() {
+            let mut crc = Crc32::new();
+            crc.update(&data[..split]);
+            crc.update(&data[split..]);
+            assert_eq!(crc.finish(), whole, "split at {}", split);
+        }
+
+        let mut crc = Crc32::new();
+        for chunk in data.chunks(3) {
+            crc.update(chunk);
+            crc.update(&[]);
+        }
+        assert_eq!(crc.finish(), whole);
+    }
+
+    #[test]
+    fn any_one_bit_flip_is_caught() {
+        let mut data = *b"123456789";
+        for i in 0..data.len() * 8 {
+            data[i / 8] ^= 1 << (i % 8);
+            assert_ne!(crc32(&data), 0xCBF4_3926);
+            data[i / 8] ^= 1 << (i % 8);
+        }
+    }
+}
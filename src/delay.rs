@@ -0,0 +1,15 @@
+use nrf52840_hal::timer::{OneShot, Timer};
+use nrf52840_pac::TIMER0;
+
+// The blocking delay used during bring-up (display reset and init, mostly).
+// It runs off TIMER0 rather than SysTick so that SYST is never tied up: it's
+// left alone in `init` for whatever wants it as a monotonic or tick source.
+// Once bring-up is done, `free` hands TIMER0 back.
+//
+// To move delays to another timer, change this alias and `new`; nothing else
+// names the concrete type.
+pub type Delay = Timer<TIMER0, OneShot>;
+
+pub fn new(timer: TIMER0) -> Delay {
+    Timer::one_shot(timer)
+}
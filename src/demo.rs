@@ -0,0 +1,223 @@
+use crate::game::{Input, State, World, MAX_SHIP_SPEED, WIDTH};
+use crate::wave::{Formation, Shape};
+
+// A canned bit of gameplay, for videos and for screenshots that should come
+// out the same every time. Rather than recorded inputs, it's a script of
+// what should happen: enemies come in where it says, and the ship is steered
+// to where it's told and fires when it's told. The game itself still plays
+// out as normal around that, from the same seed each time, so the result is
+// always the same.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Spawn(Formation),
+    // Steer the ship until it's centered on this column
+    MoveTo(i32),
+    // Hold fire down, or let it go
+    Fire(bool),
+    // Back to the title screen
+    End,
+}
+
+pub struct Cue {
+    // Ticks since the demo started
+    pub at: u32,
+    pub action: Action,
+}
+
+const fn cue(at: u32, action: Action) -> Cue {
+    Cue { at, action }
+}
+
+const fn spawn(shape: Shape, count: u8, x: i32, hp: u8) -> Action {
+    Action::Spawn(Formation {
+        shape,
+        count,
+        x,
+        hp,
+    })
+}
+
+const MID: i32 = WIDTH / 2;
+
+pub const SCRIPT: &[Cue] = &[
+    cue(0, spawn(Shape::Row, 4, MID, 1)),
+    cue(20, Action::Fire(true)),
+    cue(30, Action::MoveTo(MID - 10)),
+    cue(60, Action::MoveTo(MID + 10)),
+    cue(90, Action::MoveTo(MID)),
+    cue(120, spawn(Shape::Vee, 5, MID, 2)),
+    cue(150, Action::MoveTo(MID - 8)),
+    cue(180, Action::MoveTo(MID + 8)),
+    cue(210, Action::MoveTo(MID)),
+    cue(240, Action::Fire(false)),
+    cue(260, spawn(Shape::Column, 3, WIDTH / 4, 1)),
+    cue(260, spawn(Shape::Column, 3, 3 * WIDTH / 4, 1)),
+    cue(270, Action::MoveTo(WIDTH / 4)),
+    cue(280, Action::Fire(true)),
+    cue(340, Action::MoveTo(3 * WIDTH / 4)),
+    cue(420, Action::MoveTo(MID)),
+    cue(440, Action::Fire(false)),
+    cue(500, Action::End),
+];
+
+pub struct Demo {
+    active: bool,
+    tick: u32,
+    next: usize,
+    target: i32,
+    fire: bool,
+}
+
+impl Demo {
+    pub const fn new() -> Self {
+        Demo {
+            active: false,
+            tick: 0,
+            next: 0,
+            target: MID,
+            fire: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // The caller starts `world` from a fixed seed, for the same run each
+    // time
+    pub fn start(&mut self, world: &mut World) {
+        *self = Demo::new();
+        self.active = true;
+        world.start();
+    }
+
+    // Carries out whatever's due this tick and returns the input to advance
+    // `world` with, or None once the demo is over and `world` is back on the
+    // title screen. The demo also ends early if the game does.
+    pub fn step(&mut self, world: &mut World) -> Option<Input> {
+        if !self.active {
+            return None;
+        }
+
+        while let Some(cue) = SCRIPT.get(self.next).filter(|cue| cue.at <= self.tick) {
+            self.next += 1;
+            match cue.action {
+                Action::Spawn(formation) => {
+                    world.spawn_formation(formation);
+                }
+                Action::MoveTo(x) => self.target = x,
+                Action::Fire(fire) => self.fire = fire,
+                Action::End => self.active = false,
+            }
+        }
+        if !self.active || world.state != State::Playing {
+            self.active = false;
+            *world = World::new();
+            return None;
+        }
+        self.tick += 1;
+        // It's for showing the game off, so it never gets down to its last
+        // life and ends early on a game over
+        world.lives = world.lives.max(2);
+
+        let (x, _) = world.ship.center();
+        // Whole pixels per frame of difference, rounding up so even one
+        // pixel is enough to move
+        let speed = (127 + MAX_SHIP_SPEED - 1) / MAX_SHIP_SPEED;
+        Some(Input {
+            x: ((self.target - x) * speed).clamp(-127, 127) as i8,
+            fire: self.fire,
+            ..Input::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{self, Events};
+    use crate::rng::Rng;
+
+    const END: u32 = 500;
+
+    // Plays the demo out as the frame task does, and returns how many
+    // frames it ran for and everything that happened in it
+    fn run(seed: u32) -> (u32, Events, World) {
+        let (mut world, mut rng, mut demo) = (World::new(), Rng::new(seed), Demo::new());
+        demo.start(&mut world);
+        let (mut frames, mut events) = (0, Events::default());
+        while let Some(input) = demo.step(&mut world) {
+            game::advance_frame(&mut world, input, &mut rng);
+            events.insert(world.events);
+            frames += 1;
+            assert!(frames <= END, "the demo never ended");
+        }
+        (frames, events, world)
+    }
+
+    #[test]
+    fn the_script_is_in_order_and_ends() {
+        assert!(SCRIPT.windows(2).all(|w| w[0].at <= w[1].at));
+        assert_eq!(
+            SCRIPT.last().map(|cue| (cue.at, cue.action)),
+            Some((END, Action::End))
+        );
+        assert_eq!(
+            SCRIPT
+                .iter()
+                .filter(|cue| cue.action == Action::End)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn the_demo_plays_to_the_end_and_back_to_the_title() {
+        // The seed the console's `demo` starts from
+        let (frames, events, world) = run(0x5EED);
+        assert_eq!(frames, END);
+        assert!(events.contains(Events::FIRED));
+        assert!(events.contains(Events::ENEMY_DESTROYED));
+        assert_eq!(world.state, State::Title);
+    }
+
+    #[test]
+    fn the_same_seed_plays_the_same_demo() {
+        let score = |seed| {
+            let (mut world, mut rng, mut demo) = (World::new(), Rng::new(seed), Demo::new());
+            demo.start(&mut world);
+            let mut scores = Vec::new();
+            while let Some(input) = demo.step(&mut world) {
+                game::advance_frame(&mut world, input, &mut rng);
+                scores.push((world.score, world.ship.x));
+            }
+            scores
+        };
+        assert_eq!(score(7), score(7));
+    }
+
+    #[test]
+    fn the_ship_goes_where_it_is_told() {
+        let (mut world, mut rng, mut demo) = (World::new(), Rng::new(1), Demo::new());
+        demo.start(&mut world);
+        // Told to go to MID - 10 at 30, and it has until 60
+        for _ in 0..59 {
+            let input = demo.step(&mut world).unwrap();
+            game::advance_frame(&mut world, input, &mut rng);
+        }
+        assert_eq!(world.ship.center().0, MID - 10);
+    }
+
+    #[test]
+    fn the_demo_ends_early_if_the_game_does() {
+        let (mut world, mut demo) = (World::new(), Demo::new());
+        demo.start(&mut world);
+        assert!(demo.step(&mut world).is_some());
+        world.state = State::GameOver;
+        assert_eq!(demo.step(&mut world), None);
+        assert!(!demo.is_active());
+        assert_eq!(world.state, State::Title);
+        assert_eq!(demo.step(&mut world), None);
+    }
+}
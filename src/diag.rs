@@ -0,0 +1,62 @@
+use core::ptr;
+
+// Free stack is filled with this at boot. Any word that no longer holds it has
+// been written by the stack at some point.
+const PAINT: u32 = 0xDEAD_BEEF;
+
+// Leave this much below the live stack pointer untouched while painting, to
+// cover the painting loop's own spills
+const PAINT_MARGIN: usize = 64;
+
+extern "C" {
+    // Provided by cortex-m-rt's link.x. The stack grows down from
+    // `_stack_start` towards `_stack_end`, which sits right after .bss/.uninit.
+    static _stack_start: u32;
+    static _stack_end: u32;
+    static __sdata: u32;
+    static __sheap: u32;
+}
+
+fn stack_bounds() -> (usize, usize) {
+    (
+        ptr::addr_of!(_stack_end) as usize,
+        ptr::addr_of!(_stack_start) as usize,
+    )
+}
+
+// Must be called once, early in init, while interrupts are still disabled
+pub fn paint_stack() {
+    let (bottom, _) = stack_bounds();
+    let sp = cortex_m::register::msp::read() as usize;
+    let mut p = bottom as *mut u32;
+    let end = (sp - PAINT_MARGIN) as *mut u32;
+
+    while p < end {
+        unsafe {
+            ptr::write_volatile(p, PAINT);
+            p = p.add(1);
+        }
+    }
+}
+
+pub fn stack_size() -> usize {
+    let (bottom, top) = stack_bounds();
+    top - bottom
+}
+
+// Deepest the stack has reached since boot, in bytes
+pub fn stack_high_water() -> usize {
+    let (bottom, top) = stack_bounds();
+    let mut p = bottom as *const u32;
+
+    while (p as usize) < top && unsafe { ptr::read_volatile(p) } == PAINT {
+        p = unsafe { p.add(1) };
+    }
+
+    top - p as usize
+}
+
+// .data + .bss + .uninit
+pub fn static_ram() -> usize {
+    ptr::addr_of!(__sheap) as usize - ptr::addr_of!(__sdata) as usize
+}
@@ -0,0 +1,485 @@
+use crate::compat::Legacy;
+use crate::layout::{self, Region, Screen};
+#[cfg(feature = "st7789")]
+use crate::st7789::St7789;
+#[cfg(not(feature = "st7789"))]
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use embedded_hal::blocking::spi;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::digital::OutputPin as OutputPin1;
+use embedded_hal_1::spi::SpiBus;
+use nrf52840_hal::spim::{self, Phase, Polarity};
+use st7735_lcd::{Orientation, ST7735};
+
+// What the firmware needs from a panel's controller, whichever one it is.
+// Windows are in the controller's memory, less the offset.
+pub trait DisplayDriver {
+    // Everything after the reset pulse, which `init` below sends first
+    fn init(&mut self, delay: &mut impl DelayNs) -> Result<(), ()>;
+    fn set_orientation(&mut self, orientation: &Orientation) -> Result<(), ()>;
+    // Added to every window from now on
+    fn set_offset(&mut self, x: u16, y: u16);
+    fn set_window(&mut self, x0: u16, y0: u16, x1: u16, y1: u16) -> Result<(), ()>;
+    // Sends RAMWR and then `pixels` into the window, leaving DC high, so with
+    // no pixels at all the data can follow some other way
+    fn write_pixels(&mut self, pixels: impl IntoIterator<Item = u16>) -> Result<(), ()>;
+}
+
+impl<SPI, DC, RST> DisplayDriver for ST7735<SPI, DC, RST>
+where
+    SPI: spi::Write<u8>,
+    DC: OutputPin,
+    RST: OutputPin,
+{
+    fn init(&mut self, delay: &mut impl DelayNs) -> Result<(), ()> {
+        ST7735::init(self, &mut Legacy(delay))
+    }
+
+    fn set_orientation(&mut self, orientation: &Orientation) -> Result<(), ()> {
+        ST7735::set_orientation(self, orientation)
+    }
+
+    fn set_offset(&mut self, x: u16, y: u16) {
+        ST7735::set_offset(self, x, y)
+    }
+
+    fn set_window(&mut self, x0: u16, y0: u16, x1: u16, y1: u16) -> Result<(), ()> {
+        self.set_address_window(x0, y0, x1, y1)
+    }
+
+    fn write_pixels(&mut self, pixels: impl IntoIterator<Item = u16>) -> Result<(), ()> {
+        self.write_pixels_buffered(pixels)
+    }
+}
+
+// The panel on any SPI bus and DC pin with embedded-hal 1.0 implementations.
+// The ST7735 driver itself only knows 0.2, so it gets them through `Legacy`.
+#[cfg(not(feature = "st7789"))]
+pub type Display<SPI, DC> = ST7735<Legacy<SPI>, Legacy<DC>, NoPin>;
+#[cfg(feature = "st7789")]
+pub type Display<SPI, DC> = St7789<SPI, DC>;
+
+// `rgb` and `inverted` come from `config`; `width` and `height` are the size
+// of the image that's sent, not of the panel
+#[cfg_attr(feature = "st7789", allow(unused_variables))]
+pub fn new<SPI, DC>(
+    spi: SPI,
+    dc: DC,
+    config: &DisplayConfig,
+    width: u32,
+    height: u32,
+) -> Display<SPI, DC>
+where
+    SPI: SpiBus,
+    DC: OutputPin1,
+{
+    let (rgb, inverted) = (config.rgb, config.inverted);
+    #[cfg(not(feature = "st7789"))]
+    let disp = ST7735::new(Legacy(spi), Legacy(dc), NoPin, rgb, inverted, width, height);
+    #[cfg(feature = "st7789")]
+    let disp = St7789::new(spi, dc, rgb, inverted);
+    disp
+}
+
+// Everything that tends to differ between ST7735 modules from different
+// vendors.
+//
+// Reset timing: the datasheet asks for RESX low for at least 10 us and up to
+// 120 ms before the controller accepts commands again, but modules with weak
+// supply decoupling have been seen to need a longer low pulse. Marginal
+// values show up as intermittent init failures rather than a dead panel, so
+// err on the long side.
+#[derive(Clone, Copy)]
+pub struct DisplayConfig {
+    pub spi_mode: spim::Mode,
+    pub spi_frequency: spim::Frequency,
+    // How long RST is held low
+    pub reset_low_ms: u16,
+    // How long to wait after releasing RST before sending the first command
+    pub reset_settle_ms: u16,
+    // Wait before touching the panel at all, for modules that come up slowly
+    // from cold
+    pub boot_delay_ms: u16,
+    // Total number of reset + init sequences to try before giving up
+    pub init_attempts: u8,
+    // Extra wait before each retry, multiplied by the retry number so that
+    // later attempts give the panel progressively longer to come up
+    pub init_retry_delay_ms: u16,
+    pub rgb: bool,
+    pub inverted: bool,
+    pub orientation: Orientation,
+    // Where the glass starts in the controller's memory, in (columns, rows)
+    // for `orientation`. Most panels are smaller than the controller's memory
+    // and sit somewhere inside it, by an amount that's up to the module vendor;
+    // see the README for how to find it.
+    pub panel_offset: (u16, u16),
+    // The glass's own size from there, which the layout is checked against
+    pub panel_size: (u16, u16),
+    // What goes where on the glass, see layout.rs
+    pub layout: &'static [Region],
+}
+
+impl DisplayConfig {
+    pub const DEFAULT: DisplayConfig = DisplayConfig {
+        spi_mode: spim::MODE_0,
+        spi_frequency: spim::Frequency::M8,
+        reset_low_ms: 10,
+        reset_settle_ms: 120,
+        boot_delay_ms: 0,
+        init_attempts: 2,
+        init_retry_delay_ms: 100,
+        rgb: true,
+        inverted: false,
+        orientation: Orientation::LandscapeSwapped,
+        panel_offset: (0, 0),
+        panel_size: if cfg!(feature = "st7789") { (240, 240) } else { (128, 128) },
+        layout: layout::SPLIT,
+    };
+
+    pub fn screen(&self) -> Screen {
+        Screen::new(self.layout, self.panel_offset, self.panel_size)
+    }
+
+    pub fn log(&self) {
+        log_info!(
+            "Display: SPI mode {} at {:?}, reset low {} ms, settle {} ms, boot delay {} ms, {} attempts",
+            mode_number(self.spi_mode),
+            self.spi_frequency,
+            self.reset_low_ms,
+            self.reset_settle_ms,
+            self.boot_delay_ms,
+            self.init_attempts
+        );
+    }
+}
+
+fn mode_number(mode: spim::Mode) -> u8 {
+    match (mode.polarity, mode.phase) {
+        (Polarity::IdleLow, Phase::CaptureOnFirstTransition) => 0,
+        (Polarity::IdleLow, Phase::CaptureOnSecondTransition) => 1,
+        (Polarity::IdleHigh, Phase::CaptureOnFirstTransition) => 2,
+        (Polarity::IdleHigh, Phase::CaptureOnSecondTransition) => 3,
+    }
+}
+
+// Runs the reset pulse described by `config`. The ST7735 driver is handed a
+// `NoPin` instead of the real reset line so that its own, fixed, reset timing
+// becomes a no-op.
+pub fn reset<P, D>(rst: &mut P, delay: &mut D, config: &DisplayConfig) -> Result<(), P::Error>
+where
+    P: OutputPin1,
+    D: DelayNs,
+{
+    rst.set_high()?;
+    delay.delay_ms(1);
+    rst.set_low()?;
+    delay.delay_ms(config.reset_low_ms as u32);
+    rst.set_high()?;
+    delay.delay_ms(config.reset_settle_ms as u32);
+    Ok(())
+}
+
+// Resets and initializes the panel, retrying as configured. Returns the
+// number of attempts it took.
+pub fn init<SPI, DC, RST, D>(
+    disp: &mut Display<SPI, DC>,
+    rst: &mut RST,
+    delay: &mut D,
+    config: &DisplayConfig,
+) -> Result<u8, ()>
+where
+    SPI: SpiBus,
+    DC: OutputPin1,
+    RST: OutputPin1,
+    D: DelayNs,
+{
+    delay.delay_ms(config.boot_delay_ms as u32);
+
+    for attempt in 1..=config.init_attempts.max(1) {
+        if attempt > 1 {
+            let wait = config.init_retry_delay_ms * (attempt - 1) as u16;
+            log_warn!("Display init attempt {} failed, retrying in {} ms", attempt - 1, wait);
+            delay.delay_ms(wait as u32);
+        }
+
+        if reset(rst, delay, config).is_ok() && DisplayDriver::init(disp, delay).is_ok() {
+            DisplayDriver::set_orientation(disp, &config.orientation)?;
+            return Ok(attempt);
+        }
+    }
+
+    log_error!("Display init failed after {} attempts", config.init_attempts);
+    Err(())
+}
+
+// What it takes to bring the panel up from scratch again once it's booted:
+// after powering off, or once it's stopped taking frames
+pub struct Bringup<RST, D> {
+    rst: RST,
+    delay: D,
+    config: DisplayConfig,
+}
+
+impl<RST: OutputPin1, D: DelayNs> Bringup<RST, D> {
+    pub fn new(rst: RST, delay: D, config: DisplayConfig) -> Self {
+        Bringup { rst, delay, config }
+    }
+
+    pub fn reset(&mut self) -> Result<(), RST::Error> {
+        reset(&mut self.rst, &mut self.delay, &self.config)
+    }
+
+    // `init` again, which leaves the panel in the orientation it booted in
+    pub fn init<SPI, DC>(&mut self, disp: &mut Display<SPI, DC>) -> Result<u8, ()>
+    where
+        SPI: SpiBus,
+        DC: OutputPin1,
+    {
+        init(disp, &mut self.rst, &mut self.delay, &self.config)
+    }
+
+    pub fn config(&self) -> &DisplayConfig {
+        &self.config
+    }
+}
+
+// Columns and rows of the controller's frame memory, when upright. Addresses
+// past the end wrap around rather than being refused, so an image that runs
+// off it comes out on the other side.
+#[cfg(not(feature = "st7789"))]
+const RAM_WIDTH: u16 = 132;
+#[cfg(not(feature = "st7789"))]
+const RAM_HEIGHT: u16 = 162;
+#[cfg(feature = "st7789")]
+const RAM_WIDTH: u16 = 240;
+#[cfg(feature = "st7789")]
+const RAM_HEIGHT: u16 = 320;
+
+pub fn ram_size(orientation: Orientation) -> (u16, u16) {
+    match orientation {
+        Orientation::Portrait | Orientation::PortraitSwapped => (RAM_WIDTH, RAM_HEIGHT),
+        Orientation::Landscape | Orientation::LandscapeSwapped => (RAM_HEIGHT, RAM_WIDTH),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OffsetError {
+    // Bigger than the panel, so there's no offset it would fit at
+    TooLarge,
+}
+
+// Moves `offset` back as far as it takes for an image of `size` to fit on a
+// `panel`, each in (columns, rows)
+pub fn clamp_offset(
+    panel: (u16, u16),
+    offset: (u16, u16),
+    size: (u16, u16),
+) -> Result<(u16, u16), OffsetError> {
+    if size.0 > panel.0 || size.1 > panel.1 {
+        return Err(OffsetError::TooLarge);
+    }
+    Ok((offset.0.min(panel.0 - size.0), offset.1.min(panel.1 - size.1)))
+}
+
+// Set once an offset has had to be clamped, so a bad layout is only reported
+// once rather than for every frame
+static CLAMPED: AtomicBool = AtomicBool::new(false);
+
+// `DisplayDriver::set_offset`, for an image of `size`, that won't let the
+// image run off the panel. Offsets that would are clamped with a warning.
+pub fn set_offset(
+    disp: &mut impl DisplayDriver,
+    panel: (u16, u16),
+    offset: (u16, u16),
+    size: (u16, u16),
+) -> Result<(), OffsetError> {
+    let clamped = checked_offset(panel, offset, size)?;
+    disp.set_offset(clamped.0, clamped.1);
+    Ok(())
+}
+
+// Blacks out the whole of a `panel`, gaps and all, leaving the offset at 0
+pub fn clear(disp: &mut impl DisplayDriver, panel: (u16, u16)) -> Result<(), ()> {
+    disp.set_offset(0, 0);
+    disp.set_window(0, 0, panel.0 - 1, panel.1 - 1)?;
+    let pixels = panel.0 as usize * panel.1 as usize;
+    disp.write_pixels(core::iter::repeat_n(0, pixels))
+}
+
+// `clamp_offset`, warning the first time anything doesn't fit
+pub fn checked_offset(
+    panel: (u16, u16),
+    offset: (u16, u16),
+    size: (u16, u16),
+) -> Result<(u16, u16), OffsetError> {
+    let clamped = match clamp_offset(panel, offset, size) {
+        Ok(clamped) => clamped,
+        Err(err) => {
+            if !CLAMPED.swap(true, Ordering::Relaxed) {
+                let (w, h) = size;
+                log_warn!("{}x{} image doesn't fit on a {}x{} panel", w, h, panel.0, panel.1);
+            }
+            return Err(err);
+        }
+    };
+    if clamped != offset && !CLAMPED.swap(true, Ordering::Relaxed) {
+        log_warn!(
+            "Offset ({}, {}) runs a {}x{} image off the panel, using ({}, {})",
+            offset.0,
+            offset.1,
+            size.0,
+            size.1,
+            clamped.0,
+            clamped.1
+        );
+    }
+    Ok(clamped)
+}
+
+// A level from `set_brightness` that the frame task hasn't picked up yet, or
+// NO_BRIGHTNESS
+static BRIGHTNESS: AtomicU16 = AtomicU16::new(NO_BRIGHTNESS);
+const NO_BRIGHTNESS: u16 = u16::MAX;
+
+// Sets the backlight from anywhere, 0 off to 255 fully on. It changes at the
+// next frame, which turns automatic brightness off and keeps the level in
+// the settings, so it's saved with them. Boards without the `backlight`
+// feature still keep the setting.
+pub fn set_brightness(level: u8) {
+    BRIGHTNESS.store(level as u16, Ordering::Relaxed);
+}
+
+// The last level passed to `set_brightness` since this was last called
+pub fn take_brightness() -> Option<u8> {
+    match BRIGHTNESS.swap(NO_BRIGHTNESS, Ordering::Relaxed) {
+        NO_BRIGHTNESS => None,
+        level => Some(level as u8),
+    }
+}
+
+#[cfg(not(feature = "st7789"))]
+pub struct NoPin;
+
+#[cfg(not(feature = "st7789"))]
+impl OutputPin for NoPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brightness_is_taken_once_and_the_last_one_wins() {
+        assert_eq!(take_brightness(), None);
+        set_brightness(10);
+        set_brightness(0);
+        assert_eq!(take_brightness(), Some(0));
+        assert_eq!(take_brightness(), None);
+        set_brightness(255);
+        assert_eq!(take_brightness(), Some(255));
+    }
+
+    const PANEL: (u16, u16) = (160, 128);
+
+    // Only keeps the offset it was last given
+    struct Offset(Option<(u16, u16)>);
+
+    impl DisplayDriver for Offset {
+        fn init(&mut self, _: &mut impl DelayNs) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn set_orientation(&mut self, _: &Orientation) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn set_offset(&mut self, x: u16, y: u16) {
+            self.0 = Some((x, y));
+        }
+
+        fn set_window(&mut self, _: u16, _: u16, _: u16, _: u16) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn write_pixels(&mut self, _: impl IntoIterator<Item = u16>) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn offsets_that_fit_are_left_alone() {
+        let size = (64, 64);
+        for &offset in &[(0, 0), (10, 20), (96, 0), (0, 64), (96, 64)] {
+            assert_eq!(clamp_offset(PANEL, offset, size), Ok(offset));
+        }
+        // The whole panel, at the only offset it fits at
+        assert_eq!(clamp_offset(PANEL, (0, 0), PANEL), Ok((0, 0)));
+        assert_eq!(clamp_offset(PANEL, (160, 128), (0, 0)), Ok((160, 128)));
+    }
+
+    #[test]
+    fn offsets_past_the_edge_are_clamped_to_it() {
+        let size = (64, 64);
+        assert_eq!(clamp_offset(PANEL, (97, 0), size), Ok((96, 0)));
+        assert_eq!(clamp_offset(PANEL, (0, 65), size), Ok((0, 64)));
+        assert_eq!(clamp_offset(PANEL, (97, 65), size), Ok((96, 64)));
+        assert_eq!(
+            clamp_offset(PANEL, (u16::MAX, u16::MAX), size),
+            Ok((96, 64))
+        );
+        assert_eq!(clamp_offset(PANEL, (1, 1), PANEL), Ok((0, 0)));
+    }
+
+    #[test]
+    fn images_bigger_than_the_panel_have_no_offset() {
+        assert_eq!(
+            clamp_offset(PANEL, (0, 0), (161, 64)),
+            Err(OffsetError::TooLarge)
+        );
+        assert_eq!(
+            clamp_offset(PANEL, (0, 0), (64, 129)),
+            Err(OffsetError::TooLarge)
+        );
+
+        let mut disp = Offset(None);
+        assert_eq!(
+            set_offset(&mut disp, PANEL, (0, 0), (161, 129)),
+            Err(OffsetError::TooLarge)
+        );
+        assert_eq!(disp.0, None);
+    }
+
+    #[test]
+    fn set_offset_sends_the_clamped_offset() {
+        let mut disp = Offset(None);
+        assert_eq!(set_offset(&mut disp, PANEL, (100, 70), (64, 64)), Ok(()));
+        assert_eq!(disp.0, Some((96, 64)));
+        assert_eq!(set_offset(&mut disp, PANEL, (3, 4), (64, 64)), Ok(()));
+        assert_eq!(disp.0, Some((3, 4)));
+    }
+
+    #[test]
+    fn the_default_layout_fits_the_panel_either_way_round() {
+        // MIRRORED as well, since it's the widest
+        for region in DisplayConfig::DEFAULT.layout.iter().chain(layout::MIRRORED) {
+            let size = region.content.size();
+            for &orientation in &[Orientation::Landscape, Orientation::LandscapeSwapped] {
+                let (panel, offset) = (ram_size(orientation), region.offset);
+                assert_eq!(clamp_offset(panel, offset, size), Ok(offset));
+            }
+        }
+        let (w, h) = ram_size(Orientation::Portrait);
+        assert_eq!(ram_size(Orientation::Landscape), (h, w));
+    }
+}
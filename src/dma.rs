@@ -0,0 +1,124 @@
+use core::ops::Range;
+
+// EasyDMA on the nRF52840 counts bytes in a 16 bit MAXCNT, so one transfer
+// moves at most this many. Anything longer has to go out as several, or the
+// count wraps and the end of the buffer is quietly dropped.
+pub const MAX_TRANSFER: usize = (1 << 16) - 1;
+
+// The byte ranges to send `len` bytes as, in order, none longer than `max`.
+// There are none at all for an empty buffer.
+pub fn chunks(len: usize, max: usize) -> impl Iterator<Item = Range<usize>> {
+    (0..len)
+        .step_by(max)
+        .map(move |start| start..len.min(start + max))
+}
+
+#[derive(Debug)]
+pub enum TransferError<E> {
+    // A transfer finished having moved fewer bytes than it was given
+    Short { sent: usize, expected: usize },
+    Failed(E),
+}
+
+// Sends `bytes` through `send` in chunks of at most `max`, stopping at the
+// first one that fails or comes up short. `send` returns how many bytes the
+// hardware says it actually moved.
+pub fn write_chunked<E>(
+    bytes: &[u8],
+    max: usize,
+    mut send: impl FnMut(&[u8]) -> Result<usize, E>,
+) -> Result<(), TransferError<E>> {
+    for range in chunks(bytes.len(), max) {
+        let expected = range.len();
+        let sent = send(&bytes[range]).map_err(TransferError::Failed)?;
+        if sent != expected {
+            return Err(TransferError::Short { sent, expected });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_long_buffer_splits_into_full_transfers_and_the_rest() {
+        let ranges: Vec<_> = chunks(150_000, MAX_TRANSFER).collect();
+        assert_eq!(ranges, [0..65_535, 65_535..131_070, 131_070..150_000]);
+    }
+
+    #[test]
+    fn chunks_cover_the_buffer_exactly() {
+        for &(len, max) in &[
+            (1, 1),
+            (10, 3),
+            (9, 3),
+            (8192, MAX_TRANSFER),
+            (MAX_TRANSFER, MAX_TRANSFER),
+            (MAX_TRANSFER + 1, MAX_TRANSFER),
+        ] {
+            let ranges: Vec<_> = chunks(len, max).collect();
+            assert_eq!(ranges.len(), len.div_ceil(max), "{} in {}", len, max);
+            assert_eq!(ranges[0].start, 0);
+            assert_eq!(ranges.last().unwrap().end, len);
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+            }
+            assert!(ranges.iter().all(|r| !r.is_empty() && r.len() <= max));
+        }
+    }
+
+    #[test]
+    fn an_empty_buffer_has_no_chunks() {
+        assert_eq!(chunks(0, MAX_TRANSFER).count(), 0);
+    }
+
+    #[test]
+    fn writes_go_out_in_order() {
+        let bytes: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        let mut sent = Vec::new();
+        let result: Result<(), TransferError<()>> = write_chunked(&bytes, 300, |chunk| {
+            assert!(chunk.len() <= 300);
+            sent.extend_from_slice(chunk);
+            Ok(chunk.len())
+        });
+        assert!(result.is_ok());
+        assert_eq!(sent, bytes);
+    }
+
+    #[test]
+    fn a_short_transfer_stops_the_write() {
+        let mut calls = 0;
+        let result: Result<(), TransferError<()>> = write_chunked(&[0; 1000], 300, |chunk| {
+            calls += 1;
+            Ok(if calls == 2 {
+                chunk.len() - 1
+            } else {
+                chunk.len()
+            })
+        });
+        match result {
+            Err(TransferError::Short {
+                sent: 299,
+                expected: 300,
+            }) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn a_failed_transfer_stops_the_write() {
+        let mut calls = 0;
+        let result = write_chunked(&[0; 1000], 300, |_| {
+            calls += 1;
+            Err("nope")
+        });
+        match result {
+            Err(TransferError::Failed("nope")) => {}
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,641 @@
+#[cfg(feature = "aa-lines")]
+use crate::color;
+use crate::game::Rect;
+use crate::limits::Limits;
+
+// Integer-only primitives that write straight into a little-endian RGB565
+// frame at the screen size. Positions are signed and everything clips at the
+// screen edges, so shapes can hang off any side.
+
+const WIDTH: i32 = Limits::SCREEN_WIDTH as i32;
+const HEIGHT: i32 = Limits::SCREEN_HEIGHT as i32;
+
+pub fn pixel(frame: &mut [u8], x: i32, y: i32, color: u16) {
+    if x < 0 || y < 0 || x >= WIDTH || y >= HEIGHT {
+        return;
+    }
+    let i = (y * WIDTH + x) as usize * 2;
+    frame[i..i + 2].copy_from_slice(&color.to_le_bytes());
+}
+
+pub fn fill_rect(frame: &mut [u8], rect: Rect, color: u16) {
+    if let Some(rect) = rect.clip(WIDTH, HEIGHT) {
+        for y in rect.y..rect.y + rect.h {
+            hline(frame, rect.x, rect.x + rect.w - 1, y, color);
+        }
+    }
+}
+
+// Halves every channel inside `rect`, so what's drawn there next stands out
+// from a busy background
+pub fn darken_rect(frame: &mut [u8], rect: Rect) {
+    if let Some(rect) = rect.clip(WIDTH, HEIGHT) {
+        for y in rect.y..rect.y + rect.h {
+            for x in rect.x..rect.x + rect.w {
+                let i = (y * WIDTH + x) as usize * 2;
+                let color = u16::from_le_bytes([frame[i], frame[i + 1]]);
+                frame[i..i + 2].copy_from_slice(&((color >> 1) & 0x7BEF).to_le_bytes());
+            }
+        }
+    }
+}
+
+// From x0 to x1 inclusive
+fn hline(frame: &mut [u8], x0: i32, x1: i32, y: i32, color: u16) {
+    if !(0..HEIGHT).contains(&y) {
+        return;
+    }
+    for x in x0.max(0)..=x1.min(WIDTH - 1) {
+        pixel(frame, x, y, color);
+    }
+}
+
+// Bresenham, from (x0, y0) to (x1, y1) inclusive. Only the part that's on
+// the screen is drawn, but the whole line is walked, so it's for lines that
+// are about screen sized.
+pub fn draw_line(frame: &mut [u8], x0: i32, y0: i32, x1: i32, y1: i32, color: u16) {
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+    loop {
+        pixel(frame, x, y, color);
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+// Like `draw_line`, but Wu's: each step along the major axis covers the two
+// nearest pixels across it, blended into the frame by how close the line
+// passes. Diagonals come out smooth rather than stepped, for a read and a
+// blend of two pixels where the plain line writes one.
+#[cfg(feature = "aa-lines")]
+pub fn draw_line_aa(frame: &mut [u8], x0: i32, y0: i32, x1: i32, y1: i32, color: u16) {
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    // Walked along x, with x and y swapped for a steep line
+    let (mut a0, mut b0, mut a1, mut b1) = if steep {
+        (y0, x0, y1, x1)
+    } else {
+        (x0, y0, x1, y1)
+    };
+    if a0 > a1 {
+        core::mem::swap(&mut a0, &mut a1);
+        core::mem::swap(&mut b0, &mut b1);
+    }
+    // Across position in 16.16 fixed point, and how much it moves per step
+    let gradient = if a1 == a0 {
+        0
+    } else {
+        ((b1 - b0) << 16) / (a1 - a0)
+    };
+    let mut across = b0 << 16;
+    for a in a0..=a1 {
+        let (b, coverage) = (across >> 16, (across >> 8 & 0xFF) as u8);
+        let (near, far) = if steep { ((b, a), (b + 1, a)) } else { ((a, b), (a, b + 1)) };
+        blend_pixel(frame, near.0, near.1, color, 255 - coverage);
+        if coverage > 0 {
+            blend_pixel(frame, far.0, far.1, color, coverage);
+        }
+        across += gradient;
+    }
+}
+
+// Mixes `color` into the pixel at (x, y), `alpha` out of 255 of it
+#[cfg(feature = "aa-lines")]
+fn blend_pixel(frame: &mut [u8], x: i32, y: i32, color: u16, alpha: u8) {
+    if x < 0 || y < 0 || x >= WIDTH || y >= HEIGHT {
+        return;
+    }
+    let i = (y * WIDTH + x) as usize * 2;
+    let under = u16::from_le_bytes([frame[i], frame[i + 1]]);
+    frame[i..i + 2].copy_from_slice(&blend(under, color, alpha).to_le_bytes());
+}
+
+// 255 is all of `over`
+#[cfg(feature = "aa-lines")]
+pub fn blend(under: u16, over: u16, alpha: u8) -> u16 {
+    color::lerp(under, over, alpha as u32 + (alpha as u32 >> 7))
+}
+
+// Midpoint circle: walks one octant from the top, mirroring each step into
+// the other seven. `step` gets the current offsets.
+fn midpoint(r: i32, mut step: impl FnMut(i32, i32)) {
+    let (mut x, mut y, mut d) = (r, 0, 1 - r);
+    while y <= x {
+        step(x, y);
+        y += 1;
+        if d < 0 {
+            d += 2 * y + 1;
+        } else {
+            x -= 1;
+            d += 2 * (y - x) + 1;
+        }
+    }
+}
+
+pub fn draw_circle(frame: &mut [u8], cx: i32, cy: i32, r: i32, color: u16) {
+    if r < 0 {
+        return;
+    }
+    midpoint(r, |x, y| {
+        for &(dx, dy) in &[(x, y), (y, x), (-y, x), (-x, y)] {
+            pixel(frame, cx + dx, cy + dy, color);
+            pixel(frame, cx - dx, cy - dy, color);
+        }
+    });
+}
+
+pub fn fill_circle(frame: &mut [u8], cx: i32, cy: i32, r: i32, color: u16) {
+    if r < 0 {
+        return;
+    }
+    midpoint(r, |x, y| {
+        hline(frame, cx - x, cx + x, cy + y, color);
+        hline(frame, cx - x, cx + x, cy - y, color);
+        hline(frame, cx - y, cx + y, cy + x, color);
+        hline(frame, cx - y, cx + y, cy - x, color);
+    });
+}
+
+// A one-color bitmap of up to 32 pixels, one bit each, top row first with
+// the top left pixel in the highest used bit
+#[derive(Clone, Copy)]
+pub struct Sprite {
+    pub w: i32,
+    pub h: i32,
+    pub bits: u32,
+}
+
+impl Sprite {
+    fn is_set(&self, col: i32, row: i32) -> bool {
+        if !(0..self.w).contains(&col) || !(0..self.h).contains(&row) {
+            return false;
+        }
+        self.bits & (1 << (self.w * self.h - 1 - (row * self.w + col))) != 0
+    }
+}
+
+// Draws the set pixels of `sprite` with its top left at (x, y), leaving the
+// rest of the frame alone.
+//
+// With `dither_edges`, pixels on the sprite's outline, those with an unset
+// neighbour above, below or to either side, are only drawn on every other
+// screen pixel in a checkerboard, letting the background through in between.
+// That softens the stair steps of a hard edge. It costs a neighbour check
+// per set pixel.
+pub fn blit_sprite(
+    frame: &mut [u8],
+    x: i32,
+    y: i32,
+    sprite: &Sprite,
+    color: u16,
+    dither_edges: bool,
+) {
+    for row in 0..sprite.h {
+        for col in 0..sprite.w {
+            if !sprite.is_set(col, row) {
+                continue;
+            }
+            if dither_edges && (x + col + y + row) % 2 != 0 {
+                let edge = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .iter()
+                    .any(|&(dx, dy)| !sprite.is_set(col + dx, row + dy));
+                if edge {
+                    continue;
+                }
+            }
+            pixel(frame, x + col, y + row, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ON: u16 = 0xFFFF;
+
+    fn frame() -> Vec<u8> {
+        vec![0; Limits::FRAME_BYTES]
+    }
+
+    fn is_lit(frame: &[u8], x: i32, y: i32) -> bool {
+        let i = (y * WIDTH + x) as usize * 2;
+        frame[i] != 0 || frame[i + 1] != 0
+    }
+
+    // Every pixel that's been drawn, as offsets from (cx, cy)
+    fn lit(frame: &[u8], cx: i32, cy: i32) -> Vec<(i32, i32)> {
+        let mut lit = Vec::new();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if is_lit(frame, x, y) {
+                    lit.push((x - cx, y - cy));
+                }
+            }
+        }
+        lit
+    }
+
+    // The square around (cx, cy) out to `r`, a row a line, with a # for
+    // every pixel drawn
+    fn picture(frame: &[u8], cx: i32, cy: i32, r: i32) -> Vec<String> {
+        (cy - r..=cy + r)
+            .map(|y| {
+                (cx - r..=cx + r)
+                    .map(|x| if is_lit(frame, x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_circles_come_out_round() {
+        let mut frame = frame();
+        draw_circle(&mut frame, 10, 10, 0, ON);
+        assert_eq!(lit(&frame, 10, 10), [(0, 0)]);
+
+        let mut frame = self::frame();
+        draw_circle(&mut frame, 10, 10, 1, ON);
+        assert_eq!(picture(&frame, 10, 10, 1), [".#.", "#.#", ".#."]);
+
+        let mut frame = self::frame();
+        draw_circle(&mut frame, 10, 10, 2, ON);
+        assert_eq!(
+            picture(&frame, 10, 10, 2),
+            [".###.", "#...#", "#...#", "#...#", ".###."]
+        );
+
+        let mut frame = self::frame();
+        draw_circle(&mut frame, 10, 10, 3, ON);
+        assert_eq!(
+            picture(&frame, 10, 10, 3),
+            ["..###..", ".#...#.", "#.....#", "#.....#", "#.....#", ".#...#.", "..###..",]
+        );
+    }
+
+    #[test]
+    fn circles_are_symmetric_in_all_eight_octants() {
+        for r in 0..20 {
+            let mut frame = frame();
+            draw_circle(&mut frame, 31, 31, r, ON);
+            let lit = lit(&frame, 31, 31);
+            for &(x, y) in &lit {
+                for &mirrored in &[(-x, y), (x, -y), (y, x), (-y, -x)] {
+                    assert!(lit.contains(&mirrored), "r = {}, {:?}", r, mirrored);
+                }
+                // Never more than half a pixel out
+                let d = x * x + y * y;
+                assert!(
+                    (r * r - r..=r * r + r).contains(&d),
+                    "r = {}, {:?}",
+                    r,
+                    (x, y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn filled_circles_cover_their_outline_solidly() {
+        for r in 0..20 {
+            let (mut outline, mut filled) = (frame(), frame());
+            draw_circle(&mut outline, 31, 31, r, ON);
+            fill_circle(&mut filled, 31, 31, r, ON);
+            let filled_lit = lit(&filled, 31, 31);
+            for point in lit(&outline, 31, 31) {
+                assert!(filled_lit.contains(&point), "r = {}, {:?}", r, point);
+            }
+            // Each row is one unbroken run, and every row in reach has one
+            for y in -r..=r {
+                let xs: Vec<i32> = filled_lit
+                    .iter()
+                    .filter(|p| p.1 == y)
+                    .map(|p| p.0)
+                    .collect();
+                assert!(!xs.is_empty());
+                let (min, max) = (xs[0], xs[xs.len() - 1]);
+                assert_eq!(xs.len() as i32, max - min + 1);
+                assert_eq!(min, -max);
+            }
+        }
+    }
+
+    #[test]
+    fn negative_radii_draw_nothing() {
+        let mut frame = frame();
+        draw_circle(&mut frame, 10, 10, -1, ON);
+        fill_circle(&mut frame, 10, 10, -1, ON);
+        assert!(lit(&frame, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn circles_clip_at_the_edges() {
+        let mut whole = frame();
+        draw_circle(&mut whole, 31, 31, 6, ON);
+        let whole = lit(&whole, 31, 31);
+        for &(cx, cy) in &[
+            (2, 31),
+            (31, 2),
+            (WIDTH - 3, 31),
+            (31, HEIGHT - 3),
+            (-3, -3),
+        ] {
+            for &filled in &[false, true] {
+                let mut frame = frame();
+                if filled {
+                    fill_circle(&mut frame, cx, cy, 6, ON);
+                } else {
+                    draw_circle(&mut frame, cx, cy, 6, ON);
+                }
+                let lit = lit(&frame, cx, cy);
+                if !filled {
+                    let on_screen: Vec<_> = whole
+                        .iter()
+                        .copied()
+                        .filter(|&(x, y)| {
+                            (0..WIDTH).contains(&(cx + x)) && (0..HEIGHT).contains(&(cy + y))
+                        })
+                        .collect();
+                    assert_eq!(lit, on_screen);
+                }
+                assert!(lit.iter().all(|&(x, y)| x * x + y * y <= 36 + 6));
+            }
+        }
+    }
+
+    fn on_screen(&(x, y): &(i32, i32)) -> bool {
+        (0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y)
+    }
+
+    // Straddling the left, top, right and bottom edges in turn, and then the
+    // top left corner
+    const STRADDLING: [(i32, i32); 5] = [
+        (-1, 10),
+        (10, -1),
+        (WIDTH - 2, 10),
+        (10, HEIGHT - 2),
+        (-2, -2),
+    ];
+
+    #[test]
+    fn pixels_off_each_edge_are_dropped() {
+        let mut frame = frame();
+        for &(x, y) in &[
+            (-1, 5),
+            (5, -1),
+            (WIDTH, 5),
+            (5, HEIGHT),
+            (-WIDTH, -HEIGHT),
+            (i32::MIN, i32::MAX),
+        ] {
+            pixel(&mut frame, x, y, ON);
+        }
+        assert!(lit(&frame, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn rects_straddling_each_edge_keep_what_is_on_screen() {
+        for &(x, y) in &STRADDLING {
+            let rect = Rect { x, y, w: 3, h: 4 };
+            let mut frame = frame();
+            fill_rect(&mut frame, rect, ON);
+            let expected: Vec<_> = (y..y + 4)
+                .flat_map(|y| (x..x + 3).map(move |x| (x, y)))
+                .filter(on_screen)
+                .collect();
+            assert!(!expected.is_empty());
+            assert_eq!(lit(&frame, 0, 0), expected, "{:?}", rect);
+
+            darken_rect(&mut frame, rect);
+            assert_eq!(lit(&frame, 0, 0), expected, "{:?}", rect);
+        }
+
+        // All the way off
+        let mut frame = frame();
+        fill_rect(
+            &mut frame,
+            Rect {
+                x: -3,
+                y: 10,
+                w: 3,
+                h: 4,
+            },
+            ON,
+        );
+        fill_rect(
+            &mut frame,
+            Rect {
+                x: 10,
+                y: HEIGHT,
+                w: 3,
+                h: 4,
+            },
+            ON,
+        );
+        assert!(lit(&frame, 0, 0).is_empty());
+    }
+
+    #[test]
+    fn sprites_straddling_each_edge_keep_what_is_on_screen() {
+        // A 3x3 ring
+        let sprite = Sprite {
+            w: 3,
+            h: 3,
+            bits: 0b111_101_111,
+        };
+        for &(x, y) in &STRADDLING {
+            for &dither in &[false, true] {
+                let mut frame = frame();
+                blit_sprite(&mut frame, x, y, &sprite, ON, dither);
+                let expected: Vec<_> = (0..3)
+                    .flat_map(|row| (0..3).map(move |col| (col, row)))
+                    .filter(|&(col, row)| sprite.is_set(col, row))
+                    .map(|(col, row)| (x + col, y + row))
+                    .filter(on_screen)
+                    // Where the checkerboard falls is down to the screen
+                    // position, whichever edge the sprite hangs off
+                    .filter(|&(sx, sy)| !dither || (sx + sy) % 2 == 0)
+                    .collect();
+                assert_eq!(lit(&frame, 0, 0), expected, "at {:?}", (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn lines_from_off_screen_are_drawn_from_the_edge() {
+        let mut frame = frame();
+        draw_line(&mut frame, -10, 5, 5, 5, ON);
+        assert_eq!(
+            lit(&frame, 0, 5),
+            (0..=5).map(|x| (x, 0)).collect::<Vec<_>>()
+        );
+
+        let mut frame = self::frame();
+        draw_line(&mut frame, 3, -5, 3, 2, ON);
+        assert_eq!(
+            lit(&frame, 3, 0),
+            (0..=2).map(|y| (0, y)).collect::<Vec<_>>()
+        );
+
+        let mut frame = self::frame();
+        draw_line(&mut frame, WIDTH + 5, HEIGHT - 1, WIDTH - 2, HEIGHT - 1, ON);
+        assert_eq!(
+            lit(&frame, 0, 0),
+            [(WIDTH - 2, HEIGHT - 1), (WIDTH - 1, HEIGHT - 1)]
+        );
+
+        // Clean across, never touching the screen
+        let mut frame = self::frame();
+        draw_line(&mut frame, -5, -5, WIDTH + 5, -1, ON);
+        assert!(lit(&frame, 0, 0).is_empty());
+    }
+
+    // The pixels of a line from (x0, y0) to (x1, y1), as offsets from its
+    // start
+    fn line(x0: i32, y0: i32, x1: i32, y1: i32) -> Vec<(i32, i32)> {
+        let mut frame = frame();
+        draw_line(&mut frame, 10 + x0, 10 + y0, 10 + x1, 10 + y1, ON);
+        let mut lit = lit(&frame, 10, 10);
+        lit.sort();
+        lit
+    }
+
+    #[test]
+    fn straight_lines_cover_every_pixel_between_their_ends() {
+        assert_eq!(line(0, 0, 0, 0), [(0, 0)]);
+        assert_eq!(
+            line(0, 0, 4, 0),
+            (0..=4).map(|x| (x, 0)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            line(0, 0, 0, 4),
+            (0..=4).map(|y| (0, y)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            line(0, 0, 4, 4),
+            (0..=4).map(|i| (i, i)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            line(0, 4, 4, 0),
+            (0..=4).map(|i| (i, 4 - i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn shallow_and_steep_lines_step_evenly() {
+        assert_eq!(
+            line(0, 0, 6, 2),
+            [(0, 0), (1, 0), (2, 1), (3, 1), (4, 1), (5, 2), (6, 2)]
+        );
+        assert_eq!(
+            line(0, 0, 2, 6),
+            [(0, 0), (0, 1), (1, 2), (1, 3), (1, 4), (2, 5), (2, 6)]
+        );
+        assert_eq!(
+            line(0, 0, 8, 1),
+            [
+                (0, 0),
+                (1, 0),
+                (2, 0),
+                (3, 0),
+                (4, 1),
+                (5, 1),
+                (6, 1),
+                (7, 1),
+                (8, 1)
+            ]
+        );
+        assert_eq!(line(0, 0, -6, -2), line(-6, -2, 0, 0));
+    }
+
+    #[test]
+    fn lines_have_a_pixel_per_step_and_no_gaps() {
+        for &(x1, y1) in &[
+            (7, 3),
+            (3, 7),
+            (-7, 3),
+            (-3, -7),
+            (7, -1),
+            (1, -7),
+            (20, 9),
+            (-9, 20),
+        ] {
+            let mut frame = frame();
+            draw_line(&mut frame, 30, 30, 30 + x1, 30 + y1, ON);
+            let lit = lit(&frame, 30, 30);
+            assert_eq!(
+                lit.len() as i32,
+                x1.abs().max(y1.abs()) + 1,
+                "to {:?}",
+                (x1, y1)
+            );
+            assert!(lit.contains(&(0, 0)) && lit.contains(&(x1, y1)));
+            // Every pixel but the ends has a neighbour either side of it
+            for &(x, y) in &lit {
+                let neighbours = lit
+                    .iter()
+                    .filter(|&&(nx, ny)| {
+                        (nx, ny) != (x, y) && (nx - x).abs() <= 1 && (ny - y).abs() <= 1
+                    })
+                    .count();
+                let end = (x, y) == (0, 0) || (x, y) == (x1, y1);
+                assert_eq!(
+                    neighbours,
+                    if end { 1 } else { 2 },
+                    "to {:?} at {:?}",
+                    (x1, y1),
+                    (x, y)
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "aa-lines")]
+    fn pixel_at(frame: &[u8], x: i32, y: i32) -> u16 {
+        let i = (y * WIDTH + x) as usize * 2;
+        u16::from_le_bytes([frame[i], frame[i + 1]])
+    }
+
+    #[cfg(feature = "aa-lines")]
+    #[test]
+    fn anti_aliased_straight_lines_are_solid() {
+        let mut frame = frame();
+        draw_line_aa(&mut frame, 10, 10, 20, 10, ON);
+        draw_line_aa(&mut frame, 30, 10, 30, 20, ON);
+        assert_eq!(lit(&frame, 0, 0).len(), 22);
+        assert!((10..=20).all(|x| pixel_at(&frame, x, 10) == ON));
+        assert!((10..=20).all(|y| pixel_at(&frame, 30, y) == ON));
+    }
+
+    #[cfg(feature = "aa-lines")]
+    #[test]
+    fn anti_aliased_lines_split_their_coverage() {
+        // Half a pixel down, halfway along
+        let mut frame = frame();
+        draw_line_aa(&mut frame, 10, 10, 12, 11, ON);
+        assert_eq!(pixel_at(&frame, 10, 10), ON);
+        assert_eq!(pixel_at(&frame, 10, 11), 0);
+        let (near, far) = (pixel_at(&frame, 11, 10), pixel_at(&frame, 11, 11));
+        assert!(
+            near != 0 && near != ON && near == far,
+            "{:04x} {:04x}",
+            near,
+            far
+        );
+        assert_eq!(pixel_at(&frame, 12, 11), ON);
+
+        // Blending is all or nothing at the ends
+        assert_eq!(blend(0x1234, 0xABCD, 255), 0xABCD);
+        assert_eq!(blend(0x1234, 0xABCD, 0), 0x1234);
+    }
+}
@@ -0,0 +1,32 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    Plasma,
+    Starfield,
+    Tiles,
+    Off,
+}
+
+impl Effect {
+    // In the order the menu steps through them
+    pub const ALL: [Effect; 4] = [Effect::Plasma, Effect::Starfield, Effect::Tiles, Effect::Off];
+
+    // What the console calls it, and `from_name` takes back
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Plasma => "plasma",
+            Effect::Starfield => "stars",
+            Effect::Tiles => "tiles",
+            Effect::Off => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "plasma" => Some(Effect::Plasma),
+            "stars" => Some(Effect::Starfield),
+            "tiles" => Some(Effect::Tiles),
+            "off" => Some(Effect::Off),
+            _ => None,
+        }
+    }
+}
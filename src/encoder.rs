@@ -0,0 +1,89 @@
+use core::sync::atomic::{AtomicI32, Ordering};
+use embedded_hal::digital::v2::InputPin;
+use nrf52840_hal::gpio::{Input, Pin, PullUp};
+use nrf52840_hal::gpiote::Gpiote;
+
+// Quadrature decoding in software: each of the two encoder lines gets a
+// GPIOTE channel that fires on both edges, and the interrupt looks at where
+// the pair has moved to. The QDEC peripheral would do the same in hardware,
+// with its own debounce filter and without an interrupt per edge, but it's
+// taken as an RTIC dispatcher and freeing it would mean moving software tasks
+// to another spare interrupt. Rotation here is slow enough, a few hundred
+// edges a second at most, that the interrupts don't matter.
+
+// Quarter steps per detent on the usual mechanical encoders
+const STEPS_PER_DETENT: i32 = 4;
+
+// Indexed by (previous state << 2) | new state, where a state is (A << 1) | B.
+// The Gray code only ever changes one bit at a time, so anything that flips
+// both, or nothing at all, is bounce or a missed edge and counts for nothing.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+// Quarter steps since boot, written from the GPIOTE interrupt
+static QUARTERS: AtomicI32 = AtomicI32::new(0);
+
+// The interrupt side, which owns the pins
+pub struct Quadrature {
+    a: Pin<Input<PullUp>>,
+    b: Pin<Input<PullUp>>,
+    state: u8,
+}
+
+impl Quadrature {
+    // Uses GPIOTE channels 1 and 2. Both lines are switched to ground by
+    // the encoder, with the internal pull-ups.
+    pub fn new(gpiote: &Gpiote, a: Pin<Input<PullUp>>, b: Pin<Input<PullUp>>) -> Self {
+        gpiote.channel1().input_pin(&a).toggle().enable_interrupt();
+        gpiote.channel2().input_pin(&b).toggle().enable_interrupt();
+        let mut quadrature = Quadrature { a, b, state: 0 };
+        quadrature.state = quadrature.read();
+        quadrature
+    }
+
+    fn read(&self) -> u8 {
+        let a = self.a.is_high().unwrap_or(false) as u8;
+        let b = self.b.is_high().unwrap_or(false) as u8;
+        (a << 1) | b
+    }
+
+    // Called from the GPIOTE interrupt
+    pub fn on_interrupt(&mut self, gpiote: &Gpiote) {
+        let a = gpiote.channel1().is_event_triggered();
+        let b = gpiote.channel2().is_event_triggered();
+        if !a && !b {
+            return;
+        }
+        gpiote.channel1().reset_events();
+        gpiote.channel2().reset_events();
+
+        let state = self.read();
+        let step = TRANSITIONS[((self.state << 2) | state) as usize];
+        self.state = state;
+        if step != 0 {
+            QUARTERS.fetch_add(step as i32, Ordering::Relaxed);
+        }
+    }
+}
+
+// The reading side, for whoever polls the controls
+pub struct Encoder {
+    // Detents already handed out by `poll`, in quarter steps
+    seen: i32,
+}
+
+impl Encoder {
+    pub const fn new() -> Self {
+        Encoder { seen: 0 }
+    }
+
+    // Net detents turned since the last poll, clockwise positive. A detent
+    // that's only partly turned is kept for next time, so rocking back and
+    // forth within one never counts.
+    pub fn poll(&mut self) -> i8 {
+        let quarters = QUARTERS.load(Ordering::Relaxed);
+        let steps = quarters.wrapping_sub(self.seen) / STEPS_PER_DETENT;
+        let steps = steps.clamp(i8::MIN as i32, i8::MAX as i32);
+        self.seen = self.seen.wrapping_add(steps * STEPS_PER_DETENT);
+        steps as i8
+    }
+}
@@ -0,0 +1,62 @@
+// Whatever can go wrong with the hardware that the firmware gets over,
+// rather than panicking. The drivers underneath only say that something
+// failed, so this says which part of the board it was.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(feature = "flash"), allow(dead_code))]
+pub enum FirmwareError {
+    // A command or pixels that didn't make it out to the panel over SPI
+    Display,
+    // Flash that didn't read back as it was written
+    Flash,
+    // A record longer than a journal can hold
+    TooLong,
+}
+
+// Frames in a row that don't make it out before the panel is reset and
+// initialized again, in case it's the controller that's wedged (a brownout
+// can leave it ignoring commands) rather than one bad transfer
+const REINIT_AFTER: u8 = 3;
+
+// Keeps track of how sending to the panel is going, frame by frame
+pub struct Recovery {
+    // The first thing to go wrong this frame
+    failed: Option<FirmwareError>,
+    failures: u8,
+}
+
+impl Recovery {
+    pub const fn new() -> Self {
+        Recovery {
+            failed: None,
+            failures: 0,
+        }
+    }
+
+    // Called with everything sent during a frame, and returns the bytes that
+    // went out, for the frame's stats
+    pub fn sent(&mut self, result: Result<u32, FirmwareError>) -> u32 {
+        result.unwrap_or_else(|err| {
+            self.failed.get_or_insert(err);
+            0
+        })
+    }
+
+    // Called at the end of each frame. Returns what went wrong, if anything
+    // did, and whether that's enough frames in a row to reinitialize the
+    // panel.
+    pub fn frame_done(&mut self) -> Option<(FirmwareError, bool)> {
+        let err = match self.failed.take() {
+            Some(err) => err,
+            None => {
+                self.failures = 0;
+                return None;
+            }
+        };
+        self.failures += 1;
+        let reinit = self.failures >= REINIT_AFTER;
+        if reinit {
+            self.failures = 0;
+        }
+        Some((err, reinit))
+    }
+}
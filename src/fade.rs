@@ -0,0 +1,145 @@
+// Cross-fades from one frame to the next when the scenes change: the title,
+// the game and game over taking over from each other, or the pause screen or
+// the menu going on or coming off. The outgoing frame has to be kept for the
+// length of the fade, and rather than find another 8 KiB for it, it borrows
+// the background cache's buffer: the cache is out of action for those few
+// frames anyway, since every frame of a fade is different and goes out in
+// full.
+//
+// The cross-fade isn't the only way from one to the other: see Transition.
+
+use crate::color;
+use crate::limits::Limits;
+
+const W: usize = Limits::SCREEN_WIDTH;
+
+// How many frames a fade lasts
+pub const FRAMES: u8 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    // Blends straight from one frame to the next
+    Fade,
+    // Down to black and back up again
+    Black,
+    // The new frame sweeps in from the left
+    Wipe,
+    // The new frame shows through a pixel at a time, in an ordered pattern
+    Dissolve,
+}
+
+impl Transition {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fade" => Some(Transition::Fade),
+            "black" => Some(Transition::Black),
+            "wipe" => Some(Transition::Wipe),
+            "dissolve" => Some(Transition::Dissolve),
+            _ => None,
+        }
+    }
+}
+
+// 4x4 Bayer matrix, the order pixels come through in a dissolve
+const DISSOLVE: [u8; 16] = [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5];
+
+pub struct Fade {
+    left: u8,
+    transition: Transition,
+    // The framebuffer doesn't hold the last frame, because it was streamed
+    // straight to the panel instead
+    stale: bool,
+}
+
+impl Fade {
+    pub const fn new() -> Self {
+        Fade {
+            left: 0,
+            transition: Transition::Fade,
+            stale: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.left > 0
+    }
+
+    // The last frame was sent without going through the framebuffer. A fade
+    // starting now comes in from black instead.
+    #[cfg_attr(not(any(feature = "scanline", feature = "indexed")), allow(dead_code))]
+    pub fn mark_stale(&mut self) {
+        self.stale = true;
+    }
+
+    // Starts a fade out of `last`, the framebuffer as it was last sent, by
+    // copying it into `outgoing`. The `transition` is kept to the end, even
+    // if the setting changes on the way.
+    pub fn start(&mut self, last: &[u8], outgoing: &mut [u8], transition: Transition) {
+        if self.stale {
+            outgoing.iter_mut().for_each(|b| *b = 0);
+        } else {
+            outgoing.copy_from_slice(last);
+        }
+        self.left = FRAMES;
+        self.transition = transition;
+    }
+
+    // Called with every finished `frame` that went through the framebuffer.
+    // While fading, mixes it with `outgoing`, more of the new frame each
+    // time. Returns false once there's no fade and `outgoing` can be given
+    // back.
+    pub fn apply(&mut self, outgoing: &[u8], frame: &mut [u8]) -> bool {
+        self.stale = false;
+        if self.left == 0 {
+            return false;
+        }
+        let amount = (FRAMES - self.left + 1) as u32 * 256 / (FRAMES as u32 + 1);
+        match self.transition {
+            Transition::Fade => blend(outgoing, frame, amount),
+            Transition::Black if amount < 128 => {
+                frame.copy_from_slice(outgoing);
+                dim(frame, 256 - amount * 2);
+            }
+            Transition::Black => dim(frame, amount * 2 - 256),
+            Transition::Wipe => {
+                let edge = (W as u32 * amount / 256) as usize;
+                for (old, new) in outgoing
+                    .chunks_exact(W * 2)
+                    .zip(frame.chunks_exact_mut(W * 2))
+                {
+                    new[edge * 2..].copy_from_slice(&old[edge * 2..]);
+                }
+            }
+            Transition::Dissolve => {
+                let pixels = outgoing.chunks_exact(2).zip(frame.chunks_exact_mut(2));
+                for (i, (old, new)) in pixels.enumerate() {
+                    let (x, y) = (i % W, i / W);
+                    let order = DISSOLVE[(y % 4) * 4 + x % 4] as u32;
+                    if (order + 1) * 256 > amount * 17 {
+                        new.copy_from_slice(old);
+                    }
+                }
+            }
+        }
+        self.left -= 1;
+        self.left > 0
+    }
+}
+
+// Linear blend of two little-endian RGB565 buffers into `frame`. `amount` is
+// how much of `frame` to keep, out of 256; the rest comes from `from`.
+pub fn blend(from: &[u8], frame: &mut [u8], amount: u32) {
+    for (a, b) in from.chunks_exact(2).zip(frame.chunks_exact_mut(2)) {
+        let a = u16::from_le_bytes([a[0], a[1]]);
+        let c = u16::from_le_bytes([b[0], b[1]]);
+        b.copy_from_slice(&color::lerp(a, c, amount).to_le_bytes());
+    }
+}
+
+// Scales a little-endian RGB565 buffer by `level` out of 256, towards black
+fn dim(frame: &mut [u8], level: u32) {
+    for pixel in frame.chunks_exact_mut(2) {
+        let c = u16::from_le_bytes([pixel[0], pixel[1]]);
+        pixel.copy_from_slice(&color::scale(c, level).to_le_bytes());
+    }
+}
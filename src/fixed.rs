@@ -0,0 +1,220 @@
+use core::ops::{Add, AddAssign, Div, Mul, Sub};
+
+// Q16.16 fixed point. Unlike f32 the results don't depend on the compiler's
+// choice of instructions or evaluation order, so two boards doing the same
+// math always agree.
+//
+// Arithmetic saturates at MIN and MAX instead of wrapping. Multiplication
+// rounds towards negative infinity, like an arithmetic shift, and division
+// towards zero like integer division. Dividing by zero panics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Fixed(i32);
+
+const FRAC_BITS: u32 = 16;
+
+impl Fixed {
+    pub const ONE: Fixed = Fixed(1 << FRAC_BITS);
+    pub const HALF: Fixed = Fixed(1 << (FRAC_BITS - 1));
+    pub const PI: Fixed = Fixed(205_887);
+    pub const TAU: Fixed = Fixed(411_775);
+    pub const MIN: Fixed = Fixed(i32::MIN);
+    pub const MAX: Fixed = Fixed(i32::MAX);
+
+    pub const fn from_raw(raw: i32) -> Self {
+        Fixed(raw)
+    }
+
+    pub const fn raw(self) -> i32 {
+        self.0
+    }
+
+    // Saturates outside of -32768..=32767
+    pub const fn from_int(n: i32) -> Self {
+        saturate((n as i64) << FRAC_BITS)
+    }
+
+    // `num / den` with as much precision as fits
+    pub const fn from_ratio(num: i32, den: i32) -> Self {
+        saturate(((num as i64) << FRAC_BITS) / den as i64)
+    }
+
+    // Whole radians reduced into 0..TAU, for angles that count up forever
+    // like the frame number
+    pub const fn angle(radians: u32) -> Self {
+        Fixed((((radians as u64) << FRAC_BITS) % Self::TAU.0 as u64) as i32)
+    }
+
+    // Rounds towards negative infinity
+    pub const fn to_int(self) -> i32 {
+        self.0 >> FRAC_BITS
+    }
+
+    // Rounds to the nearest integer, halves away from zero
+    pub const fn round(self) -> i32 {
+        let half = Self::HALF.0 as i64;
+        let raw = self.0 as i64;
+        let rounded = if raw < 0 {
+            -((half - raw) >> FRAC_BITS)
+        } else {
+            (raw + half) >> FRAC_BITS
+        };
+        rounded as i32
+    }
+
+    // For the float plasma, which starts from the same angles
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+
+    pub const fn abs(self) -> Self {
+        Fixed(self.0.saturating_abs())
+    }
+}
+
+const fn saturate(raw: i64) -> Fixed {
+    if raw > i32::MAX as i64 {
+        Fixed::MAX
+    } else if raw < i32::MIN as i64 {
+        Fixed::MIN
+    } else {
+        Fixed(raw as i32)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: Fixed) -> Fixed {
+        saturate((self.0 as i64 * rhs.0 as i64) >> FRAC_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    fn div(self, rhs: Fixed) -> Fixed {
+        saturate(((self.0 as i64) << FRAC_BITS) / rhs.0 as i64)
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Fixed) {
+        *self = *self + rhs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fx(x: f64) -> Fixed {
+        Fixed((x * 65536.0) as i32)
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Fixed::from_int(3).raw(), 3 << 16);
+        assert_eq!(Fixed::from_int(-3).to_int(), -3);
+        assert_eq!(Fixed::from_ratio(1, 2), Fixed::HALF);
+        assert_eq!(Fixed::from_ratio(-3, 4).raw(), -49152);
+        assert_eq!(Fixed::from_raw(12345).raw(), 12345);
+        assert_eq!(Fixed::HALF.to_f32(), 0.5);
+        assert!((Fixed::PI.to_f32() - core::f32::consts::PI).abs() < 1e-4);
+        assert!((Fixed::TAU.to_f32() - core::f32::consts::TAU).abs() < 1e-4);
+    }
+
+    #[test]
+    fn out_of_range_saturates() {
+        assert_eq!(Fixed::from_int(32767).to_int(), 32767);
+        assert_eq!(Fixed::from_int(32768), Fixed::MAX);
+        assert_eq!(Fixed::from_int(-32768).to_int(), -32768);
+        assert_eq!(Fixed::from_int(-32769), Fixed::MIN);
+        assert_eq!(Fixed::from_ratio(i32::MAX, 1), Fixed::MAX);
+
+        assert_eq!(Fixed::MAX + Fixed::ONE, Fixed::MAX);
+        assert_eq!(Fixed::MIN - Fixed::ONE, Fixed::MIN);
+        assert_eq!(Fixed::from_int(300) * Fixed::from_int(300), Fixed::MAX);
+        assert_eq!(Fixed::from_int(-300) * Fixed::from_int(300), Fixed::MIN);
+        assert_eq!(Fixed::from_int(20000) / Fixed::from_ratio(1, 4), Fixed::MAX);
+        assert_eq!(Fixed::MIN.abs(), Fixed::MAX);
+
+        let mut x = Fixed::MAX;
+        x += Fixed::ONE;
+        assert_eq!(x, Fixed::MAX);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let (a, b) = (Fixed::from_ratio(3, 2), Fixed::from_ratio(-1, 4));
+        assert_eq!(a + b, Fixed::from_ratio(5, 4));
+        assert_eq!(a - b, Fixed::from_ratio(7, 4));
+        assert_eq!(a * b, Fixed::from_ratio(-3, 8));
+        assert_eq!(a / b, Fixed::from_int(-6));
+        assert_eq!(b.abs(), Fixed::from_ratio(1, 4));
+    }
+
+    #[test]
+    fn rounding() {
+        // to_int towards negative infinity, round to the nearest with
+        // halves away from zero
+        let cases = [
+            (1.25, 1, 1),
+            (1.5, 1, 2),
+            (1.75, 1, 2),
+            (-1.25, -2, -1),
+            (-1.5, -2, -2),
+            (-1.75, -2, -2),
+            (0.0, 0, 0),
+        ];
+        for &(x, to_int, round) in &cases {
+            assert_eq!(fx(x).to_int(), to_int, "{}", x);
+            assert_eq!(fx(x).round(), round, "{}", x);
+        }
+        assert_eq!(Fixed::MAX.round(), 32768);
+        assert_eq!(Fixed::MIN.round(), -32768);
+
+        // The smallest step: multiplication goes down, division towards 0
+        let tiny = Fixed::from_raw(1);
+        assert_eq!(tiny * Fixed::HALF, Fixed::from_raw(0));
+        assert_eq!(Fixed::from_raw(-1) * Fixed::HALF, Fixed::from_raw(-1));
+        assert_eq!(Fixed::from_raw(-1) / Fixed::from_int(2), Fixed::from_raw(0));
+        assert_eq!(Fixed::from_raw(3) / Fixed::from_int(2), Fixed::from_raw(1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn dividing_by_zero_panics() {
+        let _ = Fixed::ONE / Fixed::from_int(0);
+    }
+
+    #[test]
+    fn angles_count_round_the_circle() {
+        for radians in [0, 1, 6, 7, 1000].iter().copied() {
+            let x = Fixed::angle(radians);
+            let expected = (radians as f64).rem_euclid(core::f64::consts::TAU);
+            assert!((x.to_f32() as f64 - expected).abs() < 0.001, "{}", radians);
+        }
+        // TAU is a little out, which adds up over a lot of turns, but it
+        // always comes out as an angle
+        for radians in [1 << 20, u32::MAX - 1, u32::MAX].iter().copied() {
+            let x = Fixed::angle(radians);
+            assert!(Fixed::from_raw(0) <= x && x < Fixed::TAU, "{}", radians);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// Two frames' worth of pixels: the back buffer, which the next frame is
+// drawn or copied into, and the front buffer, which is the one going out to
+// the panel. A frame is only ever written into the back buffer, so the one
+// being sent can't change under the transfer and come out half one frame and
+// half the next. `swap` or `present` is what hands a finished frame over to
+// be sent, and must wait until nothing's reading the front buffer any more.
+//
+// Costs one extra framebuffer of RAM on top of the copy being sent.
+pub struct DoubleBuffer<const N: usize> {
+    buffers: [[u8; N]; 2],
+    // Which of `buffers` is the back one
+    back: usize,
+}
+
+impl<const N: usize> DoubleBuffer<N> {
+    pub const fn new() -> Self {
+        DoubleBuffer {
+            buffers: [[0; N]; 2],
+            back: 0,
+        }
+    }
+
+    pub fn back(&mut self) -> &mut [u8; N] {
+        &mut self.buffers[self.back]
+    }
+
+    pub fn front(&self) -> &[u8; N] {
+        &self.buffers[1 - self.back]
+    }
+
+    // The back buffer becomes the front one, and what was sent last is
+    // drawn over next
+    pub fn swap(&mut self) {
+        self.back = 1 - self.back;
+    }
+
+    // Swaps, then starts the back buffer off as a copy of the frame that's
+    // going out, for drawing that carries on from the last frame rather
+    // than starting afresh. Returns the front buffer, to send.
+    pub fn present(&mut self) -> &[u8; N] {
+        self.swap();
+        let (a, b) = self.buffers.split_at_mut(1);
+        match self.back {
+            0 => a[0] = b[0],
+            _ => b[0] = a[0],
+        }
+        self.front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swapping_sends_what_was_drawn_and_draws_over_the_one_before() {
+        let mut frames = DoubleBuffer::<2>::new();
+        frames.back().fill(1);
+        frames.swap();
+        assert_eq!(frames.front(), &[1, 1]);
+        assert_eq!(frames.back(), &mut [0, 0]);
+    }
+
+    #[test]
+    fn presenting_starts_the_next_frame_from_the_last() {
+        let mut frames = DoubleBuffer::<2>::new();
+        frames.back()[0] = 1;
+        assert_eq!(frames.present(), &[1, 0]);
+        assert_eq!(frames.back(), &mut [1, 0]);
+        frames.back()[1] = 2;
+        assert_eq!(frames.present(), &[1, 2]);
+        // Still the last frame, not the one before it
+        assert_eq!(frames.back(), &mut [1, 2]);
+        frames.back()[0] = 3;
+        assert_eq!(frames.front(), &[1, 2]);
+    }
+}
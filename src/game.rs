@@ -0,0 +1,1204 @@
+use crate::boss::{self, Boss};
+use crate::collision::{self, Grid};
+use crate::limits::Limits;
+use crate::particles::{self, Particle, Spark, MAX_PARTICLES};
+use crate::pool::Pool;
+use crate::rng::Rng;
+use crate::wave::{Formation, Spawner};
+
+// Everything in here is plain data and arithmetic so that it can run
+// anywhere. The RTIC tasks gather `Input`, call `advance_frame` and render
+// the resulting `World`.
+
+pub const WIDTH: i32 = Limits::SCREEN_WIDTH as i32;
+pub const HEIGHT: i32 = Limits::SCREEN_HEIGHT as i32;
+
+pub const SHIP_W: i32 = 5;
+pub const SHIP_H: i32 = 3;
+pub const BULLET_W: i32 = 1;
+pub const BULLET_H: i32 = 3;
+pub const ENEMY_W: i32 = 4;
+pub const ENEMY_H: i32 = 4;
+pub const POWER_UP_W: i32 = 3;
+pub const POWER_UP_H: i32 = 3;
+// Radius of the bubble drawn around a shielded ship
+pub const SHIELD_RADIUS: i32 = 5;
+
+pub const MAX_BULLETS: usize = Limits::BULLETS;
+pub const MAX_ENEMIES: usize = Limits::ENEMIES;
+pub const MAX_POWER_UPS: usize = Limits::POWER_UPS;
+pub const MAX_EXPLOSIONS: usize = Limits::EXPLOSIONS;
+
+const START_LIVES: u8 = 3;
+const FIRE_COOLDOWN: u8 = 6;
+const BULLET_SPEED: i32 = 2;
+// Full analog deflection moves the ship this many pixels per frame
+pub const MAX_SHIP_SPEED: i32 = 2;
+// One in this many frames spawns an enemy, if there's room for it
+const SPAWN_CHANCE: u32 = 24;
+// Enemies come in up to twice this far above the screen, so ones spawned
+// together don't arrive in a line
+const SPAWN_STAGGER: i32 = 3;
+const ENEMY_POINTS: u32 = 10;
+// Kills this close together keep a combo going
+const COMBO_FRAMES: u32 = 30;
+const GAME_OVER_FRAMES: u32 = 120;
+// One in this many destroyed enemies drops a power-up
+const POWER_UP_CHANCE: u32 = 8;
+const POWER_UP_FRAMES: u32 = 600;
+// How fast the game runs while slow motion lasts, in percent
+pub const SLOW_MOTION_PERCENT: u8 = 50;
+const MAX_LIVES: u8 = 9;
+const HIT_FLASH_FRAMES: u32 = 3;
+const EXPLOSION_FRAMES: u32 = 8;
+pub const EXPLOSION_RADIUS: i32 = 4;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub fire: bool,
+    // Analog horizontal axis, -127..=127, added on top of left/right
+    pub x: i8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Title,
+    Playing,
+    GameOver,
+}
+
+// Things that happened during the last frame, for the edges (sound, logging)
+// to react to
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Events(u8);
+
+impl Events {
+    pub const FIRED: Events = Events(1 << 0);
+    pub const ENEMY_DESTROYED: Events = Events(1 << 1);
+    pub const SHIP_HIT: Events = Events(1 << 2);
+    pub const GAME_OVER: Events = Events(1 << 3);
+    pub const POWER_UP: Events = Events(1 << 4);
+    pub const SHIELD_HIT: Events = Events(1 << 5);
+    pub const ENEMY_HIT: Events = Events(1 << 6);
+    // Not from the game itself but from the metronome, added on top
+    pub const BEAT: Events = Events(1 << 7);
+
+    pub fn contains(self, other: Events) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Events) {
+        self.0 |= other.0;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ship {
+    pub x: i32,
+    pub y: i32,
+    pub cooldown: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bullet {
+    pub x: i32,
+    pub y: i32,
+    // Sideways drift per frame, for spread shots
+    pub dx: i32,
+    // Fired by the partner ship, which only matters in a versus game
+    pub partner: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Enemy {
+    pub x: i32,
+    pub y: i32,
+    pub hp: u8,
+    // Tick until which the enemy is drawn flashing after taking a hit
+    pub hit_flash_until: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerUpKind {
+    RapidFire,
+    SpreadShot,
+    Shield,
+    ExtraLife,
+    SlowMotion,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerUp {
+    pub kind: PowerUpKind,
+    pub x: i32,
+    pub y: i32,
+}
+
+// Purely cosmetic, left behind by a destroyed enemy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Explosion {
+    pub x: i32,
+    pub y: i32,
+    pub started: u32,
+}
+
+// The tick (see `World::ticks`) at which each timed power-up wears off
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ActiveEffects {
+    pub rapid_fire_until: u32,
+    pub spread_shot_until: u32,
+    pub shield_until: u32,
+    pub slow_motion_until: u32,
+}
+
+pub struct World {
+    pub state: State,
+    pub ship: Ship,
+    // Second player's ship in a linked game. Lives are shared, and so is the
+    // score unless it's a versus game.
+    pub partner: Option<Ship>,
+    // A linked game where each ship scores for itself, the partner into
+    // `partner_score`
+    pub versus: bool,
+    pub partner_score: u32,
+    pub bullets: Pool<Bullet, MAX_BULLETS>,
+    pub enemies: Pool<Enemy, MAX_ENEMIES>,
+    pub power_ups: Pool<PowerUp, MAX_POWER_UPS>,
+    pub explosions: Pool<Explosion, MAX_EXPLOSIONS>,
+    pub particles: Pool<Particle, MAX_PARTICLES>,
+    pub boss: Option<Boss>,
+    // Tick the next boss comes in at, once there isn't one already
+    pub next_boss: u32,
+    pub effects: ActiveEffects,
+    pub score: u32,
+    pub lives: u8,
+    // Enemies destroyed this game, the boss included. Like the combo it
+    // isn't saved in a checkpoint, so a resumed game counts from 0.
+    pub kills: u32,
+    // Kills in a row, each within COMBO_FRAMES of the last
+    pub combo: u16,
+    combo_until: u32,
+    // Frames since the current state was entered
+    pub ticks: u32,
+    pub events: Events,
+    pub waves: Spawner,
+}
+
+impl World {
+    pub const fn new() -> Self {
+        World {
+            state: State::Title,
+            ship: Ship {
+                x: (WIDTH - SHIP_W) / 2,
+                y: HEIGHT - SHIP_H - 2,
+                cooldown: 0,
+            },
+            partner: None,
+            versus: false,
+            partner_score: 0,
+            bullets: Pool::new(),
+            enemies: Pool::new(),
+            power_ups: Pool::new(),
+            explosions: Pool::new(),
+            particles: Pool::new(),
+            boss: None,
+            next_boss: boss::INTERVAL,
+            effects: ActiveEffects {
+                rapid_fire_until: 0,
+                spread_shot_until: 0,
+                shield_until: 0,
+                slow_motion_until: 0,
+            },
+            score: 0,
+            lives: START_LIVES,
+            kills: 0,
+            combo: 0,
+            combo_until: 0,
+            ticks: 0,
+            events: Events(0),
+            waves: Spawner::new(),
+        }
+    }
+
+    pub fn start(&mut self) {
+        *self = World::new();
+        self.state = State::Playing;
+    }
+
+    // Like `start`, but with the two ships side by side
+    pub fn start_linked(&mut self, versus: bool) {
+        self.start();
+        self.versus = versus;
+        let partner = Ship {
+            x: self.ship.x + WIDTH / 4,
+            ..self.ship
+        };
+        self.ship.x -= WIDTH / 4;
+        self.partner = Some(partner);
+    }
+
+    // Bounding boxes of everything that gets drawn on top of the background
+    pub fn sprites(&self) -> impl Iterator<Item = Rect> + '_ {
+        let shielded = self.is_active(self.effects.shield_until);
+        let bounds = |ship: Ship| {
+            if shielded {
+                ship.shield_rect()
+            } else {
+                ship.rect()
+            }
+        };
+        let ship = match self.state {
+            State::Title => None,
+            _ => Some(bounds(self.ship)),
+        };
+        let partner = match self.state {
+            State::Title => None,
+            _ => self.partner.map(bounds),
+        };
+        ship.into_iter()
+            .chain(partner)
+            .chain(self.bullets.live().map(Bullet::rect))
+            .chain(self.enemies.live().map(Enemy::rect))
+            .chain(self.power_ups.live().map(PowerUp::rect))
+            .chain(self.explosions.live().map(Explosion::rect))
+            .chain(self.particles.live().map(move |particle| particle.rect(self.ticks)))
+            .chain(self.boss.map(|boss| boss.rect()))
+            .chain(self.boss.map(|_| boss::BAR))
+            .chain(self.aim_line().map(|((x0, y0), (x1, y1))| Rect {
+                x: x0.min(x1),
+                y: y0.min(y1),
+                w: (x1 - x0).abs() + 1,
+                h: (y1 - y0).abs() + 1,
+            }))
+    }
+
+    // The boss's aiming line down to the ship, while there is one
+    pub fn aim_line(&self) -> Option<((i32, i32), (i32, i32))> {
+        let target = (self.ship.x + SHIP_W / 2, self.ship.y - 1);
+        self.boss.and_then(|boss| boss.aim(target))
+    }
+
+    // How fast this world wants to be run, in percent of full speed. See
+    // timescale::TimeScale.
+    pub fn speed_percent(&self) -> u8 {
+        if self.is_active(self.effects.slow_motion_until) {
+            SLOW_MOTION_PERCENT
+        } else {
+            100
+        }
+    }
+
+    pub fn is_active(&self, until: u32) -> bool {
+        self.ticks < until
+    }
+
+    // Returns how many enemies actually fit in the pool
+    pub fn spawn_enemies(&mut self, count: u8, rng: &mut Rng) -> u8 {
+        let mut spawned = 0;
+        for slot in self.enemies.free_slots() {
+            if spawned == count {
+                break;
+            }
+            *slot = Some(Enemy {
+                x: rng.rand_range(0, WIDTH - ENEMY_W),
+                y: rng.jitter(-ENEMY_H - SPAWN_STAGGER, SPAWN_STAGGER),
+                // Mostly one-hit enemies, with the odd tougher one
+                hp: match rng.below(8) {
+                    0 => 3,
+                    1 | 2 => 2,
+                    _ => 1,
+                },
+                hit_flash_until: 0,
+            });
+            spawned += 1;
+        }
+        spawned
+    }
+
+    fn destroyed(&mut self, points: u32, by_partner: bool) {
+        if self.versus && by_partner {
+            self.partner_score += points;
+        } else {
+            self.score += points;
+        }
+        self.kills = self.kills.saturating_add(1);
+        self.combo = if self.ticks < self.combo_until {
+            self.combo.saturating_add(1)
+        } else {
+            1
+        };
+        self.combo_until = self.ticks + COMBO_FRAMES;
+        self.events.insert(Events::ENEMY_DESTROYED);
+    }
+
+    // A single enemy at `(x, y)`, if there's room for it
+    pub fn spawn_at(&mut self, (x, y): (i32, i32), hp: u8) {
+        self.enemies.spawn(Enemy {
+            x,
+            y,
+            hp,
+            hit_flash_until: 0,
+        });
+    }
+
+    // Like `spawn_enemies`, the rest of a formation that doesn't fit is
+    // dropped
+    pub fn spawn_formation(&mut self, formation: Formation) -> u8 {
+        let mut positions = formation.positions();
+        let mut spawned = 0;
+        for slot in self.enemies.free_slots() {
+            let (x, y) = match positions.next() {
+                Some(position) => position,
+                None => break,
+            };
+            *slot = Some(Enemy {
+                x,
+                y,
+                hp: formation.hp,
+                hit_flash_until: 0,
+            });
+            spawned += 1;
+        }
+        spawned
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub fn overlaps(self, other: Rect) -> bool {
+        self.x < other.x + other.w
+            && other.x < self.x + self.w
+            && self.y < other.y + other.h
+            && other.y < self.y + self.h
+    }
+
+    // The smallest rect covering both
+    pub fn union(self, other: Rect) -> Rect {
+        let x0 = self.x.min(other.x);
+        let y0 = self.y.min(other.y);
+        let x1 = (self.x + self.w).max(other.x + other.w);
+        let y1 = (self.y + self.h).max(other.y + other.h);
+        Rect {
+            x: x0,
+            y: y0,
+            w: x1 - x0,
+            h: y1 - y0,
+        }
+    }
+
+    pub fn area(self) -> i32 {
+        self.w.max(0) * self.h.max(0)
+    }
+
+    // The part of the rect that's on a `width` x `height` screen
+    pub fn clip(self, width: i32, height: i32) -> Option<Rect> {
+        let x0 = self.x.max(0);
+        let y0 = self.y.max(0);
+        let x1 = (self.x + self.w).min(width);
+        let y1 = (self.y + self.h).min(height);
+
+        if x0 < x1 && y0 < y1 {
+            Some(Rect {
+                x: x0,
+                y: y0,
+                w: x1 - x0,
+                h: y1 - y0,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Ship {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            w: SHIP_W,
+            h: SHIP_H,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        (self.x + SHIP_W / 2, self.y + SHIP_H / 2)
+    }
+
+    // Covers the shield bubble as well as the ship
+    pub fn shield_rect(&self) -> Rect {
+        let (cx, cy) = self.center();
+        Rect {
+            x: cx - SHIELD_RADIUS,
+            y: cy - SHIELD_RADIUS,
+            w: 2 * SHIELD_RADIUS + 1,
+            h: 2 * SHIELD_RADIUS + 1,
+        }
+    }
+}
+
+impl Bullet {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            w: BULLET_W,
+            h: BULLET_H,
+        }
+    }
+}
+
+impl PowerUp {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            w: POWER_UP_W,
+            h: POWER_UP_H,
+        }
+    }
+}
+
+impl Explosion {
+    // Grows to EXPLOSION_RADIUS over its lifetime
+    pub fn radius(&self, now: u32) -> i32 {
+        let age = now.saturating_sub(self.started);
+        1 + (age * (EXPLOSION_RADIUS as u32 - 1) / EXPLOSION_FRAMES) as i32
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x - EXPLOSION_RADIUS,
+            y: self.y - EXPLOSION_RADIUS,
+            w: 2 * EXPLOSION_RADIUS + 1,
+            h: 2 * EXPLOSION_RADIUS + 1,
+        }
+    }
+}
+
+impl PowerUpKind {
+    fn random(rng: &mut Rng) -> Self {
+        match rng.below(5) {
+            0 => PowerUpKind::RapidFire,
+            1 => PowerUpKind::SpreadShot,
+            2 => PowerUpKind::Shield,
+            3 => PowerUpKind::SlowMotion,
+            _ => PowerUpKind::ExtraLife,
+        }
+    }
+}
+
+impl Enemy {
+    // Takes one hit point and returns whether the enemy survived
+    pub fn damage(&mut self, now: u32) -> bool {
+        self.hp = self.hp.saturating_sub(1);
+        if self.hp == 0 {
+            return false;
+        }
+        self.hit_flash_until = now + HIT_FLASH_FRAMES;
+        true
+    }
+
+    pub fn is_flashing(&self, now: u32) -> bool {
+        now < self.hit_flash_until
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            w: ENEMY_W,
+            h: ENEMY_H,
+        }
+    }
+}
+
+pub fn advance_frame(world: &mut World, input: Input, rng: &mut Rng) {
+    step(world, input, None, rng);
+}
+
+#[cfg(feature = "link")]
+// Advances a game with two players, where `inputs[1]` steers the partner
+// ship. Both boards run this with the same inputs in the same order, so
+// their worlds stay identical. `versus` is what a game started now is.
+pub fn advance_linked(world: &mut World, inputs: [Input; 2], versus: bool, rng: &mut Rng) {
+    step(world, inputs[0], Some((inputs[1], versus)), rng);
+}
+
+fn step(world: &mut World, input: Input, partner: Option<(Input, bool)>, rng: &mut Rng) {
+    world.events = Events::default();
+    world.ticks = world.ticks.wrapping_add(1);
+
+    match world.state {
+        State::Title => match partner {
+            Some((partner, versus)) if input.fire || partner.fire => world.start_linked(versus),
+            None if input.fire => world.start(),
+            _ => (),
+        },
+        State::Playing => play(world, input, partner.map(|(input, _)| input), rng),
+        State::GameOver => {
+            if world.ticks >= GAME_OVER_FRAMES {
+                *world = World::new();
+            }
+        }
+    }
+}
+
+fn play(world: &mut World, input: Input, partner: Option<Input>, rng: &mut Rng) {
+    let spread = world.is_active(world.effects.spread_shot_until);
+    let rapid = world.is_active(world.effects.rapid_fire_until);
+
+    let was = (world.ship.x, world.partner.map(|ship| ship.x));
+    let mut fired = steer(&mut world.ship, input, &mut world.bullets, spread, rapid, false);
+    if let (Some(ship), Some(input)) = (&mut world.partner, partner) {
+        fired |= steer(ship, input, &mut world.bullets, spread, rapid, true);
+    }
+    // Exhaust every other frame, which is plenty to see and leaves the pool
+    // to the explosions
+    if world.ticks.is_multiple_of(2) {
+        let ship = world.ship;
+        exhaust(&mut world.particles, ship, ship.x - was.0, world.ticks);
+        if let (Some(ship), Some(was)) = (world.partner, was.1) {
+            exhaust(&mut world.particles, ship, ship.x - was, world.ticks);
+        }
+    }
+    if fired {
+        world.events.insert(Events::FIRED);
+    }
+
+    world.bullets.update(|bullet| {
+        bullet.x += bullet.dx;
+        bullet.y -= BULLET_SPEED;
+        bullet.y + BULLET_H >= 0 && bullet.x >= 0 && bullet.x < WIDTH
+    });
+
+    // Enemies drift down one pixel every other frame
+    let step = (world.ticks % 2) as i32;
+    world.enemies.update(|enemy| {
+        enemy.y += step;
+        enemy.y < HEIGHT
+    });
+
+    let now = world.ticks;
+    world.explosions.update(|explosion| now < explosion.started + EXPLOSION_FRAMES);
+    particles::update(&mut world.particles, now);
+
+    // Power-ups fall at the same pace as enemies
+    world.power_ups.update(|power_up| {
+        power_up.y += step;
+        power_up.y < HEIGHT
+    });
+
+    // The boss is the main event, so nothing else turns up at random
+    // while it's around
+    if world.boss.is_none() && world.ticks >= world.next_boss {
+        world.boss = Some(Boss::new());
+    }
+    let (now, target_x) = (world.ticks, world.ship.x + SHIP_W / 2);
+    if let Some(drop) = world.boss.as_mut().and_then(|boss| boss.update(now, target_x)) {
+        world.spawn_at(drop, 1);
+    }
+    if world.boss.is_none() && rng.one_in(SPAWN_CHANCE) {
+        world.spawn_enemies(1, rng);
+    }
+    while let Some(formation) = world.waves.advance(world.ticks) {
+        world.spawn_formation(formation);
+    }
+
+    // By the ship, then by the partner
+    let mut destroyed = [0; 2];
+    let grid = Grid::new(&world.enemies[..]);
+    for bullet_slot in world.bullets.iter_mut() {
+        let bullet = match bullet_slot {
+            Some(b) => *b,
+            None => continue,
+        };
+        let enemy_slot = match grid.first_hit(bullet.rect(), &world.enemies[..]) {
+            Some(i) => &mut world.enemies[i],
+            None => continue,
+        };
+        if let Some(enemy) = enemy_slot {
+            *bullet_slot = None;
+            if enemy.damage(world.ticks) {
+                world.events.insert(Events::ENEMY_HIT);
+                let (x, y) = (bullet.x, enemy.y + ENEMY_H);
+                particles::emit(&mut world.particles, Spark::Impact, x, y, world.ticks);
+                continue;
+            }
+
+            let (x, y) = (enemy.x, enemy.y);
+            let (cx, cy) = (x + ENEMY_W / 2, y + ENEMY_H / 2);
+            *enemy_slot = None;
+            world.explosions.spawn(Explosion {
+                x: cx,
+                y: cy,
+                started: world.ticks,
+            });
+            particles::emit(&mut world.particles, Spark::Explosion, cx, cy, world.ticks);
+            destroyed[bullet.partner as usize] += 1;
+
+            if rng.one_in(POWER_UP_CHANCE) {
+                drop_power_up(&mut world.power_ups, x, y, rng);
+            }
+        }
+    }
+
+    for (by_partner, &count) in destroyed.iter().enumerate() {
+        for _ in 0..count {
+            world.destroyed(ENEMY_POINTS, by_partner == 1);
+        }
+    }
+
+    if let Some(mut boss) = world.boss {
+        let mut by_partner = false;
+        for slot in world.bullets.iter_mut() {
+            let bullet = match slot {
+                Some(bullet) if bullet.rect().overlaps(boss.rect()) => *bullet,
+                _ => continue,
+            };
+            *slot = None;
+            by_partner = bullet.partner;
+            if boss.damage(world.ticks) {
+                world.events.insert(Events::ENEMY_HIT);
+                let (x, y) = (bullet.x, boss.y + boss::H);
+                particles::emit(&mut world.particles, Spark::Impact, x, y, world.ticks);
+                continue;
+            }
+            let (cx, cy) = (boss.x + boss::W / 2, boss.y + boss::H / 2);
+            particles::emit(&mut world.particles, Spark::Explosion, cx, cy, world.ticks);
+
+            // Goes out in as many explosions as there's room for, one after
+            // the other
+            let free = world.explosions.free_slots();
+            for (i, (slot, (x, y))) in free.zip(boss.burst()).enumerate() {
+                *slot = Some(Explosion {
+                    x,
+                    y,
+                    started: world.ticks + 2 * i as u32,
+                });
+            }
+            drop_power_up(&mut world.power_ups, boss.x + boss::W / 2, boss.y, rng);
+            world.next_boss = world.ticks + boss::INTERVAL;
+            break;
+        }
+        if boss.hp > 0 {
+            world.boss = Some(boss);
+        } else {
+            world.boss = None;
+            world.destroyed(boss::POINTS, by_partner);
+        }
+    }
+
+    let ships = [Some(world.ship.rect()), world.partner.map(|ship| ship.rect())];
+    let touches = |rect: Rect| ships.iter().flatten().any(|ship| ship.overlaps(rect));
+    let shielded = world.is_active(world.effects.shield_until);
+    for slot in world.enemies.iter_mut() {
+        if let Some(enemy) = slot {
+            if touches(enemy.rect()) {
+                *slot = None;
+                if shielded {
+                    world.events.insert(Events::SHIELD_HIT);
+                } else {
+                    world.lives = world.lives.saturating_sub(1);
+                    world.events.insert(Events::SHIP_HIT);
+                }
+            }
+        }
+    }
+
+    for ship in ships.iter().flatten() {
+        while let Some(i) = collision::first_hit(*ship, &world.power_ups[..]) {
+            if let Some(power_up) = world.power_ups[i].take() {
+                collect(world, power_up.kind);
+            }
+        }
+    }
+
+    if world.lives == 0 {
+        world.state = State::GameOver;
+        world.ticks = 0;
+        world.events.insert(Events::GAME_OVER);
+    }
+}
+
+// Out of whichever side of `ship` is at the back, `dx` being how far it just
+// moved, if it did
+fn exhaust(pool: &mut Pool<Particle, MAX_PARTICLES>, ship: Ship, dx: i32, now: u32) {
+    let x = match dx {
+        0 => return,
+        dx if dx > 0 => ship.x - 1,
+        _ => ship.x + SHIP_W,
+    };
+    particles::thrust(pool, x, ship.y + SHIP_H / 2, dx, now);
+}
+
+// Moves a ship and fires from it. Returns whether any bullets went out.
+fn steer(
+    ship: &mut Ship,
+    input: Input,
+    bullets: &mut Pool<Bullet, MAX_BULLETS>,
+    spread: bool,
+    rapid: bool,
+    partner: bool,
+) -> bool {
+    if input.left {
+        ship.x -= 1;
+    }
+    if input.right {
+        ship.x += 1;
+    }
+    ship.x += input.x as i32 * MAX_SHIP_SPEED / 127;
+    ship.x = ship.x.clamp(0, WIDTH - SHIP_W);
+
+    ship.cooldown = ship.cooldown.saturating_sub(1);
+    if !input.fire || ship.cooldown > 0 {
+        return false;
+    }
+
+    let (x, y) = (ship.x + SHIP_W / 2, ship.y - BULLET_H);
+    let shots: &[i32] = if spread { &[-1, 0, 1] } else { &[0] };
+
+    let mut fired = false;
+    for &dx in shots {
+        fired |= bullets.spawn(Bullet { x, y, dx, partner });
+    }
+
+    if fired {
+        ship.cooldown = if rapid {
+            FIRE_COOLDOWN / 2
+        } else {
+            FIRE_COOLDOWN
+        };
+    }
+    fired
+}
+
+fn drop_power_up(pool: &mut Pool<PowerUp, MAX_POWER_UPS>, x: i32, y: i32, rng: &mut Rng) {
+    if pool.free_slots().next().is_some() {
+        pool.spawn(PowerUp {
+            kind: PowerUpKind::random(rng),
+            x,
+            y,
+        });
+    }
+}
+
+fn collect(world: &mut World, kind: PowerUpKind) {
+    let until = world.ticks + POWER_UP_FRAMES;
+    match kind {
+        PowerUpKind::RapidFire => world.effects.rapid_fire_until = until,
+        PowerUpKind::SpreadShot => world.effects.spread_shot_until = until,
+        PowerUpKind::Shield => world.effects.shield_until = until,
+        PowerUpKind::ExtraLife => world.lives = (world.lives + 1).min(MAX_LIVES),
+        // Counted in the game's own ticks, so it lasts twice as long
+        // as the others in real time
+        PowerUpKind::SlowMotion => world.effects.slow_motion_until = until,
+    }
+    world.events.insert(Events::POWER_UP);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDLE: Input = Input {
+        left: false,
+        right: false,
+        fire: false,
+        x: 0,
+    };
+    const FIRE: Input = Input { fire: true, ..IDLE };
+    const LEFT: Input = Input { left: true, ..IDLE };
+    const RIGHT: Input = Input {
+        right: true,
+        ..IDLE
+    };
+
+    // A game a frame in, with nothing on screen. The first formation of the
+    // waves isn't for a second, and random ones start at the top, so
+    // nothing else gets near the ship for a while.
+    fn playing(seed: u32) -> (World, Rng) {
+        let mut world = World::new();
+        let mut rng = Rng::new(seed);
+        advance_frame(&mut world, FIRE, &mut rng);
+        advance_frame(&mut world, IDLE, &mut rng);
+        world.enemies = Pool::new();
+        world.bullets = Pool::new();
+        world.particles = Pool::new();
+        (world, rng)
+    }
+
+    // Runs `frames` frames of `input`, and returns everything that happened
+    // in any of them
+    fn run(world: &mut World, rng: &mut Rng, input: Input, frames: u32) -> Events {
+        let mut events = Events::default();
+        for _ in 0..frames {
+            advance_frame(world, input, rng);
+            events.insert(world.events);
+        }
+        events
+    }
+
+    // Idles through `frames` frames, with nothing getting near the ship
+    fn wait(world: &mut World, rng: &mut Rng, frames: u32) {
+        for _ in 0..frames {
+            world.enemies = Pool::new();
+            advance_frame(world, IDLE, rng);
+        }
+    }
+
+    fn enemy_above_ship(world: &mut World, hp: u8) {
+        let x = world.ship.x + SHIP_W / 2 - ENEMY_W / 2;
+        world.spawn_at((x, world.ship.y - 16), hp);
+    }
+
+    #[test]
+    fn title_waits_for_fire() {
+        let mut world = World::new();
+        let mut rng = Rng::new(1);
+        run(&mut world, &mut rng, RIGHT, 100);
+        assert_eq!(world.state, State::Title);
+
+        advance_frame(&mut world, FIRE, &mut rng);
+        assert_eq!(world.state, State::Playing);
+        assert_eq!(world.ticks, 0);
+        assert_eq!(world.lives, START_LIVES);
+    }
+
+    #[test]
+    fn ship_steers_and_stays_on_screen() {
+        let (mut world, mut rng) = playing(1);
+        let x = world.ship.x;
+        run(&mut world, &mut rng, LEFT, 3);
+        assert_eq!(world.ship.x, x - 3);
+        run(&mut world, &mut rng, RIGHT, 5);
+        assert_eq!(world.ship.x, x + 2);
+
+        // The stick on top of the d-pad
+        advance_frame(&mut world, Input { x: 127, ..RIGHT }, &mut rng);
+        assert_eq!(world.ship.x, x + 3 + MAX_SHIP_SPEED);
+        advance_frame(&mut world, Input { x: -127, ..IDLE }, &mut rng);
+        assert_eq!(world.ship.x, x + 3);
+
+        run(&mut world, &mut rng, RIGHT, WIDTH as u32);
+        assert_eq!(world.ship.x, WIDTH - SHIP_W);
+        run(
+            &mut world,
+            &mut rng,
+            Input { x: -127, ..LEFT },
+            WIDTH as u32,
+        );
+        assert_eq!(world.ship.x, 0);
+    }
+
+    #[test]
+    fn moving_ships_leave_exhaust_behind() {
+        let (mut world, mut rng) = playing(1);
+        let thrust = |world: &World| {
+            world
+                .particles
+                .live()
+                .filter(|particle| particle.kind == Spark::Thrust)
+                .count()
+        };
+        run(&mut world, &mut rng, IDLE, 4);
+        assert_eq!(thrust(&world), 0);
+        run(&mut world, &mut rng, LEFT, 4);
+        assert_eq!(thrust(&world), 2);
+        // Out of the right hand side, going left
+        let x = world.ship.x + SHIP_W;
+        assert!(world.particles.live().all(|particle| particle.rect(world.ticks).x >= x));
+    }
+
+    #[test]
+    fn holding_fire_waits_out_the_cooldown() {
+        let (mut world, mut rng) = playing(1);
+        let mut shots = 0;
+        for _ in 0..2 * FIRE_COOLDOWN {
+            advance_frame(&mut world, FIRE, &mut rng);
+            shots += world.events.contains(Events::FIRED) as u32;
+        }
+        assert_eq!(shots, 2);
+
+        // Letting go doesn't skip it
+        let (mut world, mut rng) = playing(1);
+        advance_frame(&mut world, FIRE, &mut rng);
+        advance_frame(&mut world, IDLE, &mut rng);
+        advance_frame(&mut world, FIRE, &mut rng);
+        assert!(!world.events.contains(Events::FIRED));
+    }
+
+    #[test]
+    fn a_bullet_destroys_the_enemy_it_hits() {
+        let (mut world, mut rng) = playing(1);
+        enemy_above_ship(&mut world, 1);
+        let mut events = Events::default();
+        advance_frame(&mut world, FIRE, &mut rng);
+        for _ in 0..20 {
+            events = world.events;
+            if events.contains(Events::ENEMY_DESTROYED) {
+                break;
+            }
+            advance_frame(&mut world, IDLE, &mut rng);
+        }
+        assert!(events.contains(Events::ENEMY_DESTROYED));
+        assert_eq!(world.score, ENEMY_POINTS);
+        assert_eq!(world.kills, 1);
+        assert_eq!(world.combo, 1);
+        assert_eq!(world.explosions.live().count(), 1);
+        assert_eq!(world.bullets.live().count(), 0);
+    }
+
+    #[test]
+    fn running_into_enemies_ends_the_game() {
+        let (mut world, mut rng) = playing(1);
+        for lives in (0..START_LIVES).rev() {
+            world.spawn_at((world.ship.x, world.ship.y), 1);
+            advance_frame(&mut world, IDLE, &mut rng);
+            assert!(world.events.contains(Events::SHIP_HIT));
+            assert_eq!(world.lives, lives);
+        }
+        assert!(world.events.contains(Events::GAME_OVER));
+        assert_eq!(world.state, State::GameOver);
+
+        // Fire doesn't skip the game over screen
+        run(&mut world, &mut rng, FIRE, GAME_OVER_FRAMES - 1);
+        assert_eq!(world.state, State::GameOver);
+        advance_frame(&mut world, FIRE, &mut rng);
+        assert_eq!(world.state, State::Title);
+        assert_eq!(world.score, 0);
+    }
+
+    #[test]
+    fn the_same_seed_and_input_play_the_same_game() {
+        // Weaving back and forth with the trigger held
+        let script = |frame: u32| Input {
+            left: frame % 90 < 45,
+            right: frame % 90 >= 45,
+            fire: true,
+            x: 0,
+        };
+        let play = || {
+            let mut world = World::new();
+            let mut rng = Rng::new(42);
+            for frame in 0..1_000 {
+                advance_frame(&mut world, script(frame), &mut rng);
+            }
+            world
+        };
+        let (a, b) = (play(), play());
+        assert!(a.kills > 0);
+        assert_eq!(
+            (a.state, a.score, a.kills, a.lives, a.ship),
+            (b.state, b.score, b.kills, b.lives, b.ship)
+        );
+        assert_eq!(a.enemies[..], b.enemies[..]);
+        assert_eq!(a.bullets[..], b.bullets[..]);
+    }
+
+    fn power_up_on_ship(world: &mut World, kind: PowerUpKind) {
+        let (x, y) = (world.ship.x, world.ship.y);
+        world.power_ups.spawn(PowerUp { kind, x, y });
+    }
+
+    #[test]
+    fn power_ups_are_collected_by_touching_them() {
+        let (mut world, mut rng) = playing(1);
+        // Off to the side, so the ship never touches it
+        let x = world.ship.x + SHIP_W + 4;
+        world.power_ups.spawn(PowerUp {
+            kind: PowerUpKind::Shield,
+            x,
+            y: world.ship.y,
+        });
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert!(!world.events.contains(Events::POWER_UP));
+        assert_eq!(world.power_ups.live().count(), 1);
+
+        // They fall off the bottom uncollected
+        let frames = 2 * (HEIGHT - world.ship.y) as u32;
+        run(&mut world, &mut rng, IDLE, frames);
+        assert_eq!(world.power_ups.live().count(), 0);
+
+        power_up_on_ship(&mut world, PowerUpKind::Shield);
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert!(world.events.contains(Events::POWER_UP));
+        assert_eq!(world.power_ups.live().count(), 0);
+        assert_eq!(world.effects.shield_until, world.ticks + POWER_UP_FRAMES);
+    }
+
+    #[test]
+    fn a_shield_takes_hits_until_it_wears_off() {
+        let (mut world, mut rng) = playing(1);
+        power_up_on_ship(&mut world, PowerUpKind::Shield);
+        advance_frame(&mut world, IDLE, &mut rng);
+
+        world.spawn_at((world.ship.x, world.ship.y), 1);
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert!(world.events.contains(Events::SHIELD_HIT));
+        assert_eq!(world.lives, START_LIVES);
+
+        wait(&mut world, &mut rng, POWER_UP_FRAMES - 2);
+        assert!(world.is_active(world.effects.shield_until));
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert!(!world.is_active(world.effects.shield_until));
+        world.spawn_at((world.ship.x, world.ship.y), 1);
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert!(world.events.contains(Events::SHIP_HIT));
+        assert_eq!(world.lives, START_LIVES - 1);
+    }
+
+    #[test]
+    fn spread_shot_fires_three_until_it_wears_off() {
+        let (mut world, mut rng) = playing(1);
+        power_up_on_ship(&mut world, PowerUpKind::SpreadShot);
+        advance_frame(&mut world, FIRE, &mut rng);
+        assert!(world.events.contains(Events::POWER_UP));
+        // Collected after the ship's fired for the frame
+        assert_eq!(world.bullets.live().count(), 1);
+
+        world.bullets = Pool::new();
+        run(&mut world, &mut rng, IDLE, FIRE_COOLDOWN as u32);
+        advance_frame(&mut world, FIRE, &mut rng);
+        let dxs: Vec<i32> = world.bullets.live().map(|bullet| bullet.dx).collect();
+        assert_eq!(dxs, [-1, 0, 1]);
+
+        wait(&mut world, &mut rng, POWER_UP_FRAMES);
+        world.bullets = Pool::new();
+        advance_frame(&mut world, FIRE, &mut rng);
+        assert_eq!(world.bullets.live().count(), 1);
+    }
+
+    #[test]
+    fn rapid_fire_halves_the_cooldown() {
+        let (mut world, mut rng) = playing(1);
+        power_up_on_ship(&mut world, PowerUpKind::RapidFire);
+        advance_frame(&mut world, IDLE, &mut rng);
+
+        let mut shots = 0;
+        for _ in 0..2 * FIRE_COOLDOWN {
+            advance_frame(&mut world, FIRE, &mut rng);
+            shots += world.events.contains(Events::FIRED) as u32;
+        }
+        assert_eq!(shots, 4);
+    }
+
+    #[test]
+    fn slow_motion_slows_the_game_for_a_while() {
+        let (mut world, mut rng) = playing(1);
+        assert_eq!(world.speed_percent(), 100);
+        power_up_on_ship(&mut world, PowerUpKind::SlowMotion);
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert_eq!(world.speed_percent(), SLOW_MOTION_PERCENT);
+        wait(&mut world, &mut rng, POWER_UP_FRAMES);
+        assert_eq!(world.speed_percent(), 100);
+    }
+
+    #[test]
+    fn extra_lives_stop_at_the_most() {
+        let (mut world, mut rng) = playing(1);
+        power_up_on_ship(&mut world, PowerUpKind::ExtraLife);
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert_eq!(world.lives, START_LIVES + 1);
+
+        world.lives = MAX_LIVES;
+        power_up_on_ship(&mut world, PowerUpKind::ExtraLife);
+        advance_frame(&mut world, IDLE, &mut rng);
+        assert!(world.events.contains(Events::POWER_UP));
+        assert_eq!(world.lives, MAX_LIVES);
+    }
+
+    #[test]
+    fn damage_takes_a_hit_point_and_flashes() {
+        let mut enemy = Enemy {
+            x: 0,
+            y: 0,
+            hp: 2,
+            hit_flash_until: 0,
+        };
+        assert!(!enemy.is_flashing(10));
+        assert!(enemy.damage(10));
+        assert_eq!(enemy.hp, 1);
+        assert!(enemy.is_flashing(10));
+        assert!(enemy.is_flashing(10 + HIT_FLASH_FRAMES - 1));
+        assert!(!enemy.is_flashing(10 + HIT_FLASH_FRAMES));
+
+        // The last hit point goes without a flash
+        assert!(!enemy.damage(20));
+        assert_eq!(enemy.hp, 0);
+        assert!(!enemy.is_flashing(20));
+        assert!(!enemy.damage(21));
+    }
+
+    #[test]
+    fn tough_enemies_survive_until_their_last_hit() {
+        let (mut world, mut rng) = playing(1);
+        enemy_above_ship(&mut world, 3);
+        let x = world.enemies.live().next().unwrap().x;
+        let mut hits = 0;
+        let mut destroyed = false;
+        for frame in 0..60 {
+            advance_frame(
+                &mut world,
+                if frame % 8 == 0 { FIRE } else { IDLE },
+                &mut rng,
+            );
+            if world.events.contains(Events::ENEMY_HIT) {
+                hits += 1;
+                let enemy = world.enemies.live().find(|enemy| enemy.x == x).unwrap();
+                assert_eq!(enemy.hp, 3 - hits);
+                assert!(enemy.is_flashing(world.ticks));
+            }
+            if world.events.contains(Events::ENEMY_DESTROYED) {
+                destroyed = true;
+                break;
+            }
+        }
+        assert_eq!(hits, 2);
+        assert!(destroyed);
+        assert_eq!(world.kills, 1);
+        assert_eq!(world.score, ENEMY_POINTS);
+        assert!(world.enemies.live().all(|enemy| enemy.x != x));
+        assert_eq!(world.explosions.live().count(), 1);
+    }
+
+    #[test]
+    fn a_boss_comes_in_and_pays_out_when_finished() {
+        let (mut world, mut rng) = playing(5);
+        world.next_boss = world.ticks + 1;
+        wait(&mut world, &mut rng, 2);
+        assert_eq!(
+            world.boss.map(|boss| boss.phase),
+            Some(boss::Phase::Entering)
+        );
+
+        // Worn down to its last hit point, and chasing, so it stays over
+        // the ship
+        let mut boss = world.boss.unwrap();
+        boss.hp = 1;
+        boss.y = 4;
+        boss.phase = boss::Phase::Chasing;
+        boss.x = world.ship.x + SHIP_W / 2 - boss::W / 2;
+        world.boss = Some(boss);
+        let score = world.score;
+        for _ in 0..60 {
+            world.enemies = Pool::new();
+            advance_frame(&mut world, FIRE, &mut rng);
+            if world.boss.is_none() {
+                break;
+            }
+        }
+        assert_eq!(world.boss, None);
+        assert_eq!(world.score, score + boss::POINTS);
+        assert!(world.events.contains(Events::ENEMY_DESTROYED));
+        assert_eq!(world.next_boss, world.ticks + boss::INTERVAL);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::clock::CPU_HZ;
+use crate::game::{Events, World};
+
+// Steps the game at a fixed rate, whatever the frame rate is. Each frame
+// hands over the DWT cycle count it started at, and gets back how many
+// steps of TICK_HZ have gone by since the last one: none on a fast frame,
+// several on a slow one. The time left over carries on to the next frame,
+// so it all evens out.
+//
+// Linked games are the exception, and keep to a step a frame, since that's
+// what the other board steps with.
+
+// The rate the game was tuned at, one step a frame at the 60 FPS cap
+pub const TICK_HZ: u32 = 60;
+const TICK_CYCLES: u32 = CPU_HZ / TICK_HZ;
+// Most steps a frame can catch up on. Past that the game slows down rather
+// than taking longer and longer frames to catch up, and after a long stall
+// (the debugger, or powering off) it picks up where it was.
+pub const MAX_STEPS: u32 = 4;
+
+pub struct GameLoop {
+    // Cycle count at the start of the last frame
+    last: u32,
+    // Cycles since the last step
+    owed: u32,
+    // Steps since boot, paused or not
+    ticks: u32,
+}
+
+impl GameLoop {
+    pub const fn new(now: u32) -> Self {
+        GameLoop {
+            last: now,
+            owed: 0,
+            ticks: 0,
+        }
+    }
+
+    // Called once a frame with the cycle count it started at. Returns how
+    // many steps are due. The DWT counter wraps every minute or so, which is
+    // fine as long as frames are closer together than that.
+    pub fn steps(&mut self, now: u32) -> u32 {
+        self.owed = self.owed.saturating_add(now.wrapping_sub(self.last));
+        self.last = now;
+        let mut steps = self.owed / TICK_CYCLES;
+        self.owed %= TICK_CYCLES;
+        if steps > MAX_STEPS {
+            steps = MAX_STEPS;
+        }
+        self.ticks = self.ticks.wrapping_add(steps);
+        steps
+    }
+
+    // For things that animate at the game's pace rather than the frame rate
+    pub fn ticks(&self) -> u32 {
+        self.ticks
+    }
+}
+
+// Runs `step` `steps` times over `world`, and leaves its events as everything
+// that happened in any of them, so a sound or a beat isn't lost to the step
+// after. No steps at all leaves it with none.
+pub fn run(world: &mut World, steps: u32, mut step: impl FnMut(&mut World)) {
+    let mut events = Events::default();
+    for _ in 0..steps {
+        step(world);
+        events.insert(world.events);
+    }
+    world.events = events;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_step_comes_every_tick_of_cycles() {
+        let mut game_loop = GameLoop::new(0);
+        assert_eq!(game_loop.steps(0), 0);
+        assert_eq!(game_loop.steps(TICK_CYCLES - 1), 0);
+        assert_eq!(game_loop.steps(TICK_CYCLES), 1);
+        assert_eq!(game_loop.steps(3 * TICK_CYCLES), 2);
+        assert_eq!(game_loop.ticks(), 3);
+    }
+
+    #[test]
+    fn what_is_left_over_carries_on() {
+        // Frames a step and a half long alternate one step and two
+        let mut game_loop = GameLoop::new(0);
+        let steps: Vec<u32> = (1..=6)
+            .map(|n| game_loop.steps(n * TICK_CYCLES * 3 / 2))
+            .collect();
+        assert_eq!(steps, [1, 2, 1, 2, 1, 2]);
+        assert_eq!(game_loop.ticks(), 9);
+    }
+
+    #[test]
+    fn a_stall_only_catches_up_so_far() {
+        let mut game_loop = GameLoop::new(0);
+        assert_eq!(game_loop.steps(100 * TICK_CYCLES + 5), MAX_STEPS);
+        // And picks up from there rather than owing the rest
+        assert_eq!(game_loop.steps(100 * TICK_CYCLES + 5 + TICK_CYCLES), 1);
+        // Even stalled for longer than the counter wraps in
+        let mut game_loop = GameLoop::new(0);
+        game_loop.owed = u32::MAX - 10;
+        assert_eq!(game_loop.steps(u32::MAX), MAX_STEPS);
+    }
+
+    #[test]
+    fn the_cycle_count_wrapping_doesnt_matter() {
+        let start = u32::MAX - TICK_CYCLES / 2;
+        let mut game_loop = GameLoop::new(start);
+        assert_eq!(game_loop.steps(start.wrapping_add(TICK_CYCLES)), 1);
+        assert_eq!(game_loop.steps(start.wrapping_add(2 * TICK_CYCLES)), 1);
+    }
+
+    #[test]
+    fn ticks_wrap() {
+        let mut game_loop = GameLoop::new(0);
+        game_loop.ticks = u32::MAX;
+        game_loop.steps(2 * TICK_CYCLES);
+        assert_eq!(game_loop.ticks(), 1);
+    }
+
+    #[test]
+    fn events_from_every_step_are_kept() {
+        let mut world = World::new();
+        let mut n = 0;
+        run(&mut world, 3, |world| {
+            n += 1;
+            world.events = if n == 2 { Events::FIRED } else { Events::BEAT };
+        });
+        assert_eq!(n, 3);
+        assert!(world.events.contains(Events::FIRED) && world.events.contains(Events::BEAT));
+
+        // No steps, no events
+        run(&mut world, 0, |_| unreachable!());
+        assert_eq!(world.events, Events::default());
+    }
+}
@@ -0,0 +1,184 @@
+use crate::limits::Limits;
+
+// Full color drawing into a little-endian RGB565 frame at the screen size,
+// for art that's more than the one-color bitmaps in `draw`: images with a
+// transparent color, and maps of square tiles seen through a camera.
+
+const WIDTH: i32 = Limits::SCREEN_WIDTH as i32;
+const HEIGHT: i32 = Limits::SCREEN_HEIGHT as i32;
+
+// RGB565 pixels, top row first
+#[derive(Clone, Copy)]
+pub struct Image<'a> {
+    pub w: i32,
+    pub h: i32,
+    pub pixels: &'a [u16],
+}
+
+// Where the top left of the screen is on a map, in pixels
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Camera {
+    pub x: i32,
+    pub y: i32,
+}
+
+// Draws `image` with its top left at (x, y), leaving the frame alone
+// wherever it's `key`. Clips at the screen edges.
+pub fn blit_keyed(frame: &mut [u8], image: &Image, x: i32, y: i32, key: u16) {
+    let (x0, x1) = (x.max(0), (x + image.w).min(WIDTH));
+    let (y0, y1) = (y.max(0), (y + image.h).min(HEIGHT));
+    for sy in y0..y1 {
+        let src = &image.pixels[((sy - y) * image.w) as usize..][..image.w as usize];
+        for sx in x0..x1 {
+            let color = src[(sx - x) as usize];
+            if color != key {
+                let i = ((sy * WIDTH + sx) * 2) as usize;
+                frame[i..i + 2].copy_from_slice(&color.to_le_bytes());
+            }
+        }
+    }
+}
+
+// Square tiles of `size` pixels, each `size * size` RGB565 pixels top row
+// first, one after another. 8 and 16 are the sizes in use, but any works.
+#[derive(Clone, Copy)]
+pub struct TileSet<'a> {
+    pub size: usize,
+    pub pixels: &'a [u16],
+}
+
+impl TileSet<'_> {
+    // One pixel row of tile `index`
+    fn row(&self, index: u8, row: usize) -> &[u16] {
+        let start = (index as usize * self.size + row) * self.size;
+        &self.pixels[start..start + self.size]
+    }
+}
+
+// A `w` by `h` map of tile indices, row by row, that wraps both ways
+#[derive(Clone, Copy)]
+pub struct TileMap<'a> {
+    pub tiles: TileSet<'a>,
+    pub map: &'a [u8],
+    pub w: usize,
+    pub h: usize,
+}
+
+impl TileMap<'_> {
+    // Covers all of `frame` with the part of the map under `camera`. Tiles
+    // cut by the screen edges go a run of pixels at a time, the same as
+    // whole ones.
+    pub fn draw(&self, frame: &mut [u8], camera: Camera) {
+        let size = self.tiles.size;
+        let (map_w, map_h) = ((self.w * size) as i32, (self.h * size) as i32);
+        let left = camera.x.rem_euclid(map_w) as usize;
+        for (sy, line) in frame.chunks_exact_mut(WIDTH as usize * 2).enumerate() {
+            let y = (camera.y + sy as i32).rem_euclid(map_h) as usize;
+            let tiles = &self.map[y / size * self.w..][..self.w];
+
+            let mut sx = 0;
+            while sx < WIDTH as usize {
+                let x = (left + sx) % map_w as usize;
+                let run = (size - x % size).min(WIDTH as usize - sx);
+                let row = &self.tiles.row(tiles[x / size], y % size)[x % size..][..run];
+                let out = &mut line[sx * 2..(sx + run) * 2];
+                for (&color, pixel) in row.iter().zip(out.chunks_exact_mut(2)) {
+                    pixel.copy_from_slice(&color.to_le_bytes());
+                }
+                sx += run;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: u16 = 0xF81F;
+
+    // 3x2, with the middle of the top row transparent
+    const IMAGE: Image = Image {
+        w: 3,
+        h: 2,
+        pixels: &[1, KEY, 3, 4, 5, 6],
+    };
+
+    fn at(frame: &[u8], x: i32, y: i32) -> u16 {
+        let i = ((y * WIDTH + x) * 2) as usize;
+        u16::from_le_bytes([frame[i], frame[i + 1]])
+    }
+
+    // A pixel's position and color
+    type Drawn = (i32, i32, u16);
+
+    // Every pixel drawn
+    fn drawn(frame: &[u8]) -> Vec<Drawn> {
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| (x, y, at(frame, x, y)))
+            .filter(|&(_, _, color)| color != 0)
+            .collect()
+    }
+
+    #[test]
+    fn keyed_images_leave_the_key_alone() {
+        let mut frame = vec![0; Limits::FRAME_BYTES];
+        blit_keyed(&mut frame, &IMAGE, 5, 7, KEY);
+        assert_eq!(
+            drawn(&frame),
+            [(5, 7, 1), (7, 7, 3), (5, 8, 4), (6, 8, 5), (7, 8, 6)]
+        );
+    }
+
+    #[test]
+    fn keyed_images_straddling_each_edge_keep_what_is_on_screen() {
+        let cases: [((i32, i32), &[Drawn]); 5] = [
+            ((-2, 7), &[(0, 7, 3), (0, 8, 6)]),
+            ((5, -1), &[(5, 0, 4), (6, 0, 5), (7, 0, 6)]),
+            ((WIDTH - 1, 7), &[(WIDTH - 1, 7, 1), (WIDTH - 1, 8, 4)]),
+            ((5, HEIGHT - 1), &[(5, HEIGHT - 1, 1), (7, HEIGHT - 1, 3)]),
+            ((-1, -1), &[(0, 0, 5), (1, 0, 6)]),
+        ];
+        for &((x, y), expected) in &cases {
+            let mut frame = vec![0; Limits::FRAME_BYTES];
+            blit_keyed(&mut frame, &IMAGE, x, y, KEY);
+            assert_eq!(drawn(&frame), expected, "at {:?}", (x, y));
+        }
+
+        // All the way off each side
+        let mut frame = vec![0; Limits::FRAME_BYTES];
+        for &(x, y) in &[(-3, 7), (5, -2), (WIDTH, 7), (5, HEIGHT)] {
+            blit_keyed(&mut frame, &IMAGE, x, y, KEY);
+        }
+        assert!(drawn(&frame).is_empty());
+    }
+
+    #[test]
+    fn tile_maps_wrap_for_cameras_off_either_way() {
+        // Two 2x2 tiles, side by side on a 2x1 map
+        let tiles = TileSet {
+            size: 2,
+            pixels: &[1, 2, 3, 4, 5, 6, 7, 8],
+        };
+        let map = TileMap {
+            tiles,
+            map: &[0, 1],
+            w: 2,
+            h: 1,
+        };
+        let mut frame = vec![0; Limits::FRAME_BYTES];
+        map.draw(&mut frame, Camera { x: 0, y: 0 });
+        assert_eq!(
+            [at(&frame, 0, 0), at(&frame, 2, 0), at(&frame, 3, 1)],
+            [1, 5, 8]
+        );
+
+        map.draw(&mut frame, Camera { x: -1, y: -1 });
+        // The map's bottom right pixel is in the top left corner
+        assert_eq!(
+            [at(&frame, 0, 0), at(&frame, 1, 1), at(&frame, 2, 1)],
+            [8, 1, 2]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::ring::{Overflow, RingBuffer};
+use embedded_hal_1::pwm::SetDutyCycle;
+use nrf52840_pac::PWM2;
+
+// A vibration motor on PWM2, buzzed in short patterns when things happen in
+// the game. Each pattern is a few steps of the motor at some level for so
+// long, and whoever owns this calls `step` when each one's up, from a task
+// scheduled on the monotonic.
+//
+// Nothing the game does can keep the motor going: patterns queued back to
+// back only get so long together before the motor's stopped, made to rest,
+// and whatever was still queued thrown away. A panic stops it too, since
+// the steps that would have turned it off never come.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rumble {
+    // A short knock, for the shield taking a hit
+    Pulse,
+    // Two of them, for a power-up
+    DoubleTap,
+    // Long and strong, for the ship being hit
+    Buzz,
+}
+
+impl Rumble {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pulse" => Some(Rumble::Pulse),
+            "tap" => Some(Rumble::DoubleTap),
+            "buzz" => Some(Rumble::Buzz),
+            _ => None,
+        }
+    }
+
+    fn steps(self) -> &'static [Step] {
+        match self {
+            Rumble::Pulse => PULSE,
+            Rumble::DoubleTap => DOUBLE_TAP,
+            Rumble::Buzz => BUZZ,
+        }
+    }
+}
+
+// The motor at `level` out of 255, 0 for off, for `ms`
+#[derive(Clone, Copy)]
+struct Step {
+    level: u8,
+    ms: u16,
+}
+
+const fn step(level: u8, ms: u16) -> Step {
+    Step { level, ms }
+}
+
+const PULSE: &[Step] = &[step(255, 40)];
+const DOUBLE_TAP: &[Step] = &[step(255, 30), step(0, 70), step(255, 30)];
+const BUZZ: &[Step] = &[step(200, 350)];
+
+// Patterns waiting behind the one playing. More than this at once is more
+// than anyone can feel apart anyway, so the rest are turned away.
+const QUEUE_LEN: usize = 4;
+
+// The PWM itself is set up by whoever owns it, to run at `frequency_hz`
+#[derive(Clone, Copy)]
+pub struct HapticsConfig {
+    // Above hearing, so the motor doesn't whine
+    pub frequency_hz: u32,
+    // The most the motor's ever driven at, out of 255, for one rated below
+    // the supply
+    pub max_level: u8,
+    // Longest the motor may run, counting only the time it's on, before the
+    // queue's run dry
+    pub max_on_ms: u16,
+    // How long it's then kept off for
+    pub rest_ms: u16,
+}
+
+impl HapticsConfig {
+    pub const DEFAULT: HapticsConfig = HapticsConfig {
+        frequency_hz: 20_000,
+        max_level: 255,
+        max_on_ms: 600,
+        rest_ms: 400,
+    };
+}
+
+pub struct Haptics<P: SetDutyCycle> {
+    pwm: P,
+    config: HapticsConfig,
+    queue: RingBuffer<Rumble, QUEUE_LEN>,
+    // What's playing and which of its steps is on
+    playing: Option<(Rumble, usize)>,
+    // On time since the queue last ran dry
+    on_ms: u32,
+    resting: bool,
+}
+
+impl<P: SetDutyCycle> Haptics<P> {
+    // The motor starts off
+    pub fn new(mut pwm: P, config: HapticsConfig) -> Self {
+        pwm.set_duty_cycle_fully_off().ok();
+        Haptics {
+            pwm,
+            config,
+            queue: RingBuffer::new(Rumble::Pulse, Overflow::RejectNew),
+            playing: None,
+            on_ms: 0,
+            resting: false,
+        }
+    }
+
+    // Queues `rumble` behind whatever's playing. Returns true if the motor
+    // was idle, in which case the caller has to call `step` to start it.
+    pub fn play(&mut self, rumble: Rumble) -> bool {
+        if self.resting {
+            return false;
+        }
+        let idle = self.playing.is_none() && self.queue.is_empty();
+        self.queue.push(rumble) && idle
+    }
+
+    // Moves on to the next step, returning how many ms until this should be
+    // called again, or None once everything queued has played
+    pub fn step(&mut self) -> Option<u32> {
+        self.resting = false;
+        let next = match self.playing {
+            Some((rumble, i)) if i + 1 < rumble.steps().len() => Some((rumble, i + 1)),
+            _ => self.queue.pop().map(|rumble| (rumble, 0)),
+        };
+        self.playing = next;
+        let step = match next {
+            Some((rumble, i)) => rumble.steps()[i],
+            None => {
+                self.stop();
+                return None;
+            }
+        };
+        if step.level > 0 {
+            self.on_ms += step.ms as u32;
+            if self.on_ms > self.config.max_on_ms as u32 {
+                self.stop();
+                self.resting = true;
+                log_debug!("Rumble ran too long, resting the motor");
+                return Some(self.config.rest_ms as u32);
+            }
+        }
+        let level = step.level.min(self.config.max_level) as u32;
+        let duty = level * self.pwm.max_duty_cycle() as u32 / 255;
+        self.pwm.set_duty_cycle(duty as u16).ok();
+        Some(step.ms as u32)
+    }
+
+    // Off now, with anything queued dropped
+    pub fn stop(&mut self) {
+        self.pwm.set_duty_cycle_fully_off().ok();
+        while self.queue.pop().is_some() {}
+        self.playing = None;
+        self.on_ms = 0;
+    }
+}
+
+// For the panic handler. Disabling PWM2 hands the pin back to GPIO, which
+// holds it low.
+pub fn halt() {
+    // Nothing else runs after a panic to be using it
+    let pwm = unsafe { &*PWM2::ptr() };
+    pwm.enable.write(|w| unsafe { w.bits(0) });
+}
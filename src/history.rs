@@ -0,0 +1,68 @@
+use crate::logging::{self, Level};
+use crate::ring::{Overflow, RingBuffer};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+
+// The last few log lines, kept in RAM so that a panic can show what led up
+// to it even if nobody was reading RTT at the time. Lines longer than LINE_LEN
+// are cut short, and once all LINES slots are full the oldest line goes.
+
+const LINES: usize = 16;
+const LINE_LEN: usize = 64;
+
+#[derive(Clone, Copy)]
+struct Line {
+    buf: [u8; LINE_LEN],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Only ever cut between characters, so the line stays valid UTF-8
+        let mut end = s.len().min(LINE_LEN - self.len);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        let bytes = &s.as_bytes()[..end];
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+const EMPTY: Line = Line {
+    buf: [0; LINE_LEN],
+    len: 0,
+};
+
+static HISTORY: Mutex<RefCell<RingBuffer<Line, LINES>>> =
+    Mutex::new(RefCell::new(RingBuffer::new(EMPTY, Overflow::DropOldest)));
+
+// Called by the log macros for every line they print. Interrupts are only
+// off for copying the finished line in.
+pub fn record(args: fmt::Arguments) {
+    let mut line = EMPTY;
+    // Truncation isn't an error, so this can't fail
+    line.write_fmt(args).ok();
+    interrupt::free(|cs| {
+        HISTORY.borrow(cs).borrow_mut().push(line);
+    });
+}
+
+// Prints the recorded lines, oldest first. Only meant for the panic handler.
+pub fn dump() {
+    interrupt::free(|cs| {
+        // A panic in the middle of `record` leaves the history borrowed, and
+        // there's nothing sensible to show then
+        let history = match HISTORY.borrow(cs).try_borrow() {
+            Ok(history) => history,
+            Err(_) => return,
+        };
+        logging::print(Level::Error, format_args!("last {} log lines:", history.len()));
+        for line in history.iter() {
+            let text = core::str::from_utf8(&line.buf[..line.len]).unwrap_or("");
+            logging::print(Level::Error, format_args!("  {}", text));
+        }
+    });
+}
@@ -0,0 +1,140 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+use crate::color::rgb565;
+use crate::draw::{self, Sprite};
+use crate::game::Rect;
+use crate::limits::Limits;
+use crate::text;
+
+// The score, lives and low battery icon along the top of the screen during
+// a game. They hardly ever change, so rather than the frame task drawing
+// them every frame, it says what they are with `show`, and `idle` draws
+// them into a strip of their own whenever that's something new. The frame
+// task only copies the strip in over the playfield, or sends it to its own
+// region of the panel when the layout has one.
+//
+// There are two strips. The idle task draws into whichever one the frame
+// task isn't reading, then hands it over by swapping FRONT. The frame task
+// preempts idle and never the other way round, so the frame task never sees
+// a strip half drawn, and neither side has to lock anything.
+
+pub const W: i32 = Limits::SCREEN_WIDTH as i32;
+pub const H: i32 = text::GLYPH_H + 2;
+pub const STRIP_BYTES: usize = (W * H * 2) as usize;
+
+// Left alone when the strip is copied in
+pub const CLEAR: u16 = 0xf81f;
+const NONE: u8 = 2;
+
+struct Strips(UnsafeCell<[[u8; STRIP_BYTES]; 2]>);
+
+// See above for who touches which strip when
+unsafe impl Sync for Strips {}
+
+static STRIPS: Strips = Strips(UnsafeCell::new([[0; STRIP_BYTES]; 2]));
+// The strip that's ready to copy in, or NONE before the first
+static FRONT: AtomicU8 = AtomicU8::new(NONE);
+// Bumped by `show` with anything new, and drawn as of by `idle`
+static WANTED: AtomicU32 = AtomicU32::new(0);
+static DRAWN: AtomicU32 = AtomicU32::new(0);
+static SCORE: AtomicU32 = AtomicU32::new(0);
+// Lives in the low byte, and whether the battery's low above it
+static STATUS: AtomicU32 = AtomicU32::new(0);
+
+// Also shown outside a game, by the frame task
+pub const LOW_BATTERY: Sprite = Sprite {
+    w: 7,
+    h: 4,
+    bits: 0b1111110_1000011_1000011_1111110,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Values {
+    pub score: u32,
+    pub lives: u8,
+    pub low_battery: bool,
+}
+
+// From the frame task, each frame of a game
+pub fn show(values: Values) {
+    let status = values.lives as u32 | (values.low_battery as u32) << 8;
+    let score = SCORE.swap(values.score, Ordering::Relaxed);
+    let changed = (score, STATUS.swap(status, Ordering::Relaxed)) != (values.score, status);
+    if changed || FRONT.load(Ordering::Relaxed) == NONE {
+        WANTED.fetch_add(1, Ordering::Release);
+        // Wakes idle from its WFE, even if it was just about to sleep
+        cortex_m::asm::sev();
+    }
+}
+
+// The strip for the frame task to copy in with `copy`, and its generation,
+// which changes whenever there's a new one to send
+pub fn front() -> Option<(usize, u32)> {
+    match FRONT.load(Ordering::Acquire) {
+        NONE => None,
+        front => Some((front as usize, DRAWN.load(Ordering::Relaxed))),
+    }
+}
+
+// From the frame task. Returns where it went.
+pub fn copy(frame: &mut [u8], front: usize) -> Rect {
+    // Only idle writes a strip, and never the front one
+    let strip = unsafe { &(*STRIPS.0.get())[front] };
+    for (pixel, into) in strip.chunks_exact(2).zip(frame.chunks_exact_mut(2)) {
+        if u16::from_le_bytes([pixel[0], pixel[1]]) != CLEAR {
+            into.copy_from_slice(pixel);
+        }
+    }
+    Rect { x: 0, y: 0, w: W, h: H }
+}
+
+// From the idle task, as often as it likes. Returns false if there was
+// nothing new to draw.
+pub fn idle() -> bool {
+    let wanted = WANTED.load(Ordering::Acquire);
+    if wanted == DRAWN.load(Ordering::Relaxed) {
+        return false;
+    }
+    let status = STATUS.load(Ordering::Relaxed);
+    let values = Values {
+        score: SCORE.load(Ordering::Relaxed),
+        lives: status as u8,
+        low_battery: status & 1 << 8 != 0,
+    };
+    let back = match FRONT.load(Ordering::Relaxed) {
+        0 => 1,
+        _ => 0,
+    };
+    // The frame task only reads the front one
+    let strip = unsafe { &mut (*STRIPS.0.get())[back] };
+    draw_strip(strip, values);
+    // In this order, so a frame in between sees the new strip a frame late
+    // rather than an old one as new
+    FRONT.store(back as u8, Ordering::Release);
+    DRAWN.store(wanted, Ordering::Relaxed);
+    true
+}
+
+// Also for the simulator, which has no idle task
+pub fn draw_strip(strip: &mut [u8; STRIP_BYTES], values: Values) {
+    for pixel in strip.chunks_exact_mut(2) {
+        pixel.copy_from_slice(&CLEAR.to_le_bytes());
+    }
+    let white = rgb565(31, 63, 31);
+    let mut score = [0; 7];
+    text::digits(&mut score, values.score);
+    let digits = score.iter().position(|&c| c != b' ').unwrap_or(0);
+    text::draw(strip, 1, 1, &score[digits..], white);
+
+    // A pip the ship's color, then how many lives
+    let x = W - 1 - text::GLYPH_W;
+    text::draw(strip, x, 1, &[b'0' + values.lives.min(9)], white);
+    let pip = Rect { x: x - 4, y: 2, w: 3, h: 3 };
+    draw::fill_rect(strip, pip, rgb565(0, 63, 31));
+
+    if values.low_battery {
+        let x = pip.x - 2 - LOW_BATTERY.w;
+        draw::blit_sprite(strip, x, 2, &LOW_BATTERY, rgb565(31, 0, 0), false);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::limits::Limits;
+
+// Frames of a byte per pixel, each an index into a Palette of 256 RGB565
+// colors, for half the RAM of an RGB565 frame. The colors are only looked
+// up on the way out, a row at a time, so turning the palette animates
+// everything that's drawn with it without drawing any of it again.
+
+const WIDTH: usize = Limits::SCREEN_WIDTH;
+pub const BYTES: usize = Limits::SCREEN_WIDTH * Limits::SCREEN_HEIGHT;
+
+pub type Frame = [u8; BYTES];
+
+#[derive(Clone)]
+pub struct Palette {
+    colors: [u16; 256],
+    // How far round the colors have been turned
+    turn: u8,
+}
+
+impl Palette {
+    // All black
+    pub const fn new() -> Self {
+        Palette {
+            colors: [0; 256],
+            turn: 0,
+        }
+    }
+
+    // What `index` looks up before the palette's turned
+    pub fn set(&mut self, index: u8, color: u16) {
+        self.colors[index as usize] = color;
+    }
+
+    pub fn get(&self, index: u8) -> u16 {
+        self.colors[index.wrapping_add(self.turn) as usize]
+    }
+
+    // Every index looks up the color `turn` entries on from the one it was
+    // set to, the last coming round to the first. Costs nothing, however far
+    // it turns.
+    pub fn turn(&mut self, turn: u8) {
+        self.turn = turn;
+    }
+}
+
+// Each row of `frame`, looked up in `palette`
+pub fn rows<'a>(
+    frame: &'a Frame,
+    palette: &'a Palette,
+) -> impl Iterator<Item = [u16; WIDTH]> + 'a {
+    frame.chunks_exact(WIDTH).map(move |indices| {
+        let mut row = [0; WIDTH];
+        for (color, &index) in row.iter_mut().zip(indices) {
+            *color = palette.get(index);
+        }
+        row
+    })
+}
+
+// All of `frame` as little-endian RGB565 in `out`, for whatever has to draw
+// over it
+pub fn expand(frame: &Frame, palette: &Palette, out: &mut [u8]) {
+    let colors = rows(frame, palette).flat_map(IntoIterator::into_iter);
+    for (pixel, color) in out.chunks_exact_mut(2).zip(colors) {
+        pixel.copy_from_slice(&color.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turning_moves_every_index_along_the_palette() {
+        let mut palette = Palette::new();
+        palette.set(0, 0x1111);
+        palette.set(1, 0x2222);
+        palette.set(255, 0xFFFF);
+        assert_eq!((palette.get(0), palette.get(255)), (0x1111, 0xFFFF));
+        palette.turn(1);
+        assert_eq!((palette.get(0), palette.get(255)), (0x2222, 0x1111));
+        palette.turn(0);
+        assert_eq!(palette.get(1), 0x2222);
+    }
+
+    #[test]
+    fn frames_come_out_a_row_at_a_time_through_the_palette() {
+        let mut palette = Palette::new();
+        palette.set(7, 0xABCD);
+        palette.set(8, 0x1234);
+        let mut frame = [0; BYTES];
+        frame[WIDTH + 2] = 7;
+        let rows: Vec<_> = rows(&frame, &palette).collect();
+        assert_eq!(rows.len(), Limits::SCREEN_HEIGHT);
+        assert_eq!(rows[1][2], 0xABCD);
+        assert_eq!(rows[0][2], 0);
+
+        palette.turn(1);
+        let mut out = vec![0; Limits::FRAME_BYTES];
+        expand(&frame, &palette, &mut out);
+        let i = (WIDTH + 2) * 2;
+        assert_eq!(out[i..i + 2], 0x1234u16.to_le_bytes());
+    }
+}
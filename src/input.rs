@@ -0,0 +1,225 @@
+#[cfg(feature = "buttons")]
+use crate::buttons::ButtonState;
+#[cfg(feature = "encoder")]
+use crate::encoder::Encoder;
+use crate::game::Input;
+
+#[cfg(feature = "stick")]
+use crate::stick::Stick;
+#[cfg(feature = "stick")]
+use nrf52840_hal::gpio::{p0, Floating, Input as PinInput};
+
+// Axis travel per encoder detent turned in one frame
+#[cfg(feature = "encoder")]
+const ENCODER_GAIN: i32 = 48;
+
+// How holding fire behaves, from the settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Turbo {
+    pub enabled: bool,
+    // Frames the button has to be down before it counts as held
+    pub hold_frames: u8,
+    // Frames between repeated shots, 0 for as fast as the weapon's cooldown
+    // allows
+    pub repeat_frames: u8,
+}
+
+// What the fire button is doing this frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Press {
+    Up,
+    // Went down this frame
+    Tapped,
+    // Still down, but not yet for long enough to count as held
+    Down,
+    // Down for at least the hold threshold
+    Held,
+}
+
+// Tells taps from holds by counting how many frames fire has been down
+pub struct FireButton {
+    frames: u16,
+}
+
+impl FireButton {
+    pub const fn new() -> Self {
+        FireButton { frames: 0 }
+    }
+
+    pub fn update(&mut self, down: bool, hold_frames: u8) -> Press {
+        if !down {
+            self.frames = 0;
+            return Press::Up;
+        }
+        self.frames = self.frames.saturating_add(1);
+        if self.frames == 1 {
+            Press::Tapped
+        } else if self.frames > hold_frames as u16 {
+            Press::Held
+        } else {
+            Press::Down
+        }
+    }
+
+    // Whether to shoot this frame. A tap is always one shot. Once held, and
+    // only with turbo on, shots repeat every `repeat_frames`, and the ship's
+    // cooldown still has the last say.
+    pub fn shoot(&mut self, down: bool, turbo: Turbo) -> bool {
+        match self.update(down, turbo.hold_frames) {
+            Press::Tapped => true,
+            Press::Held if turbo.enabled => {
+                let held = (self.frames - turbo.hold_frames as u16 - 1) as u32;
+                held.is_multiple_of(turbo.repeat_frames.max(1) as u32)
+            }
+            _ => false,
+        }
+    }
+}
+
+// Every input device the board has been built with, polled once per frame
+pub struct Controls {
+    #[cfg(feature = "stick")]
+    pub stick: Stick<p0::P0_04<PinInput<Floating>>, p0::P0_05<PinInput<Floating>>>,
+    #[cfg(feature = "encoder")]
+    pub encoder: Encoder,
+    // The debounced buttons, from the pad as of this frame
+    #[cfg(feature = "buttons")]
+    pub buttons: ButtonState,
+    // Steering from tipping the board, 0 unless it's turned on
+    #[cfg(feature = "tilt")]
+    pub tilt: i8,
+    pub fire: FireButton,
+}
+
+impl Controls {
+    pub fn read(&mut self, turbo: Turbo) -> Input {
+        #[allow(unused_mut)]
+        let mut input = Input::default();
+
+        #[cfg(feature = "stick")]
+        {
+            let (x, _) = self.stick.read();
+            input.x = x;
+        }
+
+        // Spinning the encoder steers, faster for a faster turn. The stick
+        // wins if both are in use.
+        #[cfg(feature = "encoder")]
+        {
+            let steps = self.encoder.poll() as i32;
+            if input.x == 0 {
+                input.x = (steps * ENCODER_GAIN).clamp(-127, 127) as i8;
+            }
+        }
+
+        // Tipping the board steers when nothing else is
+        #[cfg(feature = "tilt")]
+        if input.x == 0 {
+            input.x = self.tilt;
+        }
+
+        #[cfg(feature = "buttons")]
+        {
+            input.left |= self.buttons.contains(ButtonState::LEFT);
+            input.right |= self.buttons.contains(ButtonState::RIGHT);
+            input.fire |= self.buttons.contains(ButtonState::A);
+        }
+
+        // Remote buttons add to the local ones, and the stick wins over the
+        // remote axis whenever it's off center
+        #[cfg(feature = "ble")]
+        {
+            let remote = crate::ble::remote_input();
+            input.left |= remote.left;
+            input.right |= remote.right;
+            input.fire |= remote.fire;
+            if input.x == 0 {
+                input.x = remote.x;
+            }
+        }
+
+        input.fire = self.fire.shoot(input.fire, turbo);
+        input
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TURBO: Turbo = Turbo {
+        enabled: true,
+        hold_frames: 8,
+        repeat_frames: 4,
+    };
+
+    // Which of `frames` frames of fire held then let go shoot
+    fn shots(button: &mut FireButton, turbo: Turbo, frames: usize) -> Vec<usize> {
+        let shots = (0..frames).filter(|_| button.shoot(true, turbo)).collect();
+        button.shoot(false, turbo);
+        shots
+    }
+
+    #[test]
+    fn presses_go_from_tapped_to_held() {
+        let mut button = FireButton::new();
+        assert_eq!(button.update(false, 3), Press::Up);
+        assert_eq!(button.update(true, 3), Press::Tapped);
+        assert_eq!(button.update(true, 3), Press::Down);
+        assert_eq!(button.update(true, 3), Press::Down);
+        assert_eq!(button.update(true, 3), Press::Held);
+        assert_eq!(button.update(true, 3), Press::Held);
+        assert_eq!(button.update(false, 3), Press::Up);
+        assert_eq!(button.update(true, 3), Press::Tapped);
+    }
+
+    #[test]
+    fn a_tap_is_one_shot() {
+        let mut button = FireButton::new();
+        // Let go just before it would count as held
+        assert_eq!(shots(&mut button, TURBO, 8), [0]);
+        // And again straight away, which is another tap
+        assert_eq!(shots(&mut button, TURBO, 1), [0]);
+    }
+
+    #[test]
+    fn holding_repeats_from_the_threshold() {
+        let mut button = FireButton::new();
+        // Frame 8 is the first with fire down for more than 8 frames
+        assert_eq!(shots(&mut button, TURBO, 20), [0, 8, 12, 16]);
+
+        let every_frame = Turbo {
+            repeat_frames: 0,
+            ..TURBO
+        };
+        assert_eq!(shots(&mut button, every_frame, 11), [0, 8, 9, 10]);
+
+        let at_once = Turbo {
+            hold_frames: 1,
+            ..TURBO
+        };
+        assert_eq!(shots(&mut button, at_once, 6), [0, 1, 5]);
+    }
+
+    #[test]
+    fn without_turbo_holding_is_still_one_shot() {
+        let mut button = FireButton::new();
+        let off = Turbo {
+            enabled: false,
+            ..TURBO
+        };
+        assert_eq!(shots(&mut button, off, 100), [0]);
+    }
+
+    #[test]
+    fn holding_for_ever_keeps_repeating() {
+        let mut button = FireButton {
+            frames: u16::MAX - 1,
+        };
+        let turbo = Turbo {
+            repeat_frames: 1,
+            ..TURBO
+        };
+        assert!((0..10).all(|_| button.shoot(true, turbo)));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::hud;
+use crate::limits::Limits;
+use crate::minimap;
+
+// What goes where on the glass. The game's frame is only 64x64, and the
+// glass is bigger, so a layout splits it into regions, each showing one
+// thing at its own offset from the glass's top left corner:
+//
+// - the playfield, the frame as the game draws it
+// - the HUD, hud.rs's strip of score and lives, which is otherwise copied in
+//   over the top of the playfield
+// - the minimap, the whole world at half size
+//
+// Anything a layout leaves out isn't drawn at all, and the glass it would
+// have gone on stays black. The playfield can be in a layout more than once,
+// up to MAX_PLAYFIELDS, with the same frame in each: that's MIRRORED, which
+// is how frames used to go out.
+
+pub const MAX_PLAYFIELDS: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Content {
+    Playfield,
+    Hud,
+    Minimap,
+}
+
+impl Content {
+    // In (columns, rows)
+    pub const fn size(self) -> (u16, u16) {
+        match self {
+            Content::Playfield => (Limits::SCREEN_WIDTH as u16, Limits::SCREEN_HEIGHT as u16),
+            Content::Hud => (hud::W as u16, hud::H as u16),
+            Content::Minimap => (minimap::W as u16, minimap::H as u16),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    pub content: Content,
+    // From the glass's top left corner
+    pub offset: (u16, u16),
+}
+
+const PLAYFIELD: (u16, u16) = Content::Playfield.size();
+const HUD: (u16, u16) = Content::Hud.size();
+
+// The HUD along the top, the playfield under it and the minimap beside that,
+// a couple of pixels apart so they don't run into each other
+pub const SPLIT: &[Region] = &[
+    Region {
+        content: Content::Hud,
+        offset: (0, 0),
+    },
+    Region {
+        content: Content::Playfield,
+        offset: (0, HUD.1 + 2),
+    },
+    Region {
+        content: Content::Minimap,
+        offset: (PLAYFIELD.0 + 3, HUD.1 + 2),
+    },
+];
+
+// The blank columns and rows between MIRRORED's playfields
+pub const MIRRORED_GAP: (u16, u16) = (3, 2);
+const MIRRORED_STEP: (u16, u16) = (PLAYFIELD.0 + MIRRORED_GAP.0, PLAYFIELD.1 + MIRRORED_GAP.1);
+
+// The frame four times over, one in each corner, top left first, then across
+// and down
+pub const MIRRORED: &[Region] = &[
+    Region {
+        content: Content::Playfield,
+        offset: (0, 0),
+    },
+    Region {
+        content: Content::Playfield,
+        offset: (MIRRORED_STEP.0, 0),
+    },
+    Region {
+        content: Content::Playfield,
+        offset: (0, MIRRORED_STEP.1),
+    },
+    Region {
+        content: Content::Playfield,
+        offset: MIRRORED_STEP,
+    },
+];
+
+// A layout put on the glass: where each region starts in the controller's
+// memory, with the glass's own offset added
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Screen {
+    playfields: [(u16, u16); MAX_PLAYFIELDS],
+    len: usize,
+    pub hud: Option<(u16, u16)>,
+    pub minimap: Option<(u16, u16)>,
+    // The scanline plasma can only stream MIRRORED, which its line buffers
+    // are laid out for
+    pub mirrored: bool,
+}
+
+impl Screen {
+    // `layout` on a `glass` of (columns, rows) that starts at `origin` in the
+    // controller's memory. Regions that run off the glass are still drawn,
+    // in case it's the size that's wrong, but warned about.
+    pub fn new(layout: &[Region], origin: (u16, u16), glass: (u16, u16)) -> Self {
+        let mut screen = Screen {
+            playfields: [(0, 0); MAX_PLAYFIELDS],
+            len: 0,
+            hud: None,
+            minimap: None,
+            mirrored: layout == MIRRORED,
+        };
+        for region in layout {
+            let ((x, y), (w, h)) = (region.offset, region.content.size());
+            if x + w > glass.0 || y + h > glass.1 {
+                log_warn!(
+                    "{:?} at ({}, {}) runs off the {}x{} glass",
+                    region.content,
+                    x,
+                    y,
+                    glass.0,
+                    glass.1
+                );
+            }
+            let at = (origin.0 + x, origin.1 + y);
+            match region.content {
+                Content::Playfield if screen.len == MAX_PLAYFIELDS => {
+                    log_warn!("More than {} playfields, leaving the rest out", MAX_PLAYFIELDS)
+                }
+                Content::Playfield => {
+                    screen.playfields[screen.len] = at;
+                    screen.len += 1;
+                }
+                Content::Hud => screen.hud = Some(at),
+                Content::Minimap => screen.minimap = Some(at),
+            }
+        }
+        screen
+    }
+
+    pub fn playfields(&self) -> &[(u16, u16)] {
+        &self.playfields[..self.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLASS: (u16, u16) = (128, 128);
+
+    fn fits(layout: &[Region], glass: (u16, u16)) -> bool {
+        layout.iter().all(|region| {
+            let ((x, y), (w, h)) = (region.offset, region.content.size());
+            x + w <= glass.0 && y + h <= glass.1
+        })
+    }
+
+    fn overlap(a: &Region, b: &Region) -> bool {
+        let ((ax, ay), (aw, ah)) = (a.offset, a.content.size());
+        let ((bx, by), (bw, bh)) = (b.offset, b.content.size());
+        ax < bx + bw && bx < ax + aw && ay < by + bh && by < ay + ah
+    }
+
+    #[test]
+    fn split_fits_a_128x128_glass_without_overlapping() {
+        assert!(fits(SPLIT, GLASS));
+        for (i, a) in SPLIT.iter().enumerate() {
+            for b in &SPLIT[i + 1..] {
+                assert!(!overlap(a, b), "{:?} and {:?} overlap", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn screens_put_each_region_at_its_offset_from_the_origin() {
+        let screen = Screen::new(SPLIT, (2, 1), GLASS);
+        assert_eq!(screen.playfields(), &[(2, 1 + HUD.1 + 2)]);
+        assert_eq!(screen.hud, Some((2, 1)));
+        assert_eq!(screen.minimap, Some((2 + PLAYFIELD.0 + 3, 1 + HUD.1 + 2)));
+        assert!(!screen.mirrored);
+
+        let screen = Screen::new(MIRRORED, (0, 0), GLASS);
+        assert_eq!(screen.playfields(), &[(0, 0), (67, 0), (0, 66), (67, 66)]);
+        assert_eq!((screen.hud, screen.minimap), (None, None));
+        assert!(screen.mirrored);
+    }
+
+    #[test]
+    fn playfields_past_the_most_that_go_out_are_left_out() {
+        let mut layout = MIRRORED.to_vec();
+        layout.push(Region {
+            content: Content::Playfield,
+            offset: (1, 1),
+        });
+        let screen = Screen::new(&layout, (0, 0), GLASS);
+        assert_eq!(screen.playfields().len(), MAX_PLAYFIELDS);
+        assert!(!screen.playfields().contains(&(1, 1)));
+    }
+}
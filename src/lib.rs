@@ -0,0 +1,132 @@
+// Everything but the RTIC app and the panic handler, which are in main.rs.
+// None of it needs the board to build, so it builds for the host as well,
+// and `cargo test-host` runs the tests of the parts that don't need it to
+// run either.
+#![cfg_attr(not(test), no_std)]
+// Lints about the API a library shows other crates, and nothing outside the
+// firmware uses this one. `new` is a const fn for statics, where Default
+// can't go, and the drivers underneath only ever fail with ().
+#![allow(clippy::new_without_default, clippy::result_unit_err, clippy::len_without_is_empty)]
+
+#[macro_use]
+pub mod logging;
+#[macro_use]
+pub mod profile;
+#[macro_use]
+pub mod budget;
+
+pub mod alignment;
+pub mod assets;
+pub mod background;
+#[cfg(feature = "backlight")]
+pub mod backlight;
+pub mod banner;
+pub mod board;
+#[cfg(any(feature = "console", feature = "ble"))]
+pub mod bootloader;
+pub mod boss;
+#[cfg(feature = "battery")]
+pub mod battery;
+#[cfg(feature = "buttons")]
+pub mod buttons;
+#[cfg(feature = "ble")]
+pub mod ble;
+#[cfg(feature = "ble")]
+pub mod ble_radio;
+#[cfg(feature = "shared-spi")]
+pub mod bus;
+#[cfg(feature = "flash")]
+pub mod checkpoint;
+pub mod clock;
+pub mod collision;
+pub mod color;
+pub mod compat;
+pub mod crash;
+#[cfg(feature = "console")]
+pub mod console;
+#[cfg(any(feature = "flash", feature = "ble"))]
+pub mod crc;
+#[cfg(feature = "diag")]
+pub mod diag;
+pub mod delay;
+pub mod demo;
+pub mod display;
+pub mod dma;
+pub mod draw;
+pub mod effect;
+pub mod error;
+pub mod fade;
+#[cfg(feature = "encoder")]
+pub mod encoder;
+pub mod fixed;
+pub mod framebuffer;
+pub mod game;
+pub mod gameloop;
+pub mod gfx;
+#[cfg(feature = "haptics")]
+pub mod haptics;
+pub mod history;
+pub mod hud;
+#[cfg(feature = "indexed")]
+pub mod indexed;
+pub mod input;
+pub mod layout;
+#[cfg(feature = "light")]
+pub mod light;
+pub mod limits;
+#[cfg(feature = "link")]
+pub mod link;
+pub mod metronome;
+pub mod minimap;
+pub mod mono;
+#[cfg(feature = "dma-frames")]
+pub mod pipeline;
+pub mod plasma;
+pub mod particles;
+pub mod pool;
+#[cfg(feature = "power-off")]
+pub mod power;
+pub mod quality;
+#[cfg(feature = "link")]
+pub mod radio;
+pub mod render;
+pub mod replay;
+// Only the log history needs all of it without the buzzer
+#[cfg_attr(not(feature = "sound"), allow(dead_code))]
+pub mod ring;
+pub mod rle;
+pub mod rng;
+#[cfg(feature = "scanline")]
+pub mod scanline;
+pub mod scene;
+pub mod scheduler;
+pub mod scores;
+pub mod screensaver;
+#[cfg(feature = "console")]
+pub mod screenshot;
+pub mod settings;
+pub mod sink;
+#[cfg(feature = "sound")]
+pub mod sound;
+#[cfg(feature = "st7789")]
+pub mod st7789;
+pub mod starfield;
+pub mod stats;
+#[cfg(feature = "stick")]
+pub mod stick;
+#[cfg(feature = "flash")]
+pub mod storage;
+pub mod text;
+pub mod tilemap;
+#[cfg(feature = "tilt")]
+pub mod tilt;
+pub mod timer;
+pub mod timescale;
+pub mod totals;
+pub mod trails;
+pub mod trig;
+#[cfg(feature = "usb")]
+pub mod usb;
+pub mod vignette;
+pub mod watchdog;
+pub mod wave;
@@ -0,0 +1,96 @@
+use embedded_hal::adc::{Channel, OneShot};
+use nrf52840_hal::saadc::{Saadc, SaadcConfig};
+use nrf52840_pac::SAADC;
+
+#[derive(Clone, Copy)]
+pub struct LightConfig {
+    // Brightness never goes outside this range, however dark or bright it is
+    pub min_brightness: u8,
+    pub max_brightness: u8,
+    // Raw readings at or below `dark` map to `min_brightness`, at or above
+    // `bright` to `max_brightness`
+    pub dark: i16,
+    pub bright: i16,
+    // How far each reading moves the level towards its target, as a shift:
+    // 3 covers 1/8 of the gap per reading
+    pub smoothing: u8,
+}
+
+impl LightConfig {
+    // A phototransistor pulling AIN6 up towards VDD with more light, with the
+    // default SAADC config
+    pub const DEFAULT: LightConfig = LightConfig {
+        min_brightness: 16,
+        max_brightness: 255,
+        dark: 500,
+        bright: 12000,
+        smoothing: 3,
+    };
+}
+
+// Turns ambient light readings into a brightness level. Readings are
+// smoothed so that passing shadows and mains flicker don't show.
+pub struct AutoBrightness {
+    config: LightConfig,
+    // Brightness with 8 fractional bits
+    level: i32,
+}
+
+impl AutoBrightness {
+    pub fn new(config: LightConfig) -> Self {
+        AutoBrightness {
+            config,
+            level: (config.max_brightness as i32) << 8,
+        }
+    }
+
+    pub fn update(&mut self, raw: i16) -> u8 {
+        let c = &self.config;
+        let (min, max) = (c.min_brightness as i32, c.max_brightness as i32);
+        let span = (c.bright as i32 - c.dark as i32).max(1);
+        let light = (raw as i32 - c.dark as i32).clamp(0, span);
+        let target = (min + (max - min) * light / span) << 8;
+
+        self.level += (target - self.level) >> c.smoothing;
+        (self.level >> 8).clamp(min, max) as u8
+    }
+}
+
+pub struct LightSensor<P> {
+    saadc: Saadc,
+    pin: P,
+}
+
+impl<P: Channel<Saadc, ID = u8>> LightSensor<P> {
+    pub fn new(saadc: SAADC, pin: P) -> Self {
+        LightSensor {
+            saadc: Saadc::new(saadc, SaadcConfig::default()),
+            pin,
+        }
+    }
+
+    // Blocks for one conversion
+    pub fn read(&mut self) -> Option<i16> {
+        self.saadc.read(&mut self.pin).ok()
+    }
+}
+
+pub struct AmbientLight<P> {
+    sensor: LightSensor<P>,
+    auto: AutoBrightness,
+}
+
+impl<P: Channel<Saadc, ID = u8>> AmbientLight<P> {
+    pub fn new(sensor: LightSensor<P>, config: LightConfig) -> Self {
+        AmbientLight {
+            sensor,
+            auto: AutoBrightness::new(config),
+        }
+    }
+
+    // The brightness to use now, or None if the sensor couldn't be read
+    pub fn sample(&mut self) -> Option<u8> {
+        let raw = self.sensor.read()?;
+        Some(self.auto.update(raw))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::game::{Bullet, Enemy, Explosion, PowerUp, Rect};
+use crate::minimap;
+use crate::particles::Particle;
+#[cfg(feature = "indexed")]
+use crate::plasma::Cycling;
+#[cfg(feature = "sound")]
+use crate::sound::{Note, CHANNELS};
+use core::mem::size_of;
+
+// Fixed capacities for everything that's statically allocated per entity or
+// per pixel. The `small-pools` feature halves the entity pools, for builds
+// that want the RAM for something else.
+pub struct Limits;
+
+impl Limits {
+    pub const BULLETS: usize = if cfg!(feature = "small-pools") { 4 } else { 8 };
+    pub const ENEMIES: usize = if cfg!(feature = "small-pools") { 4 } else { 8 };
+    pub const POWER_UPS: usize = if cfg!(feature = "small-pools") { 1 } else { 2 };
+    pub const EXPLOSIONS: usize = if cfg!(feature = "small-pools") { 2 } else { 4 };
+    pub const PARTICLES: usize = if cfg!(feature = "small-pools") { 8 } else { 16 };
+    // Foreground rects remembered for dirty-rect updates: one per sprite and
+    // ship, three for the boss, its health bar and its aiming line, plus some
+    // slack
+    pub const SPRITE_RECTS: usize = 2
+        + 3
+        + Self::BULLETS
+        + Self::ENEMIES
+        + Self::POWER_UPS
+        + Self::EXPLOSIONS
+        + Self::PARTICLES
+        + 4;
+    #[cfg(feature = "sound")]
+    pub const NOTES: usize = 16;
+
+    pub const SCREEN_WIDTH: usize = 64;
+    pub const SCREEN_HEIGHT: usize = 64;
+    pub const FRAME_BYTES: usize = Self::SCREEN_WIDTH * Self::SCREEN_HEIGHT * 2;
+    // The render buffer's back and front, and the cached background, which
+    // doubles as the outgoing frame during a fade. `dma-frames` sends from
+    // two more of its own.
+    #[cfg(not(feature = "dma-frames"))]
+    pub const FRAMEBUFFERS: usize = 3;
+    #[cfg(feature = "dma-frames")]
+    pub const FRAMEBUFFERS: usize = 5;
+    // One brightness byte per pixel
+    pub const VIGNETTE_BYTES: usize = Self::SCREEN_WIDTH * Self::SCREEN_HEIGHT;
+
+    pub const RAM_BYTES: usize = 256 * 1024;
+    // Kept free for the stack, which grows down towards the statics
+    pub const STACK_RESERVE: usize = 32 * 1024;
+}
+
+const POOL_BYTES: usize = size_of::<[Option<Bullet>; Limits::BULLETS]>()
+    + size_of::<[Option<Enemy>; Limits::ENEMIES]>()
+    + size_of::<[Option<PowerUp>; Limits::POWER_UPS]>()
+    + size_of::<[Option<Explosion>; Limits::EXPLOSIONS]>()
+    + size_of::<[Option<Particle>; Limits::PARTICLES]>()
+    + size_of::<[Rect; Limits::SPRITE_RECTS]>()
+    + NOTE_BYTES;
+
+// The synth's queues, one a channel, when there's sound
+#[cfg(feature = "sound")]
+const NOTE_BYTES: usize = size_of::<[Note; Limits::NOTES]>() * CHANNELS;
+#[cfg(not(feature = "sound"))]
+const NOTE_BYTES: usize = 0;
+
+// The palette plasma's frame of indices and its palette, on top of the
+// RGB565 frames everything else is drawn into
+#[cfg(feature = "indexed")]
+const CYCLING_BYTES: usize = size_of::<Cycling>();
+#[cfg(not(feature = "indexed"))]
+const CYCLING_BYTES: usize = 0;
+
+// The frame task keeps the minimap whether the layout shows it or not
+const FRAME_RAM: usize = Limits::FRAME_BYTES * Limits::FRAMEBUFFERS
+    + Limits::VIGNETTE_BYTES
+    + minimap::BYTES
+    + CYCLING_BYTES;
+
+const _: () = assert!(
+    POOL_BYTES + FRAME_RAM <= Limits::RAM_BYTES - Limits::STACK_RESERVE,
+    "entity pools and framebuffers don't fit in RAM alongside the stack"
+);
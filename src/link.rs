@@ -0,0 +1,342 @@
+use crate::game::Input;
+use crate::radio::{Radio, MAX_PAYLOAD};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use nrf52840_pac::{FICR, RADIO};
+
+// Lockstep input exchange between two boards over the radio (see radio.rs
+// for the channel and address). Every frame each board broadcasts its recent
+// inputs:
+//
+//   byte 0:      MAGIC
+//   bytes 1-4:   sender's device ID (FICR DEVICEID[0]), little-endian
+//   bytes 5-6:   packet sequence number, little-endian
+//   byte 7:      1 if the sender is in a session, 0 if it's still looking
+//   byte 8:      the sender's pairing code
+//   bytes 9-12:  the frame the first input below is for, little-endian
+//   then HISTORY inputs, newest first, two bytes each: buttons (bit 0 left,
+//   bit 1 right, bit 2 fire, bit 3 wants a versus game) and stick x as i8
+//
+// Only the two boards' inputs go over, never where anything is: with the
+// same inputs both run the same game, shots and all. Boards only pair up
+// with one on the same channel and with the same pairing code, so several
+// pairs can play in one room. Whether a new game is versus or co-op is up to
+// player one, and rides along with its inputs so both boards see it change
+// on the same frame.
+//
+// Local input is applied INPUT_DELAY frames after it's read, which gives the
+// packet carrying it that long to arrive. A frame only runs once the peer's
+// input for it is in, so both boards feed `advance_linked` the same inputs
+// and their worlds stay identical. Each packet repeats the last few inputs,
+// so a dropped packet is covered by the next one. A sequence gap only
+// counts towards `stats.lost`. If the peer stays silent for TIMEOUT_FRAMES, the
+// session is dropped.
+
+pub const INPUT_DELAY: u32 = 3;
+const HISTORY: usize = 4;
+// Has to cover how far ahead the peer can get, which is 2 * INPUT_DELAY
+const RING: usize = 16;
+const TIMEOUT_FRAMES: u32 = 120;
+
+const MAGIC: u8 = 0xA5;
+const HEADER_LEN: usize = 13;
+const PACKET_LEN: usize = HEADER_LEN + 2 * HISTORY;
+
+// Copies of the session's counts for `stats`, which isn't in the task that
+// owns the link
+static LINKED: AtomicBool = AtomicBool::new(false);
+static RECEIVED: AtomicU32 = AtomicU32::new(0);
+static LOST: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkStats {
+    // Packets from the peer that arrived, and ones that never showed up,
+    // since the session started
+    pub received: u32,
+    pub lost: u32,
+}
+
+impl LinkStats {
+    // Out of all the packets the peer sent, how many in a hundred were lost
+    pub fn loss_percent(&self) -> u32 {
+        match self.received + self.lost {
+            0 => 0,
+            sent => self.lost * 100 / sent,
+        }
+    }
+}
+
+// The session there is, if there is one
+pub fn stats() -> Option<LinkStats> {
+    LINKED.load(Ordering::Relaxed).then(|| LinkStats {
+        received: RECEIVED.load(Ordering::Relaxed),
+        lost: LOST.load(Ordering::Relaxed),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    // No peer, run the game on local input alone
+    Solo,
+    // A session with a peer just started. The game has to be reset to the
+    // same state on both ends.
+    Connected,
+    // Both players' inputs for the next frame, and whether player one wants
+    // a versus game
+    Linked([Input; 2], bool),
+    // The peer's input for this frame hasn't arrived yet; don't advance
+    Stalled,
+    // The peer went quiet and the session is over
+    Lost,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    frame: u32,
+    input: Input,
+    versus: bool,
+}
+
+const EMPTY: Slot = Slot {
+    frame: u32::MAX,
+    input: Input {
+        left: false,
+        right: false,
+        fire: false,
+        x: 0,
+    },
+    versus: false,
+};
+
+pub struct Link {
+    radio: Radio,
+    id: u32,
+    peer: Option<u32>,
+    // The next frame to run
+    frame: u32,
+    local: [Slot; RING],
+    remote: [Slot; RING],
+    seq: u16,
+    peer_seq: Option<u16>,
+    pub stats: LinkStats,
+    stalled: u32,
+    code: u8,
+}
+
+impl Link {
+    // Needs HFXO running
+    pub fn new(radio: RADIO) -> Self {
+        Link {
+            radio: Radio::new(radio),
+            id: device_id(),
+            peer: None,
+            frame: 0,
+            local: [EMPTY; RING],
+            remote: [EMPTY; RING],
+            seq: 0,
+            peer_seq: None,
+            stats: LinkStats {
+                received: 0,
+                lost: 0,
+            },
+            stalled: 0,
+            code: 0,
+        }
+    }
+
+    // The channel and pairing code to look for a peer with. Cheap enough to
+    // call every frame. After a change the peer's packets stop coming, and
+    // the session there was drops like any other lost link.
+    pub fn configure(&mut self, channel: u8, code: u8) {
+        if channel != self.radio.channel() {
+            self.radio.set_channel(channel);
+        }
+        self.code = code;
+    }
+
+    // Has to be called with the link in its final place, see `Radio::listen`
+    pub fn start(&mut self) {
+        self.radio.listen();
+    }
+
+    // Call once per frame with this board's input, and whether it would have
+    // a versus game
+    pub fn tick(&mut self, input: Input, versus: bool) -> Step {
+        let connected = self.poll();
+
+        let step = match self.peer {
+            None => Step::Solo,
+            Some(_) if connected => Step::Connected,
+            Some(peer) => match self.remote_slot(self.frame) {
+                Some(remote) => {
+                    self.stalled = 0;
+                    let ahead = self.frame + INPUT_DELAY;
+                    self.local[ahead as usize % RING] = Slot {
+                        frame: ahead,
+                        input,
+                        versus,
+                    };
+                    let local = self.local_slot(self.frame);
+                    self.frame += 1;
+
+                    // The lower device ID is always player one
+                    if self.id < peer {
+                        Step::Linked([local.input, remote.input], local.versus)
+                    } else {
+                        Step::Linked([remote.input, local.input], remote.versus)
+                    }
+                }
+                None => {
+                    self.stalled += 1;
+                    if self.stalled > TIMEOUT_FRAMES {
+                        self.peer = None;
+                        Step::Lost
+                    } else {
+                        Step::Stalled
+                    }
+                }
+            },
+        };
+
+        self.send();
+        LINKED.store(self.peer.is_some(), Ordering::Relaxed);
+        RECEIVED.store(self.stats.received, Ordering::Relaxed);
+        LOST.store(self.stats.lost, Ordering::Relaxed);
+        step
+    }
+
+    // Handles whatever arrived since the last frame. Returns true if a new
+    // session started.
+    fn poll(&mut self) -> bool {
+        let mut connected = false;
+        let mut buf = [0; MAX_PAYLOAD];
+
+        while let Some(len) = self.radio.receive(&mut buf) {
+            if len < PACKET_LEN || buf[0] != MAGIC {
+                continue;
+            }
+            let id = u32::from_le_bytes([buf[1], buf[2], buf[3], buf[4]]);
+            let seq = u16::from_le_bytes([buf[5], buf[6]]);
+            let in_session = buf[7] != 0;
+            let newest = u32::from_le_bytes([buf[9], buf[10], buf[11], buf[12]]);
+
+            if id == self.id || buf[8] != self.code {
+                continue;
+            }
+            match self.peer {
+                Some(peer) if peer != id => continue,
+                Some(_) if in_session => (),
+                // Either a new peer, or ours restarted and is looking again
+                _ => {
+                    self.connect(id);
+                    connected = true;
+                }
+            }
+
+            if let Some(prev) = self.peer_seq {
+                self.stats.lost += seq.wrapping_sub(prev).wrapping_sub(1) as u32;
+            }
+            self.peer_seq = Some(seq);
+            self.stats.received += 1;
+
+            if !in_session {
+                continue;
+            }
+            for i in 0..HISTORY {
+                let frame = match newest.checked_sub(i as u32) {
+                    Some(frame) if frame >= INPUT_DELAY => frame,
+                    _ => break,
+                };
+                let (input, versus) = decode(buf[HEADER_LEN + 2 * i], buf[HEADER_LEN + 1 + 2 * i]);
+                self.remote[frame as usize % RING] = Slot {
+                    frame,
+                    input,
+                    versus,
+                };
+            }
+        }
+
+        connected
+    }
+
+    fn connect(&mut self, peer: u32) {
+        self.peer = Some(peer);
+        self.frame = 0;
+        self.local = [EMPTY; RING];
+        self.remote = [EMPTY; RING];
+        self.peer_seq = None;
+        self.stats = LinkStats {
+            received: 0,
+            lost: 0,
+        };
+        self.stalled = 0;
+    }
+
+    fn send(&mut self) {
+        let mut packet = [0; PACKET_LEN];
+        packet[0] = MAGIC;
+        packet[1..5].copy_from_slice(&self.id.to_le_bytes());
+        packet[5..7].copy_from_slice(&self.seq.to_le_bytes());
+        packet[7] = self.peer.is_some() as u8;
+        packet[8] = self.code;
+
+        // The newest local input is for the last frame run, plus the delay
+        let newest = (self.frame + INPUT_DELAY).saturating_sub(1);
+        packet[9..13].copy_from_slice(&newest.to_le_bytes());
+        for i in 0..HISTORY {
+            let slot = newest
+                .checked_sub(i as u32)
+                .map(|frame| self.local_slot(frame))
+                .unwrap_or(EMPTY);
+            let (buttons, x) = encode(slot.input, slot.versus);
+            packet[HEADER_LEN + 2 * i] = buttons;
+            packet[HEADER_LEN + 1 + 2 * i] = x;
+        }
+
+        self.radio.send(&packet);
+        self.seq = self.seq.wrapping_add(1);
+    }
+
+    // Nobody presses anything for the first INPUT_DELAY frames, and nobody
+    // wants a versus game until they've had a chance to say
+    fn local_slot(&self, frame: u32) -> Slot {
+        let slot = self.local[frame as usize % RING];
+        if frame >= INPUT_DELAY && slot.frame == frame {
+            slot
+        } else {
+            EMPTY
+        }
+    }
+
+    fn remote_slot(&self, frame: u32) -> Option<Slot> {
+        let slot = self.remote[frame as usize % RING];
+        if frame < INPUT_DELAY {
+            Some(EMPTY)
+        } else if slot.frame == frame {
+            Some(slot)
+        } else {
+            None
+        }
+    }
+}
+
+fn encode(input: Input, versus: bool) -> (u8, u8) {
+    let buttons =
+        input.left as u8 | (input.right as u8) << 1 | (input.fire as u8) << 2 | (versus as u8) << 3;
+    (buttons, input.x as u8)
+}
+
+fn decode(buttons: u8, x: u8) -> (Input, bool) {
+    let input = Input {
+        left: buttons & 1 != 0,
+        right: buttons & 2 != 0,
+        fire: buttons & 4 != 0,
+        x: x as i8,
+    };
+    (input, buttons & 8 != 0)
+}
+
+fn device_id() -> u32 {
+    // FICR is read-only
+    let ficr = unsafe { &*FICR::ptr() };
+    ficr.deviceid[0].read().bits()
+}
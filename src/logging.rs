@@ -0,0 +1,181 @@
+use core::cell::Cell;
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+
+// Log sites go through the `log_*!` macros below rather than `rprintln!`.
+// A message is printed if its level is within both the compile-time cap
+// (the `max-level-*` features) and the runtime threshold (the `log` console
+// command). The cap is a constant, so anything above it is compiled out
+// altogether, and `release` compiles out everything. Printed messages are
+// also kept in `history`, for the panic handler, and with `usb` they go out
+// over USB serial too.
+//
+// With `defmt` they go out through defmt-rtt instead of RTT's print channel,
+// each at its level. The log sites are core::fmt's, so they're still
+// formatted on the board, and show up in defmt as strings.
+//
+// One module at a time can have a threshold of its own, `log radio trace`
+// say, for a closer look at one thing without the rest drowning it out.
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    #[cfg_attr(not(feature = "console"), allow(dead_code))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+}
+
+pub const MAX_LEVEL: Level = if cfg!(feature = "max-level-error") {
+    Level::Error
+} else if cfg!(feature = "max-level-warn") {
+    Level::Warn
+} else if cfg!(feature = "max-level-info") {
+    Level::Info
+} else if cfg!(feature = "max-level-debug") {
+    Level::Debug
+} else {
+    Level::Trace
+};
+
+// Longest module name that can have a threshold of its own
+pub const MODULE_LEN: usize = 12;
+
+// The last part of a module's path, `radio` for `pewpew::radio`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Module {
+    name: [u8; MODULE_LEN],
+    len: u8,
+}
+
+impl Module {
+    #[cfg_attr(not(feature = "console"), allow(dead_code))]
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name.len() > MODULE_LEN {
+            return None;
+        }
+        let mut module = Module {
+            name: [0; MODULE_LEN],
+            len: name.len() as u8,
+        };
+        module.name[..name.len()].copy_from_slice(name.as_bytes());
+        Some(module)
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.len as usize]).unwrap_or("")
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        path.rsplit("::").next() == Some(self.name())
+    }
+}
+
+static THRESHOLD: AtomicU8 = AtomicU8::new(Level::Info as u8);
+// The module with a threshold of its own, and that threshold, 0 while none
+// has one
+static MODULE: Mutex<Cell<Module>> = Mutex::new(Cell::new(Module {
+    name: [0; MODULE_LEN],
+    len: 0,
+}));
+static MODULE_THRESHOLD: AtomicU8 = AtomicU8::new(0);
+
+// Also drops any module's threshold of its own
+#[cfg_attr(not(feature = "console"), allow(dead_code))]
+pub fn set_threshold(level: Level) {
+    MODULE_THRESHOLD.store(0, Ordering::Relaxed);
+    THRESHOLD.store(level as u8, Ordering::Relaxed);
+}
+
+#[cfg_attr(not(feature = "console"), allow(dead_code))]
+pub fn set_module_threshold(module: Module, level: Level) {
+    MODULE_THRESHOLD.store(0, Ordering::Relaxed);
+    interrupt::free(|cs| MODULE.borrow(cs).set(module));
+    MODULE_THRESHOLD.store(level as u8, Ordering::Relaxed);
+}
+
+// `module` is the log site's `module_path!()`. Nothing's printed off the
+// board, in the host tests, where there's no RTT and no critical sections for
+// the history.
+#[inline(always)]
+pub fn enabled(level: Level, module: &str) -> bool {
+    if cfg!(feature = "release") || !cfg!(target_os = "none") || level > MAX_LEVEL {
+        return false;
+    }
+    let threshold = match MODULE_THRESHOLD.load(Ordering::Relaxed) {
+        0 => THRESHOLD.load(Ordering::Relaxed),
+        own if interrupt::free(|cs| MODULE.borrow(cs).get().contains(module)) => own,
+        _ => THRESHOLD.load(Ordering::Relaxed),
+    };
+    level as u8 <= threshold
+}
+
+// Prints a line whatever the thresholds, for the log and the panic handler
+#[cfg(not(feature = "defmt"))]
+pub fn print(_: Level, args: fmt::Arguments) {
+    rtt_target::rprintln!("{}", args);
+}
+
+#[cfg(feature = "defmt")]
+pub fn print(level: Level, args: fmt::Arguments) {
+    let args = defmt::Display2Format(&args);
+    match level {
+        Level::Error => defmt::error!("{}", args),
+        Level::Warn => defmt::warn!("{}", args),
+        Level::Info => defmt::info!("{}", args),
+        Level::Debug => defmt::debug!("{}", args),
+        Level::Trace => defmt::trace!("{}", args),
+    }
+}
+
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::logging::enabled($level, module_path!()) {
+            $crate::logging::print($level, format_args!($($arg)*));
+            $crate::history::record(format_args!($($arg)*));
+            #[cfg(feature = "usb")]
+            $crate::usb::print(format_args!($($arg)*));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Error, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Warn, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Info, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Debug, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)*) => { $crate::log_at!($crate::logging::Level::Trace, $($arg)*) };
+}
@@ -1,172 +1,2790 @@
 #![no_main]
 #![no_std]
 
-mod timer;
+#[macro_use]
+extern crate pewpew;
+
+#[cfg(all(feature = "light", feature = "stick"))]
+compile_error!("the `light` and `stick` features both need the SAADC");
+#[cfg(all(feature = "battery", any(feature = "light", feature = "stick")))]
+compile_error!("the `battery` feature needs the SAADC, which `light` and `stick` use");
+#[cfg(all(feature = "ble", feature = "link"))]
+compile_error!("the `ble` and `link` features both need the radio");
+#[cfg(all(feature = "scanline", feature = "shared-spi"))]
+compile_error!("the `scanline` renderer drives SPIM1 directly, so can't share it");
+#[cfg(all(feature = "scanline", feature = "indexed"))]
+compile_error!("the `scanline` and `indexed` features both stream the title plasma");
+#[cfg(all(feature = "dma-frames", feature = "shared-spi"))]
+compile_error!("the `dma-frames` pipeline drives SPIM1 directly, so can't share it");
+#[cfg(all(feature = "defmt", feature = "console"))]
+compile_error!("the `defmt` logger and the `console` both need RTT");
 
 use core::panic::PanicInfo;
+#[cfg(feature = "haptics")]
+use pewpew::haptics;
+use pewpew::logging::{self, Level};
+use pewpew::{crash, history};
 use rtic::app;
-use rtt_target::rprintln;
+#[cfg(feature = "defmt")]
+use defmt_rtt as _;
 
-#[app(device = pac, peripherals = true, dispatchers = [PDM, QDEC])]
+// The tasks' priorities and what they can each take are in src/budget.rs.
+// A dispatcher for each priority software tasks run at, render then input,
+// and one to spare for radio and audio.
+#[app(device = pac, peripherals = true, dispatchers = [PDM, QDEC, SWI0_EGU0])]
 mod app {
-    use crate::timer::Timer;
-    use embedded_graphics::image::{Image, ImageRaw, ImageRawLE};
-    use embedded_graphics::pixelcolor::Rgb565;
-    use embedded_graphics::prelude::*;
+    use pewpew::alignment;
+    use pewpew::assets;
+    use pewpew::background::{Background, BackgroundCache};
+    #[cfg(feature = "backlight")]
+    use pewpew::backlight::{self, BacklightConfig};
+    use pewpew::banner;
+    use pewpew::board::Board;
+    #[cfg(feature = "battery")]
+    use pewpew::battery::{self, Battery, BatteryConfig};
+    #[cfg(feature = "buttons")]
+    use pewpew::buttons::{self, ButtonState, Buttons, Pad};
+    #[cfg(feature = "ble")]
+    use pewpew::ble;
+    #[cfg(feature = "shared-spi")]
+    use pewpew::bus::{SharedSpi, SpiDevice};
+    #[cfg(feature = "flash")]
+    use pewpew::checkpoint;
+    use pewpew::clock;
+    use pewpew::color::rgb565;
+    use pewpew::compat::Compat;
+    use pewpew::crash;
+    #[cfg(feature = "console")]
+    use pewpew::console::{Command, LineBuffer};
+    #[cfg(feature = "usb")]
+    use pewpew::console::ParseError;
+    #[cfg(feature = "console")]
+    use pewpew::screenshot::{self, Screenshots};
+    use pewpew::delay;
+    use pewpew::demo::Demo;
+    use cortex_m::peripheral::DWT;
+    use pewpew::display::{self, Bringup, DisplayConfig};
+    #[cfg(feature = "st7789")]
+    use pewpew::display::DisplayDriver;
+    use pewpew::draw;
+    use pewpew::error::{FirmwareError, Recovery};
+    use pewpew::fade::Fade;
+    use pewpew::framebuffer::DoubleBuffer;
+    #[cfg(feature = "encoder")]
+    use pewpew::encoder::{Encoder, Quadrature};
+    use pewpew::game::{self, Events, State, World};
+    use pewpew::gameloop::{self, GameLoop};
+    use pewpew::gfx;
+    #[cfg(feature = "haptics")]
+    use pewpew::haptics::{Haptics, HapticsConfig, Rumble};
+    use pewpew::hud;
+    use pewpew::input::{Controls, FireButton};
+    use pewpew::layout::{Content, Screen};
+    #[cfg(feature = "light")]
+    use pewpew::light::{AmbientLight, LightConfig, LightSensor};
+    use pewpew::limits::Limits;
+    #[cfg(feature = "console")]
+    use pewpew::logging;
+    use pewpew::metronome::{self, Beats, Metronome};
+    use pewpew::minimap;
+    #[cfg(feature = "rtc-ticks")]
+    use pewpew::mono;
+    use pewpew::mono::{Duration, Mono, RtcMono};
+    #[cfg(feature = "dma-frames")]
+    use pewpew::pipeline::{self, Pipeline};
+    #[cfg(feature = "link")]
+    use pewpew::link::{self, Link, Step};
+    #[cfg(feature = "scanline")]
+    use pewpew::plasma::{Angle, Scalar};
+    use pewpew::plasma::{self, Variant};
+    #[cfg(feature = "power-off")]
+    use pewpew::power::{self, PowerOff, WakeButton};
+    #[cfg(feature = "profile")]
+    use pewpew::profile;
+    use pewpew::quality::{self, Quality};
+    use pewpew::render::{self, fill};
+    use pewpew::replay::Replay;
+    use pewpew::rle;
+    use pewpew::rng::Rng;
+    #[cfg(feature = "scanline")]
+    use pewpew::scanline;
+    use pewpew::scene::Scenes;
+    use pewpew::scheduler::Scheduler;
+    #[cfg(feature = "flash")]
+    use pewpew::scores;
+    use pewpew::scores::{Initials, Score, Table};
+    use pewpew::screensaver::{self, Screensaver};
+    #[cfg(feature = "flash")]
+    use pewpew::settings;
+    use pewpew::settings::Settings;
+    use pewpew::sink::{self, FrameSink};
+    #[cfg(feature = "sound")]
+    use pewpew::sound::{self, SfxId, Synth};
+    use pewpew::starfield::{Scroll, Starfield};
+    use pewpew::stats::RenderStats;
+    #[cfg(feature = "stick")]
+    use pewpew::stick::{Stick, StickConfig};
+    #[cfg(feature = "flash")]
+    use pewpew::storage::{Journal, Page, Storage};
+    use pewpew::text;
+    use pewpew::tilemap::Tilemap;
+    #[cfg(feature = "tilt")]
+    use pewpew::tilt::{self, Angles, ShakeConfig, SteerConfig, Tilt, TiltConfig};
+    #[cfg(feature = "sound")]
+    use pewpew::timer::{Compare, Timer};
+    use pewpew::timescale::TimeScale;
+    #[cfg(feature = "flash")]
+    use pewpew::totals;
+    use pewpew::totals::Totals;
+    use pewpew::trails::Trails;
+    #[cfg(feature = "usb")]
+    use pewpew::usb::{self, UsbClocks, UsbConsole};
+    use pewpew::vignette::Vignette;
+    use pewpew::watchdog::{self, Feeder, Heartbeat};
     use hal::clocks::{Clocks, LfOscConfiguration};
-    use hal::delay::Delay;
-    use hal::gpio::{p0, p1, Level, Output, PushPull};
+    use hal::gpio::{Output, Pin, PushPull};
+    #[cfg(any(feature = "sound", feature = "backlight", feature = "haptics"))]
+    use hal::pwm::{Channel, Prescaler, Pwm};
     use hal::spim;
+    #[cfg(any(feature = "backlight", feature = "haptics"))]
+    use hal::time::U32Ext;
     use nrf52840_hal as hal;
     use nrf52840_pac as pac;
-    use num_traits::float::Float;
-    use rtt_target::{rprintln, rtt_init_print};
-    use st7735_lcd;
-    use st7735_lcd::Orientation;
+    // Tasks get this anyway, but chores are plain functions
+    use rtic::mutex::prelude::*;
+    #[cfg(feature = "console")]
+    use rtt_target::{rprintln, DownChannel};
+    #[cfg(not(feature = "defmt"))]
+    use rtt_target::{rtt_init, set_print_channel};
+
+    const SCREEN_WIDTH: usize = Limits::SCREEN_WIDTH;
+    const SCREEN_HEIGHT: usize = Limits::SCREEN_HEIGHT;
+    const FRAME_BYTES: usize = Limits::FRAME_BYTES;
+
+    // Linked boards have to start from the same seed to stay in step
+    const SEED: u32 = 0x5EED;
+    // How long Ferris stays on the title screen after boot
+    const SPLASH_TICKS: u32 = 3 * gameloop::TICK_HZ;
+    // What `hud_sent` says once the HUD's region is black. The strip's own
+    // generations start from 1.
+    const HUD_BLANK: u32 = 0;
 
-    const SCREEN_WIDTH: usize = 64;
-    const SCREEN_HEIGHT: usize = 64;
+    type Frame = [u8; FRAME_BYTES];
+    type Buffers = DoubleBuffer<FRAME_BYTES>;
+    #[cfg(not(feature = "shared-spi"))]
+    type DisplaySpi = spim::Spim<pac::SPIM1>;
+    // The display gets its own chip select so other devices can sit on SPIM1
+    #[cfg(feature = "shared-spi")]
+    type DisplaySpi = SpiDevice<'static, spim::Spim<pac::SPIM1>, Pin<Output<PushPull>>>;
+    type Display = display::Display<Compat<DisplaySpi>, Compat<Pin<Output<PushPull>>>>;
+    type Panel = Bringup<Compat<Pin<Output<PushPull>>>, cortex_m::delay::Delay>;
+    #[cfg(feature = "dma-frames")]
+    type Frames = Pipeline;
+    #[cfg(not(feature = "dma-frames"))]
+    type Frames = ();
+    #[cfg(feature = "sound")]
+    type Sound = Synth<Compat<Pwm<pac::PWM0>>>;
+    #[cfg(feature = "sound")]
+    type SoundTimer = pac::TIMER2;
+    #[cfg(not(feature = "sound"))]
+    type SoundTimer = ();
+    #[cfg(not(feature = "sound"))]
+    type Sound = ();
+    // RTIC still types the arguments of a task that's cfg'd out
+    #[cfg(feature = "sound")]
+    type Sfx = SfxId;
+    #[cfg(not(feature = "sound"))]
+    type Sfx = ();
+    #[cfg(feature = "console")]
+    type ConsoleInput = DownChannel;
+    #[cfg(not(feature = "console"))]
+    type ConsoleInput = ();
+    #[cfg(feature = "console")]
+    type ConsoleLine = LineBuffer;
+    #[cfg(not(feature = "console"))]
+    type ConsoleLine = ();
+    #[cfg(feature = "console")]
+    type Shots = Screenshots;
+    #[cfg(not(feature = "console"))]
+    type Shots = ();
+    #[cfg(feature = "indexed")]
+    type Cycling = plasma::Cycling;
+    #[cfg(not(feature = "indexed"))]
+    type Cycling = ();
+    #[cfg(feature = "flash")]
+    type Flash = Storage;
+    #[cfg(not(feature = "flash"))]
+    type Flash = ();
+    // Something `chores` runs every so many frames
+    type Chore = fn(&mut chores::SharedResources);
+    // RTIC can't cfg out a local resource, so without a sensor this is empty
+    #[cfg(feature = "light")]
+    type Light = AmbientLight<pewpew::board::LightPin>;
+    #[cfg(not(feature = "light"))]
+    type Light = ();
+    #[cfg(feature = "backlight")]
+    type Backlight = backlight::Backlight<Compat<Pwm<pac::PWM1>>>;
+    #[cfg(not(feature = "backlight"))]
+    type Backlight = ();
+    #[cfg(feature = "haptics")]
+    type Motor = Haptics<Compat<Pwm<pac::PWM2>>>;
+    #[cfg(not(feature = "haptics"))]
+    type Motor = ();
+    #[cfg(feature = "haptics")]
+    type Rumbles = Rumble;
+    #[cfg(not(feature = "haptics"))]
+    type Rumbles = ();
+    #[cfg(feature = "battery")]
+    type Supply = Battery;
+    #[cfg(not(feature = "battery"))]
+    type Supply = ();
+    #[cfg(feature = "tilt")]
+    type Accelerometer = Tilt;
+    #[cfg(not(feature = "tilt"))]
+    type Accelerometer = ();
+    #[cfg(feature = "tilt")]
+    type TiltAngles = Angles;
+    #[cfg(not(feature = "tilt"))]
+    type TiltAngles = ();
+    #[cfg(feature = "link")]
+    type PeerLink = Link;
+    #[cfg(not(feature = "link"))]
+    type PeerLink = ();
+    #[cfg(feature = "ble")]
+    type BleLink = ble::Ble;
+    #[cfg(not(feature = "ble"))]
+    type BleLink = ();
+    #[cfg(feature = "ble")]
+    type BleResponder = rubble::link::Responder<ble::BleConfig>;
+    #[cfg(not(feature = "ble"))]
+    type BleResponder = ();
+    #[cfg(feature = "usb")]
+    type UsbSerial = UsbConsole;
+    #[cfg(not(feature = "usb"))]
+    type UsbSerial = ();
+    #[cfg(feature = "power-off")]
+    type Power = PowerOff;
+    #[cfg(not(feature = "power-off"))]
+    type Power = ();
+    #[cfg(feature = "power-off")]
+    type Wake = WakeButton;
+    #[cfg(not(feature = "power-off"))]
+    type Wake = ();
+    #[cfg(feature = "encoder")]
+    type EncoderPins = Quadrature;
+    #[cfg(not(feature = "encoder"))]
+    type EncoderPins = ();
+    #[cfg(feature = "buttons")]
+    type ButtonPins = Buttons;
+    #[cfg(not(feature = "buttons"))]
+    type ButtonPins = ();
+    #[cfg(feature = "buttons")]
+    type ButtonPad = Pad;
+    #[cfg(not(feature = "buttons"))]
+    type ButtonPad = ();
+    #[cfg(any(feature = "power-off", feature = "encoder", feature = "buttons", feature = "tilt"))]
+    type Gpio = hal::gpiote::Gpiote;
+    #[cfg(not(any(
+        feature = "power-off",
+        feature = "encoder",
+        feature = "buttons",
+        feature = "tilt"
+    )))]
+    type Gpio = ();
 
     #[shared]
-    struct Shared {}
+    struct Shared {
+        settings: Settings,
+        stats: RenderStats,
+        // What's on screen over the world, and whether it's frozen under it
+        scenes: Scenes,
+        // Showing the alignment grid instead of the game
+        grid: bool,
+        // Showing the FPS and CPU load in the corner
+        perf: bool,
+        synth: Sound,
+        world: World,
+        rng: Rng,
+        demo: Demo,
+        replay: Replay,
+        storage: Flash,
+        totals: Totals,
+        ble: BleLink,
+        // The frame being composed, in the back, and the one being sent, in
+        // the front. Anything that draws into it does so from inside a single
+        // lock that also covers sending it, so a frame can never go out half
+        // drawn by one task and half by another. Lock it together with
+        // `world` when drawing the game, rather than one inside the other, so
+        // nothing can change between drawing the world and sending it.
+        buffers: Buffers,
+        // What's left of the last frame going out, with `dma-frames`
+        frames: Frames,
+        // The debounced buttons, with `buttons`
+        pad: ButtonPad,
+        // How the board's tipped as of the accelerometer's last reading,
+        // with `tilt`
+        angles: TiltAngles,
+        // The vibration motor, with `haptics`
+        haptics: Motor,
+    }
 
     #[local]
     struct Local {
-        timer1: pac::TIMER1,
-        disp: st7735_lcd::ST7735<
-            spim::Spim<pac::SPIM1>,
-            p1::P1_08<Output<PushPull>>,
-            p0::P0_07<Output<PushPull>>,
-        >,
-        bytes: [u8; SCREEN_HEIGHT * SCREEN_WIDTH * 2],
+        timer2: SoundTimer,
+        metronome: Metronome,
+        heartbeat: Heartbeat,
+        feeder: Feeder,
+        beats: Beats,
+        disp: Display,
+        // For bringing the panel up again after boot
+        bringup: Panel,
+        // How sending frames to the panel is going
+        recovery: Recovery,
+        // Size of the controller's memory, which the regions must fit
+        panel: (u16, u16),
+        // Where each region of the layout goes on it
+        screen: Screen,
+        minimap: [u8; minimap::BYTES],
+        // Index of the orientation the panel is in
+        orientation: u8,
+        background_cache: BackgroundCache<FRAME_BYTES>,
+        fade: Fade,
+        vignette: Vignette<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        trails: Trails,
+        far_stars: Starfield<24>,
+        near_stars: Starfield<12>,
+        scroll: Scroll,
+        tilemap: Tilemap,
+        // Only used for picking how things look, so it can be truly random
+        // without upsetting linked games
+        looks: Rng,
+        plasma_variant: u8,
+        // The title plasma with `indexed`
+        cycling: Cycling,
+        quality: Quality,
         t: u32,
+        // The HUD strip's generation as of when it was last sent, or
+        // HUD_BLANK once its region's been blacked out
+        hud_sent: u32,
+        console_input: ConsoleInput,
+        console_line: ConsoleLine,
+        screenshots: Shots,
+        screensaver: Screensaver,
+        scores: Table,
+        // Set while initials are being entered for a new high score
+        initials: Option<Initials>,
+        controls: Controls,
+        time_scale: TimeScale,
+        game_loop: GameLoop,
+        chores: Scheduler<Chore, 4>,
+        light: Light,
+        backlight: Backlight,
+        battery: Supply,
+        tilt: Accelerometer,
+        ble_responder: BleResponder,
+        link: PeerLink,
+        power: Power,
+        wake: Wake,
+        quadrature: EncoderPins,
+        buttons: ButtonPins,
+        gpiote: Gpio,
+        usb: UsbSerial,
     }
 
+    // Everything that's scheduled rather than bound to an interrupt. RTIC's
+    // scheduling code can't leave out a software task that's cfg'd out, so
+    // the ones that go with a feature are always there, and do nothing
+    // without it.
+    #[monotonic(binds = TIMER1, default = true)]
+    type MonoTimer = Mono;
+    // For everything that doesn't need TIMER1's precision, which with
+    // `rtc-ticks` is everything but frames during play, so TIMER1 can stop
+    // between the rest
+    #[cfg(feature = "rtc-ticks")]
+    #[monotonic(binds = RTC2)]
+    type RtcTimer = RtcMono;
+
     #[init]
     fn init(mut ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        #[cfg(feature = "diag")]
+        pewpew::diag::paint_stack();
+
         // Configure to use external clocks, and start them
-        Clocks::new(ctx.device.CLOCK)
+        #[cfg_attr(not(feature = "usb"), allow(unused_variables))]
+        let clocks = Clocks::new(ctx.device.CLOCK)
             .enable_ext_hfosc()
             .set_lfclk_src_external(LfOscConfiguration::NoExternalNoBypass)
             .start_lfclk();
 
         ctx.core.DCB.enable_trace();
         ctx.core.DWT.enable_cycle_counter();
-        rtt_init_print!();
-        rprintln!("RTT initialized");
+        #[cfg(feature = "console")]
+        let channels = rtt_init! {
+            up: {
+                0: {
+                    size: 1024
+                    name: "Terminal"
+                }
+                // screenshot::CHANNEL_SIZE
+                1: {
+                    size: 8449
+                    name: "Screenshot"
+                }
+            }
+            down: {
+                0: {
+                    size: 64
+                    name: "Terminal"
+                }
+            }
+        };
+        // Nothing to read commands from, so just the one way
+        #[cfg(not(any(feature = "console", feature = "defmt")))]
+        let channels = rtt_init! {
+            up: {
+                0: {
+                    size: 1024
+                    name: "Terminal"
+                }
+            }
+        };
+        // defmt-rtt sets up RTT itself
+        #[cfg(not(feature = "defmt"))]
+        set_print_channel(channels.up.0);
+        log_debug!("RTT initialized");
+
+        banner::log(&ctx.device.POWER);
+        clock::log(&ctx.device.RTC0);
 
-        let interval = 1_000;
+        let mono = Mono::new(ctx.device.TIMER1);
+        #[cfg(feature = "rtc-ticks")]
+        let rtc_mono = RtcMono::new(ctx.device.RTC2);
+        frame::spawn_after(Duration::millis(1)).ok();
 
-        let mut timer1 = ctx.device.TIMER1;
-        timer1.init();
-        timer1.fire_at(1, interval);
+        let metronome = Metronome::new(ctx.device.TIMER3);
 
-        rprintln!("Timers initialized");
-        // Set up GPIO ports
-        let p0 = p0::Parts::new(ctx.device.P0);
-        let p1 = p1::Parts::new(ctx.device.P1);
+        log_debug!("Timers initialized");
+        let board = Board::new(ctx.device.P0, ctx.device.P1);
 
-        let mut delay = Delay::new(ctx.core.SYST);
+        let mut delay = Compat(delay::new(ctx.device.TIMER0));
 
-        let spiclk = p0.p0_14.into_push_pull_output(Level::Low).degrade();
-        let spimosi = p0.p0_13.into_push_pull_output(Level::Low).degrade();
         let pins = spim::Pins {
-            sck: spiclk,
+            sck: board.spi_sck,
             miso: None,
-            mosi: Some(spimosi),
-        };
-        rprintln!("SPIM initialized");
-        let spim = spim::Spim::new(ctx.device.SPIM1, pins, spim::Frequency::M8, spim::MODE_0, 0);
-        let dc = p1.p1_08.into_push_pull_output(Level::Low);
-        let rst = p0.p0_07.into_push_pull_output(Level::Low);
-        let mut disp = st7735_lcd::ST7735::new(
-            spim,
-            dc,
-            rst,
-            true,
-            false,
-            SCREEN_WIDTH as u32,
-            SCREEN_HEIGHT as u32,
+            mosi: Some(board.spi_mosi),
+        };
+        #[cfg(feature = "flash")]
+        let mut storage = Storage::new(ctx.device.NVMC);
+        #[cfg(feature = "flash")]
+        let settings = match Journal::Settings.latest(&storage, settings::LEN) {
+            Some(saved) => {
+                let mut settings = Settings::load(saved).unwrap_or_default();
+                if settings.validate() {
+                    log_warn!("Saved settings were out of range, some are back to defaults");
+                }
+                settings
+            }
+            None => Settings::default(),
+        };
+        #[cfg(not(feature = "flash"))]
+        let settings = Settings::default();
+        metronome::set_bpm(settings.bpm);
+        let config = DisplayConfig {
+            orientation: settings.orientation(),
+            ..DisplayConfig::DEFAULT
+        };
+        config.log();
+        let spim = spim::Spim::new(
+            ctx.device.SPIM1,
+            pins,
+            config.spi_frequency,
+            config.spi_mode,
+            0,
         );
-        disp.init(&mut delay).unwrap();
-        disp.set_orientation(&Orientation::LandscapeSwapped)
-            .unwrap();
-        disp.set_offset(0, 0);
-        disp.clear(Rgb565::BLACK).unwrap();
-        rprintln!("Display initialized");
+        log_debug!("SPIM initialized");
 
-        // draw ferris
-        // let bytes = *include_bytes!("ferris.raw");
-        // rprintln!("Displaying image");
+        #[cfg(feature = "shared-spi")]
+        let spim = {
+            let bus: &'static SharedSpi<_> =
+                cortex_m::singleton!(: SharedSpi<spim::Spim<pac::SPIM1>> = SharedSpi::new(spim))
+                    .unwrap();
+            crash::set_chip_select(&board.display_cs);
+            bus.device(board.display_cs)
+        };
+        let dc = board.display_dc;
+        #[cfg(feature = "dma-frames")]
+        let frames = Pipeline::new(&dc);
+        #[cfg(not(feature = "dma-frames"))]
+        let frames = ();
+        let mut rst = Compat(board.display_rst);
+        let size = (SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+        let panel = display::ram_size(config.orientation);
+        let screen = config.screen();
+        crash::set_screen(&dc, panel, screen.playfields());
+        let mut disp = display::new(Compat(spim), Compat(dc), &config, size.0, size.1);
+        let init = |disp: &mut Display| display::init(disp, &mut rst, &mut delay, &config);
+        match bring_up(&mut disp, &config, init) {
+            Ok(attempts) => {
+                log_debug!("Display init took {} attempt(s)", attempts);
+                log_info!("Display initialized");
+            }
+            // There's still the console, and the frame task tries again
+            // if frames stop going out
+            Err(err) => log_error!("Display didn't come up ({:?}), carrying on", err),
+        }
+
+        #[cfg(feature = "sound")]
+        let (synth, timer2) = {
+            let pwm = Pwm::new(ctx.device.PWM0);
+            // 16 MHz counting to 255, for a carrier of four PWM periods a
+            // sample
+            pwm.set_prescaler(Prescaler::Div1)
+                .set_max_duty(sound::MAX_DUTY)
+                .set_output_pin(Channel::C0, board.buzzer);
+            let mut timer2 = ctx.device.TIMER2;
+            timer2.init();
+            // Until there's something to play
+            timer2.pause();
+            log_debug!("Synth initialized");
+            (Synth::new(Compat(pwm)), timer2)
+        };
+        #[cfg(not(feature = "sound"))]
+        let (synth, timer2) = ((), ());
+
+        #[cfg(feature = "stick")]
+        let stick = {
+            let (x_pin, y_pin) = board.stick;
+            let stick = Stick::new(ctx.device.SAADC, x_pin, y_pin, StickConfig::DEFAULT);
+            let (x, y) = stick.center();
+            log_info!("Stick calibrated, center = ({}, {})", x, y);
+            stick
+        };
+
+        #[cfg(feature = "light")]
+        let light = {
+            let sensor = LightSensor::new(ctx.device.SAADC, board.light);
+            AmbientLight::new(sensor, LightConfig::DEFAULT)
+        };
+        #[cfg(not(feature = "light"))]
+        let light = ();
+
+        #[cfg(feature = "battery")]
+        let battery = Battery::new(ctx.device.SAADC, BatteryConfig::DEFAULT, config.spi_frequency);
+        #[cfg(not(feature = "battery"))]
+        let battery = ();
+
+        #[cfg(feature = "backlight")]
+        let backlight = {
+            // Undivided, for as many dimming steps as the frequency allows.
+            // The counter is 15 bits, so this can't go below 489 Hz.
+            let config = BacklightConfig::DEFAULT;
+            let pwm = Pwm::new(ctx.device.PWM1);
+            pwm.set_prescaler(Prescaler::Div1)
+                .set_output_pin(Channel::C0, board.backlight);
+            pwm.set_period(config.frequency_hz.hz());
+            backlight::Backlight::new(Compat(pwm), config)
+        };
+        #[cfg(not(feature = "backlight"))]
+        let backlight = ();
+
+        #[cfg(feature = "haptics")]
+        let haptics = {
+            let config = HapticsConfig::DEFAULT;
+            let pwm = Pwm::new(ctx.device.PWM2);
+            pwm.set_prescaler(Prescaler::Div1)
+                .set_output_pin(Channel::C0, board.motor);
+            pwm.set_period(config.frequency_hz.hz());
+            Haptics::new(Compat(pwm), config)
+        };
+        #[cfg(not(feature = "haptics"))]
+        let haptics = ();
+
+        #[cfg(any(
+            feature = "power-off",
+            feature = "encoder",
+            feature = "buttons",
+            feature = "tilt"
+        ))]
+        let gpiote = hal::gpiote::Gpiote::new(ctx.device.GPIOTE);
+        #[cfg(not(any(
+            feature = "power-off",
+            feature = "encoder",
+            feature = "buttons",
+            feature = "tilt"
+        )))]
+        let gpiote = ();
+
+        #[cfg(feature = "tilt")]
+        let tilt = {
+            let pins = hal::twim::Pins {
+                sda: board.tilt_sda,
+                scl: board.tilt_scl,
+            };
+            let (config, shake) = (TiltConfig::DEFAULT, ShakeConfig::DEFAULT);
+            Tilt::new(ctx.device.TWIM0, pins, board.tilt_int, &gpiote, config, shake)
+        };
+        #[cfg(not(feature = "tilt"))]
+        let tilt = ();
+
+        // The panel has to be brought back up after powering off, or if it
+        // stops taking frames, by which point TIMER0 is gone, so its delays
+        // come from SysTick
+        let systick = cortex_m::delay::Delay::new(ctx.core.SYST, clock::CPU_HZ);
+        let bringup = Bringup::new(rst, systick, config);
+        #[cfg(feature = "power-off")]
+        let (power, wake) = {
+            #[cfg(feature = "backlight-switch")]
+            let load_switch = Some(board.load_switch);
+            #[cfg(not(feature = "backlight-switch"))]
+            let load_switch = None;
+            let power = PowerOff::new(load_switch);
+            let wake = WakeButton::new(&gpiote, board.wake);
+            (power, wake)
+        };
+        #[cfg(not(feature = "power-off"))]
+        let (power, wake) = ((), ());
+
+        #[cfg(feature = "encoder")]
+        let quadrature = {
+            let [a, b] = board.encoder;
+            Quadrature::new(&gpiote, a, b)
+        };
+        #[cfg(not(feature = "encoder"))]
+        let quadrature = ();
+
+        #[cfg(feature = "buttons")]
+        let buttons = Buttons::new(&gpiote, board.buttons, ctx.device.RTC1);
+        #[cfg(not(feature = "buttons"))]
+        let buttons = ();
+        #[cfg(feature = "buttons")]
+        let pad = Pad::new();
+        #[cfg(not(feature = "buttons"))]
+        let pad = ();
+
+        // Bring-up is done with blocking delays, after that TIMER0 is only
+        // needed for BLE
+        let _timer0 = delay.0.free();
+        #[cfg(feature = "ble")]
+        let (ble, ble_responder) = {
+            let link = ble::init(ctx.device.RADIO, _timer0);
+            log_info!("BLE advertising");
+            link
+        };
+        #[cfg(not(feature = "ble"))]
+        let (ble, ble_responder) = ((), ());
+
+        #[cfg(feature = "link")]
+        let link = Link::new(ctx.device.RADIO);
+        #[cfg(not(feature = "link"))]
+        let link = ();
+
+        #[cfg(feature = "flash")]
+        crash::load(&mut storage);
+        #[cfg(feature = "flash")]
+        let (world, resumed) = match checkpoint::load(storage.read(Page::Checkpoint)) {
+            Some(world) => {
+                // One resume per save, so dying doesn't bring it back
+                storage.erase(Page::Checkpoint);
+                log_info!("Resumed from checkpoint at score {}, paused", world.score);
+                (world, true)
+            }
+            None => (World::new(), false),
+        };
+        #[cfg(feature = "flash")]
+        let scores = match Journal::Scores.latest(&storage, scores::LEN) {
+            Some(saved) => Table::load(saved),
+            // Older firmware wrote the table straight into the page
+            None => Table::load(storage.read(Page::Scores)),
+        };
+        #[cfg(feature = "flash")]
+        let totals = Totals::load(storage.read(Page::Totals));
+        // Without flash a game always starts afresh, and scores and totals
+        // only last until the next reset
+        #[cfg(not(feature = "flash"))]
+        let (storage, world, resumed, scores, totals) =
+            ((), World::new(), false, Table::new(), Totals::new());
+        #[cfg(feature = "ble")]
+        ble::publish_scores(&scores);
+        let mut scenes = Scenes::new(world.state);
+        if resumed {
+            scenes.pause();
+        }
+
+        let mut rng = Rng::new(SEED);
+        let far_stars = Starfield::new(&mut rng, 1, rgb565(12, 24, 12));
+        let near_stars = Starfield::new(&mut rng, 2, rgb565(31, 63, 31));
+        let tilemap = Tilemap::new(&mut rng);
+        let mut looks = Rng::new(hal::Rng::new(ctx.device.RNG).random_u32());
+        let plasma_variant = match settings.plasma {
+            plasma::RANDOM => looks.below(plasma::VARIANTS.len() as u32) as u8,
+            index => index,
+        };
+
+        let mut chores: Scheduler<Chore, 4> = Scheduler::new();
+        chores.add(600, 600, log_stats).ok();
+        #[cfg(feature = "flash")]
+        chores.add(SAVE_TOTALS_FRAMES, SAVE_TOTALS_FRAMES, save_totals).ok();
+        #[cfg(feature = "flash")]
+        chores.add(SAVE_SETTINGS_FRAMES, SAVE_SETTINGS_FRAMES, save_settings).ok();
+        #[cfg(feature = "diag")]
+        chores.add(512, 512, report_ram).ok();
+
+        #[cfg(feature = "usb")]
+        let usb = {
+            let clocks = cortex_m::singleton!(: UsbClocks = clocks).unwrap();
+            UsbConsole::new(ctx.device.USBD, clocks)
+        };
+        #[cfg(not(feature = "usb"))]
+        let usb = ();
+
+        // Last, so that none of the setup counts against the first frame
+        let feeder = Feeder::new(ctx.device.WDT);
+        let heartbeat = Heartbeat::new(ctx.device.RTC0);
 
         // We're all set up, hand off control back to RTIC
-        let shared = Shared {};
+        let shared = Shared {
+            settings,
+            stats: RenderStats::new(),
+            scenes,
+            grid: false,
+            perf: cfg!(feature = "perf-overlay"),
+            synth,
+            world,
+            rng,
+            demo: Demo::new(),
+            replay: Replay::new(),
+            storage,
+            totals,
+            ble,
+            buffers: DoubleBuffer::new(),
+            frames,
+            pad,
+            #[cfg(feature = "tilt")]
+            angles: Angles::default(),
+            #[cfg(not(feature = "tilt"))]
+            angles: (),
+            haptics,
+        };
 
         let local = Local {
-            timer1,
+            timer2,
+            metronome,
+            heartbeat,
+            feeder,
+            beats: Beats::new(),
             disp,
-            bytes: [0; SCREEN_HEIGHT * SCREEN_WIDTH * 2],
+            bringup,
+            recovery: Recovery::new(),
+            panel,
+            screen,
+            minimap: [0; minimap::BYTES],
+            orientation: settings.orientation,
+            background_cache: BackgroundCache::new(),
+            fade: Fade::new(),
+            vignette: Vignette::new(),
+            trails: Trails::new(),
+            far_stars,
+            near_stars,
+            scroll: Scroll::new(),
+            tilemap,
+            looks,
+            plasma_variant,
+            #[cfg(feature = "indexed")]
+            cycling: Cycling::new(),
+            #[cfg(not(feature = "indexed"))]
+            cycling: (),
+            quality: Quality::new(),
             t: 0,
+            hud_sent: 0,
+            #[cfg(feature = "console")]
+            console_input: channels.down.0,
+            #[cfg(not(feature = "console"))]
+            console_input: (),
+            #[cfg(feature = "console")]
+            console_line: LineBuffer::new(),
+            #[cfg(not(feature = "console"))]
+            console_line: (),
+            #[cfg(feature = "console")]
+            screenshots: Screenshots::new(channels.up.1),
+            #[cfg(not(feature = "console"))]
+            screenshots: (),
+            screensaver: Screensaver::new(),
+            scores,
+            initials: None,
+            controls: Controls {
+                #[cfg(feature = "stick")]
+                stick,
+                #[cfg(feature = "encoder")]
+                encoder: Encoder::new(),
+                #[cfg(feature = "buttons")]
+                buttons: ButtonState::default(),
+                #[cfg(feature = "tilt")]
+                tilt: 0,
+                fire: FireButton::new(),
+            },
+            time_scale: TimeScale::new(),
+            game_loop: GameLoop::new(DWT::cycle_count()),
+            chores,
+            light,
+            backlight,
+            battery,
+            tilt,
+            ble_responder,
+            link,
+            power,
+            wake,
+            quadrature,
+            buttons,
+            gpiote,
+            usb,
         };
 
-        (shared, local, init::Monotonics())
+        #[cfg(feature = "rtc-ticks")]
+        return (shared, local, init::Monotonics(mono, rtc_mono));
+        #[cfg(not(feature = "rtc-ticks"))]
+        (shared, local, init::Monotonics(mono))
     }
 
-    #[task(binds = TIMER1, local = [
-        timer1,
+    #[task(local = [
+        beats,
         disp,
-        bytes,
+        bringup,
+        recovery,
+        panel,
+        screen,
+        minimap,
+        orientation,
+        background_cache,
+        fade,
+        vignette,
+        trails,
+        far_stars,
+        near_stars,
+        tilemap,
+        looks,
+        plasma_variant,
+        cycling,
+        scroll,
+        quality,
         t,
+        hud_sent,
+        screenshots,
+        screensaver,
+        scores,
+        initials,
+        controls,
+        time_scale,
+        game_loop,
+        backlight,
+        link,
+        power,
+    ], shared = [
+        settings, stats, scenes, grid, perf, world, rng, demo, replay, storage, totals, buffers,
+        frames, pad, angles
     ])]
-    fn timer1(ctx: timer1::Context) {
-        let timer = ctx.local.timer1;
+    fn frame(mut ctx: frame::Context) {
+        let start = DWT::cycle_count();
+        profile_scope!("frame");
+        within_budget!("frame", RENDER_US);
+
+        let beats = ctx.local.beats;
         let disp = ctx.local.disp;
-        let bytes = ctx.local.bytes;
+        let bringup = ctx.local.bringup;
+        let recovery = ctx.local.recovery;
+        let panel = ctx.local.panel;
+        let screen = &*ctx.local.screen;
+        let tiles = screen.playfields();
+        let map = ctx.local.minimap;
+        let orientation = ctx.local.orientation;
+        let background_cache = ctx.local.background_cache;
+        let fade = ctx.local.fade;
+        let vignette = ctx.local.vignette;
+        let trails = ctx.local.trails;
+        let (far_stars, near_stars) = (ctx.local.far_stars, ctx.local.near_stars);
+        let scroll = ctx.local.scroll;
+        let tilemap = ctx.local.tilemap;
+        let looks = ctx.local.looks;
+        let plasma_variant = ctx.local.plasma_variant;
+        #[cfg(feature = "indexed")]
+        let cycling = ctx.local.cycling;
+        let quality = ctx.local.quality;
         let t = ctx.local.t;
+        let hud_sent = ctx.local.hud_sent;
+        #[cfg(feature = "console")]
+        let screenshots = ctx.local.screenshots;
+        let screensaver = ctx.local.screensaver;
+        let scores = ctx.local.scores;
+        let initials = ctx.local.initials;
+        let time_scale = ctx.local.time_scale;
+        let game_loop = ctx.local.game_loop;
+        let steps = game_loop.steps(start);
+        // What the plasma animates by
+        let ticks = game_loop.ticks();
+        #[cfg(feature = "power-off")]
+        let power = ctx.local.power;
+        #[cfg(feature = "link")]
+        let link = ctx.local.link;
+        #[cfg(feature = "link")]
+        if *t == 0 {
+            link.start();
+        }
+
+        // Powered off, nothing to do until the wake button brings the panel
+        // back. It comes back blank, so the next frame has to be sent whole.
+        #[cfg(feature = "power-off")]
+        if power.is_off() {
+            if !power.try_wake(disp, bringup) {
+                return;
+            }
+            background_cache.invalidate();
+            // It's back in the orientation it booted in
+            *orientation = u8::MAX;
+            screensaver.input();
+            log_info!("Woken up");
+        }
+
+        let settings = ctx.shared.settings.lock(|settings| {
+            // Only here, where the settings can be locked
+            if let Some(level) = display::take_brightness() {
+                settings.auto_brightness = false;
+                settings.brightness = level;
+            }
+            *settings
+        });
+        #[cfg(feature = "link")]
+        link.configure(settings.link_channel, settings.link_code);
+        #[cfg(feature = "ble")]
+        ble::publish_settings(&settings);
+        // Turned from the console or by the accelerometer. The controller's
+        // memory is another shape the other way round, and what's on the
+        // panel has to be sent again.
+        if settings.orientation != *orientation {
+            *orientation = settings.orientation;
+            #[cfg(feature = "dma-frames")]
+            pipeline::wait();
+            if disp.set_orientation(&settings.orientation()).is_err() {
+                log_warn!("Couldn't change the display's orientation");
+            }
+            *panel = display::ram_size(settings.orientation());
+            background_cache.invalidate();
+        }
+        // B opens the pause screen over a game and the menu over the title,
+        // and closes them again, once per press however long it's held
+        #[cfg(feature = "buttons")]
+        {
+            let (held, events) = ctx.shared.pad.lock(|pad| pad.take());
+            ctx.local.controls.buttons = held;
+            if !screensaver.is_active() {
+                let mut scenes = (&mut ctx.shared.scenes, &mut ctx.shared.settings);
+                let paused = scenes.lock(|scenes, settings| {
+                    let was = scenes.is_paused();
+                    scenes.handle_input(events.press(), settings);
+                    !was && scenes.is_paused()
+                });
+                if paused {
+                    save_on_pause(
+                        &mut ctx.shared.world,
+                        &mut ctx.shared.storage,
+                        &mut ctx.shared.totals,
+                    );
+                }
+            }
+        }
+        #[cfg(feature = "tilt")]
+        {
+            let angles = ctx.shared.angles.lock(|angles| *angles);
+            ctx.local.controls.tilt = match settings.tilt_steering {
+                true => tilt::steer(angles, settings.orientation, &SteerConfig::DEFAULT),
+                false => 0,
+            };
+        }
+        let input = ctx.local.controls.read(settings.turbo());
+        #[allow(unused_mut)]
+        let mut any_input = input != game::Input::default();
+        #[cfg(feature = "buttons")]
+        {
+            any_input |= ctx.local.controls.buttons != ButtonState::default();
+        }
+        // Whatever wakes the screensaver doesn't also count in the game
+        let input = match any_input && screensaver.input() {
+            true => {
+                background_cache.invalidate();
+                log_debug!("Screensaver off");
+                game::Input::default()
+            }
+            false => input,
+        };
+        #[cfg(all(feature = "backlight", feature = "power-off"))]
+        ctx.local.backlight.set(power.dim(settings.brightness));
+        #[cfg(all(feature = "backlight", not(feature = "power-off")))]
+        ctx.local.backlight.set(settings.brightness);
+        // On the world's state as of the last frame
+        let scenes = (&mut ctx.shared.scenes, &mut ctx.shared.world).lock(|scenes, world| {
+            scenes.update(world.state);
+            *scenes
+        });
+        let paused = scenes.is_paused();
+        let grid = ctx.shared.grid.lock(|grid| *grid);
+        // As of the last frame, like the totals below
+        let perf = ctx.shared.perf.lock(|perf| *perf);
+        let mut stats = ctx.shared.stats;
+        let perf = perf.then(|| stats.lock(|stats| *stats));
+        watchdog::suspend(paused);
+        if vignette.set_enabled(settings.vignette) {
+            background_cache.invalidate();
+        }
+
+        let mut spi_bytes = 0;
+        // Cycle count sending to the panel started at
+        let mut flush_start = start;
+        #[cfg(feature = "flash")]
+        let mut storage = ctx.shared.storage;
+        let mut totals = ctx.shared.totals;
+        #[cfg(feature = "dma-frames")]
+        let mut frames = ctx.shared.frames;
+        // As of the last frame, which is near enough for showing them
+        let (played, lines) = totals.lock(|totals| (totals.games > 0, totals.lines()));
+        // The demo or a replay, which don't count as anyone playing
+        let mut canned = false;
+        let mut shared = (
+            ctx.shared.buffers,
+            ctx.shared.world,
+            ctx.shared.rng,
+            ctx.shared.demo,
+            ctx.shared.replay,
+            ctx.shared.scenes,
+        );
+        shared.lock(|buffers, world, rng, demo, replay, scenes| {
+            let bytes = buffers.back();
+            canned = demo.is_active() || replay.is_playing();
+            // Everything stands still under the grid, and comes back whole
+            // once it's gone
+            if grid {
+                world.events = Events::default();
+                // Standing still on purpose
+                watchdog::check_in(watchdog::Task::Game);
+                fill(bytes, 0);
+                alignment::draw(bytes);
+                background_cache.invalidate();
+                flush_start = DWT::cycle_count();
+                spi_bytes += recovery.sent(blank_regions(disp, *panel, screen, hud_sent));
+                let bytes = buffers.present();
+                #[cfg(feature = "dma-frames")]
+                let sent = send_frame(&mut frames, *panel, tiles, bytes);
+                #[cfg(not(feature = "dma-frames"))]
+                let sent = send_frame(disp, *panel, tiles, bytes);
+                spi_bytes += recovery.sent(sent);
+                return;
+            }
+            // The same goes for the screensaver
+            if screensaver.is_active() {
+                world.events = Events::default();
+                watchdog::check_in(watchdog::Task::Game);
+                screensaver.draw(bytes, settings.screensaver, |frame, scroll| {
+                    far_stars.draw(frame, scroll);
+                    near_stars.draw(frame, scroll);
+                });
+                flush_start = DWT::cycle_count();
+                spi_bytes += recovery.sent(blank_regions(disp, *panel, screen, hud_sent));
+                let bytes = buffers.present();
+                #[cfg(feature = "dma-frames")]
+                let sent = send_frame(&mut frames, *panel, tiles, bytes);
+                #[cfg(not(feature = "dma-frames"))]
+                let sent = send_frame(disp, *panel, tiles, bytes);
+                spi_bytes += recovery.sent(sent);
+                return;
+            }
 
-        timer.ack_compare_event(1);
+            let prev_state = world.state;
+            let demo_running = demo.is_active();
+            if paused {
+                world.events = Events::default();
+            } else if let Some(entry) = initials.as_mut() {
+                // The game over screen waits for the initials
+                world.events = Events::default();
+                if let Some(name) = entry.update(input) {
+                    *initials = None;
+                    scores.insert(Score {
+                        initials: name,
+                        score: world.score,
+                    });
+                    #[cfg(feature = "ble")]
+                    ble::publish_scores(scores);
+                    #[cfg(feature = "flash")]
+                    {
+                        let mut save = [0; scores::LEN];
+                        scores.save(&mut save);
+                        match storage.lock(|storage| Journal::Scores.append(storage, &save)) {
+                            Ok(()) => log_info!("High score {} saved", world.score),
+                            Err(err) => log_error!("Couldn't save the high score: {:?}", err),
+                        }
+                    }
+                }
+            } else if demo_running {
+                // The demo plays alone, whatever the link is doing
+                gameloop::run(world, steps, |world| {
+                    if let Some(input) = demo.step(world) {
+                        game::advance_frame(world, input, rng);
+                    }
+                });
+            } else {
+                #[cfg(feature = "link")]
+                match link.tick(input, settings.versus) {
+                    Step::Solo => gameloop::run(world, steps, |world| {
+                        step_solo(world, input, rng, time_scale, replay, settings.speed)
+                    }),
+                    Step::Linked(inputs, versus) => {
+                        game::advance_linked(world, inputs, versus, rng)
+                    }
+                    Step::Connected => {
+                        replay.stop(world);
+                        *world = World::new();
+                        *rng = Rng::new(SEED);
+                        log_info!("Linked with another board");
+                    }
+                    Step::Stalled => world.events = Events::default(),
+                    Step::Lost => {
+                        world.partner = None;
+                        world.events = Events::default();
+                        log_warn!(
+                            "Link lost ({} packets dropped, {}%)",
+                            link.stats.lost,
+                            link.stats.loss_percent()
+                        );
+                    }
+                }
+                #[cfg(not(feature = "link"))]
+                gameloop::run(world, steps, |world| {
+                    step_solo(world, input, rng, time_scale, replay, settings.speed)
+                });
+            }
+            watchdog::check_in(watchdog::Task::Game);
+            // Linked games skip this, since the link can't wait on one
+            // board's initials
+            canned = demo_running || replay.is_playing();
+            let game_over = prev_state == State::Playing && world.state == State::GameOver;
+            let solo = !canned && world.partner.is_none();
+            if game_over && solo && scores.qualifies(world.score) {
+                *initials = Some(Initials::new());
+            }
+            if beats.poll() {
+                world.events.insert(Events::BEAT);
+            }
+            #[cfg(feature = "sound")]
+            if settings.sound {
+                play_events(world.events);
+            }
+            // Nobody's holding the board for the demo or a replay
+            #[cfg(feature = "haptics")]
+            if !canned {
+                rumble_events(world.events);
+            }
 
-        for i in 0..SCREEN_HEIGHT {
-            for j in 0..SCREEN_WIDTH {
-                let x = i as f32 / SCREEN_HEIGHT as f32;
-                let y = j as f32 / SCREEN_WIDTH as f32;
-                let r = 0.5 + 0.5 * (*t as f32 + x + 0.0).cos();
-                let g = 0.5 + 0.5 * (*t as f32 + y + 2.0).cos();
-                let b = 0.5 + 0.5 * (*t as f32 + x + 4.0).cos();
+            // A fresh look each time the title screen comes back, which then
+            // stays put until the next time
+            *plasma_variant = match settings.plasma {
+                plasma::RANDOM if world.state == State::Title && prev_state != State::Title => {
+                    looks.below(plasma::VARIANTS.len() as u32) as u8
+                }
+                plasma::RANDOM => *plasma_variant,
+                index => index,
+            };
+            let variant = Variant::get(*plasma_variant);
 
-                let r5 = (r * 31.0) as u16;
-                let g6 = (g * 63.0) as u16;
-                let b5 = (b * 31.0) as u16;
+            // The root follows the world as soon as it's moved on, so that
+            // goes from the frame before as well as anything pushed or popped
+            scenes.update(world.state);
+            if scenes.take_changed() {
+                fade.start(bytes, background_cache.lend(), settings.transition);
+            }
 
-                let x = (b5 << 11) + (g6 << 5) + r5;
-                let [hi, low] = x.to_le_bytes();
+            let background = Background::of(world.state, &settings);
+            #[cfg(feature = "indexed")]
+            if background == Background::Plasma {
+                cycling.update(ticks, *plasma_variant);
+            }
 
-                bytes[(i * SCREEN_HEIGHT + j) * 2 + 0] = hi;
-                bytes[(i * SCREEN_HEIGHT + j) * 2 + 1] = low;
+            let ship_center = world.ship.x + game::SHIP_W / 2;
+            for _ in 0..steps {
+                scroll.advance(ship_center - SCREEN_WIDTH as i32 / 2);
+                tilemap.advance();
             }
-        }
 
-        let image_raw: ImageRawLE<Rgb565> = ImageRaw::new(bytes, SCREEN_WIDTH as u32);
-        let image = Image::new(&image_raw, Point::new(0, 0));
+            // The high scores and totals, rolling up the title screen
+            let roll = !scores.is_empty() || played;
+            let splash = world.state == State::Title && ticks < SPLASH_TICKS;
+            // Streamed straight to the panel, so there's no frame to draw into.
+            // Fades need one, so they go the long way. So do the roll and
+            // Ferris, which have to be drawn on top. The logo is unpacked a
+            // row at a time. It only knows the mirrored layout.
+            // The same goes for the palette plasma, which can be looked up on
+            // its way to any layout, but not for the menu over it either
+            #[cfg(feature = "indexed")]
+            if background == Background::Plasma
+                && !fade.is_active()
+                && !roll
+                && !splash
+                && !paused
+            {
+                fade.mark_stale();
+                background_cache.flush_dirty(world.sprites(), |_| ());
+                let pixels = || cycling_pixels(cycling, vignette, &assets::TITLE);
+                #[cfg(feature = "dma-frames")]
+                let sent = send_pixels(&mut frames, *panel, tiles, pixels);
+                #[cfg(not(feature = "dma-frames"))]
+                let sent = send_pixels(disp, *panel, tiles, pixels);
+                spi_bytes = recovery.sent(sent);
+                if let Some(offset) = screen.minimap {
+                    minimap::draw(map, world);
+                    spi_bytes += recovery.sent(send_minimap(disp, *panel, offset, map));
+                }
+                return;
+            }
+            #[cfg(feature = "scanline")]
+            if screen.mirrored
+                && background == Background::Plasma
+                && !fade.is_active()
+                && !roll
+                && !splash
+            {
+                fade.mark_stale();
+                background_cache.flush_dirty(world.sprites(), |_| ());
+                #[cfg(feature = "dma-frames")]
+                pipeline::wait();
+                let sent = plasma_lines(disp, tiles[0], ticks, variant, vignette, &assets::TITLE);
+                spi_bytes = recovery.sent(sent);
+                return;
+            }
 
-        disp.set_offset(0, 0);
-        image.draw(disp).unwrap();
-        disp.set_offset(67, 0);
-        image.draw(disp).unwrap();
-        disp.set_offset(0, 66);
-        image.draw(disp).unwrap();
-        disp.set_offset(67, 66);
-        image.draw(disp).unwrap();
+            // Trails only fade to a plain color. Everything else is drawn
+            // fresh every frame anyway.
+            let trail_color = match background {
+                Background::Solid(color) if settings.trails > 0 => Some(color),
+                _ => None,
+            };
+            let cached = trail_color.is_none() && background_cache.restore(background, bytes);
+            if let Some(color) = trail_color {
+                trails.apply(bytes, color, settings.trails);
+                background_cache.invalidate();
+            } else if !cached {
+                profile_scope!("background");
+                match background {
+                    #[cfg(feature = "indexed")]
+                    Background::Plasma => cycling.render(bytes),
+                    #[cfg(not(feature = "indexed"))]
+                    Background::Plasma => {
+                        plasma::render(bytes, ticks, quality.level(), variant, u8::MAX)
+                    }
+                    Background::Backdrop(intensity) => {
+                        plasma::render(bytes, ticks, quality.level(), variant, intensity)
+                    }
+                    Background::Starfield => {
+                        fill(bytes, 0);
+                        // The far layer is the first thing to go
+                        if quality.level() > 0 {
+                            far_stars.draw(bytes, scroll);
+                        }
+                        near_stars.draw(bytes, scroll);
+                    }
+                    Background::Tiles => tilemap.draw(bytes),
+                    Background::Solid(color) => fill(bytes, color),
+                }
+                vignette.apply(bytes);
+                background_cache.store(background, bytes);
+            }
+            if trail_color.is_none() {
+                trails.stop();
+            }
+
+            // A pixel of shade around everything keeps it readable against
+            // the plasma, since some of its colors are as bright as sprites
+            if let Background::Backdrop(_) = background {
+                for rect in world.sprites().chain(initials.as_ref().map(Initials::rect)) {
+                    let halo = game::Rect {
+                        x: rect.x - 1,
+                        y: rect.y - 1,
+                        w: rect.w + 2,
+                        h: rect.h + 2,
+                    };
+                    draw::darken_rect(bytes, halo);
+                }
+            }
+            if world.state != State::Title {
+                render::draw_world(bytes, world, settings.dither_edges);
+            }
+            let whole = game::Rect {
+                x: 0,
+                y: 0,
+                w: SCREEN_WIDTH as i32,
+                h: SCREEN_HEIGHT as i32,
+            };
+            // He's wider than the screen, so his claws are cut off
+            let ferris = splash.then(|| {
+                let x = (SCREEN_WIDTH as i32 - assets::FERRIS.w) / 2;
+                gfx::blit_keyed(bytes, &assets::FERRIS, x, 0, assets::KEY);
+                whole
+            });
+            // Then the logo, under the roll
+            let logo = (world.state == State::Title && !splash).then(|| {
+                rle::draw(bytes, &assets::TITLE, assets::KEY);
+                whole
+            });
+            // Text isn't one of the world's sprites, so its area has to be
+            // sent along with theirs
+            let text = rgb565(31, 63, 31);
+            let overlay = match initials {
+                Some(entry) => Some(entry.draw(bytes, text)),
+                None if world.state == State::Title && roll => {
+                    let heading: &[u8] = b"TOTALS";
+                    let after = [heading, &lines[0], &lines[1], &lines[2], &lines[3]];
+                    let after: &[&[u8]] = if played { &after } else { &[] };
+                    scores.draw_scrolling(bytes, world.ticks, after, text)
+                }
+                None if world.state == State::GameOver && world.versus => {
+                    Some(render::draw_versus(bytes, world, text))
+                }
+                None => None,
+            };
+            // Then whatever scene is showing, over all of that
+            let scene = scenes.render(bytes, world, &settings);
+            let overlay = overlay.into_iter().chain(scene);
+            #[cfg(feature = "battery")]
+            let battery_low = battery::is_low();
+            #[cfg(not(feature = "battery"))]
+            let battery_low = false;
+            // The HUD has it during a game
+            #[cfg(feature = "battery")]
+            let low_battery =
+                (battery_low && world.state == State::Title).then(|| draw_low_battery(bytes));
+            #[cfg(not(feature = "battery"))]
+            let low_battery = None;
+            // Drawn by the idle task, and only sent when that's drawn it again
+            let hud = match world.state {
+                State::Title => None,
+                _ => {
+                    hud::show(hud::Values {
+                        score: world.score,
+                        lives: world.lives,
+                        low_battery: battery_low,
+                    });
+                    hud::front()
+                }
+            };
+            let hud = match screen.hud {
+                // Over the playfield
+                None => hud.and_then(|(front, generation)| {
+                    let rect = hud::copy(bytes, front);
+                    (core::mem::replace(hud_sent, generation) != generation).then_some(rect)
+                }),
+                // Or in a region of its own, black outside a game, sent again
+                // whenever it changes or the rest goes out whole
+                Some(offset) => {
+                    let generation = hud.map_or(HUD_BLANK, |(_, generation)| generation);
+                    if core::mem::replace(hud_sent, generation) != generation || !cached {
+                        let front = hud.map(|(front, _)| front);
+                        spi_bytes += recovery.sent(send_hud(disp, *panel, offset, front));
+                    }
+                    None
+                }
+            };
+            if let Some(offset) = screen.minimap {
+                minimap::draw(map, world);
+                spi_bytes += recovery.sent(send_minimap(disp, *panel, offset, map));
+            }
+            let perf = perf.map(|stats| draw_perf(bytes, &stats));
+
+            // Every frame of a fade is new, and `cached` is always false
+            // while the cache's buffer is lent, so this still goes out whole
+            if !fade.apply(background_cache.lent(), bytes) {
+                background_cache.reclaim();
+            }
+            #[cfg(feature = "console")]
+            match screenshots.take(bytes) {
+                Some(0) => log_info!("Screenshot sent"),
+                Some(dropped) => log_warn!("Screenshot sent, {} rows dropped", dropped),
+                None => (),
+            }
+
+            flush_start = DWT::cycle_count();
+            let bytes = buffers.present();
+            if cached {
+                let dirty = world.sprites().chain(overlay).chain(low_battery).chain(hud);
+                background_cache.flush_dirty(dirty.chain(ferris).chain(logo).chain(perf), |rect| {
+                    spi_bytes += recovery.sent(send_rect(disp, *panel, tiles, bytes, rect));
+                });
+            } else {
+                // Nothing to send piecemeal, but the cache still has to learn
+                // where the sprites are
+                let dirty = world.sprites().chain(overlay).chain(low_battery).chain(hud);
+                background_cache.flush_dirty(dirty.chain(ferris).chain(logo).chain(perf), |_| ());
+                #[cfg(feature = "dma-frames")]
+                let sent = send_frame(&mut frames, *panel, tiles, bytes);
+                #[cfg(not(feature = "dma-frames"))]
+                let sent = send_frame(disp, *panel, tiles, bytes);
+                spi_bytes += recovery.sent(sent);
+            }
+        });
+
+        // What didn't go out has to be sent again, and if nothing's gone out
+        // for a while the panel's brought up again from scratch
+        if let Some((err, reinit)) = recovery.frame_done() {
+            background_cache.invalidate();
+            log_warn!("Frame didn't go out: {:?}", err);
+            if reinit {
+                log_error!("Display isn't taking frames, bringing it up again");
+                #[cfg(feature = "dma-frames")]
+                pipeline::wait();
+                let config = *bringup.config();
+                match bring_up(disp, &config, |disp| bringup.init(disp)) {
+                    Ok(_) => log_info!("Display back up"),
+                    Err(err) => log_error!("Display didn't come back up: {:?}", err),
+                }
+                // It's back in the orientation it booted in
+                *orientation = u8::MAX;
+            }
+        }
 
         *t = t.wrapping_add(1);
+        watchdog::check_in(watchdog::Task::Render);
+        let end = DWT::cycle_count();
+        let elapsed = clock::cycles_to_us(end.wrapping_sub(start));
+        let flushed = clock::cycles_to_us(end.wrapping_sub(flush_start));
+        // Always leave a little time for the lower priority tasks
+        let period = match screensaver.is_active() {
+            true => 1_000_000 / screensaver::SAVER_FPS,
+            false => settings.frame_period_us(),
+        };
+        let wait = period.saturating_sub(elapsed).max(1000);
+        let dropped = elapsed > quality::BUDGET_US;
+        stats.lock(|stats| {
+            stats.record(elapsed, flushed, elapsed + wait, spi_bytes, dropped)
+        });
+        (&mut shared.1, &mut totals).lock(|world, totals| {
+            totals.update(world, !paused && !grid && !canned, elapsed + wait)
+        });
+        if quality.update(elapsed) {
+            log_debug!("quality = {} (last frame {} us)", quality.level(), elapsed);
+        }
+
+        #[cfg(feature = "console")]
+        poll_console::spawn().ok();
+        // If the last lot haven't run yet they'll pick up where they left off
+        chores::spawn(*t).ok();
+
+        #[cfg(feature = "light")]
+        if t.is_multiple_of(64) {
+            sample_light::spawn().ok();
+        }
+
+        // The accelerometer's data ready interrupt is what reads it. This is
+        // only in case the read after an edge failed, which would leave INT1
+        // high with no edge to come.
+        #[cfg(feature = "tilt")]
+        if t.is_multiple_of(64) {
+            check_tilt::spawn().ok();
+        }
+
+        #[cfg(feature = "battery")]
+        if t.is_multiple_of(256) {
+            check_battery::spawn().ok();
+        }
+
+        // Save what's worth keeping while there's still the voltage to write
+        // flash, then turn everything off
+        #[cfg(feature = "battery")]
+        if battery::is_flat() {
+            log_warn!("Shutting down");
+            #[cfg(feature = "flash")]
+            {
+                flush_settings(&mut ctx.shared.settings, &mut storage);
+                flush_totals(&mut totals, &mut storage);
+                if save_checkpoint(&mut shared.1, &mut storage) {
+                    log_info!("Checkpoint saved");
+                }
+            }
+            #[cfg(feature = "dma-frames")]
+            pipeline::wait();
+            #[cfg(feature = "power-off")]
+            power.power_off(bringup);
+            battery::system_off();
+        }
+
+        // Not in the middle of a game, which no input is going to end soon
+        // enough anyway
+        let playing =
+            shared.1.lock(|world| world.state == State::Playing && !paused && !canned);
+        let timeout = if playing { 0 } else { settings.screensaver_us() };
+        if !any_input && screensaver.idle(elapsed + wait, timeout) {
+            background_cache.invalidate();
+            log_info!("No input for {} min, screensaver on", settings.screensaver_mins);
+        }
+
+        // Nothing's scheduled, the wake button restarts frames
+        #[cfg(feature = "power-off")]
+        if power.tick(input != game::Input::default(), elapsed + wait, settings.power_off_us()) {
+            log_info!("No input for {} min, powering off", settings.power_off_mins);
+            watchdog::suspend(true);
+            #[cfg(feature = "dma-frames")]
+            pipeline::wait();
+            let off = power.power_off(bringup);
+            #[cfg(feature = "rtc-ticks")]
+            {
+                mono::low_power();
+                deep_sleep::RtcTimer::spawn_after(Duration::millis(power::SYSTEM_OFF_MS), off).ok();
+            }
+            #[cfg(not(feature = "rtc-ticks"))]
+            deep_sleep::spawn_after(Duration::millis(power::SYSTEM_OFF_MS), off).ok();
+            return;
+        }
+
+        // Nothing on these screens needs the next frame within a tick of
+        // the RTC's
+        #[cfg(feature = "rtc-ticks")]
+        if paused || screensaver.is_active() || shared.1.lock(|world| world.state == State::Title) {
+            mono::low_power();
+            frame::RtcTimer::spawn_after(Duration::micros(wait)).ok();
+            return;
+        }
+        #[cfg(feature = "rtc-ticks")]
+        mono::precise();
+        frame::spawn_after(Duration::micros(wait)).ok();
+    }
+
+    // A tick of a game played alone, recorded or played back
+    fn step_solo(
+        world: &mut World,
+        input: game::Input,
+        rng: &mut Rng,
+        time_scale: &mut TimeScale,
+        replay: &mut Replay,
+        speed: u8,
+    ) {
+        if let Some(input) = time_scale.due(world, input, speed) {
+            let input = replay.step(world, rng, input);
+            game::advance_frame(world, input, rng);
+        }
+    }
+
+    // Same as plasma::render at full quality, a row at a time. The column
+    // parts of each angle are worked out once up front.
+    #[cfg(feature = "scanline")]
+    fn plasma_lines(
+        disp: &mut Display,
+        origin: (u16, u16),
+        t: u32,
+        variant: &Variant,
+        vignette: &Vignette<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        logo: &rle::Image,
+    ) -> Result<u32, FirmwareError> {
+        let t = Angle::wrapped(t);
+        let mut cols = [[Angle::of_int(0); 3]; SCREEN_WIDTH];
+        for (j, col) in cols.iter_mut().enumerate() {
+            *col = variant.col_angles(Angle::of_ratio(j as i32, SCREEN_WIDTH as i32));
+        }
+
+        let mut pixels = logo.pixels();
+        scanline::stream(disp, origin, |i, row| {
+            let x = Angle::of_ratio(i as i32, SCREEN_HEIGHT as i32);
+            let [r, g, b] = variant.row_angles(t, x);
+            for (color, col) in row.iter_mut().zip(cols.iter()) {
+                *color = plasma::color([r + col[0], g + col[1], b + col[2]]);
+            }
+            vignette.apply_row(i, row);
+            // Each tile row is a fresh pass over the frame
+            if i == 0 {
+                pixels = logo.pixels();
+            }
+            for (color, pixel) in row.iter_mut().zip(&mut pixels) {
+                if pixel != assets::KEY {
+                    *color = pixel;
+                }
+            }
+        })
+        .map_err(|_| FirmwareError::Display)?;
+        Ok(scanline::FRAME_BYTES as u32)
+    }
+
+    // The palette plasma's colors, row by row, with the logo over them
+    #[cfg(feature = "indexed")]
+    fn cycling_pixels<'a>(
+        cycling: &'a Cycling,
+        vignette: &'a Vignette<SCREEN_WIDTH, SCREEN_HEIGHT>,
+        logo: &'a rle::Image,
+    ) -> impl Iterator<Item = u16> + 'a {
+        let colors = cycling.rows().enumerate().flat_map(move |(y, mut row)| {
+            vignette.apply_row(y, &mut row);
+            IntoIterator::into_iter(row)
+        });
+        colors.zip(logo.pixels()).map(|(color, pixel)| match pixel {
+            assets::KEY => color,
+            pixel => pixel,
+        })
+    }
+
+    // These return how many bytes of pixels they sent
+    #[cfg(not(feature = "dma-frames"))]
+    fn send_frame(
+        disp: &mut Display,
+        panel: (u16, u16),
+        tiles: &[(u16, u16)],
+        bytes: &Frame,
+    ) -> Result<u32, FirmwareError> {
+        let full = game::Rect {
+            x: 0,
+            y: 0,
+            w: SCREEN_WIDTH as i32,
+            h: SCREEN_HEIGHT as i32,
+        };
+        send_rect(disp, panel, tiles, bytes, full)
+    }
+
+    // Only starts it going out, once the last one has finished, and returns
+    // straight away. The copy is made while the last one is still going, and
+    // holds up its next transfer at most. It's the last one going wrong that
+    // this reports, which is as soon as anything can tell.
+    #[cfg(feature = "dma-frames")]
+    fn send_frame(
+        frames: &mut impl Mutex<T = Pipeline>,
+        panel: (u16, u16),
+        tiles: &[(u16, u16)],
+        bytes: &Frame,
+    ) -> Result<u32, FirmwareError> {
+        profile_scope!("queue frame");
+        frames.lock(|frames| frames.prepare(bytes));
+        pipeline::wait();
+        let size = (SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16);
+        let sent = frames.lock(|frames| frames.start(panel, tiles, size));
+        match pipeline::take_failed() {
+            true => Err(FirmwareError::Display),
+            false => Ok(sent),
+        }
+    }
+
+    // A whole frame that's only put together as it goes out, from the
+    // pixels `pixels` makes afresh for each tile
+    #[cfg(all(feature = "indexed", not(feature = "dma-frames")))]
+    fn send_pixels<I: Iterator<Item = u16>>(
+        disp: &mut Display,
+        panel: (u16, u16),
+        tiles: &[(u16, u16)],
+        pixels: impl Fn() -> I,
+    ) -> Result<u32, FirmwareError> {
+        let size = (SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16);
+        let whole = game::Rect {
+            x: 0,
+            y: 0,
+            w: SCREEN_WIDTH as i32,
+            h: SCREEN_HEIGHT as i32,
+        };
+        let mut sent = 0;
+        for &offset in tiles {
+            if display::set_offset(disp, panel, offset, size).is_err() {
+                continue;
+            }
+            disp.write_rect(whole, pixels()).map_err(|_| FirmwareError::Display)?;
+            sent += FRAME_BYTES as u32;
+        }
+        Ok(sent)
+    }
+
+    // Or put together in the pipeline's back buffer, once for every tile
+    #[cfg(all(feature = "indexed", feature = "dma-frames"))]
+    fn send_pixels<I: Iterator<Item = u16>>(
+        frames: &mut impl Mutex<T = Pipeline>,
+        panel: (u16, u16),
+        tiles: &[(u16, u16)],
+        pixels: impl Fn() -> I,
+    ) -> Result<u32, FirmwareError> {
+        frames.lock(|frames| frames.prepare_pixels(pixels()));
+        pipeline::wait();
+        let size = (SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16);
+        let sent = frames.lock(|frames| frames.start(panel, tiles, size));
+        match pipeline::take_failed() {
+            true => Err(FirmwareError::Display),
+            false => Ok(sent),
+        }
+    }
+
+    fn send_rect(
+        disp: &mut Display,
+        panel: (u16, u16),
+        tiles: &[(u16, u16)],
+        bytes: &Frame,
+        rect: game::Rect,
+    ) -> Result<u32, FirmwareError> {
+        profile_scope!("send");
+        let rect = match rect.clip(SCREEN_WIDTH as i32, SCREEN_HEIGHT as i32) {
+            Some(rect) => rect,
+            None => return Ok(0),
+        };
+        // These go the slow way, through the driver
+        #[cfg(feature = "dma-frames")]
+        pipeline::wait();
 
-        let _ = timer.fire_at(1, 1000);
+        let size = (SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16);
+        let mut sent = 0;
+        for &offset in tiles {
+            // A tile that doesn't fit at all is left out
+            if display::set_offset(disp, panel, offset, size).is_err() {
+                continue;
+            }
+            sink::send(disp, bytes, SCREEN_WIDTH, rect).map_err(|_| FirmwareError::Display)?;
+            sent += (rect.w * rect.h * 2) as u32;
+        }
+        Ok(sent)
+    }
+
+    // The HUD's strip on black, or all black outside a game, in its region
+    fn send_hud(
+        disp: &mut Display,
+        panel: (u16, u16),
+        offset: (u16, u16),
+        front: Option<usize>,
+    ) -> Result<u32, FirmwareError> {
+        let mut strip = [0; hud::STRIP_BYTES];
+        if let Some(front) = front {
+            hud::copy(&mut strip, front);
+        }
+        send_region(disp, panel, offset, Content::Hud, pixels(&strip))
+    }
+
+    fn send_minimap(
+        disp: &mut Display,
+        panel: (u16, u16),
+        offset: (u16, u16),
+        map: &[u8; minimap::BYTES],
+    ) -> Result<u32, FirmwareError> {
+        send_region(disp, panel, offset, Content::Minimap, pixels(map))
+    }
+
+    // Blacks out the HUD's region and the minimap for the grid or the
+    // screensaver, unless that's already been done
+    fn blank_regions(
+        disp: &mut Display,
+        panel: (u16, u16),
+        screen: &Screen,
+        hud_sent: &mut u32,
+    ) -> Result<u32, FirmwareError> {
+        if core::mem::replace(hud_sent, HUD_BLANK) == HUD_BLANK {
+            return Ok(0);
+        }
+        let mut sent = 0;
+        if let Some(offset) = screen.hud {
+            sent += send_hud(disp, panel, offset, None)?;
+        }
+        if let Some(offset) = screen.minimap {
+            let black = core::iter::repeat_n(0, minimap::W * minimap::H);
+            sent += send_region(disp, panel, offset, Content::Minimap, black)?;
+        }
+        Ok(sent)
+    }
+
+    // All of a region at `offset`, through the driver like `send_rect`
+    fn send_region(
+        disp: &mut Display,
+        panel: (u16, u16),
+        offset: (u16, u16),
+        content: Content,
+        pixels: impl Iterator<Item = u16>,
+    ) -> Result<u32, FirmwareError> {
+        #[cfg(feature = "dma-frames")]
+        pipeline::wait();
+        let size = content.size();
+        // Left out if it doesn't fit at all, like a tile
+        if display::set_offset(disp, panel, offset, size).is_err() {
+            return Ok(0);
+        }
+        let rect = game::Rect { x: 0, y: 0, w: size.0 as i32, h: size.1 as i32 };
+        disp.write_rect(rect, pixels).map_err(|_| FirmwareError::Display)?;
+        Ok(size.0 as u32 * size.1 as u32 * 2)
+    }
+
+    // Little-endian RGB565, as the game draws it
+    fn pixels(bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+        bytes.chunks_exact(2).map(|pixel| u16::from_le_bytes([pixel[0], pixel[1]]))
+    }
+
+    // Initializes the panel with `init`, then blacks out all of it, gaps and
+    // all. Returns how many attempts `init` took.
+    fn bring_up(
+        disp: &mut Display,
+        config: &DisplayConfig,
+        init: impl FnOnce(&mut Display) -> Result<u8, ()>,
+    ) -> Result<u8, FirmwareError> {
+        let attempts = init(disp).map_err(|_| FirmwareError::Display)?;
+        let panel = display::ram_size(config.orientation);
+        display::clear(disp, panel).map_err(|_| FirmwareError::Display)?;
+        disp.set_offset(config.panel_offset.0, config.panel_offset.1);
+        Ok(attempts)
+    }
+
+    // An empty battery in the top right corner, returning where it went
+    #[cfg(feature = "battery")]
+    fn draw_low_battery(bytes: &mut Frame) -> game::Rect {
+        let sprite = hud::LOW_BATTERY;
+        let (x, y) = (game::WIDTH - sprite.w - 2, 2);
+        draw::blit_sprite(bytes, x, y, &sprite, rgb565(31, 0, 0), false);
+        game::Rect { x, y, w: sprite.w, h: sprite.h }
+    }
+
+    // The last second's FPS over a bar of how busy the frame task was, in
+    // the top left corner. Returns where it went.
+    fn draw_perf(bytes: &mut Frame, stats: &RenderStats) -> game::Rect {
+        const BAR_W: i32 = 20;
+        let (x, y) = (2, 2);
+        let mut fps = [0; 3];
+        text::digits(&mut fps, stats.fps as u32);
+        text::draw(bytes, x, y, &fps, rgb565(31, 63, 31));
+
+        let load = stats.load_percent.min(100) as i32;
+        let color = match load {
+            0..=59 => rgb565(0, 63, 0),
+            60..=89 => rgb565(31, 63, 0),
+            _ => rgb565(31, 0, 0),
+        };
+        let bar = game::Rect {
+            x,
+            y: y + text::LINE_H,
+            w: BAR_W,
+            h: 2,
+        };
+        let filled = BAR_W * load / 100;
+        draw::fill_rect(bytes, game::Rect { w: filled, ..bar }, color);
+        let empty = game::Rect {
+            x: bar.x + filled,
+            w: BAR_W - filled,
+            ..bar
+        };
+        draw::fill_rect(bytes, empty, rgb565(8, 8, 8));
+        game::Rect {
+            x,
+            y,
+            w: BAR_W,
+            h: text::LINE_H + bar.h,
+        }
+    }
+
+    #[cfg(feature = "sound")]
+    fn play_events(events: Events) {
+        let sfx = if events.contains(Events::GAME_OVER) {
+            SfxId::GameOver
+        } else if events.contains(Events::SHIP_HIT)
+            || events.contains(Events::SHIELD_HIT)
+            || events.contains(Events::ENEMY_HIT)
+        {
+            SfxId::Hit
+        } else if events.contains(Events::POWER_UP) {
+            SfxId::PowerUp
+        } else if events.contains(Events::ENEMY_DESTROYED) {
+            SfxId::Explode
+        } else if events.contains(Events::FIRED) {
+            SfxId::Fire
+        } else {
+            return;
+        };
+        play_sfx::spawn(sfx).ok();
+    }
+
+    #[cfg(feature = "haptics")]
+    fn rumble_events(events: Events) {
+        let rumble = if events.contains(Events::SHIP_HIT) || events.contains(Events::GAME_OVER) {
+            Rumble::Buzz
+        } else if events.contains(Events::POWER_UP) {
+            Rumble::DoubleTap
+        } else if events.contains(Events::SHIELD_HIT) {
+            Rumble::Pulse
+        } else {
+            return;
+        };
+        rumble::spawn(rumble).ok();
+    }
+
+    // Drains whatever has arrived on the RTT down channel since the last
+    // frame. Runs at the lowest priority so a chatty host can't stall
+    // rendering.
+    #[cfg_attr(not(feature = "console"), allow(unused_variables))]
+    #[task(priority = 1, local = [console_input, console_line], shared = [settings, stats, scenes, grid, perf, world, rng, demo, replay, storage, totals])]
+    fn poll_console(ctx: poll_console::Context) {
+        #[cfg(feature = "console")]
+        drain_console(ctx);
+    }
+
+    #[cfg(feature = "console")]
+    fn drain_console(mut ctx: poll_console::Context) {
+        profile_scope!("console");
+        let mut buf = [0u8; 16];
+
+        loop {
+            let count = ctx.local.console_input.read(&mut buf);
+            if count == 0 {
+                break;
+            }
+
+            for &byte in &buf[..count] {
+                match ctx.local.console_line.push(byte) {
+                    Some(Ok(Command::SetBrightness(level))) => {
+                        display::set_brightness(level);
+                        if cfg!(feature = "backlight") {
+                            rprintln!("brightness = {}", level);
+                        } else {
+                            rprintln!("brightness = {} (no backlight control on this board)", level);
+                        }
+                    }
+                    Some(Ok(Command::AutoBrightness)) => {
+                        if cfg!(feature = "light") {
+                            ctx.shared.settings.lock(|settings| settings.auto_brightness = true);
+                            rprintln!("brightness = auto");
+                        } else {
+                            rprintln!("no light sensor, brightness stays manual");
+                        }
+                    }
+                    Some(Ok(Command::Orientation(index))) => {
+                        let index = ctx.shared.settings.lock(|settings| {
+                            settings.auto_orientation = false;
+                            settings.orientation = index;
+                            settings.validate();
+                            settings.orientation
+                        });
+                        rprintln!("orientation = {}", index);
+                    }
+                    Some(Ok(Command::AutoOrientation)) => {
+                        if cfg!(feature = "tilt") {
+                            ctx.shared.settings.lock(|settings| settings.auto_orientation = true);
+                            rprintln!("orientation = auto");
+                        } else {
+                            rprintln!("no accelerometer, orientation stays fixed");
+                        }
+                    }
+                    Some(Ok(Command::TiltSteering(enabled))) => {
+                        if cfg!(feature = "tilt") {
+                            ctx.shared.settings.lock(|settings| settings.tilt_steering = enabled);
+                            rprintln!("tilt = {}", enabled);
+                        } else {
+                            rprintln!("no accelerometer, can't steer by tilt");
+                        }
+                    }
+                    Some(Ok(Command::Effect(effect))) => {
+                        ctx.shared.settings.lock(|settings| settings.effect = effect);
+                        rprintln!("effect = {:?}", effect);
+                    }
+                    Some(Ok(Command::Transition(transition))) => {
+                        ctx.shared.settings.lock(|settings| settings.transition = transition);
+                        rprintln!("transition = {:?}", transition);
+                    }
+                    Some(Ok(Command::Plasma(index))) => {
+                        let index = ctx.shared.settings.lock(|settings| {
+                            settings.plasma = index;
+                            settings.validate();
+                            settings.plasma
+                        });
+                        if index == plasma::RANDOM {
+                            rprintln!("plasma = random");
+                        } else {
+                            rprintln!("plasma = {}", Variant::get(index).name);
+                        }
+                    }
+                    Some(Ok(Command::Backdrop(intensity))) => {
+                        ctx.shared.settings.lock(|settings| settings.backdrop = intensity);
+                        rprintln!("backdrop = {}", intensity);
+                    }
+                    Some(Ok(Command::Trails(shift))) => {
+                        let shift = ctx.shared.settings.lock(|settings| {
+                            settings.trails = shift;
+                            settings.validate();
+                            settings.trails
+                        });
+                        rprintln!("trails = {}", shift);
+                    }
+                    Some(Ok(Command::ClearColor(r, g, b))) => {
+                        let color = rgb565(r as u16 >> 3, g as u16 >> 2, b as u16 >> 3);
+                        ctx.shared.settings.lock(|settings| settings.clear_color = color);
+                        rprintln!("clear = {:#06x}", color);
+                    }
+                    Some(Ok(Command::Spawn(count))) => {
+                        let spawned = (&mut ctx.shared.world, &mut ctx.shared.rng).lock(|world, rng| {
+                            if world.state != State::Playing {
+                                world.start();
+                            }
+                            world.spawn_enemies(count, rng)
+                        });
+                        rprintln!("spawned {} enemies", spawned);
+                    }
+                    #[cfg(feature = "sound")]
+                    Some(Ok(Command::Sfx(sfx))) => {
+                        play_sfx::spawn(sfx).ok();
+                    }
+                    #[cfg(feature = "haptics")]
+                    Some(Ok(Command::Rumble(rumble))) => {
+                        rumble::spawn(rumble).ok();
+                    }
+                    Some(Ok(Command::Vignette(enabled))) => {
+                        ctx.shared.settings.lock(|settings| settings.vignette = enabled);
+                        rprintln!("vignette = {}", enabled);
+                    }
+                    Some(Ok(Command::Grid(enabled))) => {
+                        ctx.shared.grid.lock(|grid| *grid = enabled);
+                        rprintln!("grid = {}", enabled);
+                    }
+                    Some(Ok(Command::Perf(enabled))) => {
+                        ctx.shared.perf.lock(|perf| *perf = enabled);
+                        rprintln!("perf = {}", enabled);
+                    }
+                    Some(Ok(Command::Dither(enabled))) => {
+                        ctx.shared.settings.lock(|settings| settings.dither_edges = enabled);
+                        rprintln!("sprite edge dithering = {}", enabled);
+                    }
+                    Some(Ok(Command::Sound(enabled))) => {
+                        ctx.shared.settings.lock(|settings| settings.sound = enabled);
+                        if cfg!(feature = "sound") {
+                            rprintln!("sound = {}", enabled);
+                        } else {
+                            rprintln!("sound = {} (built without sound)", enabled);
+                        }
+                    }
+                    Some(Ok(Command::Volume(percent))) => {
+                        let percent = ctx.shared.settings.lock(|settings| {
+                            settings.volume = percent;
+                            settings.validate();
+                            settings.volume
+                        });
+                        rprintln!("volume = {}%", percent);
+                    }
+                    Some(Ok(Command::FpsCap(fps))) => {
+                        let fps = ctx.shared.settings.lock(|settings| {
+                            settings.fps_cap = fps;
+                            settings.validate();
+                            settings.fps_cap
+                        });
+                        if fps == 0 {
+                            rprintln!("fps = uncapped");
+                        } else {
+                            rprintln!("fps cap = {}", fps);
+                        }
+                    }
+                    Some(Ok(Command::ScreensaverMins(mins))) => {
+                        ctx.shared.settings.lock(|settings| settings.screensaver_mins = mins);
+                        match mins {
+                            0 => rprintln!("screensaver = never"),
+                            mins => rprintln!("screensaver after {} min", mins),
+                        }
+                    }
+                    Some(Ok(Command::Screensaver(style))) => {
+                        ctx.shared.settings.lock(|settings| settings.screensaver = style);
+                        rprintln!("screensaver = {:?}", style);
+                    }
+                    Some(Ok(Command::PowerOff(mins))) => {
+                        ctx.shared.settings.lock(|settings| settings.power_off_mins = mins);
+                        if !cfg!(feature = "power-off") {
+                            rprintln!("no wake button, the display never powers off");
+                        } else if mins == 0 {
+                            rprintln!("power off = never");
+                        } else {
+                            rprintln!("power off after {} min", mins);
+                        }
+                    }
+                    Some(Ok(Command::Speed(percent))) => {
+                        let percent = ctx.shared.settings.lock(|settings| {
+                            settings.speed = percent;
+                            settings.validate();
+                            settings.speed
+                        });
+                        rprintln!("speed = {}%", percent);
+                    }
+                    Some(Ok(Command::Bpm(bpm))) => {
+                        let bpm = ctx.shared.settings.lock(|settings| {
+                            settings.bpm = bpm;
+                            settings.validate();
+                            settings.bpm
+                        });
+                        metronome::set_bpm(bpm);
+                        if bpm == 0 {
+                            rprintln!("metronome stopped");
+                        } else {
+                            rprintln!("bpm = {}", bpm);
+                        }
+                    }
+                    Some(Ok(Command::Turbo(enabled))) => {
+                        ctx.shared.settings.lock(|settings| settings.turbo = enabled);
+                        rprintln!("turbo = {}", enabled);
+                    }
+                    Some(Ok(Command::TurboHold(frames))) => {
+                        let frames = ctx.shared.settings.lock(|settings| {
+                            settings.turbo_hold_frames = frames;
+                            settings.validate();
+                            settings.turbo_hold_frames
+                        });
+                        rprintln!("turbo after {} frames held", frames);
+                    }
+                    Some(Ok(Command::TurboRepeat(frames))) => {
+                        let frames = ctx.shared.settings.lock(|settings| {
+                            settings.turbo_repeat_frames = frames;
+                            settings.validate();
+                            settings.turbo_repeat_frames
+                        });
+                        if frames == 0 {
+                            rprintln!("turbo repeat = weapon cooldown");
+                        } else {
+                            rprintln!("turbo repeat every {} frames", frames);
+                        }
+                    }
+                    Some(Ok(Command::LinkChannel(channel))) => {
+                        let channel = ctx.shared.settings.lock(|settings| {
+                            settings.link_channel = channel;
+                            settings.validate();
+                            settings.link_channel
+                        });
+                        rprintln!("link channel = {} ({} MHz)", channel, 2400 + channel as u32);
+                    }
+                    Some(Ok(Command::PairingCode(code))) => {
+                        ctx.shared.settings.lock(|settings| settings.link_code = code);
+                        rprintln!("pairing code = {}", code);
+                    }
+                    Some(Ok(Command::Versus(enabled))) => {
+                        ctx.shared.settings.lock(|settings| settings.versus = enabled);
+                        if cfg!(feature = "link") {
+                            rprintln!("versus = {}", enabled);
+                        } else {
+                            rprintln!("versus = {} (built without link)", enabled);
+                        }
+                    }
+                    Some(Ok(Command::Pause)) => {
+                        match toggle_pause(
+                            &mut ctx.shared.scenes,
+                            &mut ctx.shared.world,
+                            &mut ctx.shared.storage,
+                            &mut ctx.shared.totals,
+                        ) {
+                            (false, _) => rprintln!("resumed"),
+                            (true, true) => rprintln!("paused, checkpoint saved"),
+                            (true, false) => rprintln!("paused"),
+                        }
+                    }
+                    Some(Ok(Command::Log(level))) => {
+                        logging::set_threshold(level);
+                        rprintln!("log level = {:?}", level);
+                    }
+                    Some(Ok(Command::LogModule(module, level))) => {
+                        logging::set_module_threshold(module, level);
+                        rprintln!("log level = {:?} for {}", level, module.name());
+                    }
+                    Some(Ok(Command::Demo)) => {
+                        let mut shared = (
+                            &mut ctx.shared.world,
+                            &mut ctx.shared.rng,
+                            &mut ctx.shared.demo,
+                            &mut ctx.shared.replay,
+                        );
+                        shared.lock(|world, rng, demo, replay| {
+                            replay.stop(world);
+                            // Same seed, same demo
+                            *rng = Rng::new(SEED);
+                            demo.start(world);
+                        });
+                        ctx.shared.scenes.lock(|scenes| scenes.resume());
+                        rprintln!("playing demo");
+                    }
+                    Some(Ok(Command::Replay)) => {
+                        let mut shared = (
+                            &mut ctx.shared.world,
+                            &mut ctx.shared.rng,
+                            &mut ctx.shared.demo,
+                            &mut ctx.shared.replay,
+                        );
+                        let started = shared.lock(|world, rng, demo, replay| {
+                            *demo = Demo::new();
+                            replay.start(world, rng)
+                        });
+                        match started {
+                            true => {
+                                ctx.shared.scenes.lock(|scenes| scenes.resume());
+                                rprintln!("replaying the last game");
+                            }
+                            false => rprintln!("no game recorded yet"),
+                        }
+                    }
+                    Some(Ok(Command::Screenshot)) => {
+                        screenshot::request();
+                        rprintln!("screenshot of the next frame on RTT channel 1");
+                    }
+                    Some(Ok(Command::Stats)) => {
+                        let stats = ctx.shared.stats.lock(|stats| *stats);
+                        let (effect, brightness) =
+                            ctx.shared.settings.lock(|settings| (settings.effect, settings.brightness));
+                        rprintln!(
+                            "frames = {}, {} fps, last frame {} us, {} dropped, {} SPI bytes",
+                            stats.frames,
+                            stats.fps,
+                            stats.frame_time_us,
+                            stats.dropped_frames,
+                            stats.spi_bytes
+                        );
+                        rprintln!(
+                            "render {} us, flush {} us, {}% busy",
+                            stats.render_time_us(),
+                            stats.flush_time_us,
+                            stats.load_percent
+                        );
+                        rprintln!("effect = {:?}, brightness = {}", effect, brightness);
+                        #[cfg(feature = "battery")]
+                        match battery::percent() {
+                            Some(percent) => rprintln!("battery = {}%", percent),
+                            None => rprintln!("battery = not read yet"),
+                        }
+                        #[cfg(feature = "link")]
+                        match link::stats() {
+                            Some(link) => rprintln!(
+                                "link: {} received, {} lost ({}%)",
+                                link.received,
+                                link.lost,
+                                link.loss_percent()
+                            ),
+                            None => rprintln!("link: not linked"),
+                        }
+                        let totals = ctx.shared.totals.lock(|totals| *totals);
+                        rprintln!(
+                            "lifetime: {} games, {} s played, {} kills, best combo {}",
+                            totals.games,
+                            totals.play_secs,
+                            totals.kills,
+                            totals.best_combo
+                        );
+                    }
+                    #[cfg(feature = "profile")]
+                    Some(Ok(Command::Profile)) => profile::dump(|line| rprintln!("{}", line)),
+                    #[cfg(feature = "profile")]
+                    Some(Ok(Command::ProfileReset)) => {
+                        profile::reset();
+                        rprintln!("profile reset");
+                    }
+                    Some(Ok(Command::Reset)) => {
+                        restart::spawn(false).ok();
+                    }
+                    Some(Ok(Command::Dfu)) => {
+                        restart::spawn(true).ok();
+                    }
+                    Some(Err(err)) => log_warn!("console: {:?}", err),
+                    None => (),
+                }
+            }
+        }
+    }
+
+    // Resetting, or rebooting into the bootloader, from the console or USB
+    // serial. The settings are saved first, rather than lose a change made in
+    // the last few seconds. Down here rather than in the USB interrupt, which
+    // would otherwise have to wait out the flash's lock.
+    #[cfg_attr(
+        not(all(feature = "console", feature = "flash")),
+        allow(unused_variables)
+    )]
+    #[task(priority = 1, shared = [settings, storage])]
+    fn restart(ctx: restart::Context, dfu: bool) {
+        #[cfg(feature = "flash")]
+        {
+            let mut shared = ctx.shared;
+            flush_settings(&mut shared.settings, &mut shared.storage);
+        }
+        #[cfg(feature = "console")]
+        if dfu {
+            pewpew::bootloader::reboot();
+        }
+        log_info!("Resetting");
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+
+    // Same priority as the frame task, so chores never run mid-frame. Each
+    // one gets the shared resources listed here, so a chore that needs
+    // something else needs it adding.
+    #[task(priority = 1, local = [chores], shared = [settings, stats, storage, totals])]
+    fn chores(ctx: chores::Context, frame: u32) {
+        profile_scope!("chores");
+        let mut shared = ctx.shared;
+        while let Some(chore) = ctx.local.chores.due(frame) {
+            chore(&mut shared);
+        }
+    }
+
+    fn log_stats(shared: &mut chores::SharedResources) {
+        let stats = shared.stats.lock(|stats| *stats);
+        log_debug!("{} fps, {} dropped frames", stats.fps, stats.dropped_frames);
+        #[cfg(feature = "battery")]
+        if let Some(percent) = battery::percent() {
+            log_debug!("battery at {}%", percent);
+        }
+        #[cfg(feature = "link")]
+        if let Some(link) = link::stats() {
+            log_debug!("link lost {} of {} packets", link.lost, link.received + link.lost);
+        }
+    }
+
+    // Somewhere between 5 and 10 minutes, depending on the frame rate. Flash
+    // is good for 10000 erases, which is a couple of months of solid play.
+    #[cfg(feature = "flash")]
+    const SAVE_TOTALS_FRAMES: u32 = 18000;
+
+    #[cfg(feature = "flash")]
+    fn save_totals(shared: &mut chores::SharedResources) {
+        flush_totals(&mut shared.totals, &mut shared.storage);
+    }
+
+    // Settings are only looked at every few seconds, so a run of changes
+    // from the console is one save rather than one each
+    #[cfg(feature = "flash")]
+    const SAVE_SETTINGS_FRAMES: u32 = 300;
+
+    #[cfg(feature = "flash")]
+    fn save_settings(shared: &mut chores::SharedResources) {
+        flush_settings(&mut shared.settings, &mut shared.storage);
+    }
+
+    // Appends the settings if they're not what was last saved. The journal's
+    // pages only need erasing after a hundred or so saves.
+    #[cfg(feature = "flash")]
+    fn flush_settings(
+        settings: &mut impl Mutex<T = Settings>,
+        storage: &mut impl Mutex<T = Storage>,
+    ) {
+        let mut save = [0; settings::LEN];
+        settings.lock(|settings| settings.save(&mut save));
+        storage.lock(|storage| {
+            if Journal::Settings.latest(storage, settings::LEN) != Some(&save[..]) {
+                if let Err(err) = Journal::Settings.append(storage, &save) {
+                    log_error!("Couldn't save the settings: {:?}", err);
+                }
+            }
+        });
+    }
+
+    // Writes the totals out if they've changed since they last were
+    #[cfg(feature = "flash")]
+    fn flush_totals(
+        totals: &mut impl Mutex<T = Totals>,
+        storage: &mut impl Mutex<T = Storage>,
+    ) {
+        let mut save = [0; totals::LEN];
+        if totals.lock(|totals| totals.take_dirty().then(|| totals.save(&mut save))).is_some() {
+            if let Err(err) = storage.lock(|storage| storage.write(Page::Totals, &save)) {
+                log_error!("Couldn't save the totals: {:?}", err);
+            }
+        }
+    }
+
+    // Pauses the game if it's running and resumes it if it isn't, for the
+    // console's `pause`. Returns whether it's now paused, and whether a
+    // checkpoint was saved.
+    #[cfg(feature = "console")]
+    fn toggle_pause(
+        scenes: &mut impl Mutex<T = Scenes>,
+        world: &mut impl Mutex<T = World>,
+        storage: &mut impl Mutex<T = Flash>,
+        totals: &mut impl Mutex<T = Totals>,
+    ) -> (bool, bool) {
+        let paused = scenes.lock(|scenes| {
+            match scenes.is_paused() {
+                true => scenes.resume(),
+                false => scenes.pause(),
+            }
+            scenes.is_paused()
+        });
+        match paused {
+            true => (true, save_on_pause(world, storage, totals)),
+            false => (false, false),
+        }
+    }
+
+    // Pausing, from the console or a scene that freezes the game, is the one
+    // moment a flash stall goes unnoticed, so it saves a checkpoint and the
+    // totals. Returns whether a checkpoint was saved.
+    #[cfg(any(feature = "console", feature = "buttons"))]
+    #[cfg_attr(not(feature = "flash"), allow(unused_variables))]
+    fn save_on_pause(
+        world: &mut impl Mutex<T = World>,
+        storage: &mut impl Mutex<T = Flash>,
+        totals: &mut impl Mutex<T = Totals>,
+    ) -> bool {
+        #[cfg(feature = "flash")]
+        let saved = save_checkpoint(world, storage);
+        #[cfg(not(feature = "flash"))]
+        let saved = false;
+        #[cfg(feature = "flash")]
+        flush_totals(totals, storage);
+        saved
+    }
+
+    // Saves a checkpoint of a game in progress, returning whether there was
+    // one. A linked game can't be resumed alone, so isn't one.
+    #[cfg(all(
+        feature = "flash",
+        any(feature = "console", feature = "buttons", feature = "power-off", feature = "battery")
+    ))]
+    fn save_checkpoint(
+        world: &mut impl Mutex<T = World>,
+        storage: &mut impl Mutex<T = Storage>,
+    ) -> bool {
+        let mut save = [0; checkpoint::LEN];
+        let playing = world.lock(|world| {
+            checkpoint::save(world, &mut save);
+            world.state == State::Playing && world.partner.is_none()
+        });
+        if playing {
+            if let Err(err) = storage.lock(|storage| storage.write(Page::Checkpoint, &save)) {
+                log_error!("Couldn't save the checkpoint: {:?}", err);
+            }
+        }
+        playing
+    }
+
+    #[cfg(feature = "diag")]
+    fn report_ram(_: &mut chores::SharedResources) {
+        use pewpew::diag;
+
+        log_info!(
+            "RAM: {} B static, stack high water {} / {} B",
+            diag::static_ram(),
+            diag::stack_high_water(),
+            diag::stack_size()
+        );
+    }
+
+    #[cfg_attr(not(feature = "light"), allow(unused_mut, unused_variables))]
+    #[task(priority = 1, local = [light], shared = [settings])]
+    fn sample_light(mut ctx: sample_light::Context) {
+        #[cfg(feature = "light")]
+        {
+            if !ctx.shared.settings.lock(|settings| settings.auto_brightness) {
+                return;
+            }
+            if let Some(level) = ctx.local.light.sample() {
+                ctx.shared.settings.lock(|settings| settings.brightness = level);
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "tilt"), allow(unused_mut, unused_variables))]
+    #[task(
+        priority = 1,
+        local = [tilt],
+        shared = [settings, scenes, world, demo, replay, rng, angles]
+    )]
+    fn check_tilt(ctx: check_tilt::Context) {
+        #[cfg(feature = "tilt")]
+        {
+            profile_scope!("tilt");
+            let mut shared = (
+                ctx.shared.settings,
+                ctx.shared.scenes,
+                ctx.shared.world,
+                ctx.shared.demo,
+                ctx.shared.replay,
+                ctx.shared.rng,
+            );
+            let (auto, steering, current) = shared.0.lock(|settings| {
+                (settings.auto_orientation, settings.tilt_steering, settings.orientation)
+            });
+            let sample = match ctx.local.tilt.sample(current) {
+                Some(sample) => sample,
+                None => return,
+            };
+            let mut angles = ctx.shared.angles;
+            angles.lock(|angles| *angles = sample.angles);
+            // Tipping the board to steer would turn the panel under the player
+            if auto && !steering {
+                shared.0.lock(|settings| settings.orientation = sample.orientation);
+            }
+            // Only a game being played here and now starts over. A linked one
+            // would leave the other board behind.
+            if sample.shaken {
+                let mut game =
+                    (&mut shared.1, &mut shared.2, &mut shared.3, &mut shared.4, &mut shared.5);
+                game.lock(|scenes, world, demo, replay, rng| {
+                    if world.state == State::Playing
+                        && world.partner.is_none()
+                        && !scenes.is_paused()
+                        && !demo.is_active()
+                        && !replay.is_playing()
+                    {
+                        world.start();
+                        replay.restarted(rng);
+                        log_info!("Shaken, starting again");
+                    }
+                });
+            }
+        }
     }
 
-    #[idle]
-    fn idle(_: idle::Context) -> ! {
+    // Above the frame task, so each transfer of a frame starts as soon as the
+    // last one ends, whatever the frame task is up to
+    #[cfg(feature = "dma-frames")]
+    #[task(binds = SPIM1_SPIS1_TWIM1_TWIS1_SPI1_TWI1, priority = 2, shared = [frames])]
+    fn spim1(mut ctx: spim1::Context) {
+        within_budget!("spim1", INPUT_US);
+        ctx.shared.frames.lock(|frames| frames.on_end());
+    }
+
+    // The last tier of powering off, unless the wake button's been pressed
+    // since. Waking up from here is a reset, so everything's saved first.
+    #[cfg_attr(
+        not(all(feature = "power-off", feature = "flash")),
+        allow(unused_mut, unused_variables)
+    )]
+    #[task(priority = 1, shared = [settings, world, storage, totals])]
+    fn deep_sleep(mut ctx: deep_sleep::Context, off: u32) {
+        #[cfg(feature = "power-off")]
+        {
+            match power::is_still_off(off) {
+                Ok(true) => (),
+                Ok(false) => return,
+                // Woken and powered off again while this was still waiting,
+                // so the one for the new power off couldn't be spawned
+                Err(now) => {
+                    let after = Duration::millis(power::SYSTEM_OFF_MS);
+                    #[cfg(feature = "rtc-ticks")]
+                    deep_sleep::RtcTimer::spawn_after(after, now).ok();
+                    #[cfg(not(feature = "rtc-ticks"))]
+                    deep_sleep::spawn_after(after, now).ok();
+                    return;
+                }
+            }
+            #[cfg(feature = "flash")]
+            {
+                flush_settings(&mut ctx.shared.settings, &mut ctx.shared.storage);
+                flush_totals(&mut ctx.shared.totals, &mut ctx.shared.storage);
+                if save_checkpoint(&mut ctx.shared.world, &mut ctx.shared.storage) {
+                    log_info!("Checkpoint saved");
+                }
+            }
+            log_info!("System OFF until the wake button");
+            power::system_off();
+        }
+    }
+
+    // Same priority as the frame task, so never mid-frame
+    #[cfg_attr(not(feature = "battery"), allow(unused_mut, unused_variables))]
+    #[task(priority = 1, local = [battery])]
+    fn check_battery(ctx: check_battery::Context) {
+        #[cfg(feature = "battery")]
+        {
+            ctx.local.battery.update();
+        }
+    }
+
+    // The link layer has hard timing requirements, so it gets to interrupt
+    // rendering
+    #[cfg(feature = "ble")]
+    #[task(binds = RADIO, priority = 3, shared = [ble])]
+    fn radio(mut ctx: radio::Context) {
+        within_budget!("radio", RADIO_US);
+        if ctx.shared.ble.lock(|ble| ble.on_radio()) {
+            ble_worker::spawn().ok();
+        }
+    }
+
+    #[cfg(feature = "ble")]
+    #[task(binds = TIMER0, priority = 3, shared = [ble])]
+    fn timer0(mut ctx: timer0::Context) {
+        within_budget!("timer0", RADIO_US);
+        if ctx.shared.ble.lock(|ble| ble.on_timer()) {
+            ble_worker::spawn().ok();
+        }
+    }
+
+    #[cfg_attr(not(feature = "ble"), allow(unused_mut, unused_variables))]
+    #[task(priority = 2, local = [ble_responder], shared = [settings])]
+    fn ble_worker(mut ctx: ble_worker::Context) {
+        #[cfg(feature = "ble")]
+        {
+            within_budget!("ble_worker", INPUT_US);
+            let responder = ctx.local.ble_responder;
+            while responder.has_work() {
+                if let Err(err) = responder.process_one() {
+                    log_warn!("BLE: {:?}", err);
+                }
+            }
+            if let Some(mut settings) = ble::take_settings() {
+                if settings.validate() {
+                    log_warn!("Settings written over BLE were out of range, some are defaults");
+                }
+                metronome::set_bpm(settings.bpm);
+                ctx.shared.settings.lock(|shared| *shared = settings);
+                log_info!("Settings written over BLE");
+            }
+            ble::reboot_if_asked();
+        }
+    }
+
+    #[cfg_attr(not(feature = "sound"), allow(unused_mut, unused_variables))]
+    #[task(capacity = 4, shared = [settings, synth])]
+    fn play_sfx(mut ctx: play_sfx::Context, sfx: Sfx) {
+        #[cfg(feature = "sound")]
+        {
+            let volume = ctx.shared.settings.lock(|settings| settings.volume);
+            let start = ctx.shared.synth.lock(|synth| {
+                synth.set_volume(volume);
+                synth.play_sfx(sfx)
+            });
+            if start {
+                rtic::pend(pac::Interrupt::TIMER2);
+            }
+        }
+    }
+
+    #[cfg_attr(not(feature = "haptics"), allow(unused_mut, unused_variables))]
+    #[task(capacity = 4, shared = [haptics])]
+    fn rumble(mut ctx: rumble::Context, rumble: Rumbles) {
+        #[cfg(feature = "haptics")]
+        if ctx.shared.haptics.lock(|haptics| haptics.play(rumble)) {
+            rumble_step::spawn().ok();
+        }
+    }
+
+    // Each step of a rumble schedules the next, until the queue's empty
+    #[cfg_attr(not(feature = "haptics"), allow(unused_mut, unused_variables))]
+    #[task(shared = [haptics])]
+    fn rumble_step(mut ctx: rumble_step::Context) {
+        #[cfg(feature = "haptics")]
+        if let Some(ms) = ctx.shared.haptics.lock(|haptics| haptics.step()) {
+            // Not on TIMER1, which may be stopped by the time it's due
+            #[cfg(feature = "rtc-ticks")]
+            rumble_step::RtcTimer::spawn_after(Duration::millis(ms)).ok();
+            #[cfg(not(feature = "rtc-ticks"))]
+            rumble_step::spawn_after(Duration::millis(ms)).ok();
+        }
+    }
+
+    // The synth's mixer, a sample a compare event for as long as anything's
+    // playing. Pended to start it, when the next compare is a sample period
+    // from now rather than from the last one. Up with the radio, so neither
+    // a long frame nor a burst of input crackles.
+    #[cfg(feature = "sound")]
+    #[task(binds = TIMER2, priority = 3, local = [timer2], shared = [synth])]
+    fn timer2(mut ctx: timer2::Context) {
+        profile_scope!("synth");
+        within_budget!("synth", AUDIO_US);
+        let timer = ctx.local.timer2;
+        let on_time = timer.is_compare_event(Compare::One);
+        timer.ack_compare_event(Compare::One);
+
+        if !ctx.shared.synth.lock(|synth| synth.sample()) {
+            timer.stop(Compare::One);
+            timer.pause();
+        } else if on_time {
+            timer.fire_again(Compare::One, sound::SAMPLE_PERIOD_US);
+        } else {
+            timer.fire_at(Compare::One, sound::SAMPLE_PERIOD_US);
+        }
+    }
+
+    // Above the frame task, so a slow frame doesn't hold up a beat
+    #[task(binds = TIMER3, priority = 2, local = [metronome])]
+    fn timer3(ctx: timer3::Context) {
+        within_budget!("timer3", INPUT_US);
+        ctx.local.metronome.on_interrupt();
+    }
+
+    // The input tier's check-in for the watchdog, so at the same priority as
+    // the rest of it
+    #[task(binds = RTC0, priority = 2, local = [heartbeat])]
+    fn rtc0(ctx: rtc0::Context) {
+        within_budget!("rtc0", INPUT_US);
+        ctx.local.heartbeat.on_interrupt();
+    }
+
+    // Above the frame task with the rest of input, so an edge or a step of
+    // the encoder doesn't wait for a frame to finish
+    #[cfg(any(feature = "power-off", feature = "encoder", feature = "buttons", feature = "tilt"))]
+    #[task(binds = GPIOTE, priority = 2, local = [gpiote, wake, quadrature])]
+    fn gpiote(ctx: gpiote::Context) {
+        profile_scope!("gpiote");
+        within_budget!("gpiote", INPUT_US);
+        let gpiote = ctx.local.gpiote;
+        #[cfg(feature = "tilt")]
+        if tilt::on_gpiote(gpiote) {
+            check_tilt::spawn().ok();
+        }
+        #[cfg(feature = "buttons")]
+        if buttons::on_gpiote(gpiote) {
+            rtic::pend(pac::Interrupt::RTC1);
+        }
+        #[cfg(feature = "encoder")]
+        ctx.local.quadrature.on_interrupt(gpiote);
+        #[cfg(feature = "power-off")]
+        if ctx.local.wake.on_interrupt(gpiote) {
+            frame::spawn().ok();
+        }
+    }
+
+    // Above the frame task, so enumeration gets its answers in time however
+    // long a frame takes. Only a few of the console's commands make sense
+    // without a debugger, the rest are turned away.
+    #[cfg(feature = "usb")]
+    #[task(binds = USBD, priority = 2, local = [usb], shared = [stats, totals])]
+    fn usbd(ctx: usbd::Context) {
+        within_budget!("usbd", INPUT_US);
+        let mut shared = (ctx.shared.stats, ctx.shared.totals);
+        ctx.local.usb.on_interrupt(|result| match result {
+            Ok(Command::SetBrightness(level)) => {
+                display::set_brightness(level);
+                usb::print(format_args!("brightness = {}", level));
+            }
+            Ok(Command::Stats) => {
+                let stats = shared.0.lock(|stats| *stats);
+                let totals = shared.1.lock(|totals| *totals);
+                usb::print(format_args!(
+                    "frames = {}, {} fps, last frame {} us, {} dropped, {} SPI bytes",
+                    stats.frames,
+                    stats.fps,
+                    stats.frame_time_us,
+                    stats.dropped_frames,
+                    stats.spi_bytes
+                ));
+                usb::print(format_args!(
+                    "lifetime: {} games, {} s played, {} kills, best combo {}",
+                    totals.games,
+                    totals.play_secs,
+                    totals.kills,
+                    totals.best_combo
+                ));
+            }
+            Ok(Command::Reset) => {
+                restart::spawn(false).ok();
+            }
+            Ok(Command::Dfu) => {
+                restart::spawn(true).ok();
+            }
+            #[cfg(feature = "profile")]
+            Ok(Command::Profile) => profile::dump(usb::print),
+            #[cfg(feature = "profile")]
+            Ok(Command::ProfileReset) => {
+                profile::reset();
+                usb::print(format_args!("profile reset"));
+            }
+            Ok(_) => usb::print(format_args!(
+                "only reset, dfu, set brightness, stats and profile over USB"
+            )),
+            Err(ParseError::Empty) => (),
+            Err(err) => usb::print(format_args!("{:?}", err)),
+        });
+    }
+
+    // Above the frame task, so a long frame doesn't hold up the debouncing
+    #[cfg(feature = "buttons")]
+    #[task(binds = RTC1, priority = 2, local = [buttons], shared = [pad])]
+    fn rtc1(mut ctx: rtc1::Context) {
+        within_budget!("rtc1", INPUT_US);
+        let buttons = ctx.local.buttons;
+        ctx.shared.pad.lock(|pad| buttons.on_interrupt(pad));
+    }
+
+    // Below everything else, so it only gets what the tasks leave. The HUD
+    // is drawn here, and `hud::show` wakes it for that. The watchdog's fed
+    // from here too, since getting here at all says nothing's stuck.
+    #[idle(local = [feeder])]
+    fn idle(ctx: idle::Context) -> ! {
         loop {
-            cortex_m::asm::wfi();
+            ctx.local.feeder.poll();
+            if !hud::idle() {
+                cortex_m::asm::wfe();
+            }
         }
     }
 }
@@ -175,6 +2793,10 @@ mod app {
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
     cortex_m::interrupt::disable();
-    rprintln!("{}", info);
+    #[cfg(feature = "haptics")]
+    haptics::halt();
+    logging::print(Level::Error, format_args!("{}", info));
+    history::dump();
+    crash::report(info);
     loop {}
 }
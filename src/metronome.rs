@@ -0,0 +1,83 @@
+use crate::timer::{Compare, Timer};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use nrf52840_pac::{Interrupt, TIMER3};
+
+// A beat at a steady tempo, for anything that wants to move in time with it.
+// TIMER3 is given over to it, so the beats keep to the tempo however long
+// frames take: each compare is scheduled from the last one rather than from
+// whenever the interrupt got round to running.
+
+// Slowest and fastest tempos, 0 meaning stopped
+pub const MIN_BPM: u8 = 40;
+pub const MAX_BPM: u8 = 240;
+
+// Beats per minute, 0 when stopped
+static BPM: AtomicU8 = AtomicU8::new(0);
+// Beats since boot, written from the TIMER3 interrupt
+static BEATS: AtomicU32 = AtomicU32::new(0);
+
+pub fn interval_us(bpm: u8) -> u32 {
+    60_000_000 / bpm as u32
+}
+
+// Changes the tempo from now on. The next beat comes straight away, rather
+// than some leftover part of a beat at the old tempo.
+pub fn set_bpm(bpm: u8) {
+    BPM.store(bpm, Ordering::Relaxed);
+    rtic::pend(Interrupt::TIMER3);
+}
+
+// The interrupt side, which owns the timer
+pub struct Metronome {
+    timer: TIMER3,
+}
+
+impl Metronome {
+    // Uses compare channel 1. Nothing happens until `set_bpm`, and the timer
+    // doesn't count until then either.
+    pub fn new(mut timer: TIMER3) -> Self {
+        timer.init();
+        timer.pause();
+        Metronome { timer }
+    }
+
+    // Called from the TIMER3 interrupt, either for a beat or because the
+    // tempo changed
+    pub fn on_interrupt(&mut self) {
+        let on_time = self.timer.is_compare_event(Compare::One);
+        self.timer.ack_compare_event(Compare::One);
+
+        let bpm = BPM.load(Ordering::Relaxed);
+        if bpm == 0 {
+            self.timer.stop(Compare::One);
+            self.timer.pause();
+            return;
+        }
+        BEATS.fetch_add(1, Ordering::Relaxed);
+        if on_time {
+            self.timer.fire_again(Compare::One, interval_us(bpm));
+        } else {
+            self.timer.fire_at(Compare::One, interval_us(bpm));
+        }
+    }
+}
+
+// The reading side, for whoever is keeping time with it
+pub struct Beats {
+    seen: u32,
+}
+
+impl Beats {
+    pub const fn new() -> Self {
+        Beats { seen: 0 }
+    }
+
+    // Whether there's been a beat since the last poll. Several beats between
+    // polls still only count once.
+    pub fn poll(&mut self) -> bool {
+        let beats = BEATS.load(Ordering::Relaxed);
+        let beat = beats != self.seen;
+        self.seen = beats;
+        beat
+    }
+}
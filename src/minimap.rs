@@ -0,0 +1,102 @@
+use crate::color::rgb565;
+use crate::game::{Rect, State, World};
+use crate::limits::Limits;
+
+// The whole world at half size, for a layout with a minimap in it: a dot
+// for the ships, each enemy and power-up, and the boss, inside a dim border.
+// Enemies still coming in from above the screen sit along the top edge until
+// they're on it, so the minimap shows what's coming before the playfield
+// does. It's drawn here rather than with draw.rs, which takes everything to
+// be the frame's width.
+
+const SCALE: i32 = 2;
+pub const W: usize = Limits::SCREEN_WIDTH / SCALE as usize;
+pub const H: usize = Limits::SCREEN_HEIGHT / SCALE as usize;
+pub const BYTES: usize = W * H * 2;
+
+const BORDER: u16 = rgb565(6, 12, 6);
+const SHIP: u16 = rgb565(0, 63, 31);
+const PARTNER: u16 = rgb565(0, 63, 0);
+const ENEMY: u16 = rgb565(31, 0, 0);
+const POWER_UP: u16 = rgb565(31, 63, 0);
+const BOSS: u16 = rgb565(31, 0, 31);
+
+pub fn draw(map: &mut [u8; BYTES], world: &World) {
+    for (i, pixel) in map.chunks_exact_mut(2).enumerate() {
+        let (x, y) = (i % W, i / W);
+        let edge = x == 0 || y == 0 || x == W - 1 || y == H - 1;
+        let color = if edge { BORDER } else { 0 };
+        pixel.copy_from_slice(&color.to_le_bytes());
+    }
+    for enemy in world.enemies.live() {
+        dot(map, middle(enemy.rect()), ENEMY);
+    }
+    for power_up in world.power_ups.live() {
+        dot(map, middle(power_up.rect()), POWER_UP);
+    }
+    // Two by two, to stand out from everything else
+    if let Some(boss) = world.boss {
+        let (x, y) = middle(boss.rect());
+        for &(dx, dy) in [(0, 0), (SCALE, 0), (0, SCALE), (SCALE, SCALE)].iter() {
+            dot(map, (x + dx - SCALE / 2, y + dy - SCALE / 2), BOSS);
+        }
+    }
+    if world.state != State::Title {
+        if let Some(partner) = world.partner {
+            dot(map, middle(partner.rect()), PARTNER);
+        }
+        dot(map, middle(world.ship.rect()), SHIP);
+    }
+}
+
+fn middle(rect: Rect) -> (i32, i32) {
+    (rect.x + rect.w / 2, rect.y + rect.h / 2)
+}
+
+// The pixel under (x, y) in the world, kept inside the border
+fn dot(map: &mut [u8; BYTES], (x, y): (i32, i32), color: u16) {
+    let x = (x / SCALE).clamp(1, W as i32 - 2) as usize;
+    let y = (y / SCALE).clamp(1, H as i32 - 2) as usize;
+    let i = (y * W + x) * 2;
+    map[i..i + 2].copy_from_slice(&color.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Enemy;
+    use crate::pool::Pool;
+
+    fn at(map: &[u8; BYTES], x: usize, y: usize) -> u16 {
+        let i = (y * W + x) * 2;
+        u16::from_le_bytes([map[i], map[i + 1]])
+    }
+
+    #[test]
+    fn the_ship_is_a_dot_at_half_its_position() {
+        let mut world = World::new();
+        world.start();
+        let mut map = [0xaa; BYTES];
+        draw(&mut map, &world);
+        let (x, y) = middle(world.ship.rect());
+        assert_eq!(at(&map, x as usize / 2, y as usize / 2), SHIP);
+        assert_eq!(at(&map, 0, 0), BORDER);
+        assert_eq!(at(&map, W / 2, 1), 0);
+    }
+
+    #[test]
+    fn enemies_above_the_screen_wait_along_the_top() {
+        let mut world = World::new();
+        world.start();
+        world.enemies = Pool::new();
+        world.enemies.spawn(Enemy {
+            x: 40,
+            y: -20,
+            hp: 1,
+            hit_flash_until: 0,
+        });
+        let mut map = [0; BYTES];
+        draw(&mut map, &world);
+        assert_eq!(at(&map, (40 + 2) / 2, 1), ENEMY);
+    }
+}
@@ -0,0 +1,236 @@
+#[cfg(feature = "rtc-ticks")]
+use crate::clock;
+use core::ops::{Add, Sub};
+use nrf52840_pac::{RTC2, TIMER1};
+use rtic::Monotonic;
+
+// RTIC's monotonic, so tasks can be scheduled with `spawn_at` and
+// `spawn_after` rather than each owning a timer and its compare registers.
+// TIMER1 counts microseconds in 32 bits, which wrap every 71 minutes, so it's
+// stretched out to 64 with a count of the wraps: CC[1] is left at zero and
+// fires as the counter goes round. CC[0] is the next task's compare, and
+// CC[2] is where `now` captures the counter.
+//
+// The interrupt stays on with nothing scheduled, since it's also what counts
+// the wraps.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant(u64);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration(u64);
+
+impl Duration {
+    pub const fn micros(us: u32) -> Self {
+        Duration(us as u64)
+    }
+
+    pub const fn millis(ms: u32) -> Self {
+        Duration(ms as u64 * 1000)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0 + rhs.0)
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, rhs: Duration) -> Instant {
+        Instant(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        Duration(self.0.saturating_sub(rhs.0))
+    }
+}
+
+pub struct Mono {
+    timer: TIMER1,
+    // How many times the counter has wrapped
+    wraps: u32,
+}
+
+impl Mono {
+    pub fn new(timer: TIMER1) -> Self {
+        Mono { timer, wraps: 0 }
+    }
+}
+
+impl Monotonic for Mono {
+    const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+    type Instant = Instant;
+    type Duration = Duration;
+
+    // Only ever called with interrupts off, so a wrap can't be counted in
+    // between reading the two halves
+    fn now(&mut self) -> Instant {
+        self.timer.tasks_capture[2].write(|w| w.tasks_capture().set_bit());
+        let count = self.timer.cc[2].read().bits();
+        let mut wraps = self.wraps;
+        // Wrapped, but the interrupt hasn't been in to count it yet. A count
+        // from the top half must have been captured before it wrapped.
+        if self.timer.events_compare[1].read().bits() != 0 && count < 1 << 31 {
+            wraps += 1;
+        }
+        Instant((wraps as u64) << 32 | count as u64)
+    }
+
+    // Only the bottom 32 bits can be compared against, so anything further
+    // off fires early, and RTIC sees it's not due yet and sets it again
+    fn set_compare(&mut self, instant: Instant) {
+        self.timer.cc[0].write(|w| unsafe { w.bits(instant.0 as u32) });
+    }
+
+    fn clear_compare_flag(&mut self) {
+        self.timer.events_compare[0].reset();
+    }
+
+    fn zero() -> Instant {
+        Instant(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        let timer = &self.timer;
+        timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+        timer.mode.write(|w| w.mode().timer());
+        timer.bitmode.write(|w| w.bitmode()._32bit());
+        // 16 MHz / 2^4
+        timer.prescaler.write(|w| w.prescaler().bits(4));
+        timer.cc[1].write(|w| w.bits(0));
+        timer.events_compare[0].reset();
+        timer.events_compare[1].reset();
+        timer
+            .intenset
+            .write(|w| w.compare0().set().compare1().set());
+        timer.tasks_clear.write(|w| w.tasks_clear().set_bit());
+        timer.tasks_start.write(|w| w.tasks_start().set_bit());
+        self.wraps = 0;
+    }
+
+    fn on_interrupt(&mut self) {
+        if self.timer.events_compare[1].read().bits() != 0 {
+            self.timer.events_compare[1].reset();
+            self.wraps += 1;
+        }
+    }
+}
+
+// Only frames during play need TIMER1 with `rtc-ticks`, so whenever the
+// next frame is on RTC2, TIMER1 is paused, and so is HFXO where nothing else
+// needs it. Then with the CPU asleep between frames and every other timer
+// stopped, the HF clock is off. Mono's time stands still while TIMER1 is
+// paused, which nothing minds, since everything else that's scheduled is on
+// RTC2 as well. RTIC owns TIMER1, but with nothing scheduled on it, these
+// are the only things that touch it.
+#[cfg(feature = "rtc-ticks")]
+pub fn low_power() {
+    let timer = unsafe { &*TIMER1::ptr() };
+    timer.tasks_stop.write(|w| w.tasks_stop().set_bit());
+    if clock::CAN_STOP_HFXO {
+        clock::stop_hfxo();
+    }
+}
+
+// Before scheduling a frame on TIMER1, which counts on from where it stopped,
+// on the crystal again
+#[cfg(feature = "rtc-ticks")]
+pub fn precise() {
+    if clock::CAN_STOP_HFXO {
+        clock::start_hfxo();
+    }
+    let timer = unsafe { &*TIMER1::ptr() };
+    timer.tasks_start.write(|w| w.tasks_start().set_bit());
+}
+
+// The same instants on RTC2, for when the precision isn't needed. RTC2 runs
+// from the 32.768 kHz LFCLK, so it ticks every 30.5 us, and its counter is
+// 24 bits, which wraps every 512 s and is stretched out by counting
+// overflows. It's a second monotonic rather than a replacement: a task asks
+// for it with `task::RtcTimer::spawn_after` where it would otherwise
+// `spawn_after`. Only with `rtc-ticks`, but RTIC's scheduling code names the
+// type even with the monotonic cfg'd out, so it's always here.
+pub struct RtcMono {
+    rtc: RTC2,
+    overflows: u32,
+}
+
+const RTC_HZ: u64 = 32768;
+// A compare only fires if it's at least this many ticks ahead of the counter
+const MIN_TICKS_AHEAD: u32 = 2;
+const COUNTER_MASK: u32 = (1 << 24) - 1;
+
+impl RtcMono {
+    #[cfg_attr(not(feature = "rtc-ticks"), allow(dead_code))]
+    pub fn new(rtc: RTC2) -> Self {
+        RtcMono { rtc, overflows: 0 }
+    }
+}
+
+impl Monotonic for RtcMono {
+    const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+    type Instant = Instant;
+    type Duration = Duration;
+
+    // Like Mono's, only ever called with interrupts off
+    fn now(&mut self) -> Instant {
+        let count = self.rtc.counter.read().bits();
+        let mut overflows = self.overflows;
+        if self.rtc.events_ovrflw.read().bits() != 0 && count < 1 << 23 {
+            overflows += 1;
+        }
+        let ticks = (overflows as u64) << 24 | count as u64;
+        Instant(ticks * 1_000_000 / RTC_HZ)
+    }
+
+    // Rounded up to a whole tick, so it never fires before it's due
+    fn set_compare(&mut self, instant: Instant) {
+        let ticks = (instant.0 * RTC_HZ).div_ceil(1_000_000);
+        let now = self.rtc.counter.read().bits();
+        let ahead = (ticks as u32).wrapping_sub(now) & COUNTER_MASK;
+        let ticks = if ahead < MIN_TICKS_AHEAD {
+            now + MIN_TICKS_AHEAD
+        } else {
+            ticks as u32
+        };
+        self.rtc.cc[0].write(|w| unsafe { w.bits(ticks & COUNTER_MASK) });
+    }
+
+    fn clear_compare_flag(&mut self) {
+        self.rtc.events_compare[0].reset();
+    }
+
+    fn zero() -> Instant {
+        Instant(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        let rtc = &self.rtc;
+        rtc.tasks_stop.write(|w| w.bits(1));
+        rtc.prescaler.write(|w| w.bits(0));
+        rtc.events_compare[0].reset();
+        rtc.events_ovrflw.reset();
+        rtc.intenset.write(|w| w.compare0().set().ovrflw().set());
+        rtc.tasks_clear.write(|w| w.bits(1));
+        rtc.tasks_start.write(|w| w.bits(1));
+        self.overflows = 0;
+    }
+
+    fn on_interrupt(&mut self) {
+        if self.rtc.events_ovrflw.read().bits() != 0 {
+            self.rtc.events_ovrflw.reset();
+            self.overflows += 1;
+        }
+    }
+}
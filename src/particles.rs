@@ -0,0 +1,200 @@
+use crate::color::{self, rgb565};
+use crate::fixed::Fixed;
+use crate::game::{Rect, HEIGHT, WIDTH};
+use crate::limits::Limits;
+use crate::pool::Pool;
+use crate::trig;
+
+// Sparks that fly out of whatever gets hit and fade away, purely for show.
+// They go out evenly spaced in a ring, turned a little further each frame so
+// no two bursts look quite the same, which takes no random numbers and so
+// leaves the game's rng exactly as it would have been without them. The
+// ships' exhaust is the same sort of thing, a spark at a time out of the
+// back of a ship that's moving.
+//
+// Each spark has its own color, picked from its preset's as it goes out, and
+// dims from there as it burns out.
+
+pub const MAX_PARTICLES: usize = Limits::PARTICLES;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spark {
+    // Out of a destroyed enemy or the boss, 2x2 for the first half
+    Explosion,
+    // Off an enemy that took a hit and survived
+    Impact,
+    // Out of the back of a moving ship
+    Thrust,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Particle {
+    pub kind: Spark,
+    color: u16,
+    x: Fixed,
+    y: Fixed,
+    dx: Fixed,
+    dy: Fixed,
+    started: u32,
+    ends: u32,
+}
+
+impl Particle {
+    pub fn rect(&self, now: u32) -> Rect {
+        let size = match self.kind {
+            Spark::Explosion if now < (self.started + self.ends) / 2 => 2,
+            _ => 1,
+        };
+        Rect {
+            x: self.x.to_int(),
+            y: self.y.to_int(),
+            w: size,
+            h: size,
+        }
+    }
+
+    // How much of its life is left, out of 256, for fading it out
+    pub fn life(&self, now: u32) -> u32 {
+        let total = (self.ends - self.started).max(1);
+        self.ends.saturating_sub(now) * 256 / total
+    }
+
+    // Its own color, down to a quarter of that by the time it's gone
+    pub fn color(&self, now: u32) -> u16 {
+        color::scale(self.color, 64 + self.life(now) * 3 / 4)
+    }
+}
+
+// Explosions go out yellow, orange and red, impacts white and exhaust a
+// hot orange
+const EXPLOSION: &[u16] = &[rgb565(31, 63, 0), rgb565(31, 40, 0), rgb565(31, 16, 0)];
+const IMPACT: &[u16] = &[rgb565(31, 63, 31)];
+const THRUST: &[u16] = &[rgb565(31, 48, 8), rgb565(31, 63, 16)];
+
+struct Preset {
+    count: i32,
+    // Pixels a frame
+    speed: Fixed,
+    frames: u32,
+    // Taken in turn, one spark after another and a frame after another
+    colors: &'static [u16],
+}
+
+impl Spark {
+    fn preset(self) -> Preset {
+        match self {
+            Spark::Explosion => Preset {
+                count: 8,
+                speed: Fixed::from_ratio(3, 4),
+                frames: 12,
+                colors: EXPLOSION,
+            },
+            Spark::Impact => Preset {
+                count: 3,
+                speed: Fixed::HALF,
+                frames: 6,
+                colors: IMPACT,
+            },
+            Spark::Thrust => Preset {
+                count: 1,
+                speed: Fixed::HALF,
+                frames: 6,
+                colors: THRUST,
+            },
+        }
+    }
+}
+
+// As many of `kind`'s sparks around (x, y) as there's room for
+pub fn emit(pool: &mut Pool<Particle, MAX_PARTICLES>, kind: Spark, x: i32, y: i32, now: u32) {
+    let count = kind.preset().count;
+    let turn = Fixed::angle(now);
+    spray(pool, kind, x, y, now, |i| turn + Fixed::TAU * Fixed::from_ratio(i, count));
+}
+
+// Exhaust out of (x, y), away from the way the ship's going, `dx` being how
+// far it went, and slanting down, swinging a little either side from one
+// frame to the next
+pub fn thrust(pool: &mut Pool<Particle, MAX_PARTICLES>, x: i32, y: i32, dx: i32, now: u32) {
+    let slant = Fixed::PI * Fixed::from_ratio(1, 8);
+    let back = if dx > 0 { Fixed::PI - slant } else { slant };
+    let sway = Fixed::PI * Fixed::from_ratio((now % 3) as i32 - 1, 16);
+    spray(pool, Spark::Thrust, x, y, now, |_| back + sway);
+}
+
+// `kind`'s sparks out of (x, y), the i'th at `angle(i)` radians
+fn spray(
+    pool: &mut Pool<Particle, MAX_PARTICLES>,
+    kind: Spark,
+    x: i32,
+    y: i32,
+    now: u32,
+    angle: impl Fn(i32) -> Fixed,
+) {
+    let preset = kind.preset();
+    for (i, slot) in (0..preset.count).zip(pool.free_slots()) {
+        let angle = angle(i);
+        let color = (i as usize + now as usize) % preset.colors.len();
+        *slot = Some(Particle {
+            kind,
+            color: preset.colors[color],
+            x: Fixed::from_int(x),
+            y: Fixed::from_int(y),
+            dx: preset.speed * trig::cos_fix(angle),
+            dy: preset.speed * trig::sin_fix(angle),
+            started: now,
+            ends: now + preset.frames,
+        });
+    }
+}
+
+// Moves every particle along, despawning the ones that have burnt out or
+// left the screen
+pub fn update(pool: &mut Pool<Particle, MAX_PARTICLES>, now: u32) {
+    pool.update(|particle| {
+        particle.x += particle.dx;
+        particle.y += particle.dy;
+        let (x, y) = (particle.x.to_int(), particle.y.to_int());
+        now < particle.ends && (0..WIDTH).contains(&x) && (0..HEIGHT).contains(&y)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sparks(pool: &Pool<Particle, MAX_PARTICLES>) -> Vec<Particle> {
+        pool.live().copied().collect()
+    }
+
+    #[test]
+    fn each_spark_takes_its_presets_colors_in_turn() {
+        let mut pool = Pool::new();
+        emit(&mut pool, Spark::Explosion, 32, 32, 0);
+        let colors = Spark::Explosion.preset().colors;
+        let sparks = sparks(&pool);
+        assert_eq!(sparks.len(), 8);
+        for (i, spark) in sparks.iter().enumerate() {
+            assert_eq!(spark.color, colors[i % colors.len()]);
+        }
+        // Full strength to start with, and dimmer on the way out
+        assert_eq!(sparks[0].color(0), colors[0]);
+        assert_ne!(sparks[0].color(11), colors[0]);
+    }
+
+    #[test]
+    fn thrust_goes_out_the_back_and_down() {
+        let mut pool = Pool::new();
+        thrust(&mut pool, 32, 32, 1, 0);
+        thrust(&mut pool, 32, 32, -1, 1);
+        match sparks(&pool)[..] {
+            [right, left] => {
+                assert_eq!((right.kind, left.kind), (Spark::Thrust, Spark::Thrust));
+                assert!(right.dx < Fixed::from_int(0) && right.dy > Fixed::from_int(0));
+                assert!(left.dx > Fixed::from_int(0) && left.dy > Fixed::from_int(0));
+                assert_ne!(right.color, left.color);
+            }
+            _ => panic!("one spark each"),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::display;
+use crate::dma::MAX_TRANSFER;
+use crate::framebuffer::DoubleBuffer;
+use crate::layout::MAX_PLAYFIELDS;
+use crate::limits::Limits;
+use core::sync::atomic::{compiler_fence, AtomicBool, Ordering};
+use embedded_hal::digital::v2::OutputPin;
+use nrf52840_hal::gpio::{Output, Pin, PushPull};
+use nrf52840_pac::{spim0, SPIM1};
+
+// Sends whole frames to the panel with EasyDMA, one transfer after another
+// from SPIM1's END interrupt, so the frame task can get on with the next
+// frame while this one goes out. The ST7735 driver waits out every transfer
+// it starts, so this is a command/data pipeline of its own: for each tile,
+// CASET, RASET and RAMWR, each a command byte with DC low followed by its
+// parameters with DC high, then the tile's pixels.
+//
+// The frame is copied into the back of a double buffer, byte swapped for the
+// panel, so the framebuffer can be drawn into again straight away. That copy
+// can be made while the last frame is still going out of the front, but
+// there's only ever one frame in flight, so it's then waited for before the
+// buffers swap and the new one starts. Anything else that uses the bus, the
+// driver included, has to `wait` for it first too.
+
+const FRAME_BYTES: usize = Limits::FRAME_BYTES;
+// Each tile's pixels go out in a single transfer
+const _: () = assert!(FRAME_BYTES <= MAX_TRANSFER);
+
+const CASET: u8 = 0x2A;
+const RASET: u8 = 0x2B;
+const RAMWR: u8 = 0x2C;
+// CASET and its four bytes, RASET and its four, then RAMWR
+const COMMAND_BYTES: usize = 11;
+// Whether DC is high, and which of a tile's command bytes go out, for each
+// step before its pixels
+const COMMAND_STEPS: [(bool, usize, usize); 5] = [
+    (false, 0, 1),
+    (true, 1, 5),
+    (false, 5, 6),
+    (true, 6, 10),
+    (false, 10, 11),
+];
+const STEPS_PER_TILE: usize = COMMAND_STEPS.len() + 1;
+
+static BUSY: AtomicBool = AtomicBool::new(false);
+// Set when a frame's dropped part way out, until the frame task asks
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+pub struct Pipeline {
+    pixels: DoubleBuffer<FRAME_BYTES>,
+    commands: [[u8; COMMAND_BYTES]; MAX_PLAYFIELDS],
+    // How many of `commands` are in use, since a tile that doesn't fit is
+    // left out
+    tiles: usize,
+    // The transfer going out, counting on across the tiles
+    step: usize,
+    // A second handle on the driver's DC pin, only ever used while the
+    // driver's waiting
+    dc: Pin<Output<PushPull>>,
+}
+
+impl Pipeline {
+    pub fn new(dc: &Pin<Output<PushPull>>) -> Self {
+        Pipeline {
+            pixels: DoubleBuffer::new(),
+            commands: [[0; COMMAND_BYTES]; MAX_PLAYFIELDS],
+            tiles: 0,
+            step: 0,
+            dc: unsafe { Pin::from_psel_bits(dc.psel_bits()) },
+        }
+    }
+
+    // Copies `frame`, little-endian RGB565, into the back buffer. Can be
+    // called with the last frame still in flight.
+    pub fn prepare(&mut self, frame: &[u8]) {
+        let back = self.pixels.back();
+        for (out, pixel) in back.chunks_exact_mut(2).zip(frame.chunks_exact(2)) {
+            out.copy_from_slice(&[pixel[1], pixel[0]]);
+        }
+    }
+
+    // The same from RGB565 colors, for a frame that's only put together on
+    // its way out
+    #[cfg(feature = "indexed")]
+    pub fn prepare_pixels(&mut self, pixels: impl Iterator<Item = u16>) {
+        let back = self.pixels.back();
+        for (out, color) in back.chunks_exact_mut(2).zip(pixels) {
+            out.copy_from_slice(&color.to_be_bytes());
+        }
+    }
+
+    // Starts the frame that was prepared going out to each of `tiles` on a
+    // `panel`, as an image of `size`. Returns how many bytes of pixels that
+    // comes to. The last frame must have been waited for.
+    pub fn start(&mut self, panel: (u16, u16), tiles: &[(u16, u16)], size: (u16, u16)) -> u32 {
+        self.pixels.swap();
+        self.tiles = 0;
+        for &offset in tiles.iter().take(MAX_PLAYFIELDS) {
+            let (x, y) = match display::checked_offset(panel, offset, size) {
+                Ok(offset) => offset,
+                Err(_) => continue,
+            };
+            let [x0h, x0l] = x.to_be_bytes();
+            let [x1h, x1l] = (x + size.0 - 1).to_be_bytes();
+            let [y0h, y0l] = y.to_be_bytes();
+            let [y1h, y1l] = (y + size.1 - 1).to_be_bytes();
+            self.commands[self.tiles] =
+                [CASET, x0h, x0l, x1h, x1l, RASET, y0h, y0l, y1h, y1l, RAMWR];
+            self.tiles += 1;
+        }
+        if self.tiles == 0 {
+            return 0;
+        }
+
+        BUSY.store(true, Ordering::Release);
+        self.step = 0;
+        let spim = spim();
+        spim.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(0) });
+        spim.events_end.reset();
+        spim.intenset.write(|w| w.end().set());
+        self.send_step(spim);
+        (self.tiles * FRAME_BYTES) as u32
+    }
+
+    // Called from SPIM1's interrupt. Checks the transfer that just ended and
+    // starts the next, or finishes the frame after the last. A short one
+    // drops the rest of the frame rather than sending it out of step.
+    pub fn on_end(&mut self) {
+        let spim = spim();
+        if spim.events_end.read().bits() == 0 {
+            return;
+        }
+        spim.events_end.reset();
+        compiler_fence(Ordering::SeqCst);
+
+        let expected = self.current().1.len();
+        let sent = spim.txd.amount.read().amount().bits() as usize;
+        if sent != expected {
+            log_error!("Frame transfer sent {} of {} bytes", sent, expected);
+            FAILED.store(true, Ordering::Relaxed);
+            finish(spim);
+            return;
+        }
+        self.step += 1;
+        if self.step == self.tiles * STEPS_PER_TILE {
+            finish(spim);
+            return;
+        }
+        self.send_step(spim);
+    }
+
+    // Whether DC is high for the transfer going out, and its bytes
+    fn current(&self) -> (bool, &[u8]) {
+        let (tile, step) = (self.step / STEPS_PER_TILE, self.step % STEPS_PER_TILE);
+        match COMMAND_STEPS.get(step) {
+            Some(&(data, start, end)) => (data, &self.commands[tile][start..end]),
+            None => (true, self.pixels.front()),
+        }
+    }
+
+    // The bus is idle between transfers, so DC can change under it
+    fn send_step(&mut self, spim: &spim0::RegisterBlock) {
+        let (data, bytes) = self.current();
+        let (ptr, len) = (bytes.as_ptr() as u32, bytes.len() as u16);
+        if data {
+            self.dc.set_high().ok();
+        } else {
+            self.dc.set_low().ok();
+        }
+        compiler_fence(Ordering::SeqCst);
+        spim.txd.ptr.write(|w| unsafe { w.ptr().bits(ptr) });
+        spim.txd.maxcnt.write(|w| unsafe { w.maxcnt().bits(len) });
+        spim.tasks_start.write(|w| unsafe { w.bits(1) });
+    }
+}
+
+// The driver doesn't expect the interrupt, so it's off again until the next
+// frame
+fn finish(spim: &spim0::RegisterBlock) {
+    spim.intenclr.write(|w| w.end().clear());
+    BUSY.store(false, Ordering::Release);
+}
+
+fn spim() -> &'static spim0::RegisterBlock {
+    unsafe { &*SPIM1::ptr() }
+}
+
+// Spins until the frame in flight, if there is one, has gone out. SPIM1's
+// interrupt is above everything that touches the display, so it still gets
+// to run, as long as the caller isn't holding the pipeline's lock.
+pub fn wait() {
+    while BUSY.load(Ordering::Acquire) {
+        core::hint::spin_loop();
+    }
+}
+
+// Whether a frame has been dropped since this was last asked
+pub fn take_failed() -> bool {
+    FAILED.swap(false, Ordering::Relaxed)
+}
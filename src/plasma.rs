@@ -0,0 +1,477 @@
+use crate::color;
+use crate::draw;
+use crate::fixed::Fixed;
+#[cfg(feature = "indexed")]
+use crate::indexed::{self, Palette};
+use crate::limits::Limits;
+use crate::quality;
+use crate::trig;
+use core::ops::{Add, Mul};
+#[cfg(feature = "plasma-float")]
+use num_traits::float::Float;
+
+const WIDTH: usize = Limits::SCREEN_WIDTH;
+const HEIGHT: usize = Limits::SCREEN_HEIGHT;
+
+// Stands in for a variant index to pick a new random one every time the
+// title screen comes up
+pub const RANDOM: u8 = u8::MAX;
+
+// The math is fixed point, but the f32 version it replaced is kept behind
+// `plasma-float`, for when the fixed point one looks wrong on some panel.
+// Everything goes through `Scalar`, so the two share every line but the
+// arithmetic, and their colors come out at most a step apart in any channel.
+// Only the fixed point one gives the same bytes on every machine.
+#[cfg(not(feature = "plasma-float"))]
+pub type Angle = Fixed;
+#[cfg(feature = "plasma-float")]
+pub type Angle = f32;
+
+pub trait Scalar: Copy + Add<Output = Self> + Mul<Output = Self> {
+    const TAU: Self;
+
+    fn of_int(n: i32) -> Self;
+    fn of_ratio(num: i32, den: i32) -> Self;
+    // Whole radians reduced into 0..TAU, for angles that count up forever
+    // like the frame number
+    fn wrapped(radians: u32) -> Self;
+    // 0.5 + 0.5 cos(self), scaled to 0..=max
+    fn level(self, max: i32) -> u16;
+}
+
+impl Scalar for Fixed {
+    const TAU: Self = Fixed::TAU;
+
+    fn of_int(n: i32) -> Self {
+        Fixed::from_int(n)
+    }
+
+    fn of_ratio(num: i32, den: i32) -> Self {
+        Fixed::from_ratio(num, den)
+    }
+
+    fn wrapped(radians: u32) -> Self {
+        Fixed::angle(radians)
+    }
+
+    fn level(self, max: i32) -> u16 {
+        let level = Fixed::HALF + Fixed::HALF * trig::cos_fix(self);
+        (level * Fixed::from_int(max)).to_int().clamp(0, max) as u16
+    }
+}
+
+#[cfg(feature = "plasma-float")]
+impl Scalar for f32 {
+    const TAU: Self = core::f32::consts::TAU;
+
+    fn of_int(n: i32) -> Self {
+        n as f32
+    }
+
+    fn of_ratio(num: i32, den: i32) -> Self {
+        num as f32 / den as f32
+    }
+
+    // Reduced in integer math, since an f32 that's counted up for long
+    // enough has no fraction left
+    fn wrapped(radians: u32) -> Self {
+        Fixed::angle(radians).to_f32()
+    }
+
+    fn level(self, max: i32) -> u16 {
+        ((0.5 + 0.5 * self.cos()) * max as f32).clamp(0.0, max as f32) as u16
+    }
+}
+
+// Each color channel is 0.5 + 0.5 cos(t + row * x + col * y + phase), with x
+// and y going from 0 to 1 down and across the screen
+#[derive(Clone, Copy)]
+struct Channel {
+    row: i8,
+    col: i8,
+    phase: i8,
+}
+
+const fn channel(row: i8, col: i8, phase: i8) -> Channel {
+    Channel { row, col, phase }
+}
+
+impl Channel {
+    fn row(self, t: Angle, x: Angle) -> Angle {
+        t + Angle::of_int(self.row as i32) * x + Angle::of_int(self.phase as i32)
+    }
+
+    fn col(self, y: Angle) -> Angle {
+        Angle::of_int(self.col as i32) * y
+    }
+
+    // The part of the angle that doesn't move
+    #[cfg(feature = "indexed")]
+    fn shape(self, x: Angle, y: Angle) -> Angle {
+        Angle::of_int(self.row as i32) * x + self.col(y)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Variant {
+    // Only the console goes by name
+    #[cfg_attr(not(feature = "console"), allow(dead_code))]
+    pub name: &'static str,
+    // Red, green, blue
+    channels: [Channel; 3],
+}
+
+pub const VARIANTS: [Variant; 5] = [
+    // The original: red and blue sweep down the screen, green across it
+    Variant {
+        name: "classic",
+        channels: [channel(1, 0, 0), channel(0, 1, 2), channel(1, 0, 4)],
+    },
+    Variant {
+        name: "diagonal",
+        channels: [channel(4, 4, 0), channel(-4, 4, 2), channel(2, -6, 4)],
+    },
+    Variant {
+        name: "stripes",
+        channels: [channel(9, 0, 0), channel(0, 9, 1), channel(6, 6, 3)],
+    },
+    Variant {
+        name: "ember",
+        channels: [channel(2, 1, 0), channel(3, 2, 1), channel(0, 0, 3)],
+    },
+    Variant {
+        name: "lagoon",
+        channels: [channel(0, 0, 3), channel(5, -3, 0), channel(-2, 5, 1)],
+    },
+];
+
+impl Variant {
+    // Out of range indices, RANDOM included, get the classic look
+    pub fn get(index: u8) -> &'static Variant {
+        VARIANTS.get(index as usize).unwrap_or(&VARIANTS[0])
+    }
+
+    #[cfg_attr(not(feature = "console"), allow(dead_code))]
+    pub fn from_name(name: &str) -> Option<u8> {
+        VARIANTS
+            .iter()
+            .position(|variant| variant.name == name)
+            .map(|index| index as u8)
+    }
+
+    pub fn color(&self, t: Angle, x: Angle, y: Angle) -> u16 {
+        let rows = self.row_angles(t, x);
+        let cols = self.col_angles(y);
+        color([rows[0] + cols[0], rows[1] + cols[1], rows[2] + cols[2]])
+    }
+
+    // The angle splits into a part that only depends on the row and one that
+    // only depends on the column, so a renderer going a row at a time can
+    // work out the columns once per frame
+    pub fn row_angles(&self, t: Angle, x: Angle) -> [Angle; 3] {
+        let [r, g, b] = self.channels;
+        [r.row(t, x), g.row(t, x), b.row(t, x)]
+    }
+
+    pub fn col_angles(&self, y: Angle) -> [Angle; 3] {
+        let [r, g, b] = self.channels;
+        [r.col(y), g.col(y), b.col(y)]
+    }
+}
+
+// Draws the plasma for frame `t` over all of `frame`. A pure function of its
+// arguments: with the fixed point math, the same inputs give the same bytes
+// on any machine.
+//
+// Below full quality each computed color covers a square block of pixels:
+// 2x2 at one level down, 4x4 at two. `intensity` scales every color, out of
+// 255, for a plasma that sits behind something else.
+pub fn render(frame: &mut [u8], t: u32, quality: u8, variant: &Variant, intensity: u8) {
+    let block = 1 << (quality::MAX_LEVEL - quality);
+    let t = Angle::wrapped(t);
+    for i in (0..HEIGHT).step_by(block) {
+        for j in (0..WIDTH).step_by(block) {
+            let x = Angle::of_ratio(i as i32, HEIGHT as i32);
+            let y = Angle::of_ratio(j as i32, WIDTH as i32);
+
+            let color = dim(variant.color(t, x, y), intensity);
+            for y in i..i + block {
+                for x in j..j + block {
+                    draw::pixel(frame, x as i32, y as i32, color);
+                }
+            }
+        }
+    }
+}
+
+// With `indexed`, the title's plasma is the old demoscene kind instead: a
+// frame of indices that's drawn once, and a palette that's turned a little
+// every tick to animate it, which is a lookup a pixel rather than three
+// cosines. Each index is the average of the three channels' cosines with
+// the time left out, and the palette goes once round the circle through
+// each channel's phase, so a variant keeps its shapes and colors, but they
+// flow through each other rather than sweeping across.
+#[cfg(feature = "indexed")]
+pub struct Cycling {
+    indices: indexed::Frame,
+    palette: Palette,
+    // The variant the indices were drawn for, if any have been yet
+    variant: Option<u8>,
+}
+
+// Steps in each channel's level when working out the indices, fine enough
+// that they don't band once they're stretched
+#[cfg(feature = "indexed")]
+const FINE: i32 = 4096;
+
+// Palette entries turned a tick, once round in a little over two seconds
+#[cfg(feature = "indexed")]
+const TURN_PER_TICK: u32 = 2;
+
+#[cfg(feature = "indexed")]
+impl Cycling {
+    pub const fn new() -> Self {
+        Cycling {
+            indices: [0; indexed::BYTES],
+            palette: Palette::new(),
+            variant: None,
+        }
+    }
+
+    // Turns to frame `t` of the variant at `index`. The indices are only
+    // drawn again when that's a different variant from last time.
+    pub fn update(&mut self, t: u32, index: u8) {
+        if self.variant != Some(index) {
+            let variant = Variant::get(index);
+            let [r, g, b] = variant.channels;
+            let sum = |i: usize, j: usize| {
+                let x = Angle::of_ratio(i as i32, HEIGHT as i32);
+                let y = Angle::of_ratio(j as i32, WIDTH as i32);
+                let levels = [r.shape(x, y), g.shape(x, y), b.shape(x, y)].map(|a| a.level(FINE));
+                levels.iter().map(|&level| level as u32).sum::<u32>()
+            };
+            // Some variants' shapes only go a little way round, and would
+            // only ever show a few of the colors, so they're stretched over
+            // all of them. That takes a pass to find how far they go.
+            let (mut low, mut high) = (u32::MAX, 0);
+            for i in 0..HEIGHT {
+                for j in 0..WIDTH {
+                    let sum = sum(i, j);
+                    (low, high) = (low.min(sum), high.max(sum));
+                }
+            }
+            let range = (high - low).max(1);
+            for (i, row) in self.indices.chunks_exact_mut(WIDTH).enumerate() {
+                for (j, index) in row.iter_mut().enumerate() {
+                    *index = ((sum(i, j) - low) * 255 / range) as u8;
+                }
+            }
+            for step in 0..=u8::MAX {
+                let turn = Angle::TAU * Angle::of_ratio(step as i32, 256);
+                let phase = |channel: Channel| turn + Angle::of_int(channel.phase as i32);
+                self.palette.set(step, color([phase(r), phase(g), phase(b)]));
+            }
+            self.variant = Some(index);
+        }
+        self.palette.turn(t.wrapping_mul(TURN_PER_TICK) as u8);
+    }
+
+    // Each row's RGB565 colors, for streaming
+    pub fn rows(&self) -> impl Iterator<Item = [u16; WIDTH]> + '_ {
+        indexed::rows(&self.indices, &self.palette)
+    }
+
+    // Over all of `frame`, for when something has to be drawn on top
+    pub fn render(&self, frame: &mut [u8]) {
+        indexed::expand(&self.indices, &self.palette, frame);
+    }
+}
+
+// RGB565 for the summed angles of each channel
+pub fn color(angles: [Angle; 3]) -> u16 {
+    let r5 = angles[0].level(31);
+    let g6 = angles[1].level(63);
+    let b5 = angles[2].level(31);
+    color::rgb565(r5, g6, b5)
+}
+
+// An RGB565 color scaled by `intensity` out of 255
+pub fn dim(color: u16, intensity: u8) -> u16 {
+    if intensity == u8::MAX {
+        return color;
+    }
+    color::scale(color, intensity as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames rendered by the fixed point plasma when it was last known to
+    // look right: the frame number, quality, variant and intensity each was
+    // drawn with, and its bytes in the `golden` directory.
+    //
+    // Setting PEWPEW_BLESS writes them out again from whatever the plasma
+    // draws now, for when a change to how it looks is meant.
+    struct Golden {
+        t: u32,
+        quality: u8,
+        variant: u8,
+        intensity: u8,
+        name: &'static str,
+        bytes: &'static [u8],
+    }
+
+    const GOLDEN: [Golden; 3] = [
+        Golden {
+            t: 0,
+            quality: quality::MAX_LEVEL,
+            variant: 0,
+            intensity: 255,
+            name: "plasma-classic-0.bin",
+            bytes: include_bytes!("../golden/plasma-classic-0.bin"),
+        },
+        Golden {
+            t: 1000,
+            quality: quality::MAX_LEVEL,
+            variant: 0,
+            intensity: 255,
+            name: "plasma-classic-1000.bin",
+            bytes: include_bytes!("../golden/plasma-classic-1000.bin"),
+        },
+        // In blocks, and dimmed behind the game
+        Golden {
+            t: 123,
+            quality: quality::MAX_LEVEL - 1,
+            variant: 1,
+            intensity: 128,
+            name: "plasma-diagonal-123.bin",
+            bytes: include_bytes!("../golden/plasma-diagonal-123.bin"),
+        },
+    ];
+
+    // Each channel of every pixel
+    fn channels(frame: &[u8]) -> impl Iterator<Item = (usize, [u16; 3])> + '_ {
+        frame.chunks_exact(2).enumerate().map(|(i, pixel)| {
+            let color = u16::from_le_bytes([pixel[0], pixel[1]]);
+            (i, [color >> 11, color >> 5 & 0x3F, color & 0x1F])
+        })
+    }
+
+    #[test]
+    fn renders_match_the_golden_frames() {
+        let bless = std::env::var_os("PEWPEW_BLESS").is_some();
+        for golden in &GOLDEN {
+            let mut frame = vec![0; Limits::FRAME_BYTES];
+            let variant = Variant::get(golden.variant);
+            render(
+                &mut frame,
+                golden.t,
+                golden.quality,
+                variant,
+                golden.intensity,
+            );
+            if bless && !cfg!(feature = "plasma-float") {
+                let path = concat!(env!("CARGO_MANIFEST_DIR"), "/golden/");
+                std::fs::write(format!("{}{}", path, golden.name), &frame).unwrap();
+                continue;
+            }
+
+            // The fixed point math gives the same bytes on any machine, so
+            // anything at all different is a change. The float plasma is
+            // only ever a step out in any channel, between rounding the
+            // other way at the edge of a step and the table's
+            // interpolation.
+            let tolerance = if cfg!(feature = "plasma-float") { 1 } else { 0 };
+            assert_eq!(frame.len(), golden.bytes.len());
+            for ((i, ours), (_, theirs)) in channels(&frame).zip(channels(golden.bytes)) {
+                for c in 0..3 {
+                    let off = (ours[c] as i32 - theirs[c] as i32).abs();
+                    assert!(
+                        off <= tolerance,
+                        "{}: pixel ({}, {}) is {:?}, not {:?}",
+                        golden.name,
+                        i % WIDTH,
+                        i / WIDTH,
+                        ours,
+                        theirs
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rendering_twice_gives_the_same_bytes() {
+        let (mut a, mut b) = (
+            vec![0; Limits::FRAME_BYTES],
+            vec![0xFF; Limits::FRAME_BYTES],
+        );
+        render(&mut a, 4321, quality::MAX_LEVEL, &VARIANTS[2], 255);
+        render(&mut b, 4321, quality::MAX_LEVEL, &VARIANTS[2], 255);
+        assert!(a == b);
+    }
+
+    #[cfg(feature = "indexed")]
+    #[test]
+    fn cycling_only_turns_the_palette_until_the_variant_changes() {
+        let mut cycling = Cycling::new();
+        cycling.update(0, 0);
+        let indices = cycling.indices;
+        let first: Vec<_> = cycling.rows().collect();
+        // Every shade in between, not just a few
+        let mut seen = [false; 256];
+        indices.iter().for_each(|&index| seen[index as usize] = true);
+        assert!(seen.iter().filter(|&&seen| seen).count() > 128);
+
+        cycling.update(10, 0);
+        assert!(cycling.indices == indices);
+        let later: Vec<_> = cycling.rows().collect();
+        assert!(later != first);
+        // Twenty entries round, so each pixel has the color of one whose
+        // index was twenty more
+        let (i, j) = (0..indices.len())
+            .find_map(|i| {
+                let index = indices[i].wrapping_add(20);
+                let j = indices.iter().position(|&other| other == index)?;
+                Some((i, j))
+            })
+            .unwrap();
+        assert_eq!(later[i / WIDTH][i % WIDTH], first[j / WIDTH][j % WIDTH]);
+
+        cycling.update(10, 2);
+        assert!(cycling.indices != indices);
+    }
+
+    #[cfg(feature = "indexed")]
+    #[test]
+    fn cycling_renders_what_it_streams() {
+        let mut cycling = Cycling::new();
+        cycling.update(77, 1);
+        let mut frame = vec![0; Limits::FRAME_BYTES];
+        cycling.render(&mut frame);
+        let streamed: Vec<u16> = cycling.rows().flat_map(IntoIterator::into_iter).collect();
+        let rendered: Vec<u16> = channels(&frame)
+            .map(|(_, [r, g, b])| r << 11 | g << 5 | b)
+            .collect();
+        assert_eq!(streamed, rendered);
+    }
+
+    #[test]
+    fn blocks_cover_the_whole_frame() {
+        for quality in 0..=quality::MAX_LEVEL {
+            let block = 1 << (quality::MAX_LEVEL - quality);
+            let mut frame = vec![0; Limits::FRAME_BYTES];
+            render(&mut frame, 77, quality, &VARIANTS[0], 255);
+            let pixels: Vec<u16> = channels(&frame)
+                .map(|(_, [r, g, b])| r << 11 | g << 5 | b)
+                .collect();
+            for y in 0..HEIGHT {
+                for x in 0..WIDTH {
+                    let corner = (y / block * block) * WIDTH + x / block * block;
+                    assert_eq!(pixels[y * WIDTH + x], pixels[corner]);
+                }
+            }
+        }
+    }
+}
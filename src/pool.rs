@@ -0,0 +1,66 @@
+use core::ops::{Deref, DerefMut};
+
+// A fixed number of slots for one kind of thing in the game, each holding
+// one or nothing, so there's no allocating and a full pool just spawns
+// nothing more. Things are always visited in slot order, which keeps two
+// linked boards' worlds the same (see link.rs).
+//
+// Derefs to its slots for whatever needs them one by one: collisions that
+// clear a slot, checkpoints that save every slot, used or not.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pool<T: Copy, const N: usize> {
+    slots: [Option<T>; N],
+}
+
+impl<T: Copy, const N: usize> Pool<T, N> {
+    pub const fn new() -> Self {
+        Pool { slots: [None; N] }
+    }
+
+    // Into the first free slot. Returns false if there wasn't one.
+    pub fn spawn(&mut self, thing: T) -> bool {
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(thing);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // For spawning several at once, as many as there's room for
+    pub fn free_slots(&mut self) -> impl Iterator<Item = &mut Option<T>> {
+        self.slots.iter_mut().filter(|slot| slot.is_none())
+    }
+
+    // Everything in the pool, for drawing
+    pub fn live(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().flatten()
+    }
+
+    // The per-frame update: `update` moves each thing along and returns
+    // whether it's still in play, and the ones that aren't are despawned
+    pub fn update(&mut self, mut update: impl FnMut(&mut T) -> bool) {
+        for slot in self.slots.iter_mut() {
+            if let Some(thing) = slot {
+                if !update(thing) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+}
+
+impl<T: Copy, const N: usize> Deref for Pool<T, N> {
+    type Target = [Option<T>; N];
+
+    fn deref(&self) -> &Self::Target {
+        &self.slots
+    }
+}
+
+impl<T: Copy, const N: usize> DerefMut for Pool<T, N> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.slots
+    }
+}
@@ -0,0 +1,202 @@
+use crate::clock;
+use crate::display::{Bringup, Display};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::digital::OutputPin as OutputPin1;
+use embedded_hal_1::spi::SpiBus;
+use nrf52840_hal::gpio::{Input, Output, Pin, PullUp, PushPull};
+use nrf52840_hal::gpiote::Gpiote;
+use nrf52840_pac::{P0, P1, POWER};
+
+// Set from the GPIOTE interrupt, cleared by whoever looks at it
+static PRESSED: AtomicBool = AtomicBool::new(false);
+static OFF: AtomicBool = AtomicBool::new(false);
+// Counts power offs, so a System OFF scheduled for one can tell it's been
+// woken from since
+static POWER_OFFS: AtomicU32 = AtomicU32::new(0);
+// The wake button's PSEL, port and pin, for System OFF to be woken by
+static WAKE_PSEL: AtomicU32 = AtomicU32::new(u32::MAX);
+
+// Idle long enough and the backlight dims to this fraction, as a shift
+#[cfg(feature = "backlight")]
+const DIM_SHIFT: u32 = 2;
+const DIM_US: u64 = 30_000_000;
+// How long after powering off it's System OFF
+pub const SYSTEM_OFF_MS: u32 = 30 * 60 * 1000;
+
+// Power saving in tiers, as it goes longer without input:
+//
+// - After 30 s the backlight dims, with `backlight`
+// - After `set poweroff` minutes the panel is reset, which leaves the ST7735
+//   in sleep-in with the display off and its charge pumps stopped, and the
+//   backlight's load switch (if there is one) is opened. HFXO is stopped,
+//   and frames stop altogether until the wake button is pressed. Then the
+//   panel goes through the same reset and init as at boot, including the
+//   settle time the controller needs after sleep-out, and the game carries on.
+// - Half an hour after that, everything worth keeping is saved and the
+//   nRF52840 goes into System OFF, with the wake button's pin set to sense a
+//   press. Waking from it is a reset, and a game in progress comes back
+//   paused from its checkpoint with `flash`.
+//
+// Expected current while powered off, not counting the debug probe: the
+// controller is down to the tens of microamps the datasheet gives for
+// sleep-in, so the backlight is what's left. That's typically 10-20 mA for
+// these modules without a load switch, and close to nothing with one. The
+// nRF52840 sits in WFI on its internal oscillator (unless the radio or USB
+// needs HFXO), a hundred microamps or so, and in System OFF it's down to a
+// couple.
+pub struct PowerOff {
+    load_switch: Option<Pin<Output<PushPull>>>,
+    idle_us: u64,
+    dimmed: bool,
+}
+
+impl PowerOff {
+    // `load_switch` should already be driven high, powering the backlight
+    pub fn new(load_switch: Option<Pin<Output<PushPull>>>) -> Self {
+        PowerOff {
+            load_switch,
+            idle_us: 0,
+            dimmed: false,
+        }
+    }
+
+    pub fn is_off(&self) -> bool {
+        OFF.load(Ordering::Relaxed)
+    }
+
+    // Called once per frame with how long it took, counting the wait
+    // before the next one. `timeout_us` of 0 never powers off. Returns true
+    // once it's time to power off.
+    pub fn tick(&mut self, active: bool, frame_us: u32, timeout_us: u64) -> bool {
+        if active || PRESSED.swap(false, Ordering::Relaxed) {
+            self.idle_us = 0;
+            self.dimmed = false;
+            return false;
+        }
+        self.idle_us += frame_us as u64;
+        self.dimmed = timeout_us != 0 && self.idle_us >= DIM_US;
+        timeout_us != 0 && self.idle_us >= timeout_us
+    }
+
+    // What the backlight should be at instead of `level`
+    #[cfg(feature = "backlight")]
+    pub fn dim(&self, level: u8) -> u8 {
+        match self.dimmed {
+            true => level >> DIM_SHIFT,
+            false => level,
+        }
+    }
+
+    // Returns which power off this is, for `is_still_off`
+    pub fn power_off<RST, D>(&mut self, bringup: &mut Bringup<RST, D>) -> u32
+    where
+        RST: OutputPin1,
+        D: DelayNs,
+    {
+        // A hardware reset always ends in sleep-in, and is the only way to
+        // get there through this driver
+        bringup.reset().ok();
+        if let Some(switch) = self.load_switch.as_mut() {
+            switch.set_low().ok();
+        }
+        // Nothing needs accurate timing while powered off
+        if clock::CAN_STOP_HFXO {
+            clock::stop_hfxo();
+        }
+        PRESSED.store(false, Ordering::Relaxed);
+        OFF.store(true, Ordering::Relaxed);
+        POWER_OFFS.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    // Returns true if the button has been pressed since powering off, in
+    // which case the panel is back up and needs a full frame
+    pub fn try_wake<SPI, DC, RST, D>(
+        &mut self,
+        disp: &mut Display<SPI, DC>,
+        bringup: &mut Bringup<RST, D>,
+    ) -> bool
+    where
+        SPI: SpiBus,
+        DC: OutputPin1,
+        RST: OutputPin1,
+        D: DelayNs,
+    {
+        if !PRESSED.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+
+        if clock::CAN_STOP_HFXO {
+            clock::start_hfxo();
+        }
+        if let Some(switch) = self.load_switch.as_mut() {
+            switch.set_high().ok();
+        }
+        if bringup.init(disp).is_err() {
+            log_error!("Display didn't come back from power off");
+        }
+        self.idle_us = 0;
+        self.dimmed = false;
+        OFF.store(false, Ordering::Relaxed);
+        true
+    }
+}
+
+// Whether it's still powered off from power off number `off`, without having
+// been woken in between. If it's been woken and powered off again, returns
+// the number of the power off it's in now.
+pub fn is_still_off(off: u32) -> Result<bool, u32> {
+    let now = POWER_OFFS.load(Ordering::Relaxed);
+    match OFF.load(Ordering::Relaxed) {
+        false => Ok(false),
+        true if now == off => Ok(true),
+        true => Err(now),
+    }
+}
+
+// Goes into System OFF, to be woken by the wake button pulling its pin low
+pub fn system_off() -> ! {
+    let psel = WAKE_PSEL.load(Ordering::Relaxed);
+    let port = match psel >> 5 {
+        0 => unsafe { &*P0::ptr() },
+        _ => unsafe { &*P1::ptr() },
+    };
+    port.pin_cnf[(psel & 31) as usize].modify(|_, w| w.sense().low());
+    let power = unsafe { &*POWER::ptr() };
+    power.systemoff.write(|w| w.systemoff().enter());
+    // With a debugger attached System OFF is only emulated, and this carries
+    // on until the wake button or the watchdog resets it
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+pub struct WakeButton {
+    _pin: Pin<Input<PullUp>>,
+}
+
+impl WakeButton {
+    // Uses GPIOTE channel 0. Active low, so a button to ground with the
+    // internal pull-up.
+    pub fn new(gpiote: &Gpiote, pin: Pin<Input<PullUp>>) -> Self {
+        gpiote
+            .channel0()
+            .input_pin(&pin)
+            .hi_to_lo()
+            .enable_interrupt();
+        WAKE_PSEL.store(pin.psel_bits(), Ordering::Relaxed);
+        WakeButton { _pin: pin }
+    }
+
+    // Called from the GPIOTE interrupt. Returns true if the press should
+    // wake the board up, rather than just count as activity.
+    pub fn on_interrupt(&self, gpiote: &Gpiote) -> bool {
+        if !gpiote.channel0().is_event_triggered() {
+            return false;
+        }
+        gpiote.channel0().reset_events();
+        PRESSED.store(true, Ordering::Relaxed);
+        OFF.load(Ordering::Relaxed)
+    }
+}
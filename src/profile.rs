@@ -0,0 +1,149 @@
+#![cfg_attr(not(feature = "profile"), allow(dead_code))]
+
+use core::cell::Cell;
+use core::fmt;
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::DWT;
+
+use crate::clock;
+
+// How long named pieces of the firmware take, in core cycles off the DWT
+// counter. `profile_scope!("render")` at the top of a block times from there
+// to the end of it, and each name keeps its count, total, fastest and
+// slowest in a table that the `profile` command prints. Without the
+// `profile` feature the macro is nothing at all.
+//
+// Each task's scope is named after it, so the table says where the time
+// goes task by task, and the frame task's pieces have names of their own
+// too. A scope that's interrupted counts the time the interrupt took, the
+// same as the frame's own timing always has.
+
+// Names past this many aren't kept
+const MAX_SCOPES: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Stats {
+    count: u32,
+    total: u64,
+    min: u32,
+    max: u32,
+}
+
+impl Stats {
+    const EMPTY: Stats = Stats {
+        count: 0,
+        total: 0,
+        min: u32::MAX,
+        max: 0,
+    };
+}
+
+pub struct Scope {
+    name: &'static str,
+    // None until it's first timed, and put in the table
+    stats: Mutex<Cell<Option<Stats>>>,
+}
+
+impl Scope {
+    pub const fn new(name: &'static str) -> Self {
+        Scope {
+            name,
+            stats: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    fn record(&'static self, cycles: u32) {
+        interrupt::free(|cs| {
+            let cell = self.stats.borrow(cs);
+            let mut stats = match cell.get() {
+                Some(stats) => stats,
+                None => {
+                    let table = SCOPES.borrow(cs);
+                    let mut scopes = table.get();
+                    if let Some(slot) = scopes.iter_mut().find(|slot| slot.is_none()) {
+                        *slot = Some(self);
+                    }
+                    table.set(scopes);
+                    Stats::EMPTY
+                }
+            };
+            stats.count = stats.count.wrapping_add(1);
+            stats.total += cycles as u64;
+            stats.min = stats.min.min(cycles);
+            stats.max = stats.max.max(cycles);
+            cell.set(Some(stats));
+        });
+    }
+}
+
+static SCOPES: Mutex<Cell<[Option<&'static Scope>; MAX_SCOPES]>> =
+    Mutex::new(Cell::new([None; MAX_SCOPES]));
+
+// Times its scope from when it's made until it's dropped
+pub struct Guard {
+    scope: &'static Scope,
+    start: u32,
+}
+
+impl Guard {
+    #[inline(always)]
+    pub fn new(scope: &'static Scope) -> Self {
+        Guard {
+            scope,
+            start: DWT::cycle_count(),
+        }
+    }
+}
+
+impl Drop for Guard {
+    #[inline(always)]
+    fn drop(&mut self) {
+        self.scope.record(DWT::cycle_count().wrapping_sub(self.start));
+    }
+}
+
+// A line per scope, in the order they were first timed
+pub fn dump(mut print: impl FnMut(fmt::Arguments)) {
+    let scopes = interrupt::free(|cs| SCOPES.borrow(cs).get());
+    let mut any = false;
+    for scope in scopes.iter().flatten() {
+        let stats = match interrupt::free(|cs| scope.stats.borrow(cs).get()) {
+            Some(stats) if stats.count > 0 => stats,
+            _ => continue,
+        };
+        let avg = (stats.total / stats.count as u64) as u32;
+        print(format_args!(
+            "{}: {} runs, cycles min {} avg {} max {} ({} us max)",
+            scope.name,
+            stats.count,
+            stats.min,
+            avg,
+            stats.max,
+            clock::cycles_to_us(stats.max)
+        ));
+        any = true;
+    }
+    if !any {
+        print(format_args!("nothing profiled yet"));
+    }
+}
+
+// Starts every scope over, keeping their places in the table
+pub fn reset() {
+    interrupt::free(|cs| {
+        for scope in SCOPES.borrow(cs).get().iter().flatten() {
+            scope.stats.borrow(cs).set(Some(Stats::EMPTY));
+        }
+    });
+}
+
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        #[cfg(feature = "profile")]
+        let _profile = {
+            static SCOPE: $crate::profile::Scope = $crate::profile::Scope::new($name);
+            $crate::profile::Guard::new(&SCOPE)
+        };
+    };
+}
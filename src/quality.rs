@@ -0,0 +1,60 @@
+use crate::budget;
+
+// Trades effect detail for frame time. Effects look at `level` to decide how
+// much work to do; the controller drops it when frames keep running over
+// budget and raises it again once there's been headroom for a while. The
+// asymmetry (quick to drop, slow to recover) keeps it from oscillating.
+
+pub const MAX_LEVEL: u8 = 2;
+
+// Frames slower than this count against the current level
+pub const BUDGET_US: u32 = budget::RENDER_US;
+// Frames faster than this count towards the next level up
+const HEADROOM_US: u32 = BUDGET_US / 2;
+const SLOW_FRAMES: u8 = 4;
+const FAST_FRAMES: u8 = 120;
+
+pub struct Quality {
+    level: u8,
+    slow: u8,
+    fast: u8,
+}
+
+impl Quality {
+    pub const fn new() -> Self {
+        Quality {
+            level: MAX_LEVEL,
+            slow: 0,
+            fast: 0,
+        }
+    }
+
+    pub fn level(&self) -> u8 {
+        self.level
+    }
+
+    // Feeds in the last frame's time. Returns true if the level changed.
+    pub fn update(&mut self, frame_us: u32) -> bool {
+        if frame_us > BUDGET_US {
+            self.fast = 0;
+            self.slow += 1;
+            if self.slow >= SLOW_FRAMES && self.level > 0 {
+                self.slow = 0;
+                self.level -= 1;
+                return true;
+            }
+        } else if frame_us < HEADROOM_US {
+            self.slow = 0;
+            self.fast = self.fast.saturating_add(1);
+            if self.fast >= FAST_FRAMES && self.level < MAX_LEVEL {
+                self.fast = 0;
+                self.level += 1;
+                return true;
+            }
+        } else {
+            self.slow = 0;
+            self.fast = 0;
+        }
+        false
+    }
+}
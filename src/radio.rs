@@ -0,0 +1,148 @@
+use crate::settings::{DEFAULT_LINK_CHANNEL, MAX_LINK_CHANNEL};
+use core::sync::atomic::{compiler_fence, Ordering};
+use nrf52840_pac::RADIO;
+
+// Nordic's proprietary 1 Mbit mode, on 2400 MHz plus the channel
+const BASE_ADDRESS: u32 = 0xE7E7_E7E7;
+const PREFIX: u8 = 0x50;
+
+pub const MAX_PAYLOAD: usize = 32;
+
+// A bare-bones half-duplex transceiver: it sits in RX whenever it isn't
+// sending, and `send` blocks for the ~200 us it takes to ramp up and get a
+// packet out. Packets carry a length byte and a 16 bit CRC, nothing else.
+pub struct Radio {
+    radio: RADIO,
+    // Length byte followed by the payload. Handed to EasyDMA by address, so
+    // the radio has to stay put once `listen` has been called.
+    buf: [u8; 1 + MAX_PAYLOAD],
+    channel: u8,
+}
+
+impl Radio {
+    // Needs HFXO running
+    pub fn new(radio: RADIO) -> Self {
+        radio.mode.write(|w| w.mode().nrf_1mbit());
+        radio.txpower.write(|w| w.txpower()._0d_bm());
+
+        // 8 bit length field, no S0/S1
+        radio.pcnf0.write(|w| unsafe { w.lflen().bits(8) });
+        radio.pcnf1.write(|w| unsafe {
+            w.maxlen()
+                .bits(MAX_PAYLOAD as u8)
+                .balen()
+                .bits(4)
+                .endian()
+                .little()
+                .whiteen()
+                .enabled()
+        });
+
+        radio.base0.write(|w| unsafe { w.bits(BASE_ADDRESS) });
+        radio.prefix0.write(|w| unsafe { w.ap0().bits(PREFIX) });
+        radio.txaddress.write(|w| unsafe { w.txaddress().bits(0) });
+        radio.rxaddresses.write(|w| w.addr0().enabled());
+
+        radio.crccnf.write(|w| w.len().two());
+        radio.crcinit.write(|w| unsafe { w.crcinit().bits(0xFFFF) });
+        radio
+            .crcpoly
+            .write(|w| unsafe { w.crcpoly().bits(0x1_1021) });
+
+        radio
+            .shorts
+            .write(|w| w.ready_start().enabled().end_disable().enabled());
+
+        let mut radio = Radio {
+            radio,
+            buf: [0; 1 + MAX_PAYLOAD],
+            channel: 0,
+        };
+        radio.tune(DEFAULT_LINK_CHANNEL);
+        radio
+    }
+
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    // Moves to another channel, 0 to MAX_LINK_CHANNEL, and goes back to
+    // listening there. Whatever was on its way in is lost.
+    pub fn set_channel(&mut self, channel: u8) {
+        self.disable();
+        self.tune(channel);
+        self.listen();
+    }
+
+    // Whitening is seeded from the channel as well, as in ESB
+    fn tune(&mut self, channel: u8) {
+        let channel = channel.min(MAX_LINK_CHANNEL);
+        self.radio
+            .frequency
+            .write(|w| unsafe { w.frequency().bits(channel) });
+        self.radio
+            .datawhiteiv
+            .write(|w| unsafe { w.datawhiteiv().bits(channel) });
+        self.channel = channel;
+    }
+
+    // Starts listening for the next packet
+    pub fn listen(&mut self) {
+        self.disable();
+        self.start(|radio| radio.tasks_rxen.write(|w| unsafe { w.bits(1) }));
+    }
+
+    // Returns the payload of a packet that arrived since the last `listen`,
+    // and goes back to listening. Packets with a bad CRC are dropped.
+    pub fn receive(&mut self, dest: &mut [u8; MAX_PAYLOAD]) -> Option<usize> {
+        if self.radio.events_disabled.read().bits() == 0 {
+            return None;
+        }
+        compiler_fence(Ordering::Acquire);
+
+        let received = if self.radio.crcstatus.read().crcstatus().is_crcok() {
+            let len = (self.buf[0] as usize).min(MAX_PAYLOAD);
+            dest[..len].copy_from_slice(&self.buf[1..1 + len]);
+            Some(len)
+        } else {
+            None
+        };
+
+        self.listen();
+        received
+    }
+
+    // Sends `payload` (at most MAX_PAYLOAD bytes) and goes back to listening.
+    // Anything that was half received is lost.
+    pub fn send(&mut self, payload: &[u8]) {
+        let len = payload.len().min(MAX_PAYLOAD);
+
+        self.disable();
+        self.buf[0] = len as u8;
+        self.buf[1..1 + len].copy_from_slice(&payload[..len]);
+        self.start(|radio| radio.tasks_txen.write(|w| unsafe { w.bits(1) }));
+        while self.radio.events_disabled.read().bits() == 0 {}
+
+        self.listen();
+    }
+
+    fn start(&mut self, task: impl FnOnce(&RADIO)) {
+        self.radio
+            .packetptr
+            .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+        self.radio.events_disabled.reset();
+        // Make sure the buffer is written before the radio reads it
+        compiler_fence(Ordering::Release);
+        task(&self.radio);
+    }
+
+    fn disable(&mut self) {
+        if self.radio.state.read().state().is_disabled() {
+            return;
+        }
+        self.radio.events_disabled.reset();
+        self.radio.tasks_disable.write(|w| unsafe { w.bits(1) });
+        while self.radio.events_disabled.read().bits() == 0 {}
+        self.radio.events_disabled.reset();
+    }
+}
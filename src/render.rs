@@ -0,0 +1,173 @@
+use crate::assets;
+use crate::boss::{self, Boss, Phase};
+use crate::color::rgb565;
+use crate::draw;
+use crate::game::{self, Events, PowerUp, PowerUpKind, State, World};
+use crate::gfx;
+use crate::text;
+
+// Drawing the game into a frame, for the firmware's frame task and the
+// simulator alike. The background, the title and anything over the game are
+// up to them.
+
+pub fn fill(bytes: &mut [u8], color: u16) {
+    let color = color.to_le_bytes();
+    for pixel in bytes.chunks_exact_mut(2) {
+        pixel.copy_from_slice(&color);
+    }
+}
+
+// Only the sprites' rects are sent and cleared, so the art can't be any
+// bigger
+const _: () = assert!(assets::SHIP.w == game::SHIP_W && assets::SHIP.h == game::SHIP_H);
+const _: () = assert!(assets::ENEMY_1.w == game::ENEMY_W && assets::ENEMY_1.h == game::ENEMY_H);
+
+fn draw_sprite(bytes: &mut [u8], sprite: &gfx::Image, rect: game::Rect) {
+    gfx::blit_keyed(bytes, sprite, rect.x, rect.y, assets::KEY);
+}
+
+pub fn draw_world(bytes: &mut [u8], world: &World, dither_edges: bool) {
+    profile_scope!("sprites");
+    // The partner ship is green where the local one is cyan
+    let (ship, partner) = if world.state == State::GameOver {
+        (&assets::SHIP_LOST, &assets::SHIP_LOST)
+    } else if world.is_active(world.effects.shield_until) {
+        (&assets::SHIP_SHIELDED, &assets::SHIP_SHIELDED)
+    } else {
+        (&assets::SHIP, &assets::PARTNER)
+    };
+    draw_sprite(bytes, ship, world.ship.rect());
+    if let Some(ship) = world.partner {
+        draw_sprite(bytes, partner, ship.rect());
+    }
+
+    if world.state == State::Playing && world.is_active(world.effects.shield_until) {
+        // Pulses to the metronome
+        let color = if world.events.contains(Events::BEAT) {
+            rgb565(16, 63, 31)
+        } else {
+            rgb565(0, 32, 31)
+        };
+        let ships = core::iter::once(world.ship).chain(world.partner);
+        for ship in ships {
+            let (cx, cy) = ship.center();
+            draw::draw_circle(bytes, cx, cy, game::SHIELD_RADIUS, color);
+        }
+    }
+
+    for power_up in world.power_ups.live() {
+        draw_power_up(bytes, power_up, dither_edges);
+    }
+
+    for bullet in world.bullets.live() {
+        draw::fill_rect(bytes, bullet.rect(), rgb565(31, 63, 0));
+    }
+    for explosion in world.explosions.live() {
+        let r = explosion.radius(world.ticks);
+        draw::fill_circle(bytes, explosion.x, explosion.y, r, rgb565(31, 40, 0));
+    }
+    for particle in world.particles.live() {
+        let color = particle.color(world.ticks);
+        draw::fill_rect(bytes, particle.rect(world.ticks), color);
+    }
+
+    for enemy in world.enemies.live() {
+        let sprite = if enemy.is_flashing(world.ticks) {
+            &assets::ENEMY_HIT
+        } else {
+            match enemy.hp {
+                1 => &assets::ENEMY_1,
+                2 => &assets::ENEMY_2,
+                _ => &assets::ENEMY_3,
+            }
+        };
+        draw_sprite(bytes, sprite, enemy.rect());
+    }
+
+    // Under the boss, so it comes out from beneath it
+    if let Some(((x0, y0), (x1, y1))) = world.aim_line() {
+        let color = rgb565(16, 0, 16);
+        #[cfg(feature = "aa-lines")]
+        draw::draw_line_aa(bytes, x0, y0, x1, y1, color);
+        #[cfg(not(feature = "aa-lines"))]
+        draw::draw_line(bytes, x0, y0, x1, y1, color);
+    }
+    if let Some(boss) = &world.boss {
+        draw_boss(bytes, boss, world.ticks);
+    }
+}
+
+fn draw_boss(bytes: &mut [u8], boss: &Boss, now: u32) {
+    let body = if boss.is_flashing(now) {
+        rgb565(31, 63, 31)
+    } else {
+        rgb565(24, 0, 24)
+    };
+    let rect = boss.rect();
+    draw::fill_rect(bytes, rect, body);
+    for eye in [rect.x + 2, rect.x + boss::W - 4] {
+        let eye = game::Rect {
+            x: eye,
+            y: rect.y + 2,
+            w: 2,
+            h: 2,
+        };
+        draw::fill_rect(bytes, eye, rgb565(31, 63, 0));
+    }
+
+    // Goes from yellow to red as it wears down
+    let bar = match boss.phase {
+        Phase::Entering | Phase::Sweeping => rgb565(31, 63, 0),
+        Phase::Chasing => rgb565(31, 32, 0),
+        Phase::Frenzy => rgb565(31, 0, 0),
+    };
+    let filled = boss.bar_width();
+    let empty = game::Rect {
+        x: boss::BAR.x + filled,
+        w: boss::BAR.w - filled,
+        ..boss::BAR
+    };
+    draw::fill_rect(bytes, game::Rect { w: filled, ..boss::BAR }, bar);
+    draw::fill_rect(bytes, empty, rgb565(8, 8, 8));
+}
+
+// 3x3 icons, one bit per pixel, top row first
+fn draw_power_up(bytes: &mut [u8], power_up: &PowerUp, dither_edges: bool) {
+    let (icon, color): (u32, u16) = match power_up.kind {
+        PowerUpKind::RapidFire => (0b010_010_010, rgb565(31, 63, 0)),
+        PowerUpKind::SpreadShot => (0b101_010_010, rgb565(0, 63, 0)),
+        PowerUpKind::Shield => (0b111_101_111, rgb565(0, 32, 31)),
+        PowerUpKind::ExtraLife => (0b010_111_010, rgb565(31, 0, 0)),
+        PowerUpKind::SlowMotion => (0b111_010_111, rgb565(24, 0, 31)),
+    };
+    let sprite = draw::Sprite {
+        w: 3,
+        h: 3,
+        bits: icon,
+    };
+    draw::blit_sprite(bytes, power_up.x, power_up.y, &sprite, color, dither_edges);
+}
+
+// Both scores at the end of a versus game, and who won, centered.
+// Returns where it went.
+pub fn draw_versus(bytes: &mut [u8], world: &World, color: u16) -> game::Rect {
+    let mut lines = [*b"CYAN       0", *b"GREEN      0", *b"    DRAW    "];
+    text::digits(&mut lines[0][6..], world.score);
+    text::digits(&mut lines[1][6..], world.partner_score);
+    if world.score > world.partner_score {
+        lines[2] = *b" CYAN WINS  ";
+    } else if world.partner_score > world.score {
+        lines[2] = *b" GREEN WINS ";
+    }
+    let w = text::width(lines[0].len());
+    let (x, y0) = ((game::WIDTH - w) / 2, (game::HEIGHT - 3 * text::LINE_H) / 2);
+    for (i, line) in lines.iter().enumerate() {
+        text::draw(bytes, x, y0 + i as i32 * text::LINE_H, line, color);
+    }
+    game::Rect {
+        x,
+        y: y0,
+        w,
+        h: 3 * text::LINE_H,
+    }
+}
@@ -0,0 +1,194 @@
+use crate::game::{Input, State, World};
+use crate::gameloop::TICK_HZ;
+use crate::rng::Rng;
+use rand_core::RngCore;
+
+// Every game played alone is recorded as it goes: the seed the RNG was
+// started from, and the input each step of the game got. Given those the
+// game plays out the same every time, the way the demo does, so `replay` on
+// the console shows the last game again, bug and all. The title screen also
+// plays it by itself after a while without input, until there's some.
+//
+// Inputs are kept as runs of the same one, so holding still costs next to
+// nothing, but an analog stick can change every step. A game that fills
+// RUNS stops being recorded there, and plays back that far. Anything done
+// to the game from the console, like `spawn`, isn't recorded at all.
+
+const RUNS: usize = 1024;
+// On the title screen without input, before it plays the last game itself
+const ATTRACT_TICKS: u32 = 20 * TICK_HZ;
+
+// One input, held for `ticks` steps
+#[derive(Clone, Copy)]
+struct Run {
+    // Left, right and fire, lowest first
+    buttons: u8,
+    x: i8,
+    ticks: u8,
+}
+
+impl Run {
+    const NONE: Run = Run {
+        buttons: 0,
+        x: 0,
+        ticks: 0,
+    };
+
+    fn new(input: Input) -> Self {
+        Run {
+            buttons: input.left as u8 | (input.right as u8) << 1 | (input.fire as u8) << 2,
+            x: input.x,
+            ticks: 1,
+        }
+    }
+
+    fn same_input(&self, other: &Run) -> bool {
+        (self.buttons, self.x) == (other.buttons, other.x)
+    }
+
+    fn input(&self) -> Input {
+        Input {
+            left: self.buttons & 1 != 0,
+            right: self.buttons & 2 != 0,
+            fire: self.buttons & 4 != 0,
+            x: self.x,
+        }
+    }
+}
+
+pub struct Replay {
+    seed: u32,
+    runs: [Run; RUNS],
+    len: usize,
+    recording: bool,
+    playing: bool,
+    // Played from the title screen, and over as soon as there's input
+    attract: bool,
+    // Where playback has got to: a run, and steps into it
+    at: usize,
+    into: u8,
+    idle_ticks: u32,
+}
+
+impl Replay {
+    pub const fn new() -> Self {
+        Replay {
+            seed: 0,
+            runs: [Run::NONE; RUNS],
+            len: 0,
+            recording: false,
+            playing: false,
+            attract: false,
+            at: 0,
+            into: 0,
+            idle_ticks: 0,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // Starts the last game over from the title screen, or a game still
+    // being played from its start, which also stops recording it. Returns
+    // false if nothing's been recorded yet.
+    pub fn start(&mut self, world: &mut World, rng: &mut Rng) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+        self.recording = false;
+        self.playing = true;
+        self.attract = false;
+        self.at = 0;
+        self.into = 0;
+        *world = World::new();
+        *rng = Rng::new(self.seed);
+        true
+    }
+
+    // Straight back to the title screen
+    pub fn stop(&mut self, world: &mut World) {
+        if self.playing {
+            self.playing = false;
+            *world = World::new();
+        }
+    }
+
+    // Called with the input for each step of a game played alone, before
+    // it's stepped with what this returns: `input` while recording, and the
+    // recorded one while playing back. Starting a game from the title
+    // screen reseeds `rng`, to have a seed to record.
+    pub fn step(&mut self, world: &mut World, rng: &mut Rng, input: Input) -> Input {
+        if self.playing {
+            return self.play(world, input);
+        }
+        if world.state == State::Title {
+            self.recording = false;
+            self.idle_ticks = match input == Input::default() {
+                true => self.idle_ticks + 1,
+                false => 0,
+            };
+            if input.fire {
+                // This step starts a game
+                self.record_from(rng);
+            } else if self.idle_ticks >= ATTRACT_TICKS && self.start(world, rng) {
+                self.idle_ticks = 0;
+                self.attract = true;
+                return self.play(world, input);
+            }
+        }
+        if self.recording {
+            self.record(input);
+        }
+        input
+    }
+
+    // For a game that's just been started over from the beginning without
+    // going back to the title screen, by shaking the board. It's recorded as
+    // if fire had been pressed there.
+    pub fn restarted(&mut self, rng: &mut Rng) {
+        if self.recording {
+            self.record_from(rng);
+            self.record(Input {
+                fire: true,
+                ..Input::default()
+            });
+        }
+    }
+
+    fn record_from(&mut self, rng: &mut Rng) {
+        self.seed = rng.next_u32();
+        *rng = Rng::new(self.seed);
+        self.len = 0;
+        self.recording = true;
+    }
+
+    fn record(&mut self, input: Input) {
+        let run = Run::new(input);
+        match self.runs[..self.len].last_mut() {
+            Some(last) if last.same_input(&run) && last.ticks < u8::MAX => last.ticks += 1,
+            _ if self.len < RUNS => {
+                self.runs[self.len] = run;
+                self.len += 1;
+            }
+            _ => {
+                self.recording = false;
+                log_warn!("Replay full, the rest of this game isn't recorded");
+            }
+        }
+    }
+
+    fn play(&mut self, world: &mut World, input: Input) -> Input {
+        if self.at == self.len || self.attract && input != Input::default() {
+            self.stop(world);
+            return Input::default();
+        }
+        let run = self.runs[self.at];
+        self.into += 1;
+        if self.into == run.ticks {
+            self.at += 1;
+            self.into = 0;
+        }
+        run.input()
+    }
+}
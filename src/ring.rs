@@ -0,0 +1,176 @@
+// A fixed-capacity FIFO for the various queues around the firmware. It does
+// no locking of its own: a buffer shared between tasks or with an interrupt
+// lives behind an RTIC resource or a critical section like anything else.
+
+// What `push` does when the buffer is already full
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    // Make room by throwing away the oldest item, for histories where the
+    // latest matters most
+    DropOldest,
+    // Refuse the new item, for queues where everything already in them is
+    // still owed
+    RejectNew,
+}
+
+pub struct RingBuffer<T, const N: usize> {
+    items: [T; N],
+    // Oldest item
+    head: usize,
+    len: usize,
+    overflow: Overflow,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    // `fill` is only there to initialize the storage, and is never handed
+    // out
+    pub const fn new(fill: T, overflow: Overflow) -> Self {
+        RingBuffer {
+            items: [fill; N],
+            head: 0,
+            len: 0,
+            overflow,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    // How many more items fit before the overflow policy kicks in
+    pub fn free(&self) -> usize {
+        N - self.len
+    }
+
+    // Returns false if the item was rejected. With `DropOldest` that never
+    // happens, the oldest item goes instead.
+    pub fn push(&mut self, item: T) -> bool {
+        if N == 0 {
+            return false;
+        }
+        if self.is_full() {
+            match self.overflow {
+                Overflow::RejectNew => return false,
+                Overflow::DropOldest => {
+                    self.head = (self.head + 1) % N;
+                    self.len -= 1;
+                }
+            }
+        }
+        self.items[(self.head + self.len) % N] = item;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let item = self.items[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(item)
+    }
+
+    // Oldest first, without taking anything out
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        (0..self.len).map(move |i| &self.items[(self.head + i) % N])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drain<const N: usize>(ring: &mut RingBuffer<u32, N>) -> Vec<u32> {
+        core::iter::from_fn(|| ring.pop()).collect()
+    }
+
+    #[test]
+    fn empty() {
+        let mut ring = RingBuffer::<u32, 4>::new(0, Overflow::RejectNew);
+        assert!(ring.is_empty() && !ring.is_full());
+        assert_eq!((ring.len(), ring.free()), (0, 4));
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.iter().count(), 0);
+    }
+
+    #[test]
+    fn first_in_first_out() {
+        let mut ring = RingBuffer::<u32, 4>::new(0, Overflow::RejectNew);
+        assert!(ring.push(1) && ring.push(2));
+        assert_eq!(ring.pop(), Some(1));
+        assert!(ring.push(3));
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(drain(&mut ring), [2, 3]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn full_and_rejecting() {
+        let mut ring = RingBuffer::<u32, 3>::new(0, Overflow::RejectNew);
+        for i in 0..3 {
+            assert!(ring.push(i));
+        }
+        assert!(ring.is_full());
+        assert_eq!((ring.len(), ring.free()), (3, 0));
+        assert!(!ring.push(99));
+        assert_eq!(drain(&mut ring), [0, 1, 2]);
+    }
+
+    #[test]
+    fn full_and_dropping_the_oldest() {
+        let mut ring = RingBuffer::<u32, 3>::new(0, Overflow::DropOldest);
+        for i in 0..5 {
+            assert!(ring.push(i));
+        }
+        assert!(ring.is_full());
+        assert_eq!(ring.iter().copied().collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(drain(&mut ring), [2, 3, 4]);
+    }
+
+    #[test]
+    fn wraps_round_many_times() {
+        for overflow in [Overflow::RejectNew, Overflow::DropOldest].iter().copied() {
+            let mut ring = RingBuffer::<u32, 3>::new(0, overflow);
+            let (mut next_in, mut next_out) = (0, 0);
+            // Two in, one out, drained every so often, so the head goes
+            // round at every offset
+            for round in 0..50 {
+                for _ in 0..2 {
+                    if ring.free() > 0 {
+                        assert!(ring.push(next_in));
+                        next_in += 1;
+                    }
+                }
+                assert_eq!(ring.pop(), Some(next_out));
+                next_out += 1;
+                if round % 7 == 0 {
+                    let rest: Vec<u32> = (next_out..next_in).collect();
+                    assert_eq!(ring.iter().copied().collect::<Vec<_>>(), rest);
+                    assert_eq!(drain(&mut ring), rest);
+                    next_out = next_in;
+                }
+                assert_eq!(ring.len() as u32, next_in - next_out);
+            }
+        }
+    }
+
+    #[test]
+    fn zero_capacity_takes_nothing() {
+        for overflow in [Overflow::RejectNew, Overflow::DropOldest].iter().copied() {
+            let mut ring = RingBuffer::<u32, 0>::new(0, overflow);
+            assert!(!ring.push(1));
+            assert!(ring.is_empty() && ring.is_full());
+            assert_eq!(ring.pop(), None);
+        }
+    }
+}
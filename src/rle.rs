@@ -0,0 +1,115 @@
+// Whole screens of art, and short animations of them, run-length packed by
+// build.rs from the PNGs in assets/screens/. A raw screen is 8 KB of flash,
+// which doesn't leave room for many, but splash screens and cutscenes are
+// mostly flat color and pack down to a fraction of that.
+//
+// The packing is a byte, then pixels: below 0x80 the byte's one less than
+// how many pixels follow to be copied as they are, and from 0x80 up the low
+// seven bits are two less than how many times the one pixel after it is
+// repeated. Pixels are little-endian RGB565, like the frame. Unpacking
+// never needs more than the next few bytes, so a screen can go straight
+// into the frame, or through `pixels` straight out to the panel without one.
+
+use crate::limits::Limits;
+
+const WIDTH: i32 = Limits::SCREEN_WIDTH as i32;
+const HEIGHT: i32 = Limits::SCREEN_HEIGHT as i32;
+
+const RUN: u8 = 0x80;
+
+#[derive(Clone, Copy)]
+pub struct Image<'a> {
+    pub w: i32,
+    pub h: i32,
+    pub data: &'a [u8],
+}
+
+impl<'a> Image<'a> {
+    // Every pixel, top row first. Stops early if the data does.
+    pub fn pixels(&self) -> Pixels<'a> {
+        Pixels {
+            data: self.data,
+            left: 0,
+            run: None,
+        }
+    }
+}
+
+// Frames the size of the screen, all packed the same way
+#[derive(Clone, Copy)]
+pub struct Animation<'a> {
+    pub w: i32,
+    pub h: i32,
+    pub frames: &'a [&'a [u8]],
+}
+
+impl<'a> Animation<'a> {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Wraps round, for one that loops
+    pub fn frame(&self, index: usize) -> Image<'a> {
+        Image {
+            w: self.w,
+            h: self.h,
+            data: self.frames[index % self.frames.len()],
+        }
+    }
+}
+
+pub struct Pixels<'a> {
+    data: &'a [u8],
+    // Pixels to go before the next byte of packing
+    left: u8,
+    // The pixel being repeated, or None for pixels copied as they are
+    run: Option<u16>,
+}
+
+impl Pixels<'_> {
+    fn pixel(&mut self) -> Option<u16> {
+        let (pixel, rest) = (self.data.get(..2)?, &self.data[2..]);
+        self.data = rest;
+        Some(u16::from_le_bytes([pixel[0], pixel[1]]))
+    }
+}
+
+impl Iterator for Pixels<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.left == 0 {
+            let (&packing, rest) = self.data.split_first()?;
+            self.data = rest;
+            if packing >= RUN {
+                self.left = (packing - RUN) + 2;
+                self.run = Some(self.pixel()?);
+            } else {
+                self.left = packing + 1;
+                self.run = None;
+            }
+        }
+        self.left -= 1;
+        match self.run {
+            Some(pixel) => Some(pixel),
+            None => self.pixel(),
+        }
+    }
+}
+
+// Unpacks `image` into the frame with its top left at the screen's,
+// leaving the frame alone wherever it's `key`, and past the edges
+pub fn draw(frame: &mut [u8], image: &Image, key: u16) {
+    let (mut x, mut y) = (0, 0);
+    for pixel in image.pixels() {
+        if pixel != key && x < WIDTH && y < HEIGHT {
+            let i = ((y * WIDTH + x) * 2) as usize;
+            frame[i..i + 2].copy_from_slice(&pixel.to_le_bytes());
+        }
+        x += 1;
+        if x == image.w {
+            x = 0;
+            y += 1;
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use rand_core::{impls, Error, RngCore};
+
+// xorshift32: tiny, fast, and plenty for gameplay. Not for anything that needs
+// to be unpredictable.
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub const fn new(seed: u32) -> Self {
+        // All-zero is the one state xorshift can never leave
+        Rng {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    // Uniform-enough value in 0..n. Modulo bias is negligible for the small
+    // ranges used by the game.
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+
+    // A roll of an n-sided die coming up one, the same draw as `below(n)`
+    pub fn one_in(&mut self, n: u32) -> bool {
+        self.below(n) == 0
+    }
+
+    // Uniform-enough value in lo..hi, which mustn't be empty. The same draw
+    // as `below(hi - lo)`, so from 0 it's the same value too.
+    pub fn rand_range(&mut self, lo: i32, hi: i32) -> i32 {
+        lo.wrapping_add(self.below(hi.wrapping_sub(lo) as u32) as i32)
+    }
+
+    // `value` moved by up to `by` either way, for things that shouldn't all
+    // line up
+    pub fn jitter(&mut self, value: i32, by: i32) -> i32 {
+        self.rand_range(value - by, value + by + 1)
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_stay_in_bounds_and_reach_both_ends() {
+        let mut rng = Rng::new(1);
+        let rolls: Vec<i32> = (0..1000).map(|_| rng.rand_range(-3, 4)).collect();
+        assert!(rolls.iter().all(|roll| (-3..4).contains(roll)));
+        assert!(rolls.contains(&-3) && rolls.contains(&3));
+    }
+
+    #[test]
+    fn a_range_from_zero_is_the_same_draw_as_below() {
+        let (mut a, mut b) = (Rng::new(7), Rng::new(7));
+        for _ in 0..100 {
+            assert_eq!(a.rand_range(0, 10), b.below(10) as i32);
+        }
+    }
+
+    #[test]
+    fn a_range_can_span_every_i32_but_one() {
+        let mut rng = Rng::new(3);
+        for _ in 0..100 {
+            assert!(rng.rand_range(i32::MIN, i32::MAX) < i32::MAX);
+        }
+    }
+
+    #[test]
+    fn jitter_moves_either_way_by_no_more_than_asked() {
+        let mut rng = Rng::new(5);
+        let nudged: Vec<i32> = (0..1000).map(|_| rng.jitter(10, 2)).collect();
+        assert!(nudged.iter().all(|x| (8..=12).contains(x)));
+        assert!(nudged.contains(&8) && nudged.contains(&12));
+        assert_eq!(rng.jitter(10, 0), 10);
+    }
+}
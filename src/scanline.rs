@@ -0,0 +1,119 @@
+use crate::display::DisplayDriver;
+use crate::dma::{self, MAX_TRANSFER};
+use crate::layout;
+use core::sync::atomic::{compiler_fence, Ordering};
+use nrf52840_pac::{spim0, SPIM1};
+
+// Streams a frame to the panel one scanline at a time, so a full framebuffer
+// never has to exist. It only does layout::MIRRORED, whose four tiles are
+// covered by a single address
+// window, PANEL_W by PANEL_H from the panel's origin, that's filled with one
+// RAMWR: each panel row is the frame row twice with the gap between the
+// tiles left black, and the rows between the top and bottom tiles are all
+// black.
+//
+// There are two line buffers. While EasyDMA clocks one of them out, the CPU
+// fills the other with the next row, then waits for the transfer to end and
+// starts the next one straight away. A row is only overwritten once its own
+// transfer has finished, since the one in between has to complete before the
+// next can start. Each frame row is rendered twice, once per tile row, which
+// is cheaper than keeping it around.
+//
+// A line longer than one EasyDMA transfer can carry goes out in pieces. All
+// but the last are waited for on the spot, so only the last overlaps with
+// rendering. Every transfer's byte count is checked once it ends, and a
+// short one stops the frame rather than leaving the rest of it out of step.
+pub const W: usize = 64;
+const GAP: (u16, u16) = layout::MIRRORED_GAP;
+const TILE_DX: usize = W + GAP.0 as usize;
+const TILE_DY: usize = W + GAP.1 as usize;
+const PANEL_W: usize = TILE_DX + W;
+const PANEL_H: usize = TILE_DY + W;
+const LINE_BYTES: usize = PANEL_W * 2;
+// Pixel data sent per frame
+pub const FRAME_BYTES: usize = LINE_BYTES * PANEL_H;
+
+// `render_row(y, row)` fills `row` with the RGB565 colors of frame row `y`.
+// `origin` is where the top left tile goes, normally the panel's offset.
+//
+// The display driver owns SPIM1, but it has no way to start a transfer
+// without waiting for it, so once it has sent RAMWR the rows go out through
+// the registers directly. That's only sound while nothing else uses the bus,
+// which is why this can't be combined with `shared-spi`.
+pub fn stream(
+    disp: &mut impl DisplayDriver,
+    origin: (u16, u16),
+    mut render_row: impl FnMut(usize, &mut [u16; W]),
+) -> Result<(), ()> {
+    disp.set_offset(origin.0, origin.1);
+    disp.set_window(0, 0, PANEL_W as u16 - 1, PANEL_H as u16 - 1)?;
+    // Sends RAMWR and leaves DC high for the pixel data
+    disp.write_pixels(core::iter::empty())?;
+
+    let spim = unsafe { &*SPIM1::ptr() };
+    spim.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(0) });
+
+    // The gap columns are never written, so stay black
+    let mut lines = [[0u8; LINE_BYTES]; 2];
+    let mut row = [0u16; W];
+    // Length of the transfer still going, if there is one
+    let mut in_flight = None;
+
+    for py in 0..PANEL_H {
+        let line = &mut lines[py % 2];
+        let y = match py {
+            py if py < W => Some(py),
+            py if py >= TILE_DY => Some(py - TILE_DY),
+            _ => None,
+        };
+        match y {
+            Some(y) => {
+                render_row(y, &mut row);
+                for (x, &color) in row.iter().enumerate() {
+                    let color = color.to_be_bytes();
+                    line[x * 2..x * 2 + 2].copy_from_slice(&color);
+                    line[(TILE_DX + x) * 2..(TILE_DX + x) * 2 + 2].copy_from_slice(&color);
+                }
+            }
+            None => line.iter_mut().for_each(|b| *b = 0),
+        }
+
+        for range in dma::chunks(LINE_BYTES, MAX_TRANSFER) {
+            if let Some(expected) = in_flight.take() {
+                wait(spim, expected)?;
+            }
+            in_flight = Some(range.len());
+            start(spim, &line[range]);
+        }
+    }
+    if let Some(expected) = in_flight {
+        wait(spim, expected)?;
+    }
+
+    Ok(())
+}
+
+// `bytes` is never longer than MAX_TRANSFER, so its length fits MAXCNT
+fn start(spim: &spim0::RegisterBlock, bytes: &[u8]) {
+    compiler_fence(Ordering::SeqCst);
+    spim.txd
+        .ptr
+        .write(|w| unsafe { w.ptr().bits(bytes.as_ptr() as u32) });
+    spim.txd
+        .maxcnt
+        .write(|w| unsafe { w.maxcnt().bits(bytes.len() as u16) });
+    spim.events_end.reset();
+    spim.tasks_start.write(|w| unsafe { w.bits(1) });
+}
+
+fn wait(spim: &spim0::RegisterBlock, expected: usize) -> Result<(), ()> {
+    while spim.events_end.read().bits() == 0 {}
+    spim.events_end.reset();
+    compiler_fence(Ordering::SeqCst);
+    let sent = spim.txd.amount.read().amount().bits() as usize;
+    if sent != expected {
+        log_error!("Scanline transfer sent {} of {} bytes", sent, expected);
+        return Err(());
+    }
+    Ok(())
+}
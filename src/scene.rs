@@ -0,0 +1,563 @@
+use crate::color::rgb565;
+use crate::draw;
+use crate::effect::Effect;
+use crate::game::{self, Rect, State, World};
+use crate::settings::Settings;
+use crate::text;
+
+// What's on screen, as a stack of scenes: one of the title, the game and the
+// game over screen at the bottom, following the world's state, with the
+// pause screen or the menu pushed over it. B pushes them and B pops them.
+//
+// The frame task draws the world and the title under every scene, as it
+// always has, so a scene only draws what goes over that and says where it
+// went. Anything that freezes the game stops the world where it is until
+// it's popped again.
+//
+// Whenever the stack changes, the frame task runs the transition the
+// settings call for from the frame before to the one after: see fade.rs.
+
+// Deepest the stack goes: a root, and no more than the pause screen and the
+// menu over it
+pub const DEPTH: usize = 4;
+
+// The buttons pressed this frame that scenes care about, a bit each. The
+// frame task makes it up from whatever the board has.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Press(u8);
+
+impl Press {
+    pub const BACK: Press = Press(1 << 0);
+    pub const UP: Press = Press(1 << 1);
+    pub const DOWN: Press = Press(1 << 2);
+    pub const LEFT: Press = Press(1 << 3);
+    pub const RIGHT: Press = Press(1 << 4);
+
+    pub fn contains(self, other: Press) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Press) {
+        self.0 |= other.0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Id {
+    Title,
+    Game,
+    GameOver,
+    Pause,
+    Menu,
+}
+
+// What a scene wants to happen to the stack
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    Stay,
+    Push(Id),
+    Pop,
+    Replace(Id),
+}
+
+pub trait Scene {
+    // Only the top scene hears about presses. It can change the settings,
+    // which the frame task picks up on the next frame.
+    fn handle_input(&mut self, press: Press, settings: &mut Settings) -> Transition;
+
+    // Once a frame, with the world's state as of the last one
+    fn update(&mut self, state: State) -> Transition;
+
+    // Draws over what's on the frame already, returning where
+    fn render(&self, frame: &mut [u8], world: &World, settings: &Settings) -> Option<Rect>;
+
+    // Whether the world stands still while it's on the stack
+    fn freezes_game(&self) -> bool {
+        false
+    }
+}
+
+// The root that goes with the world's state
+fn root(state: State) -> Id {
+    match state {
+        State::Title => Id::Title,
+        State::Playing => Id::Game,
+        State::GameOver => Id::GameOver,
+    }
+}
+
+// A root swaps itself for the one the world's state calls for
+fn follow(id: Id, state: State) -> Transition {
+    match root(state) {
+        next if next == id => Transition::Stay,
+        next => Transition::Replace(next),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Title;
+
+impl Scene for Title {
+    fn handle_input(&mut self, press: Press, _: &mut Settings) -> Transition {
+        match press.contains(Press::BACK) {
+            true => Transition::Push(Id::Menu),
+            false => Transition::Stay,
+        }
+    }
+
+    fn update(&mut self, state: State) -> Transition {
+        follow(Id::Title, state)
+    }
+
+    fn render(&self, _: &mut [u8], _: &World, _: &Settings) -> Option<Rect> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Game;
+
+impl Scene for Game {
+    fn handle_input(&mut self, press: Press, _: &mut Settings) -> Transition {
+        match press.contains(Press::BACK) {
+            true => Transition::Push(Id::Pause),
+            false => Transition::Stay,
+        }
+    }
+
+    fn update(&mut self, state: State) -> Transition {
+        follow(Id::Game, state)
+    }
+
+    fn render(&self, _: &mut [u8], _: &World, _: &Settings) -> Option<Rect> {
+        None
+    }
+}
+
+// The initials and a linked game's result are the frame task's, since
+// they're the game's own
+#[derive(Clone, Copy, Debug)]
+pub struct GameOver;
+
+impl Scene for GameOver {
+    fn handle_input(&mut self, _: Press, _: &mut Settings) -> Transition {
+        Transition::Stay
+    }
+
+    fn update(&mut self, state: State) -> Transition {
+        follow(Id::GameOver, state)
+    }
+
+    fn render(&self, _: &mut [u8], _: &World, _: &Settings) -> Option<Rect> {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Pause;
+
+impl Scene for Pause {
+    fn handle_input(&mut self, press: Press, _: &mut Settings) -> Transition {
+        match press.contains(Press::BACK) {
+            true => Transition::Pop,
+            false => Transition::Stay,
+        }
+    }
+
+    fn update(&mut self, _: State) -> Transition {
+        Transition::Stay
+    }
+
+    // Only over a game, so a checkpoint resumed onto the title doesn't say
+    // anything
+    fn render(&self, frame: &mut [u8], world: &World, _: &Settings) -> Option<Rect> {
+        if world.state != State::Playing {
+            return None;
+        }
+        let line = b"PAUSED";
+        let w = text::width(line.len());
+        let (x, y) = ((game::WIDTH - w) / 2, (game::HEIGHT - text::LINE_H) / 2);
+        text::draw(frame, x, y, line, TEXT);
+        Some(Rect {
+            x,
+            y,
+            w,
+            h: text::LINE_H,
+        })
+    }
+
+    fn freezes_game(&self) -> bool {
+        true
+    }
+}
+
+const TEXT: u16 = rgb565(31, 63, 31);
+const SELECTED: u16 = rgb565(4, 12, 16);
+
+// Up and down pick a line, left and right change it
+const LINES: usize = 3;
+// Longest line, "EFFECT PLASMA"
+const LINE_CHARS: usize = 13;
+const PADDING: i32 = 2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Menu {
+    selected: usize,
+}
+
+impl Menu {
+    pub const fn new() -> Self {
+        Menu { selected: 0 }
+    }
+
+    // Steps the selected line one way or the other
+    fn change(&self, settings: &mut Settings, forward: bool) {
+        match self.selected {
+            0 => settings.sound = !settings.sound,
+            1 => {
+                let all = &Effect::ALL;
+                let i = all.iter().position(|&e| e == settings.effect).unwrap_or(0);
+                let step = if forward { 1 } else { all.len() - 1 };
+                settings.effect = all[(i + step) % all.len()];
+            }
+            _ => settings.turbo = !settings.turbo,
+        }
+    }
+
+    // Lays out line `i` into `out`, returning how long it came out
+    fn line(i: usize, settings: &Settings, out: &mut [u8; LINE_CHARS]) -> usize {
+        let on_off = |on| if on { "ON" } else { "OFF" };
+        let (label, value) = match i {
+            0 => ("SOUND", on_off(settings.sound)),
+            1 => ("EFFECT", settings.effect.name()),
+            _ => ("TURBO", on_off(settings.turbo)),
+        };
+        let len = label.len() + 1 + value.len();
+        out[..label.len()].copy_from_slice(label.as_bytes());
+        out[label.len()] = b' ';
+        out[label.len() + 1..len].copy_from_slice(value.as_bytes());
+        len
+    }
+}
+
+impl Scene for Menu {
+    fn handle_input(&mut self, press: Press, settings: &mut Settings) -> Transition {
+        if press.contains(Press::BACK) {
+            return Transition::Pop;
+        }
+        if press.contains(Press::UP) {
+            self.selected = (self.selected + LINES - 1) % LINES;
+        }
+        if press.contains(Press::DOWN) {
+            self.selected = (self.selected + 1) % LINES;
+        }
+        if press.contains(Press::LEFT) {
+            self.change(settings, false);
+        }
+        if press.contains(Press::RIGHT) {
+            self.change(settings, true);
+        }
+        Transition::Stay
+    }
+
+    fn update(&mut self, _: State) -> Transition {
+        Transition::Stay
+    }
+
+    // A dark box in the middle with the selected line picked out, there
+    // being no glyph to point at it with
+    fn render(&self, frame: &mut [u8], _: &World, settings: &Settings) -> Option<Rect> {
+        let w = text::width(LINE_CHARS) + 2 * PADDING;
+        let h = LINES as i32 * text::LINE_H + 2 * PADDING;
+        let area = Rect {
+            x: (game::WIDTH - w) / 2,
+            y: (game::HEIGHT - h) / 2,
+            w,
+            h,
+        };
+        draw::darken_rect(frame, area);
+        draw::darken_rect(frame, area);
+        for i in 0..LINES {
+            let y = area.y + PADDING + i as i32 * text::LINE_H;
+            if i == self.selected {
+                let row = Rect {
+                    x: area.x,
+                    y: y - 1,
+                    w,
+                    h: text::LINE_H,
+                };
+                draw::fill_rect(frame, row, SELECTED);
+            }
+            let mut line = [0; LINE_CHARS];
+            let len = Menu::line(i, settings, &mut line);
+            text::draw(frame, area.x + PADDING, y, &line[..len], TEXT);
+        }
+        Some(area)
+    }
+
+    fn freezes_game(&self) -> bool {
+        true
+    }
+}
+
+// The stack, and one of each scene to go on it. There's only ever one of
+// each on the stack, so the menu's selection is still there when it's
+// opened again.
+#[derive(Clone, Copy, Debug)]
+pub struct Scenes {
+    stack: [Id; DEPTH],
+    len: usize,
+    title: Title,
+    game: Game,
+    game_over: GameOver,
+    pause: Pause,
+    menu: Menu,
+    // Since the frame task last asked
+    changed: bool,
+}
+
+impl Scenes {
+    pub fn new(state: State) -> Self {
+        Scenes {
+            stack: [root(state); DEPTH],
+            len: 1,
+            title: Title,
+            game: Game,
+            game_over: GameOver,
+            pause: Pause,
+            menu: Menu::new(),
+            changed: false,
+        }
+    }
+
+    pub fn top(&self) -> Id {
+        self.stack[self.len - 1]
+    }
+
+    fn scene(&self, id: Id) -> &dyn Scene {
+        match id {
+            Id::Title => &self.title,
+            Id::Game => &self.game,
+            Id::GameOver => &self.game_over,
+            Id::Pause => &self.pause,
+            Id::Menu => &self.menu,
+        }
+    }
+
+    fn scene_mut(&mut self, id: Id) -> &mut dyn Scene {
+        match id {
+            Id::Title => &mut self.title,
+            Id::Game => &mut self.game,
+            Id::GameOver => &mut self.game_over,
+            Id::Pause => &mut self.pause,
+            Id::Menu => &mut self.menu,
+        }
+    }
+
+    // The root is never popped, and a scene already on the stack isn't
+    // pushed again
+    fn apply(&mut self, transition: Transition) {
+        let (stack, len) = (self.stack, self.len);
+        match transition {
+            Transition::Stay => {}
+            Transition::Push(id) => {
+                if self.len < DEPTH && !self.stack[..self.len].contains(&id) {
+                    self.stack[self.len] = id;
+                    self.len += 1;
+                }
+            }
+            Transition::Pop => self.len = (self.len - 1).max(1),
+            Transition::Replace(id) => self.stack[self.len - 1] = id,
+        }
+        self.changed |= self.stack[..self.len] != stack[..len];
+    }
+
+    pub fn handle_input(&mut self, press: Press, settings: &mut Settings) {
+        if press.is_empty() {
+            return;
+        }
+        let top = self.top();
+        let transition = self.scene_mut(top).handle_input(press, settings);
+        self.apply(transition);
+    }
+
+    pub fn update(&mut self, state: State) {
+        let top = self.top();
+        let transition = self.scene_mut(top).update(state);
+        self.apply(transition);
+    }
+
+    // Bottom to top, returning everywhere any of them drew
+    pub fn render(&self, frame: &mut [u8], world: &World, settings: &Settings) -> Option<Rect> {
+        self.stack[..self.len]
+            .iter()
+            .filter_map(|&id| self.scene(id).render(frame, world, settings))
+            .reduce(Rect::union)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.stack[..self.len].iter().any(|&id| self.scene(id).freezes_game())
+    }
+
+    pub fn pause(&mut self) {
+        if !self.is_paused() {
+            self.apply(Transition::Push(Id::Pause));
+        }
+    }
+
+    // Back down to the root, whatever's over it
+    pub fn resume(&mut self) {
+        self.changed |= self.len > 1;
+        self.len = 1;
+    }
+
+    // Whether anything's been pushed, popped or replaced since the last time
+    // this was asked
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::replace(&mut self.changed, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+
+    #[test]
+    fn back_pauses_and_resumes_a_game() {
+        let mut settings = Settings::default();
+        let mut scenes = Scenes::new(State::Playing);
+        assert!(!scenes.is_paused());
+        scenes.handle_input(Press::BACK, &mut settings);
+        assert_eq!(scenes.top(), Id::Pause);
+        assert!(scenes.is_paused());
+        scenes.handle_input(Press::BACK, &mut settings);
+        assert_eq!(scenes.top(), Id::Game);
+        assert!(!scenes.is_paused());
+    }
+
+    #[test]
+    fn root_follows_the_world() {
+        let mut scenes = Scenes::new(State::Title);
+        scenes.update(State::Playing);
+        assert_eq!(scenes.top(), Id::Game);
+        scenes.update(State::GameOver);
+        assert_eq!(scenes.top(), Id::GameOver);
+        scenes.update(State::Title);
+        assert_eq!(scenes.top(), Id::Title);
+    }
+
+    #[test]
+    fn nothing_under_the_pause_screen_moves_on() {
+        let mut scenes = Scenes::new(State::Playing);
+        scenes.pause();
+        scenes.update(State::GameOver);
+        assert_eq!(scenes.top(), Id::Pause);
+        scenes.resume();
+        scenes.update(State::GameOver);
+        assert_eq!(scenes.top(), Id::GameOver);
+    }
+
+    #[test]
+    fn pausing_twice_pushes_once() {
+        let mut scenes = Scenes::new(State::Playing);
+        scenes.pause();
+        scenes.pause();
+        scenes.apply(Transition::Pop);
+        assert_eq!(scenes.top(), Id::Game);
+        // Nor does popping take the root with it
+        scenes.apply(Transition::Pop);
+        assert_eq!(scenes.top(), Id::Game);
+    }
+
+    #[test]
+    fn only_changes_to_the_stack_count() {
+        let mut settings = Settings::default();
+        let mut scenes = Scenes::new(State::Title);
+        scenes.update(State::Title);
+        scenes.handle_input(Press::UP, &mut settings);
+        assert!(!scenes.take_changed());
+
+        scenes.update(State::Playing);
+        assert!(scenes.take_changed());
+        assert!(!scenes.take_changed());
+        scenes.handle_input(Press::BACK, &mut settings);
+        assert!(scenes.take_changed());
+        // Already paused
+        scenes.pause();
+        assert!(!scenes.take_changed());
+        scenes.resume();
+        assert!(scenes.take_changed());
+        scenes.resume();
+        scenes.apply(Transition::Pop);
+        assert!(!scenes.take_changed());
+    }
+
+    #[test]
+    fn menu_changes_the_settings() {
+        let mut settings = Settings {
+            effect: Effect::Plasma,
+            ..Settings::default()
+        };
+        let sound = settings.sound;
+        let mut scenes = Scenes::new(State::Title);
+        scenes.handle_input(Press::BACK, &mut settings);
+        assert_eq!(scenes.top(), Id::Menu);
+        scenes.handle_input(Press::RIGHT, &mut settings);
+        assert_eq!(settings.sound, !sound);
+        scenes.handle_input(Press::DOWN, &mut settings);
+        scenes.handle_input(Press::RIGHT, &mut settings);
+        assert_eq!(settings.effect, Effect::Starfield);
+        scenes.handle_input(Press::LEFT, &mut settings);
+        scenes.handle_input(Press::LEFT, &mut settings);
+        assert_eq!(settings.effect, Effect::Off);
+        // Up from the top wraps round to turbo
+        scenes.handle_input(Press::UP, &mut settings);
+        scenes.handle_input(Press::UP, &mut settings);
+        let turbo = settings.turbo;
+        scenes.handle_input(Press::RIGHT, &mut settings);
+        assert_eq!(settings.turbo, !turbo);
+        scenes.handle_input(Press::BACK, &mut settings);
+        assert_eq!(scenes.top(), Id::Title);
+    }
+
+    #[test]
+    fn longest_line_fits() {
+        let mut settings = Settings::default();
+        let mut out = [0; LINE_CHARS];
+        for &effect in Effect::ALL.iter() {
+            settings.effect = effect;
+            for i in 0..LINES {
+                assert!(Menu::line(i, &settings, &mut out) <= LINE_CHARS);
+            }
+        }
+    }
+
+    #[test]
+    fn renders_where_it_says() {
+        let settings = Settings::default();
+        let mut world = World::new();
+        let mut frame = vec![0; Limits::FRAME_BYTES];
+        let scenes = Scenes::new(State::Playing);
+        assert_eq!(scenes.render(&mut frame, &world, &settings), None);
+        assert!(frame.iter().all(|&b| b == 0));
+
+        let mut scenes = Scenes::new(State::Title);
+        scenes.handle_input(Press::BACK, &mut Settings::default());
+        let area = scenes.render(&mut frame, &world, &settings).unwrap();
+        for (i, pixel) in frame.chunks(2).enumerate() {
+            let (x, y) = (i as i32 % game::WIDTH, i as i32 / game::WIDTH);
+            let inside = x >= area.x && x < area.x + area.w && y >= area.y && y < area.y + area.h;
+            assert!(inside || pixel == [0, 0]);
+        }
+
+        world.state = State::Playing;
+        let mut scenes = Scenes::new(State::Playing);
+        scenes.pause();
+        assert!(scenes.render(&mut frame, &world, &settings).is_some());
+    }
+}
@@ -0,0 +1,164 @@
+// Runs small periodic chores that aren't worth an RTIC task each: reports,
+// housekeeping, anything that only has to happen every so often and can wait
+// for a quiet moment. A chore is a function pointer with the tick it's next
+// due and how often it repeats. Ticks are whatever the caller counts, frames
+// here, and the comparisons wrap, so a tick counter can roll over.
+//
+// It's cooperative: a chore runs to completion, and a slow one holds up the
+// others. One that's fallen behind runs once and carries on from now, rather
+// than running again for every interval it missed.
+
+struct Job<F> {
+    next: u32,
+    interval: u32,
+    run: F,
+}
+
+pub struct Scheduler<F, const N: usize> {
+    jobs: [Option<Job<F>>; N],
+}
+
+impl<F: Copy, const N: usize> Scheduler<F, N> {
+    pub const fn new() -> Self {
+        Scheduler {
+            jobs: [const { None }; N],
+        }
+    }
+
+    // Runs `run` at tick `first`, then every `interval` ticks after that.
+    // Gives `run` back if the list is already full.
+    pub fn add(&mut self, first: u32, interval: u32, run: F) -> Result<(), F> {
+        match self.jobs.iter_mut().find(|job| job.is_none()) {
+            Some(slot) => {
+                *slot = Some(Job {
+                    next: first,
+                    interval: interval.max(1),
+                    run,
+                });
+                Ok(())
+            }
+            None => Err(run),
+        }
+    }
+
+    // The next chore due at `now`, the most overdue first, rescheduled as
+    // it's handed out. Call until it returns None.
+    pub fn due(&mut self, now: u32) -> Option<F> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .flatten()
+            .filter(|job| overdue(job.next, now).is_some())
+            .max_by_key(|job| overdue(job.next, now))?;
+        job.next = job.next.wrapping_add(job.interval);
+        if overdue(job.next, now).is_some() {
+            job.next = now.wrapping_add(job.interval);
+        }
+        Some(job.run)
+    }
+}
+
+// How long ago `next` was at `now`, or None if it's still to come
+fn overdue(next: u32, now: u32) -> Option<u32> {
+    let late = now.wrapping_sub(next);
+    if late < 1 << 31 {
+        Some(late)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Everything due at `now`, in the order it's handed out
+    fn drain<const N: usize>(scheduler: &mut Scheduler<char, N>, now: u32) -> Vec<char> {
+        core::iter::from_fn(|| scheduler.due(now)).collect()
+    }
+
+    #[test]
+    fn nothing_runs_before_it_is_due() {
+        let mut scheduler: Scheduler<char, 2> = Scheduler::new();
+        scheduler.add(10, 5, 'a').unwrap();
+        assert_eq!(drain(&mut scheduler, 9), []);
+        assert_eq!(drain(&mut scheduler, 10), ['a']);
+        assert_eq!(drain(&mut scheduler, 14), []);
+        assert_eq!(drain(&mut scheduler, 15), ['a']);
+    }
+
+    #[test]
+    fn each_chore_repeats_at_its_own_interval() {
+        let mut scheduler: Scheduler<char, 2> = Scheduler::new();
+        scheduler.add(0, 2, 'a').unwrap();
+        scheduler.add(0, 3, 'b').unwrap();
+        let runs: Vec<(u32, char)> = (0..7)
+            .flat_map(|now| {
+                drain(&mut scheduler, now)
+                    .into_iter()
+                    .map(move |c| (now, c))
+            })
+            .collect();
+        let a: Vec<u32> = runs.iter().filter(|r| r.1 == 'a').map(|r| r.0).collect();
+        let b: Vec<u32> = runs.iter().filter(|r| r.1 == 'b').map(|r| r.0).collect();
+        assert_eq!(a, [0, 2, 4, 6]);
+        assert_eq!(b, [0, 3, 6]);
+    }
+
+    #[test]
+    fn the_most_overdue_goes_first() {
+        let mut scheduler: Scheduler<char, 3> = Scheduler::new();
+        scheduler.add(8, 100, 'a').unwrap();
+        scheduler.add(2, 100, 'b').unwrap();
+        scheduler.add(5, 100, 'c').unwrap();
+        assert_eq!(drain(&mut scheduler, 10), ['b', 'c', 'a']);
+    }
+
+    #[test]
+    fn a_chore_that_fell_behind_runs_once_and_carries_on_from_now() {
+        let mut scheduler: Scheduler<char, 1> = Scheduler::new();
+        scheduler.add(0, 10, 'a').unwrap();
+        assert_eq!(drain(&mut scheduler, 55), ['a']);
+        assert_eq!(drain(&mut scheduler, 64), []);
+        assert_eq!(drain(&mut scheduler, 65), ['a']);
+    }
+
+    #[test]
+    fn a_chore_only_just_late_keeps_its_rhythm() {
+        let mut scheduler: Scheduler<char, 1> = Scheduler::new();
+        scheduler.add(0, 10, 'a').unwrap();
+        assert_eq!(drain(&mut scheduler, 3), ['a']);
+        assert_eq!(drain(&mut scheduler, 10), ['a']);
+    }
+
+    #[test]
+    fn a_full_list_gives_the_chore_back() {
+        let mut scheduler: Scheduler<char, 2> = Scheduler::new();
+        assert_eq!(scheduler.add(0, 1, 'a'), Ok(()));
+        assert_eq!(scheduler.add(0, 1, 'b'), Ok(()));
+        assert_eq!(scheduler.add(0, 1, 'c'), Err('c'));
+        let mut due = drain(&mut scheduler, 0);
+        due.sort();
+        assert_eq!(due, ['a', 'b']);
+    }
+
+    #[test]
+    fn a_zero_interval_runs_once_a_tick() {
+        let mut scheduler: Scheduler<char, 1> = Scheduler::new();
+        scheduler.add(0, 0, 'a').unwrap();
+        assert_eq!(drain(&mut scheduler, 0), ['a']);
+        assert_eq!(drain(&mut scheduler, 1), ['a']);
+    }
+
+    #[test]
+    fn ticks_wrap() {
+        let mut scheduler: Scheduler<char, 1> = Scheduler::new();
+        scheduler.add(u32::MAX - 2, 4, 'a').unwrap();
+        assert_eq!(drain(&mut scheduler, u32::MAX - 3), []);
+        assert_eq!(drain(&mut scheduler, u32::MAX - 2), ['a']);
+        // Next due at 1, past the wrap, so not yet at u32::MAX
+        assert_eq!(drain(&mut scheduler, u32::MAX), []);
+        assert_eq!(drain(&mut scheduler, 0), []);
+        assert_eq!(drain(&mut scheduler, 1), ['a']);
+    }
+}
@@ -0,0 +1,252 @@
+#[cfg(any(feature = "flash", feature = "ble"))]
+use crate::crc;
+use crate::draw;
+use crate::game::{Input, Rect, HEIGHT, WIDTH};
+use crate::text;
+
+// The high score table, kept in its own flash page, and the screen for
+// entering initials after a game that makes it in. The saved layout has the
+// same kind of header and trailing CRC32 as a checkpoint. Anything that
+// doesn't check out, erased flash on first boot included, loads as an empty
+// table.
+
+pub const ENTRIES: usize = 5;
+
+#[cfg(any(feature = "flash", feature = "ble"))]
+const MAGIC: [u8; 4] = *b"PEWH";
+#[cfg(any(feature = "flash", feature = "ble"))]
+const VERSION: u8 = 1;
+#[cfg(any(feature = "flash", feature = "ble"))]
+const ENTRY_LEN: usize = 3 + 4;
+#[cfg(any(feature = "flash", feature = "ble"))]
+pub const LEN: usize = 4 + 1 + ENTRIES * ENTRY_LEN + 4;
+
+// What initials can be made of, in the order the cursor goes through them
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Score {
+    pub initials: [u8; 3],
+    pub score: u32,
+}
+
+// Highest first, with any empty slots at the end
+pub struct Table {
+    entries: [Option<Score>; ENTRIES],
+}
+
+impl Table {
+    pub const fn new() -> Self {
+        Table {
+            entries: [None; ENTRIES],
+        }
+    }
+
+    #[cfg(feature = "flash")]
+    pub fn load(buf: &[u8]) -> Self {
+        let mut table = Table::new();
+        if buf.len() < LEN || buf[..4] != MAGIC || buf[4] != VERSION {
+            return table;
+        }
+        let stored = u32::from_le_bytes([buf[LEN - 4], buf[LEN - 3], buf[LEN - 2], buf[LEN - 1]]);
+        if crc::crc32(&buf[..LEN - 4]) != stored {
+            log_warn!("High score table is corrupt, starting a new one");
+            return table;
+        }
+
+        let mut scores = buf[5..LEN - 4].chunks_exact(ENTRY_LEN).filter_map(|entry| {
+            let initials = [entry[0], entry[1], entry[2]];
+            let score = u32::from_le_bytes([entry[3], entry[4], entry[5], entry[6]]);
+            // Unused slots are saved as zeroes
+            match score {
+                0 => None,
+                score => Some(Score { initials, score }),
+            }
+        });
+        for slot in table.entries.iter_mut() {
+            *slot = scores.next();
+        }
+        table
+    }
+
+    #[cfg(any(feature = "flash", feature = "ble"))]
+    pub fn save(&self, buf: &mut [u8; LEN]) {
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4] = VERSION;
+        for (slot, entry) in self
+            .entries
+            .iter()
+            .zip(buf[5..].chunks_exact_mut(ENTRY_LEN))
+        {
+            let score = slot.unwrap_or(Score {
+                initials: [0; 3],
+                score: 0,
+            });
+            entry[..3].copy_from_slice(&score.initials);
+            entry[3..].copy_from_slice(&score.score.to_le_bytes());
+        }
+        let crc = crc::crc32(&buf[..LEN - 4]);
+        buf[LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries[0].is_none()
+    }
+
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && self
+                .entries
+                .iter()
+                .any(|slot| slot.is_none_or(|s| score > s.score))
+    }
+
+    // Returns false if it didn't make the table. Ties go below the score
+    // that was there first.
+    pub fn insert(&mut self, new: Score) -> bool {
+        let at = match self
+            .entries
+            .iter()
+            .position(|slot| slot.is_none_or(|s| new.score > s.score))
+        {
+            Some(at) => at,
+            None => return false,
+        };
+        self.entries[at..].rotate_right(1);
+        self.entries[at] = Some(new);
+        true
+    }
+
+    // The table, scrolling up the screen from the bottom over and over, at
+    // half a pixel per frame `t`, with the lines of `after` a line below it.
+    // Returns the area drawn over.
+    pub fn draw_scrolling(
+        &self,
+        frame: &mut [u8],
+        t: u32,
+        after: &[&[u8]],
+        color: u16,
+    ) -> Option<Rect> {
+        let scores = self.entries.iter().flatten().count() as i32;
+        let gap = if after.is_empty() { 0 } else { 1 };
+        let rows = 1 + scores + gap + after.len() as i32;
+        let height = rows * text::LINE_H;
+        let y0 = HEIGHT - (t / 2 % (HEIGHT + height) as u32) as i32;
+
+        let header = b"HI SCORES";
+        text::draw(frame, centered(header.len()), y0, header, color);
+        for (i, score) in self.entries.iter().flatten().enumerate() {
+            let mut line = *b"1 AAA 000000";
+            line[0] = b'1' + i as u8;
+            line[2..5].copy_from_slice(&score.initials);
+            text::digits(&mut line[6..], score.score);
+            let y = y0 + (i as i32 + 1) * text::LINE_H;
+            text::draw(frame, centered(line.len()), y, &line, color);
+        }
+        for (i, line) in after.iter().enumerate() {
+            let y = y0 + (1 + scores + gap + i as i32) * text::LINE_H;
+            text::draw(frame, centered(line.len()), y, line, color);
+        }
+
+        Rect {
+            x: 0,
+            y: y0,
+            w: WIDTH,
+            h: height,
+        }
+        .clip(WIDTH, HEIGHT)
+    }
+}
+
+fn centered(chars: usize) -> i32 {
+    (WIDTH - text::width(chars)) / 2
+}
+
+// Frames between letter changes while the stick is held over
+const REPEAT_FRAMES: u8 = 10;
+// Top of the initials screen
+const ENTRY_TOP: i32 = HEIGHT / 2 - 2 * text::LINE_H;
+
+// Picking three characters: steer to change the one under the cursor, fire
+// to move on to the next
+pub struct Initials {
+    chars: [u8; 3],
+    cursor: usize,
+    held: u8,
+    // Fire has to be let go first, so the shot that ended the game can't
+    // pick the first letter
+    armed: bool,
+}
+
+impl Initials {
+    pub const fn new() -> Self {
+        Initials {
+            chars: [0; 3],
+            cursor: 0,
+            held: 0,
+            armed: false,
+        }
+    }
+
+    // Returns the initials once the last one has been picked
+    pub fn update(&mut self, input: Input) -> Option<[u8; 3]> {
+        let step = if input.right || input.x > 64 {
+            1
+        } else if input.left || input.x < -64 {
+            ALPHABET.len() - 1
+        } else {
+            0
+        };
+        if step == 0 {
+            self.held = 0;
+        } else {
+            if self.held.is_multiple_of(REPEAT_FRAMES) {
+                let c = &mut self.chars[self.cursor];
+                *c = ((*c as usize + step) % ALPHABET.len()) as u8;
+            }
+            self.held = self.held.wrapping_add(1);
+        }
+
+        if !input.fire {
+            self.armed = true;
+        } else if self.armed {
+            self.armed = false;
+            self.cursor += 1;
+            if self.cursor == self.chars.len() {
+                self.cursor = 0;
+                return Some(self.chars.map(|c| ALPHABET[c as usize]));
+            }
+        }
+        None
+    }
+
+    // Where `draw` draws, which is the same every frame
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: ENTRY_TOP,
+            w: WIDTH,
+            h: 3 * text::LINE_H + 1,
+        }
+    }
+
+    // Returns the area drawn over
+    pub fn draw(&self, frame: &mut [u8], color: u16) -> Rect {
+        let title = b"HIGH SCORE";
+        text::draw(frame, centered(title.len()), ENTRY_TOP, title, color);
+
+        let initials = self.chars.map(|c| ALPHABET[c as usize]);
+        let x = centered(initials.len());
+        let y = ENTRY_TOP + 2 * text::LINE_H;
+        text::draw(frame, x, y, &initials, color);
+        let under = Rect {
+            x: x + self.cursor as i32 * text::ADVANCE,
+            y: y + text::LINE_H,
+            w: text::GLYPH_W,
+            h: 1,
+        };
+        draw::fill_rect(frame, under, color);
+
+        self.rect()
+    }
+}
@@ -0,0 +1,154 @@
+use crate::color;
+use crate::limits::Limits;
+use crate::starfield::Scroll;
+use crate::text;
+
+// What the panel shows after `set screensaver` minutes without input, in
+// place of whatever was on it: the name bouncing around, or the starfield
+// turned down low. Frames slow to SAVER_FPS while it's up, and the world
+// stands still underneath.
+//
+// Neither leaves anything in one place for long, but the whole picture is
+// also moved a pixel every so often, round a little square, so nothing
+// stays lit on the same pixels either.
+
+const W: i32 = Limits::SCREEN_WIDTH as i32;
+const H: i32 = Limits::SCREEN_HEIGHT as i32;
+
+pub const SAVER_FPS: u32 = 15;
+
+const LOGO: &[u8] = b"PEWPEW";
+// Out of 256
+const STARS_LEVEL: u32 = 80;
+// Frames between each step round SHIFTS
+const SHIFT_FRAMES: u32 = 8 * SAVER_FPS;
+const SHIFTS: [(i32, i32); 4] = [(0, 0), (1, 0), (1, 1), (0, 1)];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    Logo,
+    Stars,
+}
+
+impl Style {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "logo" => Some(Style::Logo),
+            "stars" => Some(Style::Stars),
+            _ => None,
+        }
+    }
+}
+
+pub struct Screensaver {
+    idle_us: u64,
+    active: bool,
+    frames: u32,
+    // Top left of the logo, and which way it's going
+    x: i32,
+    y: i32,
+    dx: i32,
+    dy: i32,
+    scroll: Scroll,
+}
+
+impl Screensaver {
+    pub const fn new() -> Self {
+        Screensaver {
+            idle_us: 0,
+            active: false,
+            frames: 0,
+            x: 0,
+            y: 0,
+            dx: 1,
+            dy: 1,
+            scroll: Scroll::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // For any input at all, as soon as it's seen. Returns true if that's
+    // woken it, in which case whatever was on the panel before has to be
+    // sent whole again.
+    pub fn input(&mut self) -> bool {
+        self.idle_us = 0;
+        core::mem::replace(&mut self.active, false)
+    }
+
+    // Called once per frame without input, with how long it took counting
+    // the wait after it. A `timeout_us` of 0 holds it off, and starts the
+    // wait over. Returns true as it comes on.
+    pub fn idle(&mut self, frame_us: u32, timeout_us: u64) -> bool {
+        if timeout_us == 0 {
+            self.idle_us = 0;
+            return false;
+        }
+        self.idle_us += frame_us as u64;
+        if self.active || self.idle_us < timeout_us {
+            return false;
+        }
+        self.active = true;
+        self.frames = 0;
+        self.x = (W - text::width(LOGO.len())) / 2;
+        self.y = (H - text::GLYPH_H) / 2;
+        true
+    }
+
+    // Draws the next frame over all of `frame`. `stars` draws the starfield
+    // at a scroll.
+    pub fn draw(&mut self, frame: &mut [u8], style: Style, stars: impl FnOnce(&mut [u8], &Scroll)) {
+        self.frames = self.frames.wrapping_add(1);
+        frame.fill(0);
+        match style {
+            Style::Logo => self.draw_logo(frame),
+            Style::Stars => {
+                self.scroll.advance(0);
+                stars(frame, &self.scroll);
+                for pixel in frame.chunks_exact_mut(2) {
+                    let dim = color::scale(u16::from_le_bytes([pixel[0], pixel[1]]), STARS_LEVEL);
+                    pixel.copy_from_slice(&dim.to_le_bytes());
+                }
+            }
+        }
+        let (dx, dy) = SHIFTS[(self.frames / SHIFT_FRAMES) as usize % SHIFTS.len()];
+        shift(frame, dx, dy);
+    }
+
+    fn draw_logo(&mut self, frame: &mut [u8]) {
+        let w = text::width(LOGO.len());
+        // Bounces off the edges, less the room the shift needs
+        if !(0..W - w - 1).contains(&(self.x + self.dx)) {
+            self.dx = -self.dx;
+        }
+        if !(0..H - text::GLYPH_H - 1).contains(&(self.y + self.dy)) {
+            self.dy = -self.dy;
+        }
+        self.x += self.dx;
+        self.y += self.dy;
+        // Round the colors once every 17 s or so
+        let hue = self.frames as u8;
+        text::draw(frame, self.x, self.y, LOGO, color::hsv(hue, 255, 160));
+    }
+}
+
+// Moves the whole frame right by `dx` and down by `dy`, each 0 or more,
+// filling in behind with black
+fn shift(frame: &mut [u8], dx: i32, dy: i32) {
+    let row = W as usize * 2;
+    let (dx, dy) = (dx as usize * 2, dy as usize);
+    if dx == 0 && dy == 0 {
+        return;
+    }
+    // Bottom up, so no row's written over before it's been moved
+    for y in (0..H as usize).rev() {
+        if y >= dy {
+            frame.copy_within((y - dy) * row..(y - dy + 1) * row - dx, y * row + dx);
+            frame[y * row..y * row + dx].fill(0);
+        } else {
+            frame[y * row..(y + 1) * row].fill(0);
+        }
+    }
+}
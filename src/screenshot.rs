@@ -0,0 +1,58 @@
+use crate::limits::Limits;
+use core::sync::atomic::{AtomicBool, Ordering};
+use rtt_target::UpChannel;
+
+// `screenshot` on the console sends the next frame that goes through the
+// framebuffer out on an RTT channel of its own, "Screenshot", a row at a time.
+// Each row is a record of its own: b'S', the row number, the width and the
+// height, a byte each, then the row's pixels as little-endian RGB565 with red
+// in the low bits, the panel's BGR order. A row that doesn't fit in the
+// channel is dropped rather than waited for, so a host that's slow to read
+// gets a shot with rows missing instead of a stuck frame task.
+
+const W: usize = Limits::SCREEN_WIDTH;
+const H: usize = Limits::SCREEN_HEIGHT;
+const HEADER: usize = 4;
+const RECORD_LEN: usize = HEADER + W * 2;
+// What main.rs gives rtt_init!, which only takes a literal: room for a whole
+// shot and the byte an RTT buffer always keeps free
+pub const CHANNEL_SIZE: usize = 8449;
+
+const _: () = assert!(
+    CHANNEL_SIZE > RECORD_LEN * H,
+    "a whole screenshot doesn't fit in its RTT channel"
+);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+pub fn request() {
+    REQUESTED.store(true, Ordering::Relaxed);
+}
+
+pub struct Screenshots {
+    channel: UpChannel,
+}
+
+impl Screenshots {
+    pub fn new(channel: UpChannel) -> Self {
+        Screenshots { channel }
+    }
+
+    // Sends `frame` if a screenshot was asked for, returning how many rows
+    // had to be dropped
+    pub fn take(&mut self, frame: &[u8]) -> Option<usize> {
+        if !REQUESTED.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+        let mut record = [0; RECORD_LEN];
+        let mut dropped = 0;
+        for (y, row) in frame.chunks_exact(W * 2).enumerate() {
+            record[..HEADER].copy_from_slice(&[b'S', y as u8, W as u8, H as u8]);
+            record[HEADER..].copy_from_slice(row);
+            if self.channel.write(&record) < RECORD_LEN {
+                dropped += 1;
+            }
+        }
+        Some(dropped)
+    }
+}
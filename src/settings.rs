@@ -0,0 +1,475 @@
+use crate::effect::Effect;
+use crate::fade::Transition;
+use crate::input::Turbo;
+use crate::metronome;
+use crate::plasma;
+use crate::screensaver::Style;
+use crate::timescale;
+use crate::trails;
+use st7735_lcd::Orientation;
+
+pub const VERSION: u8 = 1;
+
+// Lowest FPS cap that still plays, 0 meaning uncapped
+pub const MIN_FPS: u8 = 10;
+pub const MAX_FPS: u8 = 60;
+
+// Longest a hold threshold or turbo repeat can be, in frames
+pub const MAX_TURBO_FRAMES: u8 = 60;
+
+// Radio channels for the link, in MHz above 2400
+pub const DEFAULT_LINK_CHANNEL: u8 = 40;
+pub const MAX_LINK_CHANNEL: u8 = 100;
+
+// Everything the player can change that's worth keeping between boots. What
+// gets loaded might have come from an older firmware or a half-written page,
+// so anything that didn't pass `validate` shouldn't be trusted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub version: u8,
+    pub brightness: u8,
+    // Brightness follows the light sensor rather than `set brightness`
+    pub auto_brightness: bool,
+    pub sound: bool,
+    // Percent of the synth's full volume
+    pub volume: u8,
+    pub effect: Effect,
+    // Index into plasma::VARIANTS, or plasma::RANDOM
+    pub plasma: u8,
+    // How bright the plasma shows behind the game, out of 255. At 0 the game
+    // is played on black.
+    pub backdrop: u8,
+    // What the game's played on when there's no backdrop or other effect
+    pub clear_color: u16,
+    // Leave a fading trail behind everything that moves, on a plain
+    // background. 0 for none, otherwise the shift in trails::Trails, so
+    // bigger is longer.
+    pub trails: u8,
+    pub vignette: bool,
+    // Checkerboard the outlines of sprites into the background
+    pub dither_edges: bool,
+    // Index into ORIENTATIONS
+    pub orientation: u8,
+    // Orientation follows the accelerometer rather than `orientation <n>`
+    pub auto_orientation: bool,
+    // Tipping the board steers, see tilt::steer. The orientation stays put
+    // while it does, even with auto_orientation.
+    pub tilt_steering: bool,
+    pub fps_cap: u8,
+    // Minutes without input before the display powers off, 0 for never
+    pub power_off_mins: u8,
+    // Minutes without input before the screensaver comes on, 0 for never
+    pub screensaver_mins: u8,
+    pub screensaver: Style,
+    // Metronome tempo, 0 for no beat
+    pub bpm: u8,
+    // How fast the game runs, in percent of the frame rate
+    pub speed: u8,
+    // Holding fire keeps shooting, see input::Turbo
+    pub turbo: bool,
+    pub turbo_hold_frames: u8,
+    pub turbo_repeat_frames: u8,
+    // Boards only link up with others on the same channel and pairing code
+    pub link_channel: u8,
+    pub link_code: u8,
+    // Linked games are head to head rather than co-op. Player one's is the
+    // one that counts.
+    pub versus: bool,
+    // How the screen changes over when the game does
+    pub transition: Transition,
+}
+
+// The order effects are saved in
+#[cfg(any(feature = "flash", feature = "ble"))]
+const EFFECTS: [Effect; 4] = [
+    Effect::Plasma,
+    Effect::Starfield,
+    Effect::Tiles,
+    Effect::Off,
+];
+
+// The order transitions are saved in
+#[cfg(any(feature = "flash", feature = "ble"))]
+const TRANSITIONS: [Transition; 4] = [
+    Transition::Fade,
+    Transition::Black,
+    Transition::Wipe,
+    Transition::Dissolve,
+];
+
+// The order screensaver styles are saved in
+#[cfg(any(feature = "flash", feature = "ble"))]
+const STYLES: [Style; 2] = [Style::Logo, Style::Stars];
+
+// Saved length, a byte a field apart from the two byte clear color
+#[cfg(any(feature = "flash", feature = "ble"))]
+pub const LEN: usize = 29;
+
+const ORIENTATIONS: [Orientation; 4] = [
+    Orientation::Portrait,
+    Orientation::Landscape,
+    Orientation::PortraitSwapped,
+    Orientation::LandscapeSwapped,
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: VERSION,
+            brightness: 255,
+            auto_brightness: cfg!(feature = "light"),
+            sound: true,
+            volume: 100,
+            effect: Effect::Plasma,
+            plasma: plasma::RANDOM,
+            backdrop: 0,
+            clear_color: 0,
+            trails: 0,
+            vignette: false,
+            dither_edges: false,
+            orientation: 3,
+            auto_orientation: false,
+            fps_cap: 0,
+            power_off_mins: 5,
+            screensaver_mins: 2,
+            screensaver: Style::Logo,
+            bpm: 120,
+            speed: 100,
+            turbo: true,
+            turbo_hold_frames: 8,
+            turbo_repeat_frames: 0,
+            link_channel: DEFAULT_LINK_CHANNEL,
+            link_code: 0,
+            versus: false,
+            transition: Transition::Fade,
+            tilt_steering: false,
+        }
+    }
+}
+
+impl Settings {
+    // Puts anything out of range back to its default. Settings from another
+    // version are thrown out entirely, since their fields may not mean the
+    // same thing. Returns true if anything had to change.
+    pub fn validate(&mut self) -> bool {
+        let defaults = Settings::default();
+        if self.version != VERSION {
+            *self = defaults;
+            return true;
+        }
+
+        let mut changed = false;
+        if self.orientation as usize >= ORIENTATIONS.len() {
+            self.orientation = defaults.orientation;
+            changed = true;
+        }
+        if self.plasma != plasma::RANDOM && self.plasma as usize >= plasma::VARIANTS.len() {
+            self.plasma = defaults.plasma;
+            changed = true;
+        }
+        if self.fps_cap != 0 && !(MIN_FPS..=MAX_FPS).contains(&self.fps_cap) {
+            self.fps_cap = defaults.fps_cap;
+            changed = true;
+        }
+        if self.bpm != 0 && !(metronome::MIN_BPM..=metronome::MAX_BPM).contains(&self.bpm) {
+            self.bpm = defaults.bpm;
+            changed = true;
+        }
+        if !(timescale::MIN_PERCENT..=100).contains(&self.speed) {
+            self.speed = defaults.speed;
+            changed = true;
+        }
+        if !(1..=MAX_TURBO_FRAMES).contains(&self.turbo_hold_frames) {
+            self.turbo_hold_frames = defaults.turbo_hold_frames;
+            changed = true;
+        }
+        if self.turbo_repeat_frames > MAX_TURBO_FRAMES {
+            self.turbo_repeat_frames = defaults.turbo_repeat_frames;
+            changed = true;
+        }
+        if self.trails > trails::MAX_SHIFT {
+            self.trails = defaults.trails;
+            changed = true;
+        }
+        if self.auto_brightness && !cfg!(feature = "light") {
+            self.auto_brightness = false;
+            changed = true;
+        }
+        if self.volume > 100 {
+            self.volume = defaults.volume;
+            changed = true;
+        }
+        if self.auto_orientation && !cfg!(feature = "tilt") {
+            self.auto_orientation = false;
+            changed = true;
+        }
+        if self.tilt_steering && !cfg!(feature = "tilt") {
+            self.tilt_steering = false;
+            changed = true;
+        }
+        if self.link_channel > MAX_LINK_CHANNEL {
+            self.link_channel = defaults.link_channel;
+            changed = true;
+        }
+        changed
+    }
+
+    // Unpacks what `save` wrote, for `validate` to check over
+    #[cfg(any(feature = "flash", feature = "ble"))]
+    pub fn load(buf: &[u8]) -> Option<Self> {
+        if buf.len() < LEN {
+            return None;
+        }
+        let defaults = Settings::default();
+        Some(Settings {
+            version: buf[0],
+            brightness: buf[1],
+            auto_brightness: buf[2] != 0,
+            sound: buf[3] != 0,
+            volume: buf[4],
+            effect: EFFECTS
+                .get(buf[5] as usize)
+                .copied()
+                .unwrap_or(defaults.effect),
+            plasma: buf[6],
+            backdrop: buf[7],
+            clear_color: u16::from_le_bytes([buf[8], buf[9]]),
+            trails: buf[10],
+            vignette: buf[11] != 0,
+            dither_edges: buf[12] != 0,
+            orientation: buf[13],
+            auto_orientation: buf[14] != 0,
+            fps_cap: buf[15],
+            power_off_mins: buf[16],
+            bpm: buf[17],
+            speed: buf[18],
+            turbo: buf[19] != 0,
+            turbo_hold_frames: buf[20],
+            turbo_repeat_frames: buf[21],
+            link_channel: buf[22],
+            link_code: buf[23],
+            versus: buf[24] != 0,
+            transition: TRANSITIONS
+                .get(buf[25] as usize)
+                .copied()
+                .unwrap_or(defaults.transition),
+            tilt_steering: buf[26] != 0,
+            screensaver_mins: buf[27],
+            screensaver: STYLES
+                .get(buf[28] as usize)
+                .copied()
+                .unwrap_or(defaults.screensaver),
+        })
+    }
+
+    // Brightness and orientation are saved as their defaults while they
+    // follow a sensor, so that the sensor doesn't keep them changing and
+    // being saved again
+    #[cfg(any(feature = "flash", feature = "ble"))]
+    pub fn save(&self, buf: &mut [u8; LEN]) {
+        let defaults = Settings::default();
+        let brightness = match self.auto_brightness {
+            true => defaults.brightness,
+            false => self.brightness,
+        };
+        let orientation = match self.auto_orientation {
+            true => defaults.orientation,
+            false => self.orientation,
+        };
+        let effect = EFFECTS
+            .iter()
+            .position(|&effect| effect == self.effect)
+            .unwrap_or(0);
+        let transition = TRANSITIONS
+            .iter()
+            .position(|&transition| transition == self.transition)
+            .unwrap_or(0);
+        let screensaver = STYLES
+            .iter()
+            .position(|&style| style == self.screensaver)
+            .unwrap_or(0);
+        let clear_color = self.clear_color.to_le_bytes();
+        *buf = [
+            self.version,
+            brightness,
+            self.auto_brightness as u8,
+            self.sound as u8,
+            self.volume,
+            effect as u8,
+            self.plasma,
+            self.backdrop,
+            clear_color[0],
+            clear_color[1],
+            self.trails,
+            self.vignette as u8,
+            self.dither_edges as u8,
+            orientation,
+            self.auto_orientation as u8,
+            self.fps_cap,
+            self.power_off_mins,
+            self.bpm,
+            self.speed,
+            self.turbo as u8,
+            self.turbo_hold_frames,
+            self.turbo_repeat_frames,
+            self.link_channel,
+            self.link_code,
+            self.versus as u8,
+            transition as u8,
+            self.tilt_steering as u8,
+            self.screensaver_mins,
+            screensaver as u8,
+        ];
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        ORIENTATIONS[self.orientation as usize % ORIENTATIONS.len()]
+    }
+
+    pub fn turbo(&self) -> Turbo {
+        Turbo {
+            enabled: self.turbo,
+            hold_frames: self.turbo_hold_frames,
+            repeat_frames: self.turbo_repeat_frames,
+        }
+    }
+
+    pub fn power_off_us(&self) -> u64 {
+        self.power_off_mins as u64 * 60_000_000
+    }
+
+    pub fn screensaver_us(&self) -> u64 {
+        self.screensaver_mins as u64 * 60_000_000
+    }
+
+    // Shortest time a frame may take, in microseconds
+    pub fn frame_period_us(&self) -> u32 {
+        match self.fps_cap {
+            0 => 0,
+            fps => 1_000_000 / fps as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Validates one change to the defaults, and returns what came of it
+    fn validated(change: impl FnOnce(&mut Settings)) -> (Settings, bool) {
+        let mut settings = Settings::default();
+        change(&mut settings);
+        let changed = settings.validate();
+        (settings, changed)
+    }
+
+    #[test]
+    fn the_defaults_are_valid() {
+        assert_eq!(validated(|_| ()), (Settings::default(), false));
+    }
+
+    #[test]
+    fn another_version_is_thrown_out() {
+        let (settings, changed) = validated(|settings| {
+            settings.version = VERSION + 1;
+            settings.brightness = 10;
+        });
+        assert!(changed);
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn out_of_range_values_go_back_to_their_defaults() {
+        let defaults = Settings::default();
+        let cases: [fn(&mut Settings); 11] = [
+            |s| s.orientation = ORIENTATIONS.len() as u8,
+            |s| s.plasma = plasma::VARIANTS.len() as u8,
+            |s| s.fps_cap = MIN_FPS - 1,
+            |s| s.fps_cap = MAX_FPS + 1,
+            |s| s.bpm = metronome::MAX_BPM + 1,
+            |s| s.speed = timescale::MIN_PERCENT - 1,
+            |s| s.turbo_hold_frames = 0,
+            |s| s.turbo_repeat_frames = MAX_TURBO_FRAMES + 1,
+            |s| s.trails = trails::MAX_SHIFT + 1,
+            |s| s.volume = 101,
+            |s| s.link_channel = MAX_LINK_CHANNEL + 1,
+        ];
+        for (i, &change) in cases.iter().enumerate() {
+            let (settings, changed) = validated(change);
+            assert!(changed, "case {}", i);
+            assert_eq!(settings, defaults, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn values_at_the_ends_of_their_ranges_are_kept() {
+        let cases: [fn(&mut Settings); 8] = [
+            |s| s.plasma = plasma::VARIANTS.len() as u8 - 1,
+            |s| s.fps_cap = MIN_FPS,
+            |s| s.fps_cap = MAX_FPS,
+            |s| s.bpm = 0,
+            |s| s.bpm = metronome::MIN_BPM,
+            |s| s.speed = timescale::MIN_PERCENT,
+            |s| s.turbo_hold_frames = MAX_TURBO_FRAMES,
+            |s| s.link_channel = MAX_LINK_CHANNEL,
+        ];
+        for (i, &change) in cases.iter().enumerate() {
+            assert!(!validated(change).1, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn sensors_the_build_lacks_are_turned_off() {
+        let (settings, changed) = validated(|s| {
+            s.auto_brightness = true;
+            s.auto_orientation = true;
+            s.tilt_steering = true;
+        });
+        assert_eq!(changed, !cfg!(all(feature = "light", feature = "tilt")));
+        assert_eq!(settings.auto_brightness, cfg!(feature = "light"));
+        assert_eq!(settings.auto_orientation, cfg!(feature = "tilt"));
+        assert_eq!(settings.tilt_steering, cfg!(feature = "tilt"));
+    }
+
+    #[cfg(any(feature = "flash", feature = "ble"))]
+    #[test]
+    fn saved_settings_load_back_the_same() {
+        let settings = Settings {
+            brightness: 100,
+            sound: false,
+            volume: 30,
+            effect: Effect::Tiles,
+            plasma: 2,
+            clear_color: 0x1234,
+            trails: 3,
+            orientation: 1,
+            fps_cap: 30,
+            bpm: 90,
+            turbo_hold_frames: 12,
+            link_channel: 80,
+            link_code: 7,
+            transition: Transition::Wipe,
+            screensaver: Style::Stars,
+            ..Settings::default()
+        };
+        let mut buf = [0; LEN];
+        settings.save(&mut buf);
+        let mut loaded = Settings::load(&buf).unwrap();
+        assert!(!loaded.validate());
+        assert_eq!(loaded, settings);
+
+        assert_eq!(Settings::load(&buf[..LEN - 1]), None);
+    }
+
+    #[cfg(any(feature = "flash", feature = "ble"))]
+    #[test]
+    fn garbage_loads_as_something_valid() {
+        for &byte in &[0x00, 0x5A, 0xFF] {
+            let mut buf = [byte; LEN];
+            buf[0] = VERSION;
+            let mut loaded = Settings::load(&buf).unwrap();
+            loaded.validate();
+            assert!(!loaded.validate(), "{:#x}", byte);
+        }
+    }
+}
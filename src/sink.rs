@@ -0,0 +1,165 @@
+use crate::display::DisplayDriver;
+use crate::game::Rect;
+
+// Somewhere finished pixels can be sent. Rendering code only needs this, so
+// it doesn't care whether the other end is the panel or something recording
+// the output.
+pub trait FrameSink {
+    type Error;
+
+    // `pixels` are RGB565, row-major, exactly `rect.w * rect.h` of them
+    fn write_rect(
+        &mut self,
+        rect: Rect,
+        pixels: impl Iterator<Item = u16>,
+    ) -> Result<(), Self::Error>;
+}
+
+impl<D: DisplayDriver> FrameSink for D {
+    type Error = ();
+
+    fn write_rect(&mut self, rect: Rect, pixels: impl Iterator<Item = u16>) -> Result<(), ()> {
+        self.set_window(
+            rect.x as u16,
+            rect.y as u16,
+            (rect.x + rect.w - 1) as u16,
+            (rect.y + rect.h - 1) as u16,
+        )?;
+        self.write_pixels(pixels)
+    }
+}
+
+// Sends the part of a little-endian RGB565 `frame`, `width` pixels wide, that
+// lies under `rect`. `rect` must already be clipped to the frame.
+pub fn send<S: FrameSink>(
+    sink: &mut S,
+    frame: &[u8],
+    width: usize,
+    rect: Rect,
+) -> Result<(), S::Error> {
+    let (x0, y0) = (rect.x as usize, rect.y as usize);
+    let (x1, y1) = (x0 + rect.w as usize, y0 + rect.h as usize);
+    let pixels = (y0..y1).flat_map(|y| {
+        (x0..x1).map(move |x| {
+            let i = (y * width + x) * 2;
+            u16::from_le_bytes([frame[i], frame[i + 1]])
+        })
+    });
+
+    sink.write_rect(rect, pixels)
+}
+
+// Records everything sent to it as the panel would get it, a window and then
+// its pixels high byte first, so tests can check the exact bytes rendering
+// code puts out
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockSink {
+    pub windows: Vec<Rect>,
+    pub bytes: Vec<u8>,
+}
+
+#[cfg(test)]
+impl FrameSink for MockSink {
+    type Error = ();
+
+    fn write_rect(&mut self, rect: Rect, pixels: impl Iterator<Item = u16>) -> Result<(), ()> {
+        let start = self.bytes.len();
+        self.windows.push(rect);
+        self.bytes.extend(pixels.flat_map(u16::to_be_bytes));
+        assert_eq!(self.bytes.len() - start, (rect.w * rect.h) as usize * 2);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::draw::{self, Sprite};
+    use crate::limits::Limits;
+
+    const WIDTH: usize = Limits::SCREEN_WIDTH;
+    const WHITE: u16 = 0xFFFF;
+
+    fn frame() -> Vec<u8> {
+        vec![0; Limits::FRAME_BYTES]
+    }
+
+    // A 3x3 plus
+    const PLUS: Sprite = Sprite {
+        w: 3,
+        h: 3,
+        bits: 0b010_111_010,
+    };
+
+    #[test]
+    fn send_writes_the_rect_row_by_row() {
+        let mut frame = frame();
+        draw::pixel(&mut frame, 2, 1, 0x1234);
+        draw::pixel(&mut frame, 3, 2, 0xABCD);
+
+        let mut sink = MockSink::default();
+        let rect = Rect {
+            x: 2,
+            y: 1,
+            w: 2,
+            h: 2,
+        };
+        send(&mut sink, &frame, WIDTH, rect).unwrap();
+        assert_eq!(sink.windows, [rect]);
+        assert_eq!(sink.bytes, [0x12, 0x34, 0, 0, 0, 0, 0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn a_sprite_comes_out_where_it_was_drawn() {
+        let mut frame = frame();
+        draw::blit_sprite(&mut frame, 10, 20, &PLUS, WHITE, false);
+
+        let mut sink = MockSink::default();
+        let rect = Rect {
+            x: 10,
+            y: 20,
+            w: 3,
+            h: 3,
+        };
+        send(&mut sink, &frame, WIDTH, rect).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(sink.bytes, [
+            0, 0, 0xFF, 0xFF, 0, 0,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0, 0, 0xFF, 0xFF, 0, 0,
+        ]);
+
+        // One pixel along, and the plus is too
+        let mut sink = MockSink::default();
+        send(&mut sink, &frame, WIDTH, Rect { x: 11, ..rect }).unwrap();
+        assert_eq!(sink.bytes[..6], [0xFF, 0xFF, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn a_sprite_off_the_corner_sends_only_what_is_on_screen() {
+        let mut frame = frame();
+        draw::blit_sprite(&mut frame, -1, -1, &PLUS, WHITE, false);
+
+        let rect = Rect {
+            x: -1,
+            y: -1,
+            w: 3,
+            h: 3,
+        }
+        .clip(WIDTH as i32, WIDTH as i32)
+        .unwrap();
+        let mut sink = MockSink::default();
+        send(&mut sink, &frame, WIDTH, rect).unwrap();
+        assert_eq!(
+            sink.windows,
+            [Rect {
+                x: 0,
+                y: 0,
+                w: 2,
+                h: 2
+            }]
+        );
+        assert_eq!(sink.bytes, [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0]);
+    }
+}
@@ -0,0 +1,384 @@
+use crate::limits::Limits;
+use crate::ring::{Overflow, RingBuffer};
+use embedded_hal_1::pwm::SetDutyCycle;
+
+// A frequency of 0 is a rest
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    pub freq: u16,
+    pub duration_ms: u16,
+}
+
+const fn note(freq: u16, duration_ms: u16) -> Note {
+    Note { freq, duration_ms }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SfxId {
+    Fire,
+    Hit,
+    Explode,
+    PowerUp,
+    GameOver,
+}
+
+// Which of the synth's channels each effect plays on. The two squares are
+// for anything with a pitch, the noise for anything that goes bang.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Square1,
+    Square2,
+    Noise,
+}
+
+pub const CHANNELS: usize = 3;
+
+// Short bright downward chirp
+const FIRE: &[Note] = &[note(1760, 15), note(1318, 15), note(988, 20)];
+const HIT: &[Note] = &[note(440, 20), note(0, 10), note(330, 30)];
+// Noise that darkens as it dies away, noticeably longer than a shot. On the
+// noise channel the frequency is how fast the noise changes, so it's a lot
+// higher than a pitch would be.
+const EXPLODE: &[Note] = &[
+    note(6000, 30),
+    note(4000, 30),
+    note(3000, 30),
+    note(2000, 40),
+    note(1400, 40),
+    note(900, 60),
+];
+const POWER_UP: &[Note] = &[note(523, 50), note(659, 50), note(784, 50), note(1047, 100)];
+const GAME_OVER: &[Note] = &[
+    note(392, 200),
+    note(0, 50),
+    note(330, 200),
+    note(0, 50),
+    note(262, 400),
+];
+
+impl SfxId {
+    pub fn notes(self) -> &'static [Note] {
+        match self {
+            SfxId::Fire => FIRE,
+            SfxId::Hit => HIT,
+            SfxId::Explode => EXPLODE,
+            SfxId::PowerUp => POWER_UP,
+            SfxId::GameOver => GAME_OVER,
+        }
+    }
+
+    pub fn channel(self) -> Channel {
+        match self {
+            SfxId::Fire | SfxId::GameOver => Channel::Square1,
+            SfxId::Hit | SfxId::PowerUp => Channel::Square2,
+            SfxId::Explode => Channel::Noise,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "fire" => Some(SfxId::Fire),
+            "hit" => Some(SfxId::Hit),
+            "explode" => Some(SfxId::Explode),
+            "powerup" => Some(SfxId::PowerUp),
+            "gameover" => Some(SfxId::GameOver),
+            _ => None,
+        }
+    }
+}
+
+pub const QUEUE_LEN: usize = Limits::NOTES;
+
+pub struct NoteQueue {
+    notes: RingBuffer<Note, QUEUE_LEN>,
+}
+
+impl NoteQueue {
+    pub const fn new() -> Self {
+        NoteQueue {
+            notes: RingBuffer::new(note(0, 0), Overflow::RejectNew),
+        }
+    }
+
+    // All or nothing, so that a sound effect never plays with its tail cut off
+    pub fn extend(&mut self, notes: &[Note]) -> bool {
+        if notes.len() > self.notes.free() {
+            return false;
+        }
+        for &n in notes {
+            self.notes.push(n);
+        }
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<Note> {
+        self.notes.pop()
+    }
+}
+
+// Samples a second. The PWM carrier runs at 16 MHz / 256, four times that,
+// so it's well above hearing.
+pub const SAMPLE_HZ: u32 = 15_625;
+pub const SAMPLE_PERIOD_US: u32 = 1_000_000 / SAMPLE_HZ;
+// The PWM's counter top, so a duty of 0..=MAX_DUTY is one sample
+pub const MAX_DUTY: u16 = 255;
+// How far each channel swings either side of the middle. Three of them at
+// once still fit in a sample.
+const VOLUME: i16 = 40;
+
+// One channel's notes, one after another, and where it is in the current one
+struct Voice {
+    queue: NoteQueue,
+    // Samples left of the current note
+    left: u32,
+    // Phase accumulator, a full turn being 2^32, and how far it goes each
+    // sample
+    phase: u32,
+    step: u32,
+    // Whether the current note is a rest, or there isn't one
+    silent: bool,
+}
+
+impl Voice {
+    const fn new() -> Self {
+        Voice {
+            queue: NoteQueue::new(),
+            left: 0,
+            phase: 0,
+            step: 0,
+            silent: true,
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.left == 0 && self.silent
+    }
+
+    // Moves on to the next note once the current one's over. Returns true
+    // on a sample where the phase went round.
+    fn tick(&mut self) -> bool {
+        if self.left == 0 {
+            match self.queue.pop() {
+                Some(n) => {
+                    self.left = (n.duration_ms as u32 * SAMPLE_HZ / 1000).max(1);
+                    self.step = (n.freq as u64 * (1 << 32) / SAMPLE_HZ as u64) as u32;
+                    self.silent = n.freq == 0;
+                }
+                None => {
+                    self.silent = true;
+                    return false;
+                }
+            }
+        }
+        self.left -= 1;
+        let (phase, wrapped) = self.phase.overflowing_add(self.step);
+        self.phase = phase;
+        wrapped
+    }
+}
+
+// Two square waves and a noise channel, mixed in software a sample at a time
+// and played out through the PWM's duty. Each channel has a note queue of
+// its own, so a shot doesn't cut off an explosion. Sample timing is left to
+// whoever owns the synth, which calls `sample` every SAMPLE_PERIOD_US for as
+// long as it returns true.
+pub struct Synth<P: SetDutyCycle> {
+    pwm: P,
+    voices: [Voice; CHANNELS],
+    // 15 bit LFSR, clocked every time the noise channel's phase goes round
+    lfsr: u16,
+    // How far each channel swings, VOLUME scaled by the volume setting
+    swing: i16,
+    playing: bool,
+}
+
+impl<P: SetDutyCycle> Synth<P> {
+    pub fn new(mut pwm: P) -> Self {
+        pwm.set_duty_cycle_fully_off().ok();
+
+        Synth {
+            pwm,
+            voices: [Voice::new(), Voice::new(), Voice::new()],
+            lfsr: 1,
+            swing: VOLUME,
+            playing: false,
+        }
+    }
+
+    // Queues `notes` on `channel`, all or nothing, so a sound never plays
+    // with its tail cut off. Returns true if sampling needs to be started.
+    pub fn play(&mut self, channel: Channel, notes: &[Note]) -> bool {
+        self.voices[channel as usize].queue.extend(notes) && !self.playing
+    }
+
+    // In percent, taking effect from the next sample
+    pub fn set_volume(&mut self, percent: u8) {
+        self.swing = VOLUME * percent.min(100) as i16 / 100;
+    }
+
+    pub fn play_sfx(&mut self, sfx: SfxId) -> bool {
+        self.play(sfx.channel(), sfx.notes())
+    }
+
+    // Works out and plays the next sample. Returns false, with the output
+    // left off, once every channel has run out of notes.
+    pub fn sample(&mut self) -> bool {
+        let mut level: i16 = 0;
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            let wrapped = voice.tick();
+            let high = if i == Channel::Noise as usize {
+                if wrapped {
+                    let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                    self.lfsr = (self.lfsr >> 1) | (bit << 14);
+                }
+                self.lfsr & 1 != 0
+            } else {
+                voice.phase < 1 << 31
+            };
+            if !voice.silent {
+                level += if high { self.swing } else { -self.swing };
+            }
+        }
+
+        self.playing = !self.voices.iter().all(Voice::is_idle);
+        if !self.playing {
+            self.pwm.set_duty_cycle_fully_off().ok();
+            return false;
+        }
+        let middle = (MAX_DUTY / 2) as i16;
+        let duty = (middle + level).clamp(0, MAX_DUTY as i16) as u16;
+        self.pwm.set_duty_cycle(duty).ok();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use embedded_hal_1::pwm::ErrorType;
+
+    const ALL: [SfxId; 5] = [
+        SfxId::Fire,
+        SfxId::Hit,
+        SfxId::Explode,
+        SfxId::PowerUp,
+        SfxId::GameOver,
+    ];
+    const NAMES: [&str; 5] = ["fire", "hit", "explode", "powerup", "gameover"];
+
+    // Remembers the last duty it was given, None being off
+    struct Pwm(Option<u16>);
+
+    impl ErrorType for Pwm {
+        type Error = Infallible;
+    }
+
+    impl SetDutyCycle for Pwm {
+        fn max_duty_cycle(&self) -> u16 {
+            MAX_DUTY
+        }
+
+        fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+            self.0 = if duty == 0 { None } else { Some(duty) };
+            Ok(())
+        }
+    }
+
+    fn total_ms(notes: &[Note]) -> u32 {
+        notes.iter().map(|n| n.duration_ms as u32).sum()
+    }
+
+    #[test]
+    fn every_effect_is_well_formed() {
+        for sfx in ALL {
+            let notes = sfx.notes();
+            assert!(!notes.is_empty(), "{:?} has no notes", sfx);
+            assert!(notes.len() <= QUEUE_LEN, "{:?} can't ever be queued", sfx);
+            assert!(
+                notes.iter().all(|n| n.duration_ms > 0),
+                "{:?} has an empty note",
+                sfx
+            );
+            // Starting or ending on a rest would only be silence
+            assert_ne!(notes[0].freq, 0, "{:?} starts with a rest", sfx);
+            assert_ne!(notes[notes.len() - 1].freq, 0, "{:?} ends with a rest", sfx);
+            // Short enough not to hold up the next one on its channel
+            assert!(total_ms(notes) <= 1_000, "{:?} is too long", sfx);
+            // Under half the sample rate, or the phase goes round more than
+            // once a sample
+            assert!(notes.iter().all(|n| (n.freq as u32) < SAMPLE_HZ / 2));
+        }
+    }
+
+    #[test]
+    fn fire_is_distinct_from_explode() {
+        assert_ne!(SfxId::Fire.notes(), SfxId::Explode.notes());
+        assert_ne!(SfxId::Fire.channel(), SfxId::Explode.channel());
+        assert!(total_ms(SfxId::Explode.notes()) > total_ms(SfxId::Fire.notes()));
+        assert_eq!(SfxId::Explode.channel(), Channel::Noise);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for (&sfx, name) in ALL.iter().zip(NAMES) {
+            assert_eq!(SfxId::from_name(name), Some(sfx));
+        }
+        assert_eq!(SfxId::from_name("Fire"), None);
+        assert_eq!(SfxId::from_name(""), None);
+    }
+
+    #[test]
+    fn queue_is_all_or_nothing() {
+        let mut queue = NoteQueue::new();
+        let fill = [note(440, 10); QUEUE_LEN - 1];
+        assert!(queue.extend(&fill));
+        assert!(!queue.extend(&[note(1, 1), note(2, 2)]));
+        assert!(queue.extend(&[note(3, 3)]));
+        for _ in 0..QUEUE_LEN - 1 {
+            assert_eq!(queue.pop(), Some(note(440, 10)));
+        }
+        assert_eq!(queue.pop(), Some(note(3, 3)));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn synth_plays_an_effect_out_and_stops() {
+        let mut synth = Synth::new(Pwm(None));
+        assert!(synth.play_sfx(SfxId::Fire));
+        let expected = SfxId::Fire
+            .notes()
+            .iter()
+            .map(|n| (n.duration_ms as u32 * SAMPLE_HZ / 1000).max(1))
+            .sum::<u32>();
+        let mut samples = 0;
+        while synth.sample() {
+            samples += 1;
+            assert!(synth.pwm.0.is_some());
+            // Already sampling, so no need to start again
+            assert!(!synth.play(Channel::Square1, &[]));
+        }
+        assert_eq!(samples, expected);
+        assert_eq!(synth.pwm.0, None);
+        // Stopped, so the next one needs starting
+        assert!(synth.play_sfx(SfxId::Hit));
+    }
+
+    #[test]
+    fn volume_scales_the_swing() {
+        let mut synth = Synth::new(Pwm(None));
+        synth.set_volume(0);
+        synth.play(Channel::Square1, &[note(440, 10)]);
+        assert!(synth.sample());
+        assert_eq!(synth.pwm.0, Some(MAX_DUTY / 2));
+
+        synth.set_volume(100);
+        assert!(synth.sample());
+        let duty = synth.pwm.0.unwrap() as i16;
+        assert_eq!((duty - (MAX_DUTY / 2) as i16).abs(), VOLUME);
+
+        // Anything over 100 is 100
+        synth.set_volume(250);
+        assert_eq!(synth.swing, VOLUME);
+    }
+}
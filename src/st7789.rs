@@ -0,0 +1,127 @@
+use crate::display::DisplayDriver;
+use embedded_hal_1::delay::DelayNs;
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_1::spi::SpiBus;
+use st7735_lcd::Orientation;
+
+// Enough of an ST7789 driver for what the firmware does with a panel: its
+// init sequence, then the same CASET, RASET and RAMWR as the ST7735, which is
+// what lets the pipeline, the scanline plasma and the panic screen write to
+// either one through SPIM1's registers. MADCTL's rotation bits are the same
+// too, so Orientation carries over as it is.
+//
+// There's no reset pin here either; display::reset pulses it.
+
+const SWRESET: u8 = 0x01;
+const SLPOUT: u8 = 0x11;
+const NORON: u8 = 0x13;
+const INVOFF: u8 = 0x20;
+const INVON: u8 = 0x21;
+const DISPON: u8 = 0x29;
+const CASET: u8 = 0x2A;
+const RASET: u8 = 0x2B;
+const RAMWR: u8 = 0x2C;
+const MADCTL: u8 = 0x36;
+const COLMOD: u8 = 0x3A;
+
+// MADCTL's BGR bit
+const BGR: u8 = 0x08;
+// 16 bits a pixel over SPI
+const RGB565: u8 = 0x55;
+
+pub struct St7789<SPI, DC> {
+    spi: SPI,
+    dc: DC,
+    rgb: bool,
+    inverted: bool,
+    offset: (u16, u16),
+}
+
+impl<SPI, DC> St7789<SPI, DC>
+where
+    SPI: SpiBus,
+    DC: OutputPin,
+{
+    pub fn new(spi: SPI, dc: DC, rgb: bool, inverted: bool) -> Self {
+        St7789 {
+            spi,
+            dc,
+            rgb,
+            inverted,
+            offset: (0, 0),
+        }
+    }
+
+    fn command(&mut self, command: u8, params: &[u8]) -> Result<(), ()> {
+        self.dc.set_low().map_err(|_| ())?;
+        self.spi.write(&[command]).map_err(|_| ())?;
+        self.dc.set_high().map_err(|_| ())?;
+        if !params.is_empty() {
+            self.spi.write(params).map_err(|_| ())?;
+        }
+        Ok(())
+    }
+
+    fn madctl(&self, orientation: &Orientation) -> u8 {
+        match self.rgb {
+            true => *orientation as u8,
+            false => *orientation as u8 | BGR,
+        }
+    }
+}
+
+impl<SPI, DC> DisplayDriver for St7789<SPI, DC>
+where
+    SPI: SpiBus,
+    DC: OutputPin,
+{
+    fn init(&mut self, delay: &mut impl DelayNs) -> Result<(), ()> {
+        self.command(SWRESET, &[])?;
+        delay.delay_ms(150);
+        self.command(SLPOUT, &[])?;
+        delay.delay_ms(120);
+        self.command(COLMOD, &[RGB565])?;
+        let madctl = self.madctl(&Orientation::Portrait);
+        self.command(MADCTL, &[madctl])?;
+        self.command(if self.inverted { INVON } else { INVOFF }, &[])?;
+        self.command(NORON, &[])?;
+        self.command(DISPON, &[])?;
+        delay.delay_ms(20);
+        Ok(())
+    }
+
+    fn set_orientation(&mut self, orientation: &Orientation) -> Result<(), ()> {
+        let madctl = self.madctl(orientation);
+        self.command(MADCTL, &[madctl])
+    }
+
+    fn set_offset(&mut self, x: u16, y: u16) {
+        self.offset = (x, y);
+    }
+
+    fn set_window(&mut self, x0: u16, y0: u16, x1: u16, y1: u16) -> Result<(), ()> {
+        let (dx, dy) = self.offset;
+        let [x0h, x0l] = (x0 + dx).to_be_bytes();
+        let [x1h, x1l] = (x1 + dx).to_be_bytes();
+        let [y0h, y0l] = (y0 + dy).to_be_bytes();
+        let [y1h, y1l] = (y1 + dy).to_be_bytes();
+        self.command(CASET, &[x0h, x0l, x1h, x1l])?;
+        self.command(RASET, &[y0h, y0l, y1h, y1l])
+    }
+
+    fn write_pixels(&mut self, pixels: impl IntoIterator<Item = u16>) -> Result<(), ()> {
+        self.command(RAMWR, &[])?;
+        let mut buffer = [0; 32];
+        let mut len = 0;
+        for pixel in pixels {
+            buffer[len..len + 2].copy_from_slice(&pixel.to_be_bytes());
+            len += 2;
+            if len == buffer.len() {
+                self.spi.write(&buffer).map_err(|_| ())?;
+                len = 0;
+            }
+        }
+        self.spi.write(&buffer[..len]).map_err(|_| ())?;
+        self.spi.flush().map_err(|_| ())
+    }
+}
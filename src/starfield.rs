@@ -0,0 +1,67 @@
+use crate::limits::Limits;
+use crate::rng::Rng;
+
+const WIDTH: i32 = Limits::SCREEN_WIDTH as i32;
+const HEIGHT: i32 = Limits::SCREEN_HEIGHT as i32;
+
+// Scroll offsets are in 1/16 px
+const SUBPIXELS: i32 = 16;
+// Forward motion per frame, before each layer's multiplier
+const SCROLL_SPEED: i32 = 4;
+
+// The offset every layer scrolls by, scaled by its own speed. `y` wraps after
+// one screen height of the slowest possible layer, which lines up for every
+// layer since speeds are whole multiples.
+pub struct Scroll {
+    x: i32,
+    y: i32,
+}
+
+impl Scroll {
+    pub const fn new() -> Self {
+        Scroll { x: 0, y: 0 }
+    }
+
+    // `lean` is how far off center the ship is, in pixels. The layers shift
+    // sideways against it, the near one more than the far one.
+    pub fn advance(&mut self, lean: i32) {
+        self.y = (self.y + SCROLL_SPEED) % (HEIGHT * SUBPIXELS);
+        self.x = -lean * SUBPIXELS / 4;
+    }
+}
+
+// A layer of single-pixel stars that wrap around the screen
+pub struct Starfield<const N: usize> {
+    stars: [(u8, u8); N],
+    speed: i32,
+    color: u16,
+}
+
+impl<const N: usize> Starfield<N> {
+    pub fn new(rng: &mut Rng, speed: i32, color: u16) -> Self {
+        let mut stars = [(0, 0); N];
+        for star in stars.iter_mut() {
+            *star = (rng.rand_range(0, WIDTH) as u8, rng.rand_range(0, HEIGHT) as u8);
+        }
+        Starfield {
+            stars,
+            speed,
+            color,
+        }
+    }
+
+    // Draws over `frame`, which is little-endian RGB565 at the screen size.
+    // Layers are drawn back to front, so the far one goes first.
+    pub fn draw(&self, frame: &mut [u8], scroll: &Scroll) {
+        let dx = scroll.x * self.speed / SUBPIXELS;
+        let dy = scroll.y * self.speed / SUBPIXELS;
+        let color = self.color.to_le_bytes();
+
+        for &(x, y) in &self.stars {
+            let x = (x as i32 + dx).rem_euclid(WIDTH) as usize;
+            let y = (y as i32 + dy).rem_euclid(HEIGHT) as usize;
+            let i = (y * WIDTH as usize + x) * 2;
+            frame[i..i + 2].copy_from_slice(&color);
+        }
+    }
+}
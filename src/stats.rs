@@ -0,0 +1,72 @@
+// Everything worth knowing about how rendering is keeping up, updated once per
+// frame by the frame task and read by whatever wants to show it
+#[derive(Clone, Copy, Debug)]
+pub struct RenderStats {
+    pub frames: u32,
+    // Frames started over the last full second
+    pub fps: u16,
+    // How long the last frame took to render and send
+    pub frame_time_us: u32,
+    // How much of that was sending. The rest was rendering, and whatever's
+    // left of the frame period is idle.
+    pub flush_time_us: u32,
+    // Share of the last full second the frame task was busy, in percent
+    pub load_percent: u8,
+    // Frames that ran over the quality budget, since boot
+    pub dropped_frames: u32,
+    // Sent to the panel by the last frame
+    pub spi_bytes: u32,
+    window_us: u32,
+    window_busy_us: u32,
+    window_frames: u16,
+}
+
+impl RenderStats {
+    pub const fn new() -> Self {
+        RenderStats {
+            frames: 0,
+            fps: 0,
+            frame_time_us: 0,
+            flush_time_us: 0,
+            load_percent: 0,
+            dropped_frames: 0,
+            spi_bytes: 0,
+            window_us: 0,
+            window_busy_us: 0,
+            window_frames: 0,
+        }
+    }
+
+    // `period_us` is from the start of this frame to the start of the next
+    pub fn record(
+        &mut self,
+        frame_time_us: u32,
+        flush_time_us: u32,
+        period_us: u32,
+        spi_bytes: u32,
+        dropped: bool,
+    ) {
+        self.frames = self.frames.wrapping_add(1);
+        self.frame_time_us = frame_time_us;
+        self.flush_time_us = flush_time_us;
+        self.spi_bytes = spi_bytes;
+        if dropped {
+            self.dropped_frames = self.dropped_frames.wrapping_add(1);
+        }
+
+        self.window_frames += 1;
+        self.window_us += period_us;
+        self.window_busy_us += frame_time_us;
+        if self.window_us >= 1_000_000 {
+            self.fps = self.window_frames;
+            self.load_percent = (self.window_busy_us as u64 * 100 / self.window_us as u64) as u8;
+            self.window_frames = 0;
+            self.window_us %= 1_000_000;
+            self.window_busy_us = 0;
+        }
+    }
+
+    pub fn render_time_us(&self) -> u32 {
+        self.frame_time_us.saturating_sub(self.flush_time_us)
+    }
+}
@@ -0,0 +1,125 @@
+use embedded_hal::adc::{Channel, OneShot};
+use nrf52840_hal::saadc::{Saadc, SaadcConfig};
+use nrf52840_pac::SAADC;
+
+// With the default SAADC config (14 bit, VDD/4 reference, 1/4 gain) a
+// potentiometer between GND and VDD spans the whole 0..=FULL_SCALE range
+const FULL_SCALE: i32 = (1 << 14) - 1;
+const CALIBRATION_SAMPLES: i32 = 16;
+
+#[derive(Clone, Copy)]
+pub struct StickConfig {
+    // Raw counts either side of the calibrated center that read as zero
+    pub deadzone: u16,
+    pub invert_x: bool,
+    pub invert_y: bool,
+}
+
+impl StickConfig {
+    pub const DEFAULT: StickConfig = StickConfig {
+        deadzone: 600,
+        invert_x: false,
+        invert_y: false,
+    };
+}
+
+struct Axis {
+    center: i32,
+    invert: bool,
+}
+
+impl Axis {
+    // Maps a raw sample to -127..=127. Each side of the center is scaled
+    // separately, since a stick resting off mid-scale has less travel on one
+    // side than the other.
+    fn normalize(&self, raw: i16, deadzone: i32) -> i8 {
+        let offset = raw as i32 - self.center;
+        let travel = if offset < 0 {
+            self.center
+        } else {
+            FULL_SCALE - self.center
+        };
+
+        let magnitude = offset.abs() - deadzone;
+        if magnitude <= 0 || travel <= deadzone {
+            return 0;
+        }
+
+        let scaled = (magnitude * 127 / (travel - deadzone)).min(127);
+        let value = if offset < 0 { -scaled } else { scaled };
+        if self.invert {
+            -value as i8
+        } else {
+            value as i8
+        }
+    }
+}
+
+// A two-axis analog thumbstick on two SAADC inputs
+pub struct Stick<X, Y> {
+    saadc: Saadc,
+    x_pin: X,
+    y_pin: Y,
+    x: Axis,
+    y: Axis,
+    deadzone: i32,
+}
+
+impl<X, Y> Stick<X, Y>
+where
+    X: Channel<Saadc, ID = u8>,
+    Y: Channel<Saadc, ID = u8>,
+{
+    // The stick must be left alone while this runs, since its current
+    // position becomes the center
+    pub fn new(saadc: SAADC, x_pin: X, y_pin: Y, config: StickConfig) -> Self {
+        let mut stick = Stick {
+            saadc: Saadc::new(saadc, SaadcConfig::default()),
+            x_pin,
+            y_pin,
+            x: Axis {
+                center: FULL_SCALE / 2,
+                invert: config.invert_x,
+            },
+            y: Axis {
+                center: FULL_SCALE / 2,
+                invert: config.invert_y,
+            },
+            deadzone: config.deadzone as i32,
+        };
+        stick.calibrate();
+        stick
+    }
+
+    pub fn calibrate(&mut self) {
+        let (mut x, mut y) = (0, 0);
+        for _ in 0..CALIBRATION_SAMPLES {
+            let (raw_x, raw_y) = self.sample();
+            x += raw_x as i32;
+            y += raw_y as i32;
+        }
+        self.x.center = x / CALIBRATION_SAMPLES;
+        self.y.center = y / CALIBRATION_SAMPLES;
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        (self.x.center, self.y.center)
+    }
+
+    // Blocks for two conversions
+    pub fn read(&mut self) -> (i8, i8) {
+        let (raw_x, raw_y) = self.sample();
+        (
+            self.x.normalize(raw_x, self.deadzone),
+            self.y.normalize(raw_y, self.deadzone),
+        )
+    }
+
+    fn sample(&mut self) -> (i16, i16) {
+        // A failed conversion reads as centered rather than full deflection
+        let x = self.saadc.read(&mut self.x_pin).unwrap_or(self.x.center as i16);
+        let y = self.saadc.read(&mut self.y_pin).unwrap_or(self.y.center as i16);
+        // Single-ended readings can dip slightly below zero
+        (x.max(0), y.max(0))
+    }
+}
@@ -0,0 +1,443 @@
+use crate::crc;
+use crate::error::FirmwareError;
+use nrf52840_pac::NVMC;
+
+pub const PAGE_SIZE: usize = 4096;
+
+// The reserved 4 KiB pages at the very end of flash, well clear of the
+// program, or with `dfu` just below the bootloader. memory-swd.x and
+// memory-dfu.x keep the program out of them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Page {
+    Checkpoint,
+    Scores,
+    Totals,
+    ScoresSpare,
+    Settings,
+    SettingsSpare,
+    Panic,
+}
+
+// Where the pages end
+#[cfg(not(feature = "dfu"))]
+const TOP: usize = 0x0010_0000;
+#[cfg(feature = "dfu")]
+const TOP: usize = 0x000E_0000;
+
+impl Page {
+    fn addr(self) -> usize {
+        let below = match self {
+            Page::Checkpoint => 1,
+            Page::Scores => 2,
+            Page::Totals => 3,
+            Page::ScoresSpare => 4,
+            Page::Settings => 5,
+            Page::SettingsSpare => 6,
+            Page::Panic => 7,
+        };
+        TOP - below * PAGE_SIZE
+    }
+}
+
+// Records that are saved over and over, each kept in a pair of pages and
+// appended to rather than rewritten, so a page is only erased once it's full
+// of them. Settings are saved every time they change, and that would wear
+// out a page rewritten each time in a few months of fiddling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Journal {
+    Settings,
+    Scores,
+}
+
+// Starts every record, then the record layout's version
+const JOURNAL_MAGIC: [u8; 2] = *b"PJ";
+const JOURNAL_FORMAT: u8 = 1;
+
+// What a journal needs of flash: Storage, or plain memory in the tests
+pub trait Pages {
+    fn page(&self, page: Page) -> &[u8; PAGE_SIZE];
+    fn erase(&mut self, page: Page);
+    // Writes `bytes`, a whole number of words, `offset` into `page`, where
+    // it has to have been erased, and checks they read back
+    fn program(&mut self, page: Page, offset: usize, bytes: &[u8]) -> Result<(), FirmwareError>;
+}
+
+impl Journal {
+    fn pages(self) -> [Page; 2] {
+        match self {
+            Journal::Settings => [Page::Settings, Page::SettingsSpare],
+            Journal::Scores => [Page::Scores, Page::ScoresSpare],
+        }
+    }
+
+    // Header (magic, format, length, sequence number), the bytes padded to a
+    // word, then the CRC
+    const fn slot_len(len: usize) -> usize {
+        8 + len.div_ceil(4) * 4 + 4
+    }
+
+    // Appends `bytes` as the journal's newest record. Each record is a
+    // header, the bytes padded to a word and a CRC32, and goes in the next
+    // erased slot of the page the newest record is in. Once that page is
+    // full the other one is erased and started on, so the newest record to
+    // have been written whole is always somewhere. A record that doesn't
+    // read back is left for `latest` to skip, and goes again in the next
+    // slot.
+    pub fn append(self, flash: &mut impl Pages, bytes: &[u8]) -> Result<(), FirmwareError> {
+        if bytes.len() > u8::MAX as usize {
+            return Err(FirmwareError::TooLong);
+        }
+        match self.append_once(flash, bytes) {
+            Ok(()) => Ok(()),
+            Err(_) => self.append_once(flash, bytes),
+        }
+    }
+
+    fn append_once(self, flash: &mut impl Pages, bytes: &[u8]) -> Result<(), FirmwareError> {
+        let slot_len = Journal::slot_len(bytes.len());
+        let (pages, newest) = (self.pages(), self.newest(flash, bytes.len()));
+        let (page, seq) = newest.map_or((pages[0], 0), |(page, seq, _)| (page, seq + 1));
+
+        let data = flash.page(page);
+        let used = data
+            .chunks_exact(slot_len)
+            .rposition(|slot| slot.iter().any(|&byte| byte != 0xFF))
+            .map_or(0, |last| last + 1);
+        let (page, slot) = if (used + 1) * slot_len <= PAGE_SIZE {
+            (page, used)
+        } else {
+            let other = if page == pages[0] { pages[1] } else { pages[0] };
+            flash.erase(other);
+            (other, 0)
+        };
+
+        let mut record = [0xFF; Journal::slot_len(u8::MAX as usize)];
+        let record = &mut record[..slot_len];
+        record[..2].copy_from_slice(&JOURNAL_MAGIC);
+        record[2] = JOURNAL_FORMAT;
+        record[3] = bytes.len() as u8;
+        record[4..8].copy_from_slice(&seq.to_le_bytes());
+        record[8..8 + bytes.len()].copy_from_slice(bytes);
+        let crc = crc::crc32(&record[..slot_len - 4]);
+        record[slot_len - 4..].copy_from_slice(&crc.to_le_bytes());
+        flash.program(page, slot * slot_len, record)
+    }
+
+    // The bytes of the newest record that's `len` long and checks out, if
+    // there is one
+    pub fn latest(self, flash: &impl Pages, len: usize) -> Option<&[u8]> {
+        self.newest(flash, len).map(|(_, _, bytes)| bytes)
+    }
+
+    fn newest(self, flash: &impl Pages, len: usize) -> Option<(Page, u32, &[u8])> {
+        let slot_len = Journal::slot_len(len);
+        let mut newest = None;
+        for page in self.pages() {
+            for slot in flash.page(page).chunks_exact(slot_len) {
+                let (head, crc) = slot.split_at(slot_len - 4);
+                if head[..2] != JOURNAL_MAGIC
+                    || head[2] != JOURNAL_FORMAT
+                    || head[3] as usize != len
+                    || crc::crc32(head).to_le_bytes() != crc
+                {
+                    continue;
+                }
+                let seq = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+                if newest.is_none_or(|(_, newest, _)| seq > newest) {
+                    newest = Some((page, seq, &head[8..8 + len]));
+                }
+            }
+        }
+        newest
+    }
+}
+
+// Raw access to the reserved flash pages. Flash can only be written from 1s
+// to 0s, so every write erases the whole page first. Both erasing (~85 ms)
+// and writing stall the CPU, so this only belongs in places where a hiccup
+// doesn't matter.
+pub struct Storage {
+    nvmc: NVMC,
+}
+
+impl Storage {
+    pub fn new(nvmc: NVMC) -> Self {
+        Storage { nvmc }
+    }
+
+    // Erased flash reads back as all 0xFF
+    pub fn read(&self, page: Page) -> &'static [u8; PAGE_SIZE] {
+        // Flash is memory mapped, and the page isn't in use by anything else
+        unsafe { &*(page.addr() as *const [u8; PAGE_SIZE]) }
+    }
+
+    pub fn erase(&mut self, page: Page) {
+        self.nvmc.config.write(|w| w.wen().een());
+        self.nvmc
+            .erasepage()
+            .write(|w| unsafe { w.bits(page.addr() as u32) });
+        self.wait_ready();
+        self.nvmc.config.write(|w| w.wen().ren());
+    }
+
+    // Replaces the page contents with `bytes`, padded with 0xFF up to a whole
+    // word. Anything longer than a page is dropped. A page that doesn't read
+    // back as written is erased and written once more before giving up.
+    pub fn write(&mut self, page: Page, bytes: &[u8]) -> Result<(), FirmwareError> {
+        let bytes = &bytes[..bytes.len().min(PAGE_SIZE)];
+        for _ in 0..2 {
+            self.erase(page);
+
+            self.nvmc.config.write(|w| w.wen().wen());
+            for (i, chunk) in bytes.chunks(4).enumerate() {
+                let mut word = [0xFF; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                let addr = (page.addr() + i * 4) as *mut u32;
+                // Only ever inside our own page, which was just erased
+                unsafe { addr.write_volatile(u32::from_le_bytes(word)) };
+                self.wait_ready();
+            }
+            self.nvmc.config.write(|w| w.wen().ren());
+            if self.read(page).starts_with(bytes) {
+                return Ok(());
+            }
+        }
+        Err(FirmwareError::Flash)
+    }
+
+    fn wait_ready(&self) {
+        while self.nvmc.ready.read().ready().is_busy() {}
+    }
+}
+
+impl Pages for Storage {
+    fn page(&self, page: Page) -> &[u8; PAGE_SIZE] {
+        self.read(page)
+    }
+
+    fn erase(&mut self, page: Page) {
+        Storage::erase(self, page)
+    }
+
+    fn program(&mut self, page: Page, offset: usize, bytes: &[u8]) -> Result<(), FirmwareError> {
+        let addr = page.addr() + offset;
+        self.nvmc.config.write(|w| w.wen().wen());
+        for (i, word) in bytes.chunks_exact(4).enumerate() {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            // Only ever inside one of our own pages
+            unsafe { ((addr + i * 4) as *mut u32).write_volatile(word) };
+            self.wait_ready();
+        }
+        self.nvmc.config.write(|w| w.wen().ren());
+        // Memory mapped, like `read`
+        let written = unsafe { core::slice::from_raw_parts(addr as *const u8, bytes.len()) };
+        match written == bytes {
+            true => Ok(()),
+            false => Err(FirmwareError::Flash),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pages of flash in memory, which like the real thing only clear bits
+    // when programmed
+    struct Ram {
+        pages: Vec<[u8; PAGE_SIZE]>,
+        erases: Vec<Page>,
+        // How many of the next programs are torn, their last word left
+        // erased, as if the power went mid-write
+        tear: usize,
+    }
+
+    impl Ram {
+        fn new() -> Self {
+            Ram {
+                pages: vec![[0xFF; PAGE_SIZE]; 7],
+                erases: Vec::new(),
+                tear: 0,
+            }
+        }
+    }
+
+    impl Pages for Ram {
+        fn page(&self, page: Page) -> &[u8; PAGE_SIZE] {
+            &self.pages[page as usize]
+        }
+
+        fn erase(&mut self, page: Page) {
+            self.pages[page as usize] = [0xFF; PAGE_SIZE];
+            self.erases.push(page);
+        }
+
+        fn program(
+            &mut self,
+            page: Page,
+            offset: usize,
+            bytes: &[u8],
+        ) -> Result<(), FirmwareError> {
+            assert!(offset.is_multiple_of(4) && bytes.len().is_multiple_of(4));
+            let torn = self.tear > 0;
+            self.tear = self.tear.saturating_sub(1);
+            let end = if torn { bytes.len() - 4 } else { bytes.len() };
+            let data = &mut self.pages[page as usize][offset..offset + bytes.len()];
+            for (byte, &new) in data.iter_mut().zip(&bytes[..end]) {
+                *byte &= new;
+            }
+            match &data[..] == bytes {
+                true => Ok(()),
+                false => Err(FirmwareError::Flash),
+            }
+        }
+    }
+
+    const LEN: usize = 20;
+    // Slots of LEN bytes that fit in a page
+    const SLOTS: u32 = (PAGE_SIZE / Journal::slot_len(LEN)) as u32;
+
+    fn record(n: u32) -> [u8; LEN] {
+        let mut record = [0xA5; LEN];
+        record[..4].copy_from_slice(&n.to_le_bytes());
+        record
+    }
+
+    fn latest(ram: &Ram) -> Option<u32> {
+        let bytes = Journal::Settings.latest(ram, LEN)?;
+        assert_eq!(bytes[4..], record(0)[4..]);
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    #[test]
+    fn an_erased_journal_has_nothing_in_it() {
+        let ram = Ram::new();
+        assert_eq!(Journal::Settings.latest(&ram, LEN), None);
+        assert_eq!(Journal::Scores.latest(&ram, 0), None);
+    }
+
+    #[test]
+    fn the_newest_record_is_the_one_read_back() {
+        let mut ram = Ram::new();
+        for n in 0..5 {
+            Journal::Settings.append(&mut ram, &record(n)).unwrap();
+            assert_eq!(latest(&ram), Some(n));
+        }
+        // One slot after another, without erasing anything
+        let slot_len = Journal::slot_len(LEN);
+        let used = ram.pages[Page::Settings as usize].chunks_exact(slot_len);
+        assert_eq!(used.filter(|slot| slot[0] != 0xFF).count(), 5);
+        assert!(ram.erases.is_empty());
+    }
+
+    #[test]
+    fn records_count_up_in_sequence() {
+        let mut ram = Ram::new();
+        for n in 0..3 {
+            Journal::Settings.append(&mut ram, &record(n)).unwrap();
+        }
+        let slot_len = Journal::slot_len(LEN);
+        let page = &ram.pages[Page::Settings as usize];
+        let seqs: Vec<u32> = page
+            .chunks_exact(slot_len)
+            .take(3)
+            .map(|slot| u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]))
+            .collect();
+        assert_eq!(seqs, [0, 1, 2]);
+        assert_eq!(page[..4], [b'P', b'J', JOURNAL_FORMAT, LEN as u8]);
+    }
+
+    #[test]
+    fn a_full_page_falls_over_to_the_spare_and_back() {
+        let mut ram = Ram::new();
+        for n in 0..SLOTS * 5 / 2 {
+            Journal::Settings.append(&mut ram, &record(n)).unwrap();
+            assert_eq!(latest(&ram), Some(n), "after {}", n);
+        }
+        assert_eq!(ram.erases, [Page::SettingsSpare, Page::Settings]);
+        // Half way through the first page again, having started it over
+        let first = &ram.pages[Page::Settings as usize];
+        let slot_len = Journal::slot_len(LEN);
+        assert_eq!(
+            first
+                .chunks_exact(slot_len)
+                .filter(|slot| slot[0] != 0xFF)
+                .count() as u32,
+            SLOTS / 2
+        );
+    }
+
+    #[test]
+    fn the_last_record_survives_power_going_at_the_turn_of_a_page() {
+        let mut ram = Ram::new();
+        for n in 0..SLOTS {
+            Journal::Settings.append(&mut ram, &record(n)).unwrap();
+        }
+        // Both tries at the first record in the spare are torn
+        ram.tear = 2;
+        assert_eq!(
+            Journal::Settings.append(&mut ram, &record(SLOTS)),
+            Err(FirmwareError::Flash)
+        );
+        assert_eq!(latest(&ram), Some(SLOTS - 1));
+    }
+
+    #[test]
+    fn a_torn_record_is_skipped_and_written_again() {
+        let mut ram = Ram::new();
+        Journal::Settings.append(&mut ram, &record(0)).unwrap();
+        ram.tear = 1;
+        Journal::Settings.append(&mut ram, &record(1)).unwrap();
+        assert_eq!(latest(&ram), Some(1));
+        Journal::Settings.append(&mut ram, &record(2)).unwrap();
+        assert_eq!(latest(&ram), Some(2));
+
+        // Torn both times it's tried, and it's the one before. The next
+        // goes in after both.
+        ram.tear = 2;
+        Journal::Settings.append(&mut ram, &record(3)).unwrap_err();
+        assert_eq!(latest(&ram), Some(2));
+        Journal::Settings.append(&mut ram, &record(4)).unwrap();
+        assert_eq!(latest(&ram), Some(4));
+    }
+
+    #[test]
+    fn a_corrupt_record_is_passed_over_for_the_one_before() {
+        let mut ram = Ram::new();
+        for n in 0..3 {
+            Journal::Settings.append(&mut ram, &record(n)).unwrap();
+        }
+        let slot_len = Journal::slot_len(LEN);
+        for i in 0..slot_len {
+            let mut bad = Ram::new();
+            bad.pages = ram.pages.clone();
+            bad.pages[Page::Settings as usize][2 * slot_len + i] ^= 0x04;
+            // A flip in the sequence number can still check out as another
+            // record, but never as a newer one
+            assert_eq!(latest(&bad), Some(1), "byte {}", i);
+        }
+    }
+
+    #[test]
+    fn records_of_another_length_or_journal_are_ignored() {
+        let mut ram = Ram::new();
+        Journal::Settings.append(&mut ram, &record(7)).unwrap();
+        assert_eq!(Journal::Settings.latest(&ram, LEN - 4), None);
+        assert_eq!(Journal::Scores.latest(&ram, LEN), None);
+        Journal::Scores.append(&mut ram, &[1, 2, 3]).unwrap();
+        assert_eq!(Journal::Scores.latest(&ram, 3), Some(&[1, 2, 3][..]));
+        assert_eq!(latest(&ram), Some(7));
+    }
+
+    #[test]
+    fn a_record_too_long_for_its_header_is_refused() {
+        let mut ram = Ram::new();
+        assert_eq!(
+            Journal::Scores.append(&mut ram, &[0; 256]),
+            Err(FirmwareError::TooLong)
+        );
+        assert!(ram.pages.iter().all(|page| page.iter().all(|&b| b == 0xFF)));
+        Journal::Scores.append(&mut ram, &[0; 255]).unwrap();
+        assert_eq!(Journal::Scores.latest(&ram, 255), Some(&[0; 255][..]));
+    }
+}
@@ -0,0 +1,106 @@
+use crate::draw::{self, Sprite};
+
+// A 3x5 font for scores, initials and anything else that needs saying on
+// screen: capitals, digits and a little punctuation. Lowercase comes out as
+// capitals and anything else blank. A `&str` goes in as its bytes, as in
+// `text::draw(frame, x, y, b"SCORE: 120", color)`.
+
+pub const GLYPH_W: i32 = 3;
+pub const GLYPH_H: i32 = 5;
+// Glyph plus the gap after it
+pub const ADVANCE: i32 = GLYPH_W + 1;
+pub const LINE_H: i32 = GLYPH_H + 1;
+
+// Rows top first, each 3 bits with the leftmost pixel highest
+const fn glyph(rows: [u16; 5]) -> u16 {
+    (rows[0] << 12) | (rows[1] << 9) | (rows[2] << 6) | (rows[3] << 3) | rows[4]
+}
+
+const LETTERS: [u16; 26] = [
+    glyph([0b010, 0b101, 0b111, 0b101, 0b101]),
+    glyph([0b110, 0b101, 0b110, 0b101, 0b110]),
+    glyph([0b011, 0b100, 0b100, 0b100, 0b011]),
+    glyph([0b110, 0b101, 0b101, 0b101, 0b110]),
+    glyph([0b111, 0b100, 0b110, 0b100, 0b111]),
+    glyph([0b111, 0b100, 0b110, 0b100, 0b100]),
+    glyph([0b011, 0b100, 0b101, 0b101, 0b011]),
+    glyph([0b101, 0b101, 0b111, 0b101, 0b101]),
+    glyph([0b111, 0b010, 0b010, 0b010, 0b111]),
+    glyph([0b001, 0b001, 0b001, 0b101, 0b010]),
+    glyph([0b101, 0b101, 0b110, 0b101, 0b101]),
+    glyph([0b100, 0b100, 0b100, 0b100, 0b111]),
+    glyph([0b101, 0b111, 0b111, 0b101, 0b101]),
+    glyph([0b110, 0b101, 0b101, 0b101, 0b101]),
+    glyph([0b010, 0b101, 0b101, 0b101, 0b010]),
+    glyph([0b110, 0b101, 0b110, 0b100, 0b100]),
+    glyph([0b010, 0b101, 0b101, 0b110, 0b011]),
+    glyph([0b110, 0b101, 0b110, 0b101, 0b101]),
+    glyph([0b011, 0b100, 0b010, 0b001, 0b110]),
+    glyph([0b111, 0b010, 0b010, 0b010, 0b010]),
+    glyph([0b101, 0b101, 0b101, 0b101, 0b111]),
+    glyph([0b101, 0b101, 0b101, 0b101, 0b010]),
+    glyph([0b101, 0b101, 0b111, 0b111, 0b101]),
+    glyph([0b101, 0b101, 0b010, 0b101, 0b101]),
+    glyph([0b101, 0b101, 0b010, 0b010, 0b010]),
+    glyph([0b111, 0b001, 0b010, 0b100, 0b111]),
+];
+
+const DIGITS: [u16; 10] = [
+    glyph([0b111, 0b101, 0b101, 0b101, 0b111]),
+    glyph([0b010, 0b110, 0b010, 0b010, 0b111]),
+    glyph([0b110, 0b001, 0b010, 0b100, 0b111]),
+    glyph([0b110, 0b001, 0b010, 0b001, 0b110]),
+    glyph([0b101, 0b101, 0b111, 0b001, 0b001]),
+    glyph([0b111, 0b100, 0b110, 0b001, 0b110]),
+    glyph([0b011, 0b100, 0b111, 0b101, 0b111]),
+    glyph([0b111, 0b001, 0b010, 0b010, 0b010]),
+    glyph([0b111, 0b101, 0b111, 0b101, 0b111]),
+    glyph([0b111, 0b101, 0b111, 0b001, 0b110]),
+];
+
+fn bits(c: u8) -> u16 {
+    match c {
+        b'A'..=b'Z' => LETTERS[(c - b'A') as usize],
+        b'a'..=b'z' => LETTERS[(c - b'a') as usize],
+        b'0'..=b'9' => DIGITS[(c - b'0') as usize],
+        b'-' => glyph([0, 0, 0b111, 0, 0]),
+        b'_' => glyph([0, 0, 0, 0, 0b111]),
+        b'.' => glyph([0, 0, 0, 0, 0b010]),
+        b':' => glyph([0, 0b010, 0, 0b010, 0]),
+        b'!' => glyph([0b010, 0b010, 0b010, 0, 0b010]),
+        b'?' => glyph([0b110, 0b001, 0b010, 0, 0b010]),
+        b'/' => glyph([0b001, 0b001, 0b010, 0b100, 0b100]),
+        b'%' => glyph([0b101, 0b001, 0b010, 0b100, 0b101]),
+        b'+' => glyph([0, 0b010, 0b111, 0b010, 0]),
+        _ => 0,
+    }
+}
+
+// Draws `text` with its top left at (x, y) and returns how wide it came out
+pub fn draw(frame: &mut [u8], x: i32, y: i32, text: &[u8], color: u16) -> i32 {
+    for (i, &c) in text.iter().enumerate() {
+        let sprite = Sprite {
+            w: GLYPH_W,
+            h: GLYPH_H,
+            bits: bits(c) as u32,
+        };
+        draw::blit_sprite(frame, x + i as i32 * ADVANCE, y, &sprite, color, false);
+    }
+    width(text.len())
+}
+
+pub fn width(chars: usize) -> i32 {
+    (chars as i32 * ADVANCE - 1).max(0)
+}
+
+// Right aligned into `out`, keeping the lowest digits if it doesn't fit
+pub fn digits(out: &mut [u8], mut n: u32) {
+    for (i, c) in out.iter_mut().rev().enumerate() {
+        *c = if n > 0 || i == 0 {
+            b'0' + (n % 10) as u8
+        } else {
+            b' '
+        };
+        n /= 10;
+    }
+}
@@ -0,0 +1,165 @@
+use crate::color::rgb565;
+use crate::gfx::{Camera, TileMap, TileSet};
+use crate::limits::Limits;
+use crate::rng::Rng;
+
+const WIDTH: usize = Limits::SCREEN_WIDTH;
+const HEIGHT: usize = Limits::SCREEN_HEIGHT;
+
+pub const TILE: usize = 8;
+pub const MAP_W: usize = WIDTH / TILE;
+// Four screens tall before it repeats
+pub const MAP_H: usize = HEIGHT * 4 / TILE;
+
+// Scroll position is in 1/16 px, the same as the starfield
+const SUBPIXELS: i32 = 16;
+const SCROLL_SPEED: i32 = 6;
+
+type Tile = [u16; TILE * TILE];
+
+// The atlas is drawn as text, one character per pixel, and turned into
+// RGB565 when the firmware is compiled
+const fn tile(art: &[u8; TILE * TILE]) -> Tile {
+    let mut pixels = [0; TILE * TILE];
+    let mut i = 0;
+    while i < pixels.len() {
+        pixels[i] = match art[i] {
+            b'#' => rgb565(10, 20, 12),
+            b'+' => rgb565(16, 34, 18),
+            b'o' => rgb565(5, 10, 7),
+            b'*' => rgb565(24, 48, 24),
+            _ => rgb565(1, 2, 3),
+        };
+        i += 1;
+    }
+    pixels
+}
+
+const ATLAS: [Tile; 5] = [
+    // Open space
+    tile(
+        b"\
+        ........\
+        ........\
+        ........\
+        ........\
+        ........\
+        ........\
+        ........\
+        ........",
+    ),
+    // A lone star
+    tile(
+        b"\
+        ........\
+        ........\
+        ........\
+        .....*..\
+        ........\
+        ........\
+        ........\
+        ........",
+    ),
+    // Hull plating
+    tile(
+        b"\
+        ++++++++\
+        +######o\
+        +#o####o\
+        +######o\
+        +######o\
+        +####o#o\
+        +######o\
+        oooooooo",
+    ),
+    // Plating with a vent
+    tile(
+        b"\
+        ++++++++\
+        +######o\
+        +#oooo#o\
+        +######o\
+        +#oooo#o\
+        +######o\
+        +######o\
+        oooooooo",
+    ),
+    // Rock
+    tile(
+        b"\
+        ........\
+        ..o##o..\
+        .o####o.\
+        o##+###o\
+        o#####+o\
+        .o####o.\
+        ..o##o..\
+        ........",
+    ),
+];
+
+const SPACE: u8 = 0;
+const STAR: u8 = 1;
+const PLATE: u8 = 2;
+const VENT: u8 = 3;
+const ROCK: u8 = 4;
+
+// A vertically scrolling background of ATLAS tiles. The map wraps, so the top
+// row follows on from the bottom one.
+pub struct Tilemap {
+    map: [u8; MAP_W * MAP_H],
+    // In 1/16 px, counting down the map
+    y: i32,
+}
+
+impl Tilemap {
+    pub fn new(rng: &mut Rng) -> Self {
+        let mut map = [SPACE; MAP_W * MAP_H];
+        for (row, tiles) in map.chunks_exact_mut(MAP_W).enumerate() {
+            // Every so often a strip of hull passes underneath
+            let hull = row % 12 < 3;
+            for tile in tiles.iter_mut() {
+                let roll = rng.below(16);
+                *tile = if hull {
+                    if roll < 2 {
+                        VENT
+                    } else {
+                        PLATE
+                    }
+                } else {
+                    match roll {
+                        0 => ROCK,
+                        1..=2 => STAR,
+                        _ => SPACE,
+                    }
+                };
+            }
+        }
+        Tilemap { map, y: 0 }
+    }
+
+    pub fn advance(&mut self) {
+        let height = (MAP_H * TILE) as i32 * SUBPIXELS;
+        // Content moves down the screen, so the view moves up the map
+        self.y = (self.y - SCROLL_SPEED).rem_euclid(height);
+    }
+
+    // Covers all of `frame`, which is little-endian RGB565 at the screen
+    // size
+    pub fn draw(&self, frame: &mut [u8]) {
+        let map = TileMap {
+            tiles: TileSet {
+                size: TILE,
+                pixels: ATLAS.as_flattened(),
+            },
+            map: &self.map,
+            w: MAP_W,
+            h: MAP_H,
+        };
+        let camera = Camera {
+            x: 0,
+            y: self.y / SUBPIXELS,
+        };
+        map.draw(frame, camera);
+    }
+}
@@ -0,0 +1,514 @@
+use nrf52840_hal::gpio::{Floating, Input, Pin};
+use nrf52840_hal::gpiote::Gpiote;
+use nrf52840_hal::twim::{self, Twim};
+use nrf52840_pac::TWIM0;
+
+// Which way up the board is held, from an LIS3DH accelerometer on TWIM0.
+// Only the two axes in the plane of the panel say anything about that: when
+// the board lies face up or face down gravity goes straight through them,
+// and the reading gives no direction at all, so the last orientation stays.
+//
+// The same readings also pick out a sharp shake, which restarts the game,
+// and, smoothed out, give how far the board's tipped either way, which can
+// steer the ship.
+//
+// The sensor says when it has a reading on its INT1 line, which goes high
+// until the reading's been taken, so nothing has to keep asking it.
+
+const ADDRESS: u8 = 0x18;
+const WHO_AM_I: u8 = 0x0F;
+const CTRL_REG1: u8 = 0x20;
+const CTRL_REG3: u8 = 0x22;
+const CTRL_REG4: u8 = 0x23;
+const OUT_X_L: u8 = 0x28;
+// Set on a register address to read on through the ones after it
+const AUTO_INCREMENT: u8 = 0x80;
+
+// Index into the settings' orientations for each way down, as the sensor
+// sees it: -Y, +X, +Y, -X. This is for an accelerometer mounted with Y
+// pointing up the panel in portrait and X to its right; another mounting
+// needs these shuffled.
+const ORIENTATIONS: [u8; 4] = [0, 1, 2, 3];
+
+#[derive(Clone, Copy)]
+pub struct TiltConfig {
+    // The in-plane pull has to be at least this much, in mg, before it
+    // counts. Below it the board is too close to flat.
+    pub min_mg: i32,
+    // How far the stronger in-plane axis has to beat the other, in percent,
+    // to be taken as down. 73 puts the line 15 degrees either side of the
+    // diagonal, so a board held near 45 degrees doesn't keep flipping.
+    pub margin_percent: i32,
+    // Orientation readings in a row that have to agree before it changes
+    pub settle: u8,
+}
+
+impl TiltConfig {
+    pub const DEFAULT: TiltConfig = TiltConfig {
+        min_mg: 400,
+        margin_percent: 73,
+        settle: 4,
+    };
+}
+
+#[derive(Clone, Copy)]
+pub struct ShakeConfig {
+    // How strong the pull has to get, in mg over all three axes, to count
+    // as a shake. Held still it's 1000, and the sensor reads up to 2 g an
+    // axis.
+    pub threshold_mg: u32,
+    // Readings in a row that have to be over it, so a knock on the table
+    // doesn't count. They come 50 times a second.
+    pub samples: u8,
+    // Readings after a shake before another can count
+    pub cooldown: u8,
+}
+
+impl ShakeConfig {
+    pub const DEFAULT: ShakeConfig = ShakeConfig {
+        threshold_mg: 1800,
+        samples: 3,
+        cooldown: 75,
+    };
+}
+
+// How tipping the board turns into steering, with `tilt on`
+#[derive(Clone, Copy)]
+pub struct SteerConfig {
+    // Degrees either way from level that don't steer at all, so a board
+    // held by hand doesn't drift
+    pub deadzone_deg: i16,
+    // Degrees from level for a full turn
+    pub full_deg: i16,
+    pub invert: bool,
+}
+
+impl SteerConfig {
+    pub const DEFAULT: SteerConfig = SteerConfig {
+        deadzone_deg: 4,
+        full_deg: 30,
+        invert: false,
+    };
+}
+
+// Length of the acceleration, in mg
+pub fn magnitude([x, y, z]: [i32; 3]) -> u32 {
+    let square = |a: i32| a.unsigned_abs() * a.unsigned_abs();
+    (square(x) + square(y) + square(z)).isqrt()
+}
+
+// Degrees, -90 to 90, of a pull of `along` against one of `across`, which
+// can't be negative. atan(t) is close enough to 45t + 15.64t(1 - t) degrees
+// for t up to 1, within a quarter of a degree, and above that it's 90 less
+// the angle the other way round.
+fn angle(along: i32, across: i32) -> i16 {
+    let (a, b) = (along.abs(), across.max(0));
+    if a == 0 && b == 0 {
+        return 0;
+    }
+    // t in 1024ths
+    let (t, steep) = match a <= b {
+        true => (a * 1024 / b, false),
+        false => (b * 1024 / a, true),
+    };
+    let degrees = (4500 * t + 1564 * t * (1024 - t) / 1024) / 102_400;
+    let degrees = if steep { 90 - degrees } else { degrees };
+    match along < 0 {
+        true => -degrees as i16,
+        false => degrees as i16,
+    }
+}
+
+// How far the board's tipped, in degrees from level: `roll` is how far the
+// sensor's X axis points down from the horizontal and `pitch` its Y axis.
+// Either way up they're the same, so face up or face down doesn't matter.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Angles {
+    pub roll: i16,
+    pub pitch: i16,
+}
+
+impl Angles {
+    pub fn new([x, y, z]: [i32; 3]) -> Self {
+        let square = |a: i32| a.unsigned_abs() * a.unsigned_abs();
+        Angles {
+            roll: angle(x, (square(y) + square(z)).isqrt() as i32),
+            pitch: angle(y, (square(x) + square(z)).isqrt() as i32),
+        }
+    }
+}
+
+// -127 to 127 for the ship, from the tip across the panel in the
+// orientation it's in, so tipping its right edge down goes right
+pub fn steer(angles: Angles, orientation: u8, config: &SteerConfig) -> i8 {
+    let direction = ORIENTATIONS
+        .iter()
+        .position(|&o| o == orientation)
+        .unwrap_or(0);
+    // The panel's right is a quarter turn on from whichever way is down
+    let sideways = match direction {
+        0 => angles.roll,
+        1 => angles.pitch,
+        2 => -angles.roll,
+        _ => -angles.pitch,
+    };
+    let magnitude = (sideways.abs() - config.deadzone_deg) as i32;
+    let travel = (config.full_deg - config.deadzone_deg) as i32;
+    if magnitude <= 0 || travel <= 0 {
+        return 0;
+    }
+    let scaled = (magnitude * 127 / travel).min(127) as i8;
+    match (sideways < 0) != config.invert {
+        true => -scaled,
+        false => scaled,
+    }
+}
+
+// Smooths readings out over the last few, which is what steering wants.
+// Shakes are picked out of the raw readings, since this would flatten them.
+pub struct Smoothing {
+    // In 1 << SMOOTHING_SHIFT'ths of a mg
+    sums: [i32; 3],
+    primed: bool,
+}
+
+// From 50 readings a second this is a time constant of 80 ms
+const SMOOTHING_SHIFT: u32 = 2;
+
+impl Smoothing {
+    pub const fn new() -> Self {
+        Smoothing {
+            sums: [0; 3],
+            primed: false,
+        }
+    }
+
+    pub fn update(&mut self, reading: [i32; 3]) -> [i32; 3] {
+        for (sum, &value) in self.sums.iter_mut().zip(reading.iter()) {
+            *sum = match self.primed {
+                true => *sum + value - (*sum >> SMOOTHING_SHIFT),
+                false => value << SMOOTHING_SHIFT,
+            };
+        }
+        self.primed = true;
+        self.sums.map(|sum| sum >> SMOOTHING_SHIFT)
+    }
+}
+
+pub struct Shake {
+    config: ShakeConfig,
+    over: u8,
+    cooling: u8,
+}
+
+impl Shake {
+    pub fn new(config: ShakeConfig) -> Self {
+        Shake {
+            config,
+            over: 0,
+            cooling: 0,
+        }
+    }
+
+    // Whether this reading finishes a shake
+    pub fn update(&mut self, reading: [i32; 3]) -> bool {
+        if self.cooling > 0 {
+            self.cooling -= 1;
+            return false;
+        }
+        if magnitude(reading) < self.config.threshold_mg {
+            self.over = 0;
+            return false;
+        }
+        self.over = self.over.saturating_add(1);
+        if self.over < self.config.samples {
+            return false;
+        }
+        self.over = 0;
+        self.cooling = self.config.cooldown;
+        true
+    }
+}
+
+// Turns readings into an orientation, keeping the current one unless
+// another is clearly and steadily down
+pub struct AutoOrientation {
+    config: TiltConfig,
+    candidate: u8,
+    agreed: u8,
+}
+
+impl AutoOrientation {
+    pub fn new(config: TiltConfig) -> Self {
+        AutoOrientation {
+            config,
+            candidate: 0,
+            agreed: 0,
+        }
+    }
+
+    // The orientation to use, given the one in use now and the acceleration
+    // in mg along the panel's two axes
+    pub fn update(&mut self, current: u8, x: i32, y: i32) -> u8 {
+        let down = match self.down(x, y) {
+            Some(down) if down != current => down,
+            _ => {
+                self.agreed = 0;
+                return current;
+            }
+        };
+        if down != self.candidate {
+            self.candidate = down;
+            self.agreed = 0;
+        }
+        self.agreed = self.agreed.saturating_add(1);
+        if self.agreed < self.config.settle {
+            return current;
+        }
+        self.agreed = 0;
+        down
+    }
+
+    fn down(&self, x: i32, y: i32) -> Option<u8> {
+        let c = &self.config;
+        let (ax, ay) = (x.abs(), y.abs());
+        let (strong, weak) = (ax.max(ay), ax.min(ay));
+        if strong < c.min_mg || strong * 100 < weak * (100 + c.margin_percent) {
+            return None;
+        }
+        let direction = match (ax > ay, x > 0, y > 0) {
+            (false, _, false) => 0,
+            (true, true, _) => 1,
+            (false, _, true) => 2,
+            (true, false, _) => 3,
+        };
+        Some(ORIENTATIONS[direction])
+    }
+}
+
+pub struct Accelerometer {
+    twim: Twim<TWIM0>,
+}
+
+impl Accelerometer {
+    // Returns None if there's no LIS3DH answering on the bus
+    pub fn new(mut twim: Twim<TWIM0>) -> Option<Self> {
+        // EasyDMA can only send from RAM, so nothing here can be a literal
+        // that ends up in flash
+        let mut id = [0];
+        let reg = [WHO_AM_I];
+        twim.write_then_read(ADDRESS, &reg, &mut id).ok()?;
+        if id[0] != 0x33 {
+            return None;
+        }
+        // 50 Hz with all three axes, data ready out on INT1, then block
+        // updates so the two halves of a reading always match, at 12 bits
+        // and +-2 g
+        let rate = [CTRL_REG1, 0x47];
+        twim.write(ADDRESS, &rate).ok()?;
+        let ready = [CTRL_REG3, 0x10];
+        twim.write(ADDRESS, &ready).ok()?;
+        let scale = [CTRL_REG4, 0x88];
+        twim.write(ADDRESS, &scale).ok()?;
+        let mut sensor = Accelerometer { twim };
+        // INT1 may already be high from before a reset, and it only goes low
+        // again once there's been a read, without which there's no edge
+        sensor.read()?;
+        Some(sensor)
+    }
+
+    // X, Y and Z in mg
+    pub fn read(&mut self) -> Option<[i32; 3]> {
+        let mut raw = [0; 6];
+        let reg = [OUT_X_L | AUTO_INCREMENT];
+        self.twim.write_then_read(ADDRESS, &reg, &mut raw).ok()?;
+        // Left justified, 1 mg per step at 12 bits
+        let axis = |i: usize| (i16::from_le_bytes([raw[i], raw[i + 1]]) >> 4) as i32;
+        Some([axis(0), axis(2), axis(4)])
+    }
+}
+
+// What one reading says
+pub struct Sample {
+    pub orientation: u8,
+    pub shaken: bool,
+    pub angles: Angles,
+}
+
+pub struct Tilt {
+    sensor: Option<Accelerometer>,
+    _ready: Pin<Input<Floating>>,
+    auto: AutoOrientation,
+    shake: Shake,
+    smoothing: Smoothing,
+    // Readings taken, since only every ORIENTATION_EVERY-th one goes to the
+    // orientation
+    reads: u8,
+}
+
+// A shake is over in a tenth of a second or so, so the sensor's read more
+// often than the orientation needs
+const ORIENTATION_EVERY: u8 = 6;
+
+impl Tilt {
+    // Uses GPIOTE channel 3 for INT1, which the sensor drives itself
+    pub fn new(
+        twim: TWIM0,
+        pins: twim::Pins,
+        ready: Pin<Input<Floating>>,
+        gpiote: &Gpiote,
+        config: TiltConfig,
+        shake: ShakeConfig,
+    ) -> Self {
+        gpiote
+            .channel3()
+            .input_pin(&ready)
+            .lo_to_hi()
+            .enable_interrupt();
+        let sensor = Accelerometer::new(Twim::new(twim, pins, twim::Frequency::K100));
+        if sensor.is_none() {
+            log_warn!("No accelerometer found, orientation won't follow tilt");
+        }
+        Tilt {
+            sensor,
+            _ready: ready,
+            auto: AutoOrientation::new(config),
+            shake: Shake::new(shake),
+            smoothing: Smoothing::new(),
+            reads: 0,
+        }
+    }
+
+    // The orientation to use instead of `current`, whether the board's just
+    // been shaken and how it's tipped, or None if the sensor couldn't be read
+    pub fn sample(&mut self, current: u8) -> Option<Sample> {
+        let reading = self.sensor.as_mut()?.read()?;
+        let smoothed = self.smoothing.update(reading);
+        self.reads = (self.reads + 1) % ORIENTATION_EVERY;
+        let orientation = match (self.reads, smoothed) {
+            (0, [x, y, _]) => self.auto.update(current, x, y),
+            _ => current,
+        };
+        Some(Sample {
+            orientation,
+            shaken: self.shake.update(reading),
+            angles: Angles::new(smoothed),
+        })
+    }
+}
+
+// Called from the GPIOTE interrupt. Returns true if the sensor has a
+// reading waiting.
+pub fn on_gpiote(gpiote: &Gpiote) -> bool {
+    if !gpiote.channel3().is_event_triggered() {
+        return false;
+    }
+    gpiote.channel3().reset_events();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STILL: [i32; 3] = [0, 0, 1000];
+    const JOLT: [i32; 3] = [1500, -1200, 1000];
+
+    // The readings that finish a shake, by index
+    fn shakes(shake: &mut Shake, readings: &[[i32; 3]]) -> Vec<usize> {
+        readings
+            .iter()
+            .enumerate()
+            .filter(|&(_, &reading)| shake.update(reading))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn magnitude_is_over_all_three_axes() {
+        assert_eq!(magnitude([0, 0, 0]), 0);
+        assert_eq!(magnitude(STILL), 1000);
+        assert_eq!(magnitude([0, -1000, 0]), 1000);
+        assert_eq!(magnitude([300, 400, 0]), 500);
+        assert_eq!(magnitude([-2000, 2000, -2000]), 3464);
+        // Full scale on every axis doesn't overflow
+        assert_eq!(magnitude([32767, -32768, 32767]), 56754);
+    }
+
+    #[test]
+    fn holding_still_is_never_a_shake() {
+        let mut shake = Shake::new(ShakeConfig::DEFAULT);
+        assert!(shakes(&mut shake, &[STILL; 500]).is_empty());
+    }
+
+    #[test]
+    fn a_shake_takes_readings_in_a_row_over_the_threshold() {
+        let mut shake = Shake::new(ShakeConfig::DEFAULT);
+        assert!(magnitude(JOLT) >= ShakeConfig::DEFAULT.threshold_mg);
+        // A knock, and another, with a still reading between
+        assert!(shakes(&mut shake, &[JOLT, JOLT, STILL, JOLT, JOLT, STILL]).is_empty());
+        assert_eq!(shakes(&mut shake, &[JOLT, JOLT, JOLT]), [2]);
+    }
+
+    #[test]
+    fn the_threshold_is_inclusive_and_configurable() {
+        let config = ShakeConfig {
+            threshold_mg: 1500,
+            samples: 1,
+            cooldown: 0,
+        };
+        let mut shake = Shake::new(config);
+        assert!(!shake.update([1499, 0, 0]));
+        assert!(shake.update([1500, 0, 0]));
+        assert!(shake.update([0, 0, -1500]));
+    }
+
+    #[test]
+    fn one_shake_only_counts_once() {
+        let ShakeConfig {
+            samples, cooldown, ..
+        } = ShakeConfig::DEFAULT;
+        let mut shake = Shake::new(ShakeConfig::DEFAULT);
+        let readings = vec![JOLT; 150];
+        let first = samples as usize - 1;
+        let every = (samples + cooldown) as usize;
+        assert_eq!(shakes(&mut shake, &readings), [first, first + every]);
+    }
+
+    #[test]
+    fn steering_has_a_deadzone_and_a_limit() {
+        let config = SteerConfig::DEFAULT;
+        let tipped = |deg| Angles {
+            roll: deg,
+            pitch: 0,
+        };
+        assert_eq!(steer(tipped(0), 0, &config), 0);
+        assert_eq!(steer(tipped(config.deadzone_deg), 0, &config), 0);
+        assert!(steer(tipped(config.deadzone_deg + 1), 0, &config) > 0);
+        assert_eq!(steer(tipped(config.full_deg), 0, &config), 127);
+        assert_eq!(steer(tipped(-90), 0, &config), -127);
+        // Upside down it goes the other way
+        assert_eq!(steer(tipped(90), 2, &config), -127);
+        let invert = SteerConfig {
+            invert: true,
+            ..config
+        };
+        assert_eq!(steer(tipped(90), 0, &invert), -127);
+    }
+
+    #[test]
+    fn angles_come_out_in_degrees() {
+        assert_eq!(Angles::new(STILL), Angles::default());
+        assert_eq!(Angles::new([1000, 0, 0]).roll, 90);
+        assert_eq!(Angles::new([0, -1000, 0]).pitch, -90);
+        for &(x, z, deg) in &[
+            (1000, 1000, 45),
+            (500, 866, 30),
+            (866, 500, 60),
+            (174, 985, 10),
+        ] {
+            let roll = Angles::new([x, 0, z]).roll;
+            assert!((roll - deg).abs() <= 1, "{} for {}", roll, deg);
+        }
+    }
+}
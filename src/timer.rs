@@ -1,11 +1,49 @@
-use nrf52840_pac::{TIMER0, TIMER1, TIMER2, TIMER3};
+use nrf52840_pac::{TIMER0, TIMER2, TIMER3};
 
+// The compare channels every timer has. CC[0] is where `now` captures the
+// counter, and TIMER0-2 stop at CC[3], so these are the ones left to fire.
+// Only One is in use so far, by each of the timers
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    One = 1,
+    Two,
+    Three,
+}
+
+// Ticks are a microsecond each
+pub const fn millis(ms: u32) -> u32 {
+    ms * 1000
+}
+
+// The count `ticks` on from `from`, wrapping
+const fn after(from: u32, ticks: u32) -> u32 {
+    from.wrapping_add(ticks)
+}
+
+// Counts run in 32 bits, and wrap every 71 minutes. All of the arithmetic on
+// them wraps, so it's only the distance between two that has to fit.
 pub trait Timer {
     fn init(&mut self);
-    fn fire_at(&mut self, id: usize, at: u32);
-    fn stop(&mut self, id: usize);
+    // Holds the count where it is, so the timer stops keeping the HF clock
+    // on, until `resume` or `fire_at`
+    fn pause(&mut self);
+    fn resume(&mut self);
+    // Fires once, `at` from now
+    fn fire_at(&mut self, compare: Compare, at: u32) {
+        self.resume();
+        let later = after(self.now(), at);
+        self.fire_on(compare, later);
+    }
+    // Fires once, when the count gets to `count`
+    fn fire_on(&mut self, compare: Compare, count: u32);
+    // Fires `interval` after the compare last fired, rather than after now,
+    // so a repeating compare doesn't drift by however late it was handled
+    fn fire_again(&mut self, compare: Compare, interval: u32);
+    fn stop(&mut self, compare: Compare);
     fn now(&self) -> u32;
-    fn ack_compare_event(&mut self, id: usize);
+    fn is_compare_event(&self, compare: Compare) -> bool;
+    fn ack_compare_event(&mut self, compare: Compare);
 }
 
 macro_rules! impl_timer {
@@ -20,68 +58,91 @@ macro_rules! impl_timer {
                 self.tasks_start.write(|w| w.tasks_start().set_bit());
             }
 
-            fn fire_at(&mut self, id: usize, at: u32) {
-                assert!(id > 0 && id <= 5);
-                let now = self.now();
-                let later = now.wrapping_add(at);
-                self.cc[id].write(|w| unsafe { w.bits(later) });
+            fn pause(&mut self) {
+                self.tasks_stop.write(|w| w.tasks_stop().set_bit());
+            }
+
+            fn resume(&mut self) {
+                self.tasks_start.write(|w| w.tasks_start().set_bit());
+            }
+
+            fn fire_on(&mut self, compare: Compare, count: u32) {
+                let id = compare as usize;
+                self.cc[id].write(|w| unsafe { w.bits(count) });
                 self.events_compare[id].reset();
-                match id {
-                    1 => {
-                        self.intenset.write(|w| w.compare1().set());
-                    }
-                    2 => {
-                        self.intenset.write(|w| w.compare2().set());
-                    }
-                    3 => {
-                        self.intenset.write(|w| w.compare3().set());
-                    }
-                    /* 4 => {
-                     *     self.intenset.write(|w| w.compare4().set());
-                     * }
-                     * 5 => {
-                     *     self.intenset.write(|w| w.compare5().set());
-                     * } */
-                    _ => (),
+                match compare {
+                    Compare::One => self.intenset.write(|w| w.compare1().set()),
+                    Compare::Two => self.intenset.write(|w| w.compare2().set()),
+                    Compare::Three => self.intenset.write(|w| w.compare3().set()),
                 }
             }
 
-            fn stop(&mut self, id: usize) {
-                assert!(id > 0 && id <= 5);
-                match id {
-                    1 => {
-                        self.intenclr.write(|w| w.compare1().clear());
-                    }
-                    2 => {
-                        self.intenclr.write(|w| w.compare2().clear());
-                    }
-                    3 => {
-                        self.intenclr.write(|w| w.compare3().clear());
-                    }
-                    /* 4 => {
-                     *     self.intenclr.write(|w| w.compare4().clear());
-                     * }
-                     * 5 => {
-                     *     self.intenclr.write(|w| w.compare5().clear());
-                     * } */
-                    _ => (),
-                }
+            fn fire_again(&mut self, compare: Compare, interval: u32) {
+                let id = compare as usize;
+                let last = self.cc[id].read().bits();
+                self.cc[id].write(|w| unsafe { w.bits(after(last, interval)) });
                 self.events_compare[id].reset();
             }
 
+            fn stop(&mut self, compare: Compare) {
+                match compare {
+                    Compare::One => self.intenclr.write(|w| w.compare1().clear()),
+                    Compare::Two => self.intenclr.write(|w| w.compare2().clear()),
+                    Compare::Three => self.intenclr.write(|w| w.compare3().clear()),
+                }
+                self.events_compare[compare as usize].reset();
+            }
+
             fn now(&self) -> u32 {
                 self.tasks_capture[0].write(|w| w.tasks_capture().set_bit());
                 self.cc[0].read().bits()
             }
 
-            fn ack_compare_event(&mut self, id: usize) {
-                self.events_compare[id].reset();
+            fn is_compare_event(&self, compare: Compare) -> bool {
+                self.events_compare[compare as usize].read().bits() != 0
+            }
+
+            fn ack_compare_event(&mut self, compare: Compare) {
+                self.events_compare[compare as usize].reset();
             }
         }
     };
 }
 
 impl_timer!(TIMER0);
-impl_timer!(TIMER1);
 impl_timer!(TIMER2);
 impl_timer!(TIMER3);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn millis_are_a_thousand_ticks() {
+        assert_eq!(millis(0), 0);
+        assert_eq!(millis(1), 1000);
+        assert_eq!(millis(4_294_967), 4_294_967_000);
+    }
+
+    #[test]
+    fn deadlines_wrap_past_the_top_of_the_count() {
+        assert_eq!(after(100, millis(5)), 5100);
+        assert_eq!(after(u32::MAX - 4, 10), 5);
+        assert_eq!(after(u32::MAX, 1), 0);
+        // Seventy one minutes on is back where it started, near enough
+        assert_eq!(after(1234, u32::MAX), 1233);
+    }
+
+    #[test]
+    fn repeating_from_the_last_compare_doesnt_drift() {
+        // However late each one's handled, the compares stay on the beat,
+        // across the wrap too
+        let interval = millis(250);
+        let start = u32::MAX - 3 * interval;
+        let mut compare = after(start, interval);
+        for n in 2..=8 {
+            compare = after(compare, interval);
+            assert_eq!(compare.wrapping_sub(start), n * interval);
+        }
+    }
+}
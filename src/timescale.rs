@@ -0,0 +1,121 @@
+use crate::game::{Events, Input, World};
+
+// Runs the game slower than the tick rate, for debugging or slow motion.
+// Every frame is still drawn, so the picture stays smooth, but only some of
+// the ticks step the game: at 50% every other tick, at 25% one in four.
+// Nothing in the game itself knows, it just sees fewer ticks go by.
+
+// Slowest speed that can be set, in percent
+pub const MIN_PERCENT: u8 = 10;
+
+pub struct TimeScale {
+    // Percent of a step built up by the ticks since the last one
+    owed: u8,
+    // Fire was pressed on a tick without a step, so the next step gets it.
+    // A tap is only one frame long and would otherwise go missing.
+    fire: bool,
+}
+
+impl TimeScale {
+    pub const fn new() -> Self {
+        TimeScale {
+            owed: 0,
+            fire: false,
+        }
+    }
+
+    // Called once a tick. Returns the input to step `world` with if it's due
+    // a step at `percent` of full speed, slowed down further by whatever
+    // `world` wants for itself. Never more than one step a tick, so anything
+    // over 100 is the same as 100.
+    pub fn due(&mut self, world: &mut World, mut input: Input, percent: u8) -> Option<Input> {
+        let percent = percent.min(100) as u16 * world.speed_percent() as u16 / 100;
+        self.owed += percent as u8;
+        if self.owed < 100 {
+            self.fire |= input.fire;
+            world.events = Events::default();
+            return None;
+        }
+        self.owed -= 100;
+        input.fire |= self.fire;
+        self.fire = false;
+        Some(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{self, State};
+    use crate::rng::Rng;
+
+    // How many of `ticks` ticks step the game at `percent`
+    fn steps(world: &mut World, percent: u8, ticks: u32) -> u32 {
+        let mut scale = TimeScale::new();
+        (0..ticks)
+            .filter(|_| scale.due(world, Input::default(), percent).is_some())
+            .count() as u32
+    }
+
+    #[test]
+    fn steps_come_at_the_scaled_rate() {
+        let mut world = World::new();
+        for &(percent, expected) in &[(100, 100), (50, 50), (25, 25), (33, 33), (MIN_PERCENT, 10)] {
+            assert_eq!(steps(&mut world, percent, 100), expected, "{}%", percent);
+        }
+        // Never more than one a tick
+        assert_eq!(steps(&mut world, 200, 100), 100);
+        assert_eq!(steps(&mut world, 0, 100), 0);
+    }
+
+    #[test]
+    fn steps_are_spread_out_evenly() {
+        let (mut world, mut scale) = (World::new(), TimeScale::new());
+        let due: Vec<bool> = (0..8)
+            .map(|_| scale.due(&mut world, Input::default(), 25).is_some())
+            .collect();
+        assert_eq!(due, [false, false, false, true, false, false, false, true]);
+    }
+
+    #[test]
+    fn the_game_runs_at_the_scaled_rate() {
+        let (mut world, mut rng, mut scale) = (World::new(), Rng::new(1), TimeScale::new());
+        world.start();
+        for _ in 0..120 {
+            if let Some(input) = scale.due(&mut world, Input::default(), 50) {
+                game::advance_frame(&mut world, input, &mut rng);
+            }
+        }
+        assert_eq!(world.state, State::Playing);
+        assert_eq!(world.ticks, 60);
+    }
+
+    #[test]
+    fn slow_motion_slows_it_further() {
+        let mut world = World::new();
+        world.effects.slow_motion_until = u32::MAX;
+        let slow = game::SLOW_MOTION_PERCENT as u32;
+        assert_eq!(steps(&mut world, 100, 100), slow);
+        assert_eq!(steps(&mut world, 50, 200), slow);
+    }
+
+    #[test]
+    fn a_tap_between_steps_is_kept_for_the_next() {
+        let (mut world, mut scale) = (World::new(), TimeScale::new());
+        let tap = Input {
+            fire: true,
+            ..Input::default()
+        };
+        world.events = Events::FIRED;
+        assert_eq!(scale.due(&mut world, tap, 50), None);
+        // Nothing happened on a tick without a step
+        assert_eq!(world.events, Events::default());
+        assert_eq!(scale.due(&mut world, Input::default(), 50), Some(tap));
+        // And only the once
+        assert_eq!(scale.due(&mut world, Input::default(), 50), None);
+        assert_eq!(
+            scale.due(&mut world, Input::default(), 50),
+            Some(Input::default())
+        );
+    }
+}
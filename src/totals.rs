@@ -0,0 +1,325 @@
+#[cfg(feature = "flash")]
+use crate::crc;
+use crate::game::{State, World};
+use crate::text;
+
+// Lifetime totals across every game on this board, kept in their own flash
+// page. They're counted up from the world once a frame and only saved every
+// so often, since every save erases the page, so a reset can lose whatever
+// came after the last one. Everything saturates rather than wrapping. The
+// saved layout has the same header and CRC32 as the high score table, and
+// anything that doesn't check out, erased flash on first boot included,
+// loads as all zeroes.
+
+#[cfg(feature = "flash")]
+const MAGIC: [u8; 4] = *b"PEWT";
+#[cfg(feature = "flash")]
+const VERSION: u8 = 1;
+#[cfg(feature = "flash")]
+pub const LEN: usize = 4 + 1 + 4 + 4 + 4 + 2 + 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Totals {
+    pub play_secs: u32,
+    pub games: u32,
+    pub kills: u32,
+    pub best_combo: u16,
+    // The rest is only for counting, and isn't saved
+    play_us: u32,
+    // The world's own kill count when it was last looked at
+    seen_kills: u32,
+    // Whether the world was mid-game when it was last looked at, None before
+    // the first look
+    was_playing: Option<bool>,
+    dirty: bool,
+}
+
+impl Totals {
+    pub const fn new() -> Self {
+        Totals {
+            play_secs: 0,
+            games: 0,
+            kills: 0,
+            best_combo: 0,
+            play_us: 0,
+            seen_kills: 0,
+            was_playing: None,
+            dirty: false,
+        }
+    }
+
+    // Called once a frame, with how long the frame was. Nothing is counted
+    // unless `counting`, so the demo and time spent paused don't add up, but
+    // the world is still followed so nothing is counted twice later. A game
+    // already under way at the first look, resumed from a checkpoint, was
+    // counted before the reset.
+    pub fn update(&mut self, world: &World, counting: bool, period_us: u32) {
+        let playing = world.state == State::Playing;
+        let started = self.was_playing == Some(false) && playing;
+        self.was_playing = Some(playing);
+        // A new game, or a different world, counts from 0 again
+        if world.kills < self.seen_kills {
+            self.seen_kills = 0;
+        }
+        let kills = world.kills - self.seen_kills;
+        self.seen_kills = world.kills;
+        if !counting {
+            return;
+        }
+
+        let before = *self;
+        self.games = self.games.saturating_add(started as u32);
+        self.kills = self.kills.saturating_add(kills);
+        self.best_combo = self.best_combo.max(world.combo);
+        if playing {
+            self.play_us += period_us;
+            self.play_secs = self.play_secs.saturating_add(self.play_us / 1_000_000);
+            self.play_us %= 1_000_000;
+        }
+        self.dirty |= (self.play_secs, self.games, self.kills, self.best_combo)
+            != (before.play_secs, before.games, before.kills, before.best_combo);
+    }
+
+    // Whether anything's changed since the last time this was called
+    #[cfg(feature = "flash")]
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::replace(&mut self.dirty, false)
+    }
+
+    #[cfg(feature = "flash")]
+    pub fn load(buf: &[u8]) -> Self {
+        let mut totals = Totals::new();
+        if buf.len() < LEN || buf[..4] != MAGIC || buf[4] != VERSION {
+            return totals;
+        }
+        let stored = u32::from_le_bytes([buf[LEN - 4], buf[LEN - 3], buf[LEN - 2], buf[LEN - 1]]);
+        if crc::crc32(&buf[..LEN - 4]) != stored {
+            log_warn!("Lifetime totals are corrupt, starting again");
+            return totals;
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        totals.play_secs = u32_at(5);
+        totals.games = u32_at(9);
+        totals.kills = u32_at(13);
+        totals.best_combo = u16::from_le_bytes([buf[17], buf[18]]);
+        totals
+    }
+
+    #[cfg(feature = "flash")]
+    pub fn save(&self, buf: &mut [u8; LEN]) {
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4] = VERSION;
+        buf[5..9].copy_from_slice(&self.play_secs.to_le_bytes());
+        buf[9..13].copy_from_slice(&self.games.to_le_bytes());
+        buf[13..17].copy_from_slice(&self.kills.to_le_bytes());
+        buf[17..19].copy_from_slice(&self.best_combo.to_le_bytes());
+        let crc = crc::crc32(&buf[..LEN - 4]);
+        buf[LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+    }
+
+    // What the title screen shows, under a TOTALS heading. Play time is in
+    // hours and minutes, and stops going up at 999 hours.
+    pub fn lines(&self) -> [[u8; 12]; 4] {
+        let mut games = *b"GAMES       ";
+        text::digits(&mut games[6..], self.games);
+        let mut time = *b"TIME    H  M";
+        let mins = self.play_secs / 60;
+        text::digits(&mut time[5..8], (mins / 60).min(999));
+        text::digits(&mut time[9..11], mins % 60);
+        let mut kills = *b"KILLS       ";
+        text::digits(&mut kills[6..], self.kills);
+        let mut combo = *b"COMBO       ";
+        text::digits(&mut combo[6..], self.best_combo as u32);
+        [games, time, kills, combo]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME_US: u32 = 33_333;
+
+    fn playing() -> World {
+        let mut world = World::new();
+        world.start();
+        world
+    }
+
+    #[test]
+    fn a_game_starting_counts_once() {
+        let (mut totals, mut world) = (Totals::new(), World::new());
+        totals.update(&world, true, FRAME_US);
+        world.start();
+        for _ in 0..10 {
+            totals.update(&world, true, FRAME_US);
+        }
+        assert_eq!(totals.games, 1);
+
+        world.state = State::GameOver;
+        totals.update(&world, true, FRAME_US);
+        world.start();
+        totals.update(&world, true, FRAME_US);
+        assert_eq!(totals.games, 2);
+    }
+
+    #[test]
+    fn a_game_under_way_at_the_first_look_was_counted_already() {
+        let mut totals = Totals::new();
+        totals.update(&playing(), true, FRAME_US);
+        assert_eq!(totals.games, 0);
+    }
+
+    #[test]
+    fn kills_are_counted_as_they_happen() {
+        let (mut totals, mut world) = (Totals::new(), playing());
+        totals.update(&world, true, FRAME_US);
+        world.kills = 3;
+        totals.update(&world, true, FRAME_US);
+        world.kills = 5;
+        totals.update(&world, true, FRAME_US);
+        assert_eq!(totals.kills, 5);
+
+        // A new game counts from 0 again
+        world.kills = 2;
+        totals.update(&world, true, FRAME_US);
+        assert_eq!(totals.kills, 7);
+    }
+
+    #[test]
+    fn nothing_counts_while_not_counting() {
+        let (mut totals, mut world) = (Totals::new(), World::new());
+        totals.update(&world, true, FRAME_US);
+        world.start();
+        world.kills = 4;
+        world.combo = 9;
+        for _ in 0..100 {
+            totals.update(&world, false, FRAME_US);
+        }
+        assert_eq!(
+            totals,
+            Totals {
+                was_playing: Some(true),
+                seen_kills: 4,
+                ..Totals::new()
+            }
+        );
+        totals.update(&world, true, FRAME_US);
+        assert_eq!((totals.games, totals.kills), (0, 0));
+    }
+
+    #[test]
+    fn play_time_adds_up_across_frames() {
+        let (mut totals, world) = (Totals::new(), playing());
+        for _ in 0..90 {
+            totals.update(&world, true, FRAME_US);
+        }
+        // 90 frames is a hair under 3 s
+        assert_eq!(totals.play_secs, 2);
+        for _ in 0..30 * 60 {
+            totals.update(&world, true, FRAME_US);
+        }
+        assert_eq!(totals.play_secs, 62);
+
+        // But none on the title screen
+        let mut title_totals = Totals::new();
+        for _ in 0..100 {
+            title_totals.update(&World::new(), true, FRAME_US);
+        }
+        assert_eq!(title_totals.play_secs, 0);
+    }
+
+    #[test]
+    fn the_best_combo_is_kept() {
+        let (mut totals, mut world) = (Totals::new(), playing());
+        for &combo in &[3, 12, 5] {
+            world.combo = combo;
+            totals.update(&world, true, FRAME_US);
+        }
+        assert_eq!(totals.best_combo, 12);
+    }
+
+    #[test]
+    fn totals_saturate() {
+        let (mut totals, mut world) = (Totals::new(), World::new());
+        totals.play_secs = u32::MAX;
+        totals.games = u32::MAX;
+        totals.kills = u32::MAX - 1;
+        totals.update(&world, true, FRAME_US);
+        world.start();
+        world.kills = 10;
+        for _ in 0..60 {
+            totals.update(&world, true, FRAME_US);
+        }
+        assert_eq!(
+            (totals.play_secs, totals.games, totals.kills),
+            (u32::MAX, u32::MAX, u32::MAX)
+        );
+    }
+
+    #[cfg(feature = "flash")]
+    #[test]
+    fn only_changes_need_saving() {
+        let (mut totals, mut world) = (Totals::new(), playing());
+        totals.update(&world, true, FRAME_US);
+        assert!(!totals.take_dirty());
+        world.kills = 1;
+        totals.update(&world, true, FRAME_US);
+        assert!(totals.take_dirty());
+        assert!(!totals.take_dirty());
+        // Less than a second of play doesn't change anything saved
+        totals.update(&world, true, FRAME_US);
+        assert!(!totals.take_dirty());
+    }
+
+    #[cfg(feature = "flash")]
+    #[test]
+    fn saves_load_back() {
+        let mut totals = Totals::new();
+        totals.play_secs = 123_456;
+        totals.games = 78;
+        totals.kills = 9_999_999;
+        totals.best_combo = 321;
+        let mut buf = [0; LEN];
+        totals.save(&mut buf);
+        assert_eq!(Totals::load(&buf), totals);
+    }
+
+    #[cfg(feature = "flash")]
+    #[test]
+    fn erased_or_corrupt_flash_loads_as_zeroes() {
+        assert_eq!(Totals::load(&[0xFF; LEN]), Totals::new());
+        assert_eq!(Totals::load(&[0; LEN]), Totals::new());
+        assert_eq!(Totals::load(&[]), Totals::new());
+
+        let mut totals = Totals::new();
+        totals.games = 5;
+        let mut buf = [0; LEN];
+        totals.save(&mut buf);
+        for i in 0..LEN {
+            let mut bad = buf;
+            bad[i] ^= 0x10;
+            assert_eq!(Totals::load(&bad), Totals::new(), "byte {}", i);
+        }
+    }
+
+    #[test]
+    fn lines_show_hours_and_minutes() {
+        let mut totals = Totals::new();
+        totals.play_secs = 3 * 3600 + 7 * 60 + 59;
+        totals.games = 42;
+        totals.kills = 1234;
+        totals.best_combo = 17;
+        assert_eq!(
+            totals.lines(),
+            [
+                *b"GAMES     42",
+                *b"TIME   3H 7M",
+                *b"KILLS   1234",
+                *b"COMBO     17"
+            ]
+        );
+        totals.play_secs = u32::MAX;
+        assert_eq!(&totals.lines()[1][..9], b"TIME 999H");
+    }
+}
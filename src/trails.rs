@@ -0,0 +1,54 @@
+// Leaves what was drawn last frame to fade out, rather than clearing it, so
+// anything that moves draws a trail behind it. Every frame each channel of
+// every pixel moves 1/2^shift of the way towards the background color, at
+// least one step at a time so it always gets there.
+//
+// The whole frame changes every time, so there's nothing for the background
+// cache to save, and the frame goes out whole instead.
+
+// Longest trails that can be set, as a shift
+pub const MAX_SHIFT: u8 = 5;
+
+pub struct Trails {
+    // The last frame was faded, so the framebuffer has something to fade.
+    // Otherwise it might be left over from anything, a frame streamed
+    // straight to the panel included, and starts from the background color.
+    running: bool,
+}
+
+impl Trails {
+    pub const fn new() -> Self {
+        Trails { running: false }
+    }
+
+    // Called every frame that isn't faded. The next one that is starts over.
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    // Fades `frame` towards `color`, ready to draw this frame on top
+    pub fn apply(&mut self, frame: &mut [u8], color: u16, shift: u8) {
+        if !self.running {
+            self.running = true;
+            for pixel in frame.chunks_exact_mut(2) {
+                pixel.copy_from_slice(&color.to_le_bytes());
+            }
+            return;
+        }
+        let color = color as i32;
+        for pixel in frame.chunks_exact_mut(2) {
+            let c = u16::from_le_bytes([pixel[0], pixel[1]]) as i32;
+            let step = |offset: u32, mask: i32| {
+                let (from, to) = ((c >> offset) & mask, (color >> offset) & mask);
+                let diff = to - from;
+                let moved = match diff / (1 << shift) {
+                    0 => diff.signum(),
+                    moved => moved,
+                };
+                (from + moved) << offset
+            };
+            let c = (step(11, 0x1F) | step(5, 0x3F) | step(0, 0x1F)) as u16;
+            pixel.copy_from_slice(&c.to_le_bytes());
+        }
+    }
+}
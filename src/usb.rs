@@ -0,0 +1,239 @@
+use crate::console::{Command, LineBuffer, ParseError};
+use crate::ring::{Overflow, RingBuffer};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+use nrf52840_hal::clocks::{Clocks, ExternalOscillator, LfOscStarted};
+use nrf52840_hal::usbd::{UsbPeripheral, Usbd};
+use nrf52840_pac::{Interrupt, USBD};
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+use usb_device::prelude::*;
+
+// A USB serial port (CDC-ACM) on the nRF52840's own USB, for the log and a
+// few console commands without a debugger attached. Log lines and replies
+// are queued in OUT from wherever they're printed and sent from the USBD
+// interrupt, a packet at a time. Until a terminal opens the port they pile
+// up, and the oldest go once OUT is full, so opening it late still shows the
+// last kilobyte or so.
+//
+// There's no usbd-serial here, so the class below is the least of CDC-ACM
+// that Linux, macOS and Windows all take to: line coding is remembered but
+// means nothing, and DTR is what says someone's listening.
+
+type Bus = Usbd<UsbPeripheral<'static>>;
+// What `init` leaves the clocks as. USB needs HFXO, which this is the proof
+// of.
+pub type UsbClocks = Clocks<ExternalOscillator, ExternalOscillator, LfOscStarted>;
+
+// pid.codes' shared VID/PID for CDC-ACM devices told apart by their strings
+const VID_PID: UsbVidPid = UsbVidPid(0x16c0, 0x27dd);
+const PACKET: usize = 64;
+const OUT_LEN: usize = 1024;
+
+static OUT: Mutex<RefCell<RingBuffer<u8, OUT_LEN>>> =
+    Mutex::new(RefCell::new(RingBuffer::new(0, Overflow::DropOldest)));
+
+struct Queue;
+
+impl Write for Queue {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        interrupt::free(|cs| {
+            let mut out = OUT.borrow(cs).borrow_mut();
+            for &byte in s.as_bytes() {
+                out.push(byte);
+            }
+        });
+        Ok(())
+    }
+}
+
+// Queues a line to go out, and gets the USBD interrupt to send it. Called by
+// the log macros for every line they print, as well as for replies.
+pub fn print(args: fmt::Arguments) {
+    // Queuing never fails, it only drops the oldest
+    Queue.write_fmt(args).ok();
+    Queue.write_str("\r\n").ok();
+    rtic::pend(Interrupt::USBD);
+}
+
+const USB_CLASS_CDC: u8 = 0x02;
+const USB_CLASS_CDC_DATA: u8 = 0x0a;
+const CDC_SUBCLASS_ACM: u8 = 0x02;
+const CS_INTERFACE: u8 = 0x24;
+const CDC_TYPE_HEADER: u8 = 0x00;
+const CDC_TYPE_CALL_MANAGEMENT: u8 = 0x01;
+const CDC_TYPE_ACM: u8 = 0x02;
+const CDC_TYPE_UNION: u8 = 0x06;
+const REQ_SET_LINE_CODING: u8 = 0x20;
+const REQ_GET_LINE_CODING: u8 = 0x21;
+const REQ_SET_CONTROL_LINE_STATE: u8 = 0x22;
+
+struct Serial<'a> {
+    comm_if: InterfaceNumber,
+    // Never written, but CDC-ACM has to have it
+    comm_ep: EndpointIn<'a, Bus>,
+    data_if: InterfaceNumber,
+    read_ep: EndpointOut<'a, Bus>,
+    write_ep: EndpointIn<'a, Bus>,
+    // 115200 8N1 until the host says otherwise
+    line_coding: [u8; 7],
+    dtr: bool,
+}
+
+impl<'a> Serial<'a> {
+    fn new(alloc: &'a UsbBusAllocator<Bus>) -> Self {
+        Serial {
+            comm_if: alloc.interface(),
+            comm_ep: alloc.interrupt(8, 255),
+            data_if: alloc.interface(),
+            read_ep: alloc.bulk(PACKET as u16),
+            write_ep: alloc.bulk(PACKET as u16),
+            line_coding: [0x00, 0xc2, 0x01, 0x00, 0, 0, 8],
+            dtr: false,
+        }
+    }
+
+    fn is_for_us(&self, req: &control::Request) -> bool {
+        req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u8::from(self.comm_if) as u16
+    }
+}
+
+impl UsbClass<Bus> for Serial<'_> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.iad(self.comm_if, 2, USB_CLASS_CDC, CDC_SUBCLASS_ACM, 0)?;
+        writer.interface(self.comm_if, USB_CLASS_CDC, CDC_SUBCLASS_ACM, 0)?;
+        writer.write(CS_INTERFACE, &[CDC_TYPE_HEADER, 0x10, 0x01])?;
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_CALL_MANAGEMENT, 0x00, self.data_if.into()],
+        )?;
+        // Line coding and DTR are understood
+        writer.write(CS_INTERFACE, &[CDC_TYPE_ACM, 0x02])?;
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_UNION, self.comm_if.into(), self.data_if.into()],
+        )?;
+        writer.endpoint(&self.comm_ep)?;
+        writer.interface(self.data_if, USB_CLASS_CDC_DATA, 0, 0)?;
+        writer.endpoint(&self.write_ep)?;
+        writer.endpoint(&self.read_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.dtr = false;
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<Bus>) {
+        let req = *xfer.request();
+        if self.is_for_us(&req) && req.request == REQ_GET_LINE_CODING {
+            xfer.accept_with(&self.line_coding).ok();
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<Bus>) {
+        let req = *xfer.request();
+        if !self.is_for_us(&req) {
+            return;
+        }
+        match req.request {
+            REQ_SET_LINE_CODING if xfer.data().len() == self.line_coding.len() => {
+                self.line_coding.copy_from_slice(xfer.data());
+                xfer.accept().ok();
+            }
+            REQ_SET_CONTROL_LINE_STATE => {
+                self.dtr = req.value & 1 != 0;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
+
+pub struct UsbConsole {
+    device: UsbDevice<'static, Bus>,
+    serial: Serial<'static>,
+    line: LineBuffer,
+    // Taken off OUT but not sent yet, because the last packet's still going
+    pending: [u8; PACKET],
+    pending_len: usize,
+}
+
+impl UsbConsole {
+    pub fn new(usbd: USBD, clocks: &'static UsbClocks) -> Self {
+        let alloc = cortex_m::singleton!(
+            : UsbBusAllocator<Bus> = Usbd::new(UsbPeripheral::new(usbd, clocks))
+        )
+        .unwrap();
+        let serial = Serial::new(alloc);
+        let device = UsbDeviceBuilder::new(alloc, VID_PID)
+            .manufacturer("knkski")
+            .product("pewpew")
+            .serial_number("console")
+            .composite_with_iads()
+            .max_packet_size_0(64)
+            .build();
+        UsbConsole {
+            device,
+            serial,
+            line: LineBuffer::new(),
+            pending: [0; PACKET],
+            pending_len: 0,
+        }
+    }
+
+    // Called from the USBD interrupt, which `print` also pends. Hands
+    // `handle` each line that's come in, parsed.
+    pub fn on_interrupt(&mut self, mut handle: impl FnMut(Result<Command, ParseError>)) {
+        if self.device.poll(&mut [&mut self.serial]) {
+            let mut buf = [0; PACKET];
+            while let Ok(count @ 1..) = self.serial.read_ep.read(&mut buf) {
+                for &byte in &buf[..count] {
+                    if let Some(result) = self.line.push(byte) {
+                        handle(result);
+                    }
+                }
+            }
+        }
+        self.send();
+    }
+
+    fn send(&mut self) {
+        if self.device.state() != UsbDeviceState::Configured || !self.serial.dtr {
+            return;
+        }
+        if self.pending_len == 0 {
+            interrupt::free(|cs| {
+                let mut out = OUT.borrow(cs).borrow_mut();
+                while self.pending_len < PACKET {
+                    match out.pop() {
+                        Some(byte) => self.pending[self.pending_len] = byte,
+                        None => break,
+                    }
+                    self.pending_len += 1;
+                }
+            });
+        }
+        if self.pending_len == 0 {
+            return;
+        }
+        // Busy means the last packet's still in flight, and its completion
+        // comes back round here
+        if self
+            .serial
+            .write_ep
+            .write(&self.pending[..self.pending_len])
+            .is_ok()
+        {
+            self.pending_len = 0;
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::color;
+
+// Darkens the frame towards its edges. The per-pixel brightness is worked out
+// once up front, so applying it is one multiply and shift per channel.
+
+// How much the corners are darkened, out of 256
+const STRENGTH: u32 = 160;
+
+pub struct Vignette<const W: usize, const H: usize> {
+    // Brightness per pixel, where 255 leaves the pixel untouched
+    table: [[u8; W]; H],
+    enabled: bool,
+}
+
+impl<const W: usize, const H: usize> Vignette<W, H> {
+    pub fn new() -> Self {
+        let mut table = [[0; W]; H];
+        let (cx, cy) = (W as i32 - 1, H as i32 - 1);
+        // Distances are doubled so the center falls on a whole number
+        let max = (cx * cx + cy * cy) as u32;
+
+        for (y, row) in table.iter_mut().enumerate() {
+            for (x, m) in row.iter_mut().enumerate() {
+                let dx = 2 * x as i32 - cx;
+                let dy = 2 * y as i32 - cy;
+                let d2 = (dx * dx + dy * dy) as u32;
+                *m = (255 - (d2 * STRENGTH / max).min(255)) as u8;
+            }
+        }
+
+        Vignette {
+            table,
+            enabled: false,
+        }
+    }
+
+    // Returns true if that changed anything
+    pub fn set_enabled(&mut self, enabled: bool) -> bool {
+        let changed = self.enabled != enabled;
+        self.enabled = enabled;
+        changed
+    }
+
+    // `frame` is little-endian RGB565, W x H
+    pub fn apply(&self, frame: &mut [u8]) {
+        if !self.enabled {
+            return;
+        }
+
+        for (pixel, &m) in frame.chunks_exact_mut(2).zip(self.table.iter().flatten()) {
+            let c = shade(u16::from_le_bytes([pixel[0], pixel[1]]), m);
+            pixel.copy_from_slice(&c.to_le_bytes());
+        }
+    }
+
+    // For renderers that never hold a whole frame: darkens row `y` of it
+    #[cfg_attr(not(any(feature = "scanline", feature = "indexed")), allow(dead_code))]
+    pub fn apply_row(&self, y: usize, row: &mut [u16; W]) {
+        if !self.enabled {
+            return;
+        }
+
+        for (c, &m) in row.iter_mut().zip(self.table[y].iter()) {
+            *c = shade(*c, m);
+        }
+    }
+}
+
+fn shade(c: u16, m: u8) -> u16 {
+    color::scale(c, m as u32 + 1)
+}
@@ -0,0 +1,134 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use nrf52840_hal::wdt::{count, handles::Hdl0, Watchdog, WatchdogHandle};
+use nrf52840_pac::{RTC0, WDT};
+
+// Resets the board if any of the tasks that have to keep going stop.
+// Feeding the watchdog from an interrupt that fires regardless would only
+// prove that interrupts still run, so it's fed from idle, below everything,
+// and only once each of these has checked in since the last feed:
+//
+// - Render, at the end of every frame
+// - Game, once the game's had its steps for the frame. That's the frame task
+//   as well, but which of the two is missing says where it stopped.
+// - Input, from RTC0 every CHECK_MS. It's at the input tier's priority,
+//   so any of that tier that never returns holds it up. RTC0 runs from the
+//   LFCLK, so this doesn't keep the HF clock on between frames.
+//
+// Idle only runs when nothing else wants to, so a task at any priority
+// spinning forever stops the feeds too, as does a frame task that stops
+// rescheduling itself while everything else carries on.
+//
+// A reset comes between TIMEOUT_MS and TIMEOUT_MS + CHECK_MS after a task's
+// last check-in: the others can take up to CHECK_MS longer to come in for
+// the feed after it, then nothing feeds. That's a long way above the slowest
+// frame, 100 ms at the lowest fps cap, or a flash page erase.
+//
+// While paused or powered off frames don't show anything new, or don't come
+// at all, so only the input tier has to keep checking in.
+
+pub const TIMEOUT_MS: u32 = 2000;
+pub const CHECK_MS: u32 = 500;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Task {
+    Render = 1 << 0,
+    Game = 1 << 1,
+    Input = 1 << 2,
+}
+
+const EVERY_TASK: u8 = Task::Render as u8 | Task::Game as u8 | Task::Input as u8;
+
+// The tasks that have checked in since the last feed, a bit each
+static CHECKED_IN: AtomicU8 = AtomicU8::new(0);
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+pub fn check_in(task: Task) {
+    CHECKED_IN.fetch_or(task as u8, Ordering::Relaxed);
+}
+
+pub fn suspend(suspended: bool) {
+    SUSPENDED.store(suspended, Ordering::Relaxed);
+}
+
+// The tasks that have to have checked in before a feed
+fn expected() -> u8 {
+    match SUSPENDED.load(Ordering::Relaxed) {
+        true => Task::Input as u8,
+        false => EVERY_TASK,
+    }
+}
+
+// CHECK_MS in RTC0 ticks, which are 32.768 kHz, and how far its 24-bit
+// counter goes before it wraps
+const CHECK_TICKS: u32 = CHECK_MS * 32768 / 1000;
+const COUNTER_MASK: u32 = (1 << 24) - 1;
+
+// The input tier's check-in, which owns the RTC. Its compare is moved on
+// from where it last was rather than from now, like the timers' fire_again.
+pub struct Heartbeat {
+    rtc: RTC0,
+}
+
+impl Heartbeat {
+    // Only once clock::log is done measuring with RTC0
+    pub fn new(rtc: RTC0) -> Self {
+        rtc.tasks_stop.write(|w| unsafe { w.bits(1) });
+        rtc.prescaler.write(|w| unsafe { w.prescaler().bits(0) });
+        rtc.tasks_clear.write(|w| unsafe { w.bits(1) });
+        rtc.cc[0].write(|w| unsafe { w.bits(CHECK_TICKS) });
+        rtc.events_compare[0].reset();
+        rtc.intenset.write(|w| w.compare0().set());
+        rtc.tasks_start.write(|w| unsafe { w.bits(1) });
+        Heartbeat { rtc }
+    }
+
+    // Called from the RTC0 interrupt
+    pub fn on_interrupt(&mut self) {
+        self.rtc.events_compare[0].reset();
+        let next = (self.rtc.cc[0].read().bits() + CHECK_TICKS) & COUNTER_MASK;
+        self.rtc.cc[0].write(|w| unsafe { w.bits(next) });
+        check_in(Task::Input);
+    }
+}
+
+// The feeding side, which idle owns along with the watchdog's handle
+pub struct Feeder {
+    handle: Option<WatchdogHandle<Hdl0>>,
+}
+
+impl Feeder {
+    // Starts the watchdog, so every task has to check in within the timeout.
+    // After a soft reset it may already be running, having survived the
+    // reset, in which case its handle is taken back as it is.
+    pub fn new(wdt: WDT) -> Self {
+        let handle = match Watchdog::try_new(wdt) {
+            Ok(mut watchdog) => {
+                watchdog.set_lfosc_ticks(TIMEOUT_MS * 32768 / 1000);
+                // Stopping at a breakpoint shouldn't reset the board
+                watchdog.run_during_debug_halt(false);
+                Some(watchdog.activate::<count::One>().handles.0)
+            }
+            Err(wdt) => match Watchdog::try_recover::<count::One>(wdt) {
+                Ok(parts) => Some(parts.handles.0),
+                Err(_) => {
+                    log_warn!("Watchdog already running with other handles, not checking tasks");
+                    None
+                }
+            },
+        };
+        Feeder { handle }
+    }
+
+    // Called from idle each time round. Feeds the watchdog if every task it
+    // needs has checked in, and starts them all over.
+    pub fn poll(&mut self) {
+        let (checked_in, expected) = (CHECKED_IN.load(Ordering::Relaxed), expected());
+        if checked_in & expected != expected {
+            return;
+        }
+        CHECKED_IN.fetch_and(!checked_in, Ordering::Relaxed);
+        if let Some(handle) = self.handle.as_mut() {
+            handle.pet();
+        }
+    }
+}
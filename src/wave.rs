@@ -0,0 +1,315 @@
+use crate::game::{ENEMY_H, ENEMY_W, WIDTH};
+
+// Scripted waves of enemies, on top of the random spawns. A wave is a list
+// of formations, each with the tick into the wave it comes in at. Once the
+// last wave has played the script starts over, with every enemy a hit
+// tougher each time round. Like the rest of the game this is all plain data,
+// so a wave can be worked out without any hardware.
+
+// Gap between neighbours in a formation
+const SPACING: i32 = 3;
+// How much tougher enemies can get from going round the script again
+const MAX_BONUS_HP: u8 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    // Side by side
+    Row,
+    // Leader in front, the rest trailing off behind it to either side
+    Vee,
+    // One behind the other
+    Column,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Formation {
+    pub shape: Shape,
+    pub count: u8,
+    // Where the formation is centered, across the screen
+    pub x: i32,
+    pub hp: u8,
+}
+
+impl Formation {
+    // Top left corner of each enemy, with the front of the formation just
+    // above the top of the screen. Anything further back starts higher up
+    // and drifts in later.
+    pub fn positions(self) -> impl Iterator<Item = (i32, i32)> {
+        let (dx, dy) = (ENEMY_W + SPACING, ENEMY_H + SPACING);
+        let left = self.x - ENEMY_W / 2;
+        (0..self.count as i32).map(move |i| {
+            let (x, y) = match self.shape {
+                Shape::Row => (left + (2 * i - (self.count as i32 - 1)) * dx / 2, 0),
+                Shape::Vee => {
+                    let rank = (i + 1) / 2;
+                    let side = if i % 2 == 1 { -1 } else { 1 };
+                    (left + side * rank * dx, -rank * dy)
+                }
+                Shape::Column => (left, -i * dy),
+            };
+            (x.clamp(0, WIDTH - ENEMY_W), y - ENEMY_H)
+        })
+    }
+}
+
+pub struct Entry {
+    // Ticks after the start of the wave
+    pub at: u32,
+    pub formation: Formation,
+}
+
+pub struct Wave {
+    pub entries: &'static [Entry],
+    // Ticks from the start of this wave to the start of the next
+    pub length: u32,
+}
+
+const fn entry(at: u32, shape: Shape, count: u8, x: i32, hp: u8) -> Entry {
+    Entry {
+        at,
+        formation: Formation {
+            shape,
+            count,
+            x,
+            hp,
+        },
+    }
+}
+
+const MID: i32 = WIDTH / 2;
+
+pub const WAVES: [Wave; 3] = [
+    Wave {
+        entries: &[
+            entry(60, Shape::Row, 3, MID, 1),
+            entry(240, Shape::Row, 4, MID, 1),
+            entry(420, Shape::Row, 3, WIDTH / 4, 1),
+            entry(480, Shape::Row, 3, 3 * WIDTH / 4, 1),
+        ],
+        length: 720,
+    },
+    Wave {
+        entries: &[
+            entry(0, Shape::Vee, 3, MID, 1),
+            entry(180, Shape::Vee, 5, MID, 1),
+            entry(400, Shape::Vee, 3, WIDTH / 4, 2),
+            entry(400, Shape::Vee, 3, 3 * WIDTH / 4, 2),
+        ],
+        length: 720,
+    },
+    Wave {
+        entries: &[
+            entry(0, Shape::Column, 3, WIDTH / 6, 1),
+            entry(90, Shape::Column, 3, 5 * WIDTH / 6, 1),
+            entry(240, Shape::Column, 4, MID, 2),
+            entry(480, Shape::Row, 5, MID, 1),
+            entry(560, Shape::Vee, 3, MID, 3),
+        ],
+        length: 840,
+    },
+];
+
+// Where the script is up to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spawner {
+    pub wave: u8,
+    // Next entry of the wave that's still to come
+    pub next: u8,
+    // Times the whole script has been played through
+    pub round: u8,
+    // Tick the current wave started at
+    pub started: u32,
+}
+
+impl Spawner {
+    pub const fn new() -> Self {
+        Spawner {
+            wave: 0,
+            next: 0,
+            round: 0,
+            started: 0,
+        }
+    }
+
+    // The next formation that's due by `tick`, if any, already toughened up
+    // for the round. Call until it returns None, since several can be due at
+    // once.
+    pub fn advance(&mut self, tick: u32) -> Option<Formation> {
+        loop {
+            let wave = &WAVES[self.wave as usize % WAVES.len()];
+            let into = tick.wrapping_sub(self.started);
+            if let Some(entry) = wave.entries.get(self.next as usize) {
+                if into < entry.at {
+                    return None;
+                }
+                self.next += 1;
+                let bonus = self.round.min(MAX_BONUS_HP);
+                return Some(Formation {
+                    hp: entry.formation.hp.saturating_add(bonus),
+                    ..entry.formation
+                });
+            }
+            if into < wave.length {
+                return None;
+            }
+
+            self.started = self.started.wrapping_add(wave.length);
+            self.next = 0;
+            self.wave += 1;
+            if self.wave as usize == WAVES.len() {
+                self.wave = 0;
+                self.round = self.round.saturating_add(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Rect, MAX_ENEMIES};
+
+    fn rect((x, y): (i32, i32)) -> Rect {
+        Rect {
+            x,
+            y,
+            w: ENEMY_W,
+            h: ENEMY_H,
+        }
+    }
+
+    // Every formation the spawner gives out up to `ticks`, with the tick it
+    // came out on
+    fn play(spawner: &mut Spawner, ticks: u32) -> Vec<(u32, Formation)> {
+        let mut out = Vec::new();
+        for tick in 0..ticks {
+            while let Some(formation) = spawner.advance(tick) {
+                out.push((tick, formation));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn the_script_is_well_formed() {
+        for (i, wave) in WAVES.iter().enumerate() {
+            assert!(!wave.entries.is_empty(), "wave {}", i);
+            let times: Vec<u32> = wave.entries.iter().map(|entry| entry.at).collect();
+            assert!(
+                times.windows(2).all(|w| w[0] <= w[1]),
+                "wave {} is out of order",
+                i
+            );
+            for entry in wave.entries {
+                let formation = entry.formation;
+                assert!(entry.at < wave.length, "wave {}", i);
+                assert!(formation.count > 0 && formation.count as usize <= MAX_ENEMIES);
+                assert!((0..WIDTH).contains(&formation.x));
+                assert!(formation.hp > 0);
+            }
+        }
+    }
+
+    #[test]
+    fn formations_come_in_from_above_without_overlapping() {
+        for shape in [Shape::Row, Shape::Vee, Shape::Column].iter().copied() {
+            for count in 1..=5 {
+                let formation = Formation {
+                    shape,
+                    count,
+                    x: WIDTH / 2,
+                    hp: 1,
+                };
+                let positions: Vec<_> = formation.positions().collect();
+                assert_eq!(positions.len(), count as usize);
+                // The front is just off the top, everything else above it
+                assert_eq!(positions.iter().map(|p| p.1).max(), Some(-ENEMY_H));
+                for (i, &a) in positions.iter().enumerate() {
+                    assert!((0..=WIDTH - ENEMY_W).contains(&a.0));
+                    for &b in &positions[i + 1..] {
+                        assert!(!rect(a).overlaps(rect(b)), "{:?} x{}", shape, count);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_are_centered_on_their_x() {
+        let formation = |shape, count| Formation {
+            shape,
+            count,
+            x: WIDTH / 2,
+            hp: 1,
+        };
+        let center = WIDTH / 2 - ENEMY_W / 2;
+        let row: Vec<_> = formation(Shape::Row, 3).positions().collect();
+        let step = ENEMY_W + SPACING;
+        let top = -ENEMY_H;
+        assert_eq!(
+            row,
+            [(center - step, top), (center, top), (center + step, top)]
+        );
+
+        let vee: Vec<_> = formation(Shape::Vee, 3).positions().collect();
+        let back = top - ENEMY_H - SPACING;
+        assert_eq!(
+            vee,
+            [(center, top), (center - step, back), (center + step, back)]
+        );
+
+        let column: Vec<_> = formation(Shape::Column, 2).positions().collect();
+        assert_eq!(column, [(center, top), (center, back)]);
+
+        // Pushed back on screen rather than hanging off the side
+        let edge = Formation {
+            x: 0,
+            ..formation(Shape::Row, 3)
+        };
+        assert_eq!(edge.positions().next(), Some((0, top)));
+    }
+
+    #[test]
+    fn formations_come_out_on_their_tick() {
+        let mut spawner = Spawner::new();
+        let length = WAVES[0].length;
+        let played = play(&mut spawner, length);
+        let expected: Vec<_> = WAVES[0]
+            .entries
+            .iter()
+            .map(|entry| (entry.at, entry.formation))
+            .collect();
+        assert_eq!(played, expected);
+
+        // Nothing more until the next wave's first
+        assert_eq!(spawner.advance(length - 1), None);
+
+        // Two at once, both on the tick they're due
+        let played = play(&mut spawner, length + 401);
+        let due = length + WAVES[1].entries[2].at;
+        assert_eq!(played.iter().filter(|&&(tick, _)| tick == due).count(), 2);
+    }
+
+    #[test]
+    fn going_round_again_toughens_enemies_up_to_a_point() {
+        let mut spawner = Spawner::new();
+        let round: u32 = WAVES.iter().map(|wave| wave.length).sum();
+        let played = play(&mut spawner, (MAX_BONUS_HP as u32 + 2) * round);
+        let per_round: usize = WAVES.iter().map(|wave| wave.entries.len()).sum();
+        assert_eq!(played.len(), per_round * (MAX_BONUS_HP as usize + 2));
+
+        for (i, &(tick, formation)) in played.iter().enumerate() {
+            let (n, scripted) = (i / per_round, i % per_round);
+            let entry = WAVES
+                .iter()
+                .flat_map(|wave| wave.entries.iter())
+                .nth(scripted)
+                .unwrap();
+            let bonus = (n as u8).min(MAX_BONUS_HP);
+            assert_eq!(formation.hp, entry.formation.hp + bonus);
+            assert_eq!(tick / round, n as u32);
+        }
+        // The last time round isn't over until a tick past its end
+        assert_eq!(spawner.round, MAX_BONUS_HP + 1);
+    }
+}